@@ -0,0 +1,383 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand, ValueHint::FilePath};
+
+use fiux::{
+    ArrayMode, ColumnType, JoinKind, KeepMode, SortKey, parse_join_key, parse_rename,
+    parse_sort_key, parse_type_override,
+};
+
+static LONG_ABT: &str = r#"
+fiux - The fastest streaming-first file conveter.
+
+ -> Supports JSON, NDJSON, TOML, YAML, CSV, TSV, PSV and more!
+
+ -> Formats are detected automatically based on file extension, except for custom 
+   delimter CSV formats (e.g. TSV, PSV, etc.), which are detected with `--input-delimiter <DELIMITER>` and `--output-delimiter <DELIMITER>`.
+
+ -> Use `-` as the input / output path to read from stdin / write to stdout, with `--from <FORMAT>` / `--to <FORMAT>` to set the formats.
+
+ -> Files without a known extension (e.g. `data`, `export.txt`) and stdin are detected from their content, `fiux detect <FILE>` prints the detected format.
+
+ -> gzip, zstd, bzip2 and xz files are decompressed / compressed based on a compound extension (e.g. `events.ndjson.gz`), compressed input is also detected from its magic bytes.
+
+ -> if there are any bugs or any features you want, open an issue at: `https://github.com/Tahaa-Dev/fiux`.
+
+
+ ╭────────────────·Examples·─────────────────╮
+ │                                       ••• │
+ │ fiux convert data.json -o out.csv         │
+ │ fiux validate broken.ndjson --delimiter   │
+ │ fiux convert big.csv -o big.json --append │
+ │ cat x.csv | fiux convert - --from csv \   │
+ │   --to ndjson -o -                        │
+ │                                           │
+ ╰───────────────────────────────────────────╯
+"#;
+
+#[derive(Parser)]
+#[command(
+    author,
+    version,
+    about = "The fastest streaming-first file conveter.",
+    long_about = LONG_ABT
+)]
+pub struct Args {
+    #[command(subcommand)]
+    pub cmd: Commands,
+
+    /// Argument for setting a file to export error logs to.
+    #[arg(short, long, value_hint = FilePath, global = true)]
+    pub log_file: Option<PathBuf>,
+}
+
+/// fiux subcommands
+#[derive(Subcommand)]
+#[allow(clippy::large_enum_variant)] // parsed once at startup
+pub enum Commands {
+    /// Convert command that takes two positional arguments for input and output, takes one
+    Convert {
+        #[command(flatten)]
+        io: IoArgs,
+
+        /// Argument to append into output file instead of overwriting it (WARNING: This
+        /// can lead to unexpected output on some formats)
+        #[arg(short, long)]
+        append: bool,
+
+        /// Argument for overriding the type of a CSV column (e.g. `zip=string`), can be used
+        /// multiple times
+        #[arg(long = "type", value_name = "COL=TYPE", value_parser = parse_type_override)]
+        types: Vec<(String, ColumnType)>,
+
+        /// Argument for the number of rows used to infer CSV column types
+        #[arg(long, default_value_t = 1000)]
+        infer_rows: usize,
+
+        /// Argument to infer CSV column types from all rows, by spilling rows into a temporary
+        /// file first
+        #[arg(long)]
+        infer_all: bool,
+
+        /// Argument for streaming the elements of a nested array in JSON input one by one
+        /// (e.g. `.data.items`). Top-level arrays are always streamed.
+        #[arg(long)]
+        json_path: Option<String>,
+
+        /// Argument for the separator used to join nested keys when flattening into CSV
+        /// (e.g. `user.address.city`)
+        #[arg(long, default_value = ".")]
+        flatten_separator: String,
+
+        /// Argument for how arrays are flattened into CSV, as indexed columns (e.g. `tags[0]`)
+        /// or as JSON-encoded cells
+        #[arg(long, value_enum, default_value_t = ArrayMode::Index)]
+        flatten_arrays: ArrayMode,
+
+        /// Argument for the number of records used to discover CSV headers when flattening
+        #[arg(long, default_value_t = 1000)]
+        sample_size: usize,
+
+        /// Argument to discover CSV headers from all records when flattening, by spilling
+        /// records into a temporary file first
+        #[arg(long)]
+        two_pass: bool,
+
+        /// Argument for keeping only the given columns / keys in the given order, nested keys
+        /// are selected with paths joined with `--flatten-separator` (e.g. `id,user.name,tags[0]`)
+        #[arg(long, value_delimiter = ',')]
+        select: Vec<String>,
+
+        /// Argument for dropping the given columns / keys, nested keys are dropped with paths
+        #[arg(long, value_delimiter = ',')]
+        exclude: Vec<String>,
+
+        /// Argument for renaming a column / key (e.g. `user.name=username`), can be used
+        /// multiple times
+        #[arg(long, value_name = "OLD=NEW", value_parser = parse_rename)]
+        rename: Vec<(String, String)>,
+
+        /// Argument for keeping only the records that match an expression (e.g.
+        /// `level == "error" && latency_ms > 500`), supports comparisons, `&&`, `||`, `!`, regex
+        /// matches with `=~` / `!~`, null checks and nested paths (e.g. `user.tags[0]`)
+        #[arg(long, value_name = "EXPR")]
+        filter: Option<String>,
+
+        /// Argument to rebuild nested values from flattened CSV headers (e.g. `user.name`,
+        /// `user.tags[0]`) instead of writing them as flat keys, uses `--flatten-separator`
+        #[arg(long)]
+        unflatten: bool,
+
+        /// Argument for the number of threads that decode and encode NDJSON / CSV in chunks,
+        /// output is the same as with one thread, `0` uses every core
+        #[arg(long, default_value_t = 1, value_name = "N")]
+        threads: usize,
+    },
+
+    /// Query command that runs a jq-style expression over every record of the input and writes
+    /// its outputs in any format, NDJSON is written to stdout unless `--to` is set
+    #[command(mut_arg("output", |a| a.required(false).default_value("-")))]
+    Query {
+        #[command(flatten)]
+        io: IoArgs,
+
+        /// Argument for the query expression (e.g. `.servers[].host`,
+        /// `.[] | select(.age > 30) | {name}`)
+        #[arg(required = true)]
+        expr: String,
+
+        /// Argument to rebuild nested values from flattened CSV headers (e.g. `user.name`)
+        /// before querying
+        #[arg(long)]
+        unflatten: bool,
+    },
+
+    /// Dedupe command that removes duplicate records by keys or by a hash of the whole record
+    Dedupe {
+        #[command(flatten)]
+        io: IoArgs,
+
+        /// Argument for the columns / keys that identify a record (e.g. `id,user.id`), whole
+        /// records are compared if it's not set
+        #[arg(short, long = "key", value_name = "KEY", value_delimiter = ',')]
+        keys: Vec<String>,
+
+        /// Argument for which record of a group of duplicates is kept
+        #[arg(long, value_enum, default_value_t = KeepMode::First)]
+        keep: KeepMode,
+
+        /// Argument to track seen records in temporary files instead of memory, for inputs with
+        /// more unique records than fit in memory
+        #[arg(long)]
+        on_disk: bool,
+    },
+
+    /// Sort command that sorts CSV rows or records by one or more keys, files larger than memory
+    /// are sorted by spilling sorted chunks into temporary files and merging them
+    Sort {
+        #[command(flatten)]
+        io: IoArgs,
+
+        /// Argument for a key to sort by as `col[:desc][:num]` (e.g. `latency_ms:desc:num`),
+        /// nested keys are paths (e.g. `user.age`), can be used multiple times in order of priority
+        #[arg(short, long = "key", required = true, value_name = "KEY", value_parser = parse_sort_key)]
+        keys: Vec<SortKey>,
+
+        /// Argument for the memory budget in MiB, sorted chunks are spilled into temporary files
+        /// when records take more memory than this
+        #[arg(short, long, default_value_t = 256)]
+        memory_limit: usize,
+    },
+
+    /// Aggregate command that groups records by keys and computes aggregations for every group
+    Aggregate {
+        #[command(flatten)]
+        io: IoArgs,
+
+        /// Argument for columns / keys to group records by (e.g. `region,user.country`), all
+        /// records are aggregated into one group if it's not set
+        #[arg(short, long, value_delimiter = ',')]
+        group_by: Vec<String>,
+
+        /// Argument for aggregations to compute for every group (e.g.
+        /// `count(),sum(amount),avg(latency),min(ts),max(ts),distinct(user)`), can be used
+        /// multiple times
+        #[arg(long, required = true)]
+        agg: Vec<String>,
+
+        /// Argument for aggregating only the records that match an expression, same as in `convert`
+        #[arg(long, value_name = "EXPR")]
+        filter: Option<String>,
+    },
+
+    /// Stats command that profiles every column / field of a file in one pass, written as a text
+    /// table to stdout unless `--to` is set, or as records in any format
+    #[command(alias = "describe", mut_arg("output", |a| a.required(false).default_value("-")))]
+    Stats {
+        #[command(flatten)]
+        io: IoArgs,
+
+        /// Argument for the number of most frequent values reported per field
+        #[arg(short, long, default_value_t = 5)]
+        top: usize,
+
+        /// Argument for profiling only the records that match an expression, same as in `convert`
+        #[arg(long, value_name = "EXPR")]
+        filter: Option<String>,
+    },
+
+    /// Schema command that infers a JSON Schema (draft 2020-12) that every record of a file
+    /// matches, JSON is written to stdout unless `--to` is set
+    #[command(mut_arg("output", |a| a.required(false).default_value("-")))]
+    Schema {
+        #[command(flatten)]
+        io: IoArgs,
+
+        /// Argument for the largest number of distinct strings written as an `enum`, `0` never
+        /// writes enums
+        #[arg(long, default_value_t = 10)]
+        enum_limit: usize,
+
+        /// Argument to rebuild nested values from flattened CSV headers (e.g. `user.name`)
+        /// before inferring the schema
+        #[arg(long)]
+        unflatten: bool,
+    },
+
+    /// Join command that joins the records of two files on keys, the right file is kept in memory
+    /// for a hash join if it fits, otherwise both files are sorted by their keys and merged. The
+    /// input flags are for the left file
+    #[command(mut_arg("input", |a| {
+        a.index(1).value_name("LEFT").help("Argument for left input file path, `-` reads from stdin")
+    }))]
+    Join {
+        #[command(flatten)]
+        io: IoArgs,
+
+        /// Argument for right input file path, `-` reads from stdin
+        #[arg(index = 2, required = true, value_hint = FilePath)]
+        right: PathBuf,
+
+        /// Argument for keys to join on as `col` or `left_col=right_col` (e.g. `id,user_id=id`),
+        /// nested keys are paths (e.g. `user.id`)
+        #[arg(long, required = true, value_delimiter = ',', value_parser = parse_join_key)]
+        on: Vec<(String, String)>,
+
+        /// Argument for the kind of join
+        #[arg(long, value_enum, default_value_t = JoinKind::Inner)]
+        how: JoinKind,
+
+        /// Argument for the memory budget in MiB, inputs are sorted by their keys with spilling
+        /// and merged when the right file takes more memory than this
+        #[arg(short, long, default_value_t = 256)]
+        memory_limit: usize,
+
+        /// Argument for specifying the right input format, same as `--from`
+        #[arg(long)]
+        right_from: Option<String>,
+
+        /// Argument for specifying delimiters for CSV / CSV-like right input formats, same as
+        /// `--input-delimiter`
+        #[arg(long)]
+        right_delimiter: Option<char>,
+    },
+
+    /// Validate command for file format validation with one positional argument for the file
+    Validate {
+        /// path to the file to be validated, `-` reads from stdin
+        #[arg(required = true, value_hint = FilePath)]
+        input: PathBuf,
+
+        /// Argument for specifying the input format (e.g. `json`, `csv`, etc.).
+        /// This flag makes fiux ignore the input file extension, the format is detected from the content
+        /// when there is no known extension (e.g. stdin).
+        #[arg(long)]
+        from: Option<String>,
+
+        /// Argument for specifying delimiters for CSV / CSV-like input formats (e.g. TSV, PSV, etc.).
+        /// This flag makes fiux ignore the extension and instead treat the file as a CSV
+        /// with the specified delimiter instead of commas.
+        #[arg(short, long)]
+        delimiter: Option<char>,
+
+        /// Argument for a JSON Schema (draft 2020-12) file in JSON or YAML that every record is
+        /// validated against, CSV columns are typed like with `--parse-numbers` before validating
+        /// rows
+        #[arg(long, value_hint = FilePath)]
+        schema: Option<PathBuf>,
+
+        /// Argument for inferring CSV column types before validating rows against `--schema`,
+        /// columns are always typed with `--schema` so this only mirrors `schema -p`
+        #[arg(short, long, requires = "schema")]
+        parse_numbers: bool,
+
+        /// Argument for overriding the type of a CSV column (e.g. `zip=string`) before validating
+        /// rows against `--schema`, can be used multiple times
+        #[arg(
+            long = "type",
+            value_name = "COL=TYPE",
+            value_parser = parse_type_override,
+            requires = "schema"
+        )]
+        types: Vec<(String, ColumnType)>,
+
+        /// Argument for a column-type schema file for CSV input mapping headers to `type`, `regex`
+        /// and `required` rules (e.g. `[id]` with `type = "int"` in TOML) that every row is checked
+        /// against after the structural checks
+        #[arg(long, value_hint = FilePath, conflicts_with = "schema")]
+        csv_schema: Option<PathBuf>,
+    },
+
+    /// Formats command that lists supported formats with their extensions and capabilities
+    Formats,
+
+    /// Detect command that detects the format of a file from its content and prints it with the
+    /// CSV delimiter, compression and a confidence from 0 to 1
+    Detect {
+        /// Argument for input file path, `-` reads from stdin
+        #[arg(required = true, value_hint = FilePath)]
+        input: PathBuf,
+    },
+}
+
+/// Input / output arguments shared by the subcommands that read records and write them in any
+/// format.
+#[derive(clap::Args)]
+pub struct IoArgs {
+    /// Argument for input file path, `-` reads from stdin
+    #[arg(required = true, value_hint = FilePath)]
+    pub input: PathBuf,
+
+    /// Argument for output file path, `-` writes to stdout
+    #[arg(short, long, value_hint = FilePath)]
+    pub output: PathBuf,
+
+    /// Argument for specifying the input format (e.g. `json`, `csv`, etc.).
+    /// This flag makes fiux ignore the input file extension, the format is detected from the
+    /// content when there is no known extension (e.g. stdin).
+    #[arg(long)]
+    pub from: Option<String>,
+
+    /// Argument for specifying the output format (e.g. `json`, `csv`, etc.).
+    /// This flag makes fiux ignore the output file extension, required when writing to stdout
+    /// unless the subcommand has a default.
+    #[arg(long)]
+    pub to: Option<String>,
+
+    /// Argument for inferring column types (int, float, bool, null, date, datetime, string)
+    /// in CSV input instead of reading every cell as a string
+    #[arg(short, long)]
+    pub parse_numbers: bool,
+
+    /// Argument for specifying delimiters for CSV / CSV-like input formats (e.g. TSV, PSV, etc.).
+    /// This flag makes fiux ignore the extension and instead treat the file as a CSV
+    /// with the specified delimiter instead of commas.
+    #[arg(long)]
+    pub input_delimiter: Option<char>,
+
+    /// Argument for specifying delimiters for CSV / CSV-like output formats (e.g. TSV, PSV, etc.).
+    /// This flag makes fiux ignore the extension and instead treat the file as a CSV
+    /// with the specified delimiter instead of commas.
+    #[arg(long)]
+    pub output_delimiter: Option<char>,
+}
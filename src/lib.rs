@@ -0,0 +1,62 @@
+/*!
+**fiux: The *fastest* multi-format file converter**
+
+Streaming conversion and validation between JSON, NDJSON, TOML, YAML and CSV (with any delimiter).
+This is the library behind the `fiux` CLI, see the README for the command-line manual.
+
+Files are converted with [`Converter`] and validated with [`validate`], errors are returned instead
+of exiting the process:
+
+```
+use fiux::{Converter, Format, validate};
+
+let csv = "name,age\nAlice,30\n";
+let mut ndjson = Vec::new();
+
+Converter::new(csv.as_bytes(), Format::Csv, Format::Ndjson)
+    .parse_numbers(true)
+    .convert(&mut ndjson)?;
+
+validate(ndjson.as_slice(), Format::Ndjson)?;
+# Ok::<(), fiux::CtxResultErr>(())
+```
+
+Every decoder produces records as [`Value`]s (null, bool, int, float, decimal, string, bytes,
+datetime, array and map with insertion order) and every encoder consumes them, so any format can be
+converted into any other.
+
+Formats are looked up by name or extension in a registry, new formats can be added by implementing
+[`FileFormat`] with any of [`FormatDecoder`], [`FormatEncoder`] and [`FormatValidator`] then
+registering it with [`register_format`], formats registered later override built-in ones with the
+same name or extension.
+
+Recoverable errors (e.g. invalid items that are skipped) are logged into stderr unless a log file is
+set with [`set_log_file`].
+
+### Cargo features
+
+- `simd-json`: Decodes and validates JSON / NDJSON with `simd-json` on CPUs with AVX2 / SSE4.2 (x86)
+  or NEON (ARM), other CPUs and input that `simd-json` parses differently (e.g. lone surrogate
+  escapes) use `serde_json`, so output and error locations are the same either way. Floats are
+  parsed with exact rounding in builds with this feature.
+*/
+
+mod utils;
+
+pub use utils::{
+    Agg, AggFn, AggregateOpts, ArrayMode, ColumnOpts, ColumnType, Compression, ConvertOptions,
+    Converter, CsvColumnRule, CsvSchema, CtxResult, CtxResultErr, CtxResultExt, DedupeOpts,
+    Detection, Encoder, FileFormat, Filter, FiuxErr, FlattenOpts, Format, FormatDecoder,
+    FormatEncoder, FormatValidator, JoinKind, JoinOpts, JsonSchema, KeepMode, Map, Query, Records,
+    Registry, SCHEMA_DIALECT, SNIFF_SIZE, STATS_HEADERS, SchemaOpts, SortKey, SortOpts, Stage,
+    StatsOpts, TypeOpts, Value, WriterStreams, aggregate_records, dedupe_records, detect_format,
+    detect_path, find_format, find_format_by_path, flush_logger, format_extension, formats,
+    infer_schema,
+    input_reader::{Input, MMAP_THRESHOLD, Mapped, input_reader, open_input},
+    is_std_stream, join_records,
+    output_writer::{Output, output_writer},
+    parse_join_key, parse_rename, parse_sort_key, parse_type_override, register_format,
+    schema_records, set_log_file, sort_records, stats_records, thread_count, validate,
+    validate_input, validate_records, validate_with_csv_schema, validate_with_delimiter,
+    validate_with_schema, write_text_table,
+};
@@ -3,7 +3,7 @@
 
 fiux provides intuitive file conversion similar to `mlr`'s `cat` verb but with a few advantages over it:
 
-1. Auto format detection through extension
+1. Auto format detection through extension, or through content for files without a known extension and stdin
 2. Better error-handling with recoverable errors, logging options, comprehensive logs and a `validate` command for debugging
 3. 5× - 6× faster than `mlr`'s `cat` verb
 
@@ -17,6 +17,7 @@ fiux provides intuitive file conversion similar to `mlr`'s `cat` verb but with a
 
 - Convert from input file to output file
 - If any item in the input file is invalid, the item is skipped and fiux logs an error message
+- Regular input files of at least 4 MiB are memory-mapped, JSON, NDJSON and CSV records and TOML documents are parsed straight from the mapping instead of being copied into buffers (this also applies to every other subcommand and to `validate` for JSON and TOML), stdin and pipes are always read through a buffer
 - Only panics on read / write failure
 ##### Usage
 
@@ -28,23 +29,193 @@ fiux convert <INPUT> -o <OUTPUT>
 
 ##### Arguments
 
-1. Input: Input file to be converted, will panic if it doesn't exist or if its extension is not supported.
-2. Output: `--output` / `-o` flag, file to write output to, will panic only if its extension is not supported, will create the file if it doesn't exist.
+1. Input: Input file to be converted, will panic if it doesn't exist or if its extension is not supported. `-` reads from stdin. gzip, zstd, bzip2 and xz input is decompressed while it's read, compression is detected from the magic bytes at the start of the input or from a compound extension (e.g. `events.ndjson.gz`, `data.json.zst`), in which case the format comes from the extension before it.
+2. Output: `--output` / `-o` flag, file to write output to, will panic only if its extension is not supported, will create the file if it doesn't exist. `-` writes to stdout. Paths ending in `.gz`, `.zst`, `.bz2` or `.xz` are compressed (e.g. `-o events.csv.gz`).
 
 ##### Flags (options)
 
 1. `--append` / `-a`: fiux overwrites existing data in the output file by default, this flag makes it append to it instead. **WARNING:** This flag can lead to corrupted output with some formats like JSON.
-2. `--parse-numbers` / `-p`: Flag to make fiux parse numbers in output when converted from CSV.
+2. `--parse-numbers` / `-p`: Flag to make fiux infer column types (int, float, bool, null, date, datetime, string) when converting from CSV instead of writing every cell as a string. Zip codes (e.g. `01234`) and IDs with a sign (e.g. `+1555`) stay strings.
 3. `--input-delimiter` / `--output-delimiter`: Flags that make fiux ignore file extension and treat them as CSV with the specified delimiter
+4. `--from` / `--to`: Flags that make fiux ignore file extension and use the specified format instead, `--to` is required when writing to stdout. Without `--from`, input without a known extension (e.g. `data`, `export.txt` or stdin) is detected from its content like in `detect`.
+5. `--json-path`: Flag for streaming the elements of a nested array in JSON input one by one (e.g. `.data.items`), top-level arrays are always streamed element by element.
+6. `--flatten-separator` / `--flatten-arrays` / `--sample-size` / `--two-pass`: Flags for flattening nested records into CSV columns, setting the separator for nested keys (default: `.`), writing arrays as indexed columns (`index`, default) or JSON-encoded cells (`json`), the number of records used to discover headers (default: 1000) or discovering headers from all records by spilling them into a temporary file.
+7. `--unflatten`: Flag for rebuilding nested values from flattened CSV headers (e.g. `user.name`, `user.tags[0]`) when converting CSV to other formats, the inverse of flattening. Empty cells in nested columns are skipped, other cells are typed like in flat output.
+8. `--type` / `--infer-rows` / `--infer-all`: Flags for overriding the types of CSV columns (e.g. `--type zip=string`, can be used multiple times), setting the number of rows used to infer column types (default: 1000) or inferring column types from all rows by spilling them into a temporary file.
+9. `--select` / `--exclude` / `--rename`: Flags for keeping only the given columns / keys in the given order (e.g. `--select id,user.name`), dropping columns / keys (e.g. `--exclude user.age`) and renaming them (e.g. `--rename user.name=username`, can be used multiple times). Nested keys are selected with paths (e.g. `user.name`, `tags[0]`) and CSV headers are matched by name or as a prefix of flattened headers (e.g. `user` matches `user.name`), nested keys are joined with `--flatten-separator` in both (e.g. `user_name` with `--flatten-separator _`). Selecting and excluding is applied before renaming, so both use the input names.
+10. `--filter`: Flag for keeping only the records that match an expression (e.g. `--filter 'level == "error" && latency_ms > 500'`). Expressions support comparisons (`==`, `!=`, `<`, `<=`, `>`, `>=`), boolean logic (`&&` / `and`, `||` / `or`, `!` / `not`, parentheses), regex matches (`=~`, `!~`), null checks (`field == null`) and nested paths (e.g. `user.tags[0]`, `` `first name` `` for keys with spaces). Missing fields and empty CSV cells are `null`, numeric strings are compared as numbers. Filters are applied before `--select` / `--exclude` / `--rename`, so they use the input names.
+11. `--threads`: Flag for decoding and encoding NDJSON / CSV on N worker threads (default: 1, `0` uses every core). Input is split into chunks of whole records (quoted CSV cells with newlines are kept whole) and the output is reassembled in order, so it's byte-identical to single-threaded runs.
 
 ---
 
-### 2. Validate
+### 2. Query
+
+##### Description
+
+- Run a jq-style expression over every record of the input (NDJSON lines, CSV rows, elements of top-level JSON arrays, YAML documents and whole JSON / TOML documents) and write its outputs in any format
+- Supports paths (`.key`, `."key with spaces"`, `.[0]`, `.[-1]`), slices (`.[2:4]`), iteration (`.[]`), pipes (`|`), multiple outputs (`,`), comparisons, `and` / `or`, alternatives (`//`), array / object construction (`[.items[].id]`, `{id, name: .user.name}`), optional access (`.key?`) and the `select`, `map`, `has`, `length`, `keys`, `not` and `empty` functions
+- Records that the query fails on (e.g. `.[]` on a number) are logged and skipped
+
+##### Usage
+
+```sh
+fiux query <INPUT> <EXPR> [-o <OUTPUT>]
+```
+
+##### Flags (options)
+
+1. `--output` / `-o`: File to write outputs to, defaults to stdout (`-`) where outputs are written as NDJSON unless `--to` is set.
+2. `--from` / `--to`, `--input-delimiter` / `--output-delimiter`, `--parse-numbers` / `-p` and `--unflatten`: Same as in `convert`, scalar outputs are written into a `value` column in CSV.
+
+---
+
+### 3. Dedupe
+
+##### Description
+
+- Remove duplicate records by keys or by a hash of the whole record, in any format
+- Records are compared with 128-bit hashes of their canonical form, so key order in objects doesn't matter (`{"a": 1, "b": 2}` equals `{"b": 2, "a": 1}`)
+- Missing keys are treated as `null`, invalid records are passed through to be logged
+
+##### Usage
+
+```sh
+fiux dedupe <INPUT> -o <OUTPUT> [--key <KEYS>]
+```
+
+##### Flags (options)
+
+1. `--key` / `-k`: Columns / keys that identify a record (e.g. `--key id,user.id`), whole records are compared if it's not set. CSV columns are matched by name and nested keys are paths.
+2. `--keep`: Which record of a group of duplicates is kept, `first` (default) streams records and drops duplicates as they're read, `last` keeps the last record in its position.
+3. `--on-disk`: Track seen records in temporary files instead of memory, for inputs with more unique records than fit in memory. Records are spilled and their hashes are partitioned into buckets that are deduped one by one.
+4. `--from` / `--to`, `--input-delimiter` / `--output-delimiter` and `--parse-numbers` / `-p`: Same as in `convert`.
+
+---
+
+### 4. Sort
+
+##### Description
+
+- Sort CSV rows or JSON / NDJSON / YAML records by one or more keys and write them in any format
+- Files larger than memory are sorted with an external merge sort: records are sorted in memory until they reach the memory budget, then sorted chunks are spilled into temporary files and merged
+- Sorting is stable and nulls / missing keys / empty cells are sorted last, invalid records are logged and skipped
+
+##### Usage
+
+```sh
+fiux sort <INPUT> -o <OUTPUT> --key <COL[:desc][:num]>
+```
+
+##### Flags (options)
+
+1. `--key` / `-k`: Key to sort by, can be used multiple times in order of priority. `:desc` sorts in descending order and `:num` compares values as numbers (e.g. `--key latency_ms:desc:num`), CSV columns are matched by name and nested keys are paths (e.g. `user.age`).
+2. `--memory-limit` / `-m`: Memory budget in MiB for records kept in memory before spilling (default: 256).
+3. `--from` / `--to`, `--input-delimiter` / `--output-delimiter` and `--parse-numbers` / `-p`: Same as in `convert`.
+
+---
+
+### 5. Aggregate
+
+##### Description
+
+- Group records of any format by keys and compute aggregations for every group, writing one record per group with the group keys followed by the aggregations (e.g. `region,count,sum_amount`)
+- Input records are streamed and only the state of every group is kept in memory, groups are written in the order they're first seen
+- Missing keys and empty CSV cells are `null`, non-numeric values are skipped by `sum` and `avg`
+
+##### Usage
+
+```sh
+fiux aggregate <INPUT> -o <OUTPUT> --group-by <KEYS> --agg <AGGS>
+```
+
+##### Flags (options)
+
+1. `--group-by` / `-g`: Columns / keys to group records by (e.g. `--group-by region,user.country`), all records are aggregated into one group if it's not set. CSV columns are matched by name and nested keys are paths.
+2. `--agg`: Aggregations to compute, can be used multiple times (e.g. `--agg 'count(),sum(amount),avg(latency),min(ts),max(ts),distinct(user)'`). `count()` counts records and `count(field)` non-null values, `sum` keeps integer sums as integers, `min` / `max` compare numbers numerically and everything else as strings and `distinct` counts distinct non-null values.
+3. `--filter`: Aggregate only the records that match an expression, same as in `convert`.
+4. `--from` / `--to`, `--input-delimiter` / `--output-delimiter` and `--parse-numbers` / `-p`: Same as in `convert`.
+
+---
+
+### 6. Stats
+
+##### Description
+
+- Profile every column / field of a file in one pass (alias: `describe`), reporting its inferred type, number of non-null values, number of nulls (including records where the field is missing), estimated number of distinct values, min / max, mean / standard deviation of numeric fields, range of string lengths and most frequent values
+- Nested objects are profiled by their dotted paths (e.g. `user.name`), cells of CSV input are typed like `--parse-numbers` and empty cells are nulls
+- Distinct values are estimated with a HyperLogLog sketch (about 1.6% error) and most frequent values are tracked with the Space-Saving algorithm, so memory stays bounded per field
+- Written as a text table on the terminal, or as one record per field in any format with `-o` / `--to`
+
+##### Usage
+
+```sh
+fiux stats <INPUT> [-o <OUTPUT>]
+```
+
+##### Flags (options)
+
+1. `--output` / `-o`: File to write statistics to, defaults to stdout (`-`) where they're written as a text table unless `--to` is set.
+2. `--top` / `-t`: Number of most frequent values reported per field (default: 5).
+3. `--filter`: Profile only the records that match an expression, same as in `convert`.
+4. `--from` / `--to` and `--input-delimiter` / `--output-delimiter`: Same as in `convert`.
+
+---
+
+### 7. Schema
+
+##### Description
+
+- Infer a JSON Schema (draft 2020-12) that every record of a file matches, walking every record and merging their types, nested objects and array items
+- Properties that are in every object are `required`, values of different types are written as a list of types (e.g. `["string", "null"]`) and integers mixed with floats are `number`
+- Strings get a `format` when every value matches it (`date-time`, `date`, `email` or `uri`), strings with few distinct values are written as an `enum`
+- CSV cells are typed like `--parse-numbers` and empty cells are nulls
+
+##### Usage
+
+```sh
+fiux schema <INPUT> [-o <OUTPUT>]
+```
+
+##### Flags (options)
+
+1. `--output` / `-o`: File to write the schema to, defaults to stdout (`-`) where it's written as JSON unless `--to` is set (e.g. `--to yaml`).
+2. `--enum-limit`: Largest number of distinct strings written as an `enum` (default: 10), strings are only written as an `enum` when each value is seen at least twice on average. `0` never writes enums.
+3. `--from` / `--to`, `--input-delimiter` and `--unflatten`: Same as in `convert`.
+
+---
+
+### 8. Join
+
+##### Description
+
+- Join the records of two files in any formats on keys, like SQL joins (`inner`, `left`, `right` and `full`)
+- The right file is loaded into memory for a hash join that keeps the order of the left file when it fits in the memory budget, otherwise both files are sorted by their keys with spilling and merged, which writes records in key order
+- Keys are compared as text so CSV cells match JSON numbers (e.g. `"1"` and `1`), records with missing, null or empty keys never match
+- Joined records have the left fields followed by the right fields, right fields with the same name as a left field get a `_right` suffix
+
+##### Usage
+
+```sh
+fiux join <LEFT> <RIGHT> -o <OUTPUT> --on <KEYS>
+```
+
+##### Flags (options)
+
+1. `--on`: Keys to join on, `id` joins on `id` on both sides and `user_id=id` joins the left `user_id` with the right `id` (e.g. `--on region,user_id=id`). CSV columns are matched by name and nested keys are paths (e.g. `user.id`).
+2. `--how`: Kind of join, `inner` (default) keeps only records with a match on both sides, `left` / `right` keep every record of that side and `full` keeps every record of both sides.
+3. `--memory-limit` / `-m`: Memory budget in MiB for the right file in a hash join (default: 256), larger files are joined with a sort-merge join.
+4. `--from` / `--right-from` and `--input-delimiter` / `--right-delimiter`: Formats and CSV delimiters of the left and right files, same as `--from` / `--input-delimiter` in `convert`.
+5. `--to`, `--output-delimiter` and `--parse-numbers` / `-p`: Same as in `convert`.
+
+---
+
+### 9. Validate
 
 ##### Description
 
 - Test if input file is valid
 - Logs a detailed error message if an item is invalid then continues to validate the rest of the items
+- CSV files are checked for blank / duplicate headers, ragged rows, trailing delimiters, invalid UTF-8 cells, mixed line endings and unbalanced quotes, each logged with its line and column
 - Only panics on read failure
 ##### Usage
 
@@ -56,13 +227,43 @@ fiux validate <INPUT>
 
 ##### Arguments
 
-Input: File to be validated, will panic if it doesn't exist
+Input: File to be validated, will panic if it doesn't exist. `-` reads from stdin. Compressed files are decompressed like in `convert`.
 
 ##### Flags (options)
 
-`--delimiter` / `-d`: Flag that makes fiux ignore file extension and treat the file as a CSV with the specified delimiter
+1. `--delimiter` / `-d`: Flag that makes fiux ignore file extension and treat the file as a CSV with the specified delimiter
+2. `--from`: Flag that makes fiux ignore file extension and use the specified format instead. Without it, input without a known extension is detected from its content like in `detect`.
+3. `--schema`: Flag for validating every record against a JSON Schema (draft 2020-12) in any format that can be decoded (e.g. JSON, YAML), every NDJSON line, JSON array element, YAML / TOML document and CSV row (columns typed like with `--parse-numbers`, so a schema from `fiux schema` with or without `-p` matches its own file) is validated and each violation is logged with its line / record number, the JSON pointer of the failing value and the failing schema keyword, `format` keywords (e.g. `email`, `date-time`) are checked too
+4. `--type` and `--parse-numbers` / `-p`: Same as in `convert`, for rows validated against `--schema`. CSV columns are always typed with `--schema`, `-p` is accepted so the flags of `schema` can be reused.
+5. `--csv-schema`: Flag for checking every row of a CSV file against a column-type schema in any format that can be decoded (e.g. TOML, JSON, YAML), mapping headers to a `type` (`int`, `float`, `bool`, `date`, `datetime`, `string`, `null`), a `regex` every non-empty cell must match as a whole and `required` (the column must exist and no cell can be empty)
+
+### 10. Formats
+
+##### Description
+
+- List supported formats with their extensions and whether they can be decoded, encoded and validated
+
+##### Usage
+
+```sh
+fiux formats
+```
+
+### 11. Detect
+
+##### Description
+
+- Detect the format of a file from its content and print it with the CSV delimiter, compression and a confidence from 0 to 1
+- Looks at the first 64 KiB: a UTF-8 BOM is skipped, compressed input is decompressed, JSON / NDJSON are detected from the first non-whitespace byte and the structure of the lines, TOML from `key = value` / `[table]` lines, YAML from `key: value` / `- item` lines or a `---` header and CSV from the delimiter (`,`, tab, `;` or `|`) that splits the first lines into the same number of fields
+- The same detection is used by every subcommand for input without `--from` and without a known extension (e.g. `data`, `export.txt` or stdin)
+
+##### Usage
+
+```sh
+fiux detect <FILE>
+```
 
-### 3. `--log-file` / `-l` global flag
+### 12. `--log-file` / `-l` global flag
 
 Flag for specifying a file to write logs to instead of printing them to stderr, preferably a Markdown file.
 
@@ -94,149 +295,286 @@ fiux validate broken.json -l err.md
 
 # Validate with delimiter
 fiux validate input.psv -d '|'
+
+# Validate every record against a JSON Schema
+fiux validate events.ndjson --schema events.schema.json -l err.md
+
+# Validate a CSV file against column types, regexes and required columns
+fiux validate users.csv --csv-schema users.schema.toml
+
+# Convert nested NDJSON to CSV with headers discovered from all records
+fiux convert logs.ndjson -o logs.csv --two-pass --flatten-arrays json
+
+# Convert flattened CSV back into nested JSON
+fiux convert users.csv -o users.json --unflatten -p
+
+# Convert CSV to JSON with inferred types, keeping a numeric-looking column as strings
+fiux convert orders.csv -o orders.json -p --type order_id=string
+
+# Convert CSV keeping and renaming only some columns
+fiux convert users.csv -o users.ndjson --select id,email --rename email=contact
+
+# Convert only slow error events into CSV
+fiux convert events.ndjson -o errors.csv --filter 'level == "error" && latency_ms > 500'
+
+# Convert a large NDJSON file into CSV on every core
+fiux convert events.ndjson -o events.csv --threads 0
+
+# Convert gzip compressed NDJSON into zstd compressed CSV
+fiux convert events.ndjson.gz -o events.csv.zst
+
+# Query the hosts of all servers in a TOML file into CSV
+fiux query config.toml '.servers[].host' --to csv
+
+# Query rows of a CSV file into JSON objects
+fiux query users.csv 'select(.age > 30) | {name, email}' -o adults.json -p
+
+# Remove events delivered more than once, keeping the latest copy of each event
+fiux dedupe events.ndjson -o unique.ndjson --key event_id --keep last
+
+# Sort a large CSV file by team then by score from highest to lowest with a 1 GiB memory budget
+fiux sort scores.csv -o sorted.csv --key team --key score:desc:num -m 1024
+
+# Count orders and sum their amounts per region
+fiux aggregate orders.csv -o totals.csv --group-by region --agg 'count(),sum(amount),avg(latency)'
+
+# Profile every field of a log file as a table on the terminal, or into JSON
+fiux stats logs.ndjson
+fiux stats logs.ndjson -o profile.json --top 10
+
+# Infer a JSON Schema for a partner's NDJSON feed
+fiux schema feed.ndjson -o feed.schema.json
+
+# Join users in CSV with their orders in NDJSON, keeping users without orders
+fiux join users.csv orders.ndjson -o joined.json --on id=user_id --how left -p
+
+# Convert from stdin to stdout
+cat input.csv | fiux convert - --from csv --to ndjson -o -
 ```
+
+---
+
+The CLI is a thin wrapper around the `fiux` library, see the library docs for using fiux from Rust.
 */
 
-mod utils;
+mod cli;
 use clap::Parser;
 use owo_colors::OwoColorize;
+use resext::ctx;
 
-use std::fs::OpenOptions;
 use std::io::{Error, ErrorKind as EK};
 use std::path::Path;
 use std::process::exit;
-use std::sync::LazyLock;
+use std::sync::{Arc, LazyLock};
 
-use utils::*;
+use cli::*;
+use fiux::*;
 
 pub static ARGS: LazyLock<Args> = LazyLock::new(Args::parse);
 
 fn run() -> CtxResult<()> {
     let args = &*ARGS;
 
+    set_log_file(args.log_file.clone().unwrap_or_else(|| "fiux.log".into()));
+
     match &args.cmd {
         Commands::Convert {
-            input,
-            output,
+            io,
             append,
-            parse_numbers,
-            input_delimiter,
-            output_delimiter,
+            types,
+            infer_rows,
+            infer_all,
+            json_path,
+            flatten_separator,
+            flatten_arrays,
+            sample_size,
+            two_pass,
+            select,
+            exclude,
+            rename,
+            filter,
+            unflatten,
+            threads,
         } => {
-            if !Path::new(&input).exists() {
-                eprintln!(
-                    "{} Input file: {} doesn't exist",
-                    "[FATAL]".red().bold(),
-                    input.to_str().unwrap_or("input_file").on_bright_red(),
-                );
+            let opts = ConvertOptions {
+                json_path: json_path.clone(),
+                flatten: FlattenOpts {
+                    separator: flatten_separator.to_string(),
+                    arrays: *flatten_arrays,
+                    sample_size: *sample_size,
+                    two_pass: *two_pass,
+                    unflatten: *unflatten,
+                },
+                types: TypeOpts {
+                    sample_size: *infer_rows,
+                    whole_file: *infer_all,
+                    overrides: types.clone(),
+                },
+                columns: ColumnOpts {
+                    select: select.clone(),
+                    exclude: exclude.clone(),
+                    rename: rename.clone(),
+                },
+                filter: filter.as_deref().map(Filter::parse).transpose()?,
+                threads: *threads,
+                ..Default::default()
+            };
 
-                exit(1);
-            }
+            convert(converter(io, opts, None)?, io, *append)
+        }
 
-            let output_file = OpenOptions::new()
-                .create(true)
-                .write(true)
-                .append(*append)
-                .open(output)
-                .context("Failed to open output file")?;
+        Commands::Query { io, expr, unflatten } => {
+            let opts = ConvertOptions {
+                flatten: FlattenOpts { unflatten: *unflatten, ..Default::default() },
+                stages: vec![Stage::Query(Query::parse(expr)?)],
+                ..Default::default()
+            };
 
-            let o_d: char;
+            // query results are written as NDJSON by default like jq
+            convert(converter(io, opts, Some("ndjson"))?, io, false)
+        }
 
-            let now = std::time::Instant::now();
+        Commands::Dedupe { io, keys, keep, on_disk } => {
+            let dedupe = DedupeOpts { keys: keys.clone(), keep: *keep, on_disk: *on_disk };
+            let opts = ConvertOptions { stages: vec![Stage::Dedupe(dedupe)], ..Default::default() };
 
-            let output_ext;
-            if let Some(ch) = output_delimiter {
-                output_ext = std::borrow::Cow::Borrowed("csv");
-                o_d = *ch;
-            } else {
-                output_ext = output
-                    .extension()
-                    .ok_or_else(|| Error::new(EK::InvalidFilename, "No valid extension"))
-                    .context("Failed to get output file extension")?
-                    .to_string_lossy();
-                o_d = ',';
-            }
+            convert(converter(io, opts, None)?, io, false)
+        }
 
-            if let Some(ch) = input_delimiter {
-                let data = csv_decoder::csv_decoder(csv_reader::csv_reader(input, *ch))
-                    .context("Deserialization failed")?;
+        Commands::Sort { io, keys, memory_limit } => {
+            let sort = SortOpts {
+                keys: keys.clone(),
+                memory_limit: memory_limit.saturating_mul(1024 * 1024),
+            };
+            let opts = ConvertOptions { stages: vec![Stage::Sort(sort)], ..Default::default() };
 
-                match_output(data, output_file, &output_ext, *parse_numbers, o_d)?;
-            } else {
-                let input_ext: &str = &input
-                    .extension()
-                    .ok_or_else(|| Error::new(EK::InvalidFilename, "No valid extension"))
-                    .context("Failed to get input file extension")?
-                    .to_string_lossy();
-
-                match input_ext {
-                    "json" => {
-                        let data = json_decoder::json_decoder(json_reader::json_reader(input))
-                            .context("Deserialization failed")?;
-
-                        match_output(data, output_file, &output_ext, *parse_numbers, o_d)?;
-                    }
-                    "toml" => {
-                        let data = toml_decoder::toml_decoder(toml_reader::toml_reader(input))
-                            .context("Deserialization failed")?;
-                        match_output(data, output_file, &output_ext, *parse_numbers, o_d)?;
-                    }
-                    "csv" => {
-                        let data = csv_decoder::csv_decoder(csv_reader::csv_reader(input, ','))
-                            .context("Deserialization failed")?;
-                        match_output(data, output_file, &output_ext, *parse_numbers, o_d)?;
-                    }
-                    "ndjson" => {
-                        let data =
-                            ndjson_decoder::ndjson_decoder(ndjson_reader::ndjson_reader(input))
-                                .context("Deserialization failed")?;
-                        match_output(data, output_file, &output_ext, *parse_numbers, o_d)?;
-                    }
-                    _ => log_invalid_ext(input_ext, false)?,
-                };
+            convert(converter(io, opts, None)?, io, false)
+        }
+
+        Commands::Aggregate { io, group_by, agg, filter } => {
+            let mut aggs = Vec::new();
+
+            for list in agg {
+                aggs.extend(Agg::parse_list(list)?);
             }
 
-            flush_logger(&format!("Finished in: {:?}", now.elapsed().bright_green()))?;
+            let opts = ConvertOptions {
+                filter: filter.as_deref().map(Filter::parse).transpose()?,
+                stages: vec![Stage::Aggregate(AggregateOpts { group_by: group_by.clone(), aggs })],
+                ..Default::default()
+            };
 
-            Ok(())
+            convert(converter(io, opts, None)?, io, false)
         }
 
-        Commands::Validate { input, delimiter } => {
-            if !Path::new(&input).exists() {
-                eprintln!(
-                    "{} Input file: {} doesn't.exist",
-                    "[FATAL]".red().bold(),
-                    input.to_str().unwrap_or("input_file").on_bright_red(),
-                );
+        Commands::Stats { io, top, filter } => {
+            let opts = ConvertOptions {
+                filter: filter.as_deref().map(Filter::parse).transpose()?,
+                stages: vec![Stage::Stats(StatsOpts { top_k: *top })],
+                ..Default::default()
+            };
 
-                exit(1);
-            }
+            // stats are written as a text table on the terminal unless a format is set
+            if is_std_stream(&io.output) && io.to.is_none() {
+                let now = std::time::Instant::now();
+                let stats = converter(io, opts, Some("ndjson"))?.records()?;
 
-            let i_d: char;
+                write_text_table(stats, std::io::stdout().lock())?;
 
-            let temp_ext;
-            if let Some(ch) = delimiter {
-                temp_ext = std::borrow::Cow::Borrowed("csv");
-                i_d = *ch;
+                flush_logger(&format!("Finished in: {:?}", now.elapsed().bright_green()))
             } else {
-                temp_ext = input
-                    .extension()
-                    .ok_or_else(|| Error::new(EK::InvalidFilename, "No valid extension"))
-                    .context("Failed to get output file extension")?
-                    .to_string_lossy();
-                i_d = ',';
+                convert(converter(io, opts, None)?, io, false)
             }
+        }
+
+        Commands::Schema { io, enum_limit, unflatten } => {
+            let opts = ConvertOptions {
+                flatten: FlattenOpts { unflatten: *unflatten, ..Default::default() },
+                stages: vec![Stage::Schema(SchemaOpts { enum_limit: *enum_limit })],
+                ..Default::default()
+            };
+
+            convert(converter(io, opts, Some("json"))?, io, false)
+        }
+
+        Commands::Join { io, right, on, how, memory_limit, right_from, right_delimiter } => {
+            check_input_exists(right);
 
-            let input_ext: &str = &temp_ext;
+            let (right_fmt, right_delimiter) =
+                input_format(right, right_from.as_deref(), *right_delimiter)?;
 
-            let res = match input_ext {
-                "json" => json_validator::validate_json(input),
-                "toml" => toml_validator::validate_toml(input),
-                "csv" => csv_validator::validate_csv(input, i_d),
-                "ndjson" => ndjson_validator::validate_ndjson(input),
-                _ => log_invalid_ext(input_ext, false),
+            let join = JoinOpts {
+                on: on.clone(),
+                kind: *how,
+                memory_limit: memory_limit.saturating_mul(1024 * 1024),
+                right_delimiter: Some(right_delimiter),
             };
 
+            let converter = converter(io, ConvertOptions::default(), None)?.join(
+                input_reader(right)?,
+                right_fmt,
+                join,
+            );
+
+            convert(converter, io, false)
+        }
+
+        Commands::Validate {
+            input,
+            from,
+            delimiter,
+            schema,
+            parse_numbers: _,
+            types,
+            csv_schema,
+        } => {
+            check_input_exists(input);
+
+            let (input_fmt, delimiter) = input_format(input, from.as_deref(), *delimiter)?;
+
+            let reader = open_input(input)?;
+
+            let is_toml = input_fmt.name() == "toml";
+            let is_mapped = matches!(reader, Input::Mapped(_)) && schema.is_none();
+            let res = match (schema, csv_schema) {
+                (Some(path), _) => {
+                    check_input_exists(path);
+
+                    let schema_fmt = get_format(path, None, false, false)?;
+                    let schema = JsonSchema::from_reader(input_reader(path)?, schema_fmt)?;
+
+                    let types = TypeOpts { overrides: types.clone(), ..Default::default() };
+
+                    validate_with_schema(reader, input_fmt, &schema, delimiter, &types)
+                }
+                (None, Some(path)) => {
+                    check_input_exists(path);
+
+                    if input_fmt.name() != "csv" {
+                        return Err(CtxResultErr::new(
+                            "`--csv-schema` only works with CSV input",
+                            format!("Input format: {} isn't CSV", input_fmt.name()),
+                        ));
+                    }
+
+                    let schema_fmt = get_format(path, None, false, false)?;
+                    let schema = CsvSchema::from_reader(input_reader(path)?, schema_fmt)?;
+
+                    validate_with_csv_schema(reader, &schema, delimiter)
+                }
+                (None, None) => validate_input(reader, input_fmt, delimiter),
+            };
+
+            if is_toml && is_mapped {
+                println!(
+                    "This file was not streamed due to TOML's limitations with streaming.\nIt was memory-mapped then validated without copying it."
+                );
+            } else if is_toml {
+                println!(
+                    "This file was not streamed due to TOML's limitations with streaming.\nIt was all loaded into memory as bytes then validated."
+                );
+            }
+
             match res {
                 Ok(_) => {
                     let msg = format!("Input file: {} is valid", input.display().bright_green());
@@ -249,6 +587,42 @@ fn run() -> CtxResult<()> {
                 }
             }
         }
+
+        Commands::Formats => {
+            let yes_no = |b: bool| if b { "yes" } else { "no" };
+
+            println!(
+                "{:<10} {:<16} {:<7} {:<7} {:<9} DESCRIPTION",
+                "NAME", "EXTENSIONS", "DECODE", "ENCODE", "VALIDATE"
+            );
+
+            for fmt in formats() {
+                println!(
+                    "{:<10} {:<16} {:<7} {:<7} {:<9} {}",
+                    fmt.name(),
+                    fmt.extensions().join(", "),
+                    yes_no(fmt.decoder().is_some()),
+                    yes_no(fmt.encoder().is_some()),
+                    yes_no(fmt.validator().is_some()),
+                    fmt.description(),
+                );
+            }
+
+            Ok(())
+        }
+
+        Commands::Detect { input } => {
+            check_input_exists(input);
+
+            let detected = detect_path(input)?
+                .ok_or_else(|| Error::new(EK::InvalidData, "Content doesn't match any format"))
+                .context("Failed to detect input format")
+                .context(ctx!("Input file: {}", input.to_string_lossy()))?;
+
+            println!("{}", detected);
+
+            Ok(())
+        }
     }
 }
 
@@ -262,30 +636,110 @@ fn main() {
     }
 }
 
+/// Creates a converter for a subcommand that converts records, the input and output formats come
+/// from the flags in `io` or are detected, `stdout_to` is the output format for stdout without
+/// `--to`.
+fn converter(io: &IoArgs, opts: ConvertOptions, stdout_to: Option<&str>) -> CtxResult<Converter> {
+    check_input_exists(&io.input);
+
+    let to = io.to.as_deref().or(stdout_to.filter(|_| is_std_stream(&io.output)));
+
+    let output_fmt = get_format(&io.output, to, io.output_delimiter.is_some(), true)?;
+    let (input_fmt, input_delimiter) =
+        input_format(&io.input, io.from.as_deref(), io.input_delimiter)?;
+
+    let opts = ConvertOptions {
+        parse_numbers: io.parse_numbers,
+        input_delimiter,
+        output_delimiter: io.output_delimiter.unwrap_or(','),
+        ..opts
+    };
+
+    Ok(Converter::from_input(open_input(&io.input)?, input_fmt, output_fmt).options(opts))
+}
+
+/// Writes the output of a converter into the output file of a subcommand, then flushes the logs
+/// with the time it took.
+fn convert(converter: Converter, io: &IoArgs, append: bool) -> CtxResult<()> {
+    let now = std::time::Instant::now();
+
+    let mut output_file = output_writer(&io.output, append)?;
+
+    converter.convert(&mut output_file)?;
+    output_file.finish().context(ctx!("Output file: {}", io.output.to_string_lossy()))?;
+
+    flush_logger(&format!("Finished in: {:?}", now.elapsed().bright_green()))
+}
+
+#[inline(always)]
+fn check_input_exists(input: &Path) {
+    if !is_std_stream(input) && !input.exists() {
+        eprintln!(
+            "{} Input file: {} doesn't exist",
+            "[FATAL]".red().bold(),
+            input.to_str().unwrap_or("input_file").on_bright_red(),
+        );
+
+        exit(1);
+    }
+}
+
+/// Gets the format of a file from `--from` / `--to`, the delimiter flags or its extension, in
+/// that order, formats are looked up in the format registry.
 #[inline(always)]
-fn match_output(
-    data: WriterStreams<impl Iterator<Item = CtxResult<DataTypes>>>,
-    output_file: std::fs::File,
-    output_ext: &str,
-    parse_numbers: bool,
-    o_d: char,
-) -> CtxResult<()> {
-    match output_ext {
-        "json" => write_json::write_json(data, output_file, parse_numbers)
-            .context("Serialization failed")?,
-        "toml" => toml_writer::toml_writer(data, output_file, parse_numbers)
-            .context("Serialization failed")?,
-        "csv" => csv_writer::csv_writer(data, output_file, o_d).context("Serialization failed")?,
-        "ndjson" => ndjson_writer::ndjson_writer(data, output_file, parse_numbers)
-            .context("Serialization failed")?,
-        _ => log_invalid_ext(output_ext, true)?,
+fn get_format(
+    path: &Path,
+    explicit: Option<&str>,
+    has_delimiter: bool,
+    is_output: bool,
+) -> CtxResult<Arc<dyn FileFormat>> {
+    let s = if is_output { "out" } else { "in" };
+
+    let name = if let Some(fmt) = explicit {
+        fmt.to_string()
+    } else if has_delimiter {
+        return Ok(Format::Csv.into());
+    } else if is_std_stream(path) {
+        let flag = if is_output { "--to" } else { "--from" };
+
+        Err(Error::new(EK::InvalidInput, format!("No format specified for std{}", s)))
+            .context(ctx!("Use `{}` to specify the {}put format", flag, s))?
+    } else {
+        format_extension(path)
+            .ok_or_else(|| Error::new(EK::InvalidFilename, "No valid extension"))
+            .context(ctx!("Failed to get {}put file extension", s))?
+            .to_string_lossy()
+            .to_string()
     };
 
-    Ok(())
+    match find_format(&name) {
+        Some(fmt) => Ok(fmt),
+        None => log_invalid_ext(&name, is_output),
+    }
+}
+
+/// Gets the format and CSV delimiter of an input like [`get_format`], inputs without an extension
+/// or with an unknown one (e.g. `data`, `export.txt`) are detected from their content.
+fn input_format(
+    path: &Path,
+    explicit: Option<&str>,
+    delimiter: Option<char>,
+) -> CtxResult<(Arc<dyn FileFormat>, char)> {
+    let known = explicit.is_some()
+        || delimiter.is_some()
+        || format_extension(path).is_some_and(|ext| find_format(&ext.to_string_lossy()).is_some());
+
+    if !known && let Some(detected) = detect_path(path)? {
+        return Ok((detected.format.into(), detected.delimiter.unwrap_or(',')));
+    }
+
+    let format = get_format(path, explicit, delimiter.is_some(), false)?;
+
+    Ok((format, delimiter.unwrap_or(',')))
 }
 
 #[inline(always)]
-fn log_invalid_ext(input_ext: &str, is_output: bool) -> CtxResult<()> {
+fn log_invalid_ext<T>(input_ext: &str, is_output: bool) -> CtxResult<T> {
     let s = if is_output { "Out" } else { "In" };
     let repo_link = "https://github.com/Tahaa-Dev/fiux";
 
@@ -0,0 +1,393 @@
+use indexmap::IndexMap;
+use resext::ctx;
+use std::{cmp::Ordering, collections::HashSet};
+
+use crate::utils::{
+    CtxResult, CtxResultErr, CtxResultExt, Log, Records, Value, WriterStreams,
+    dedupe::canonical,
+    filter::{as_number, compare},
+    sort::KeyRef,
+};
+
+/// Aggregate functions computed per group.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AggFn {
+    /// Number of records, or of non-null values of a field
+    Count,
+
+    /// Sum of numeric values, integers stay integers unless the sum overflows
+    Sum,
+
+    /// Mean of numeric values
+    Avg,
+
+    /// Smallest value, numbers are compared numerically and everything else as strings
+    Min,
+
+    /// Largest value, numbers are compared numerically and everything else as strings
+    Max,
+
+    /// Number of distinct non-null values
+    Distinct,
+}
+
+impl AggFn {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Count => "count",
+            Self::Sum => "sum",
+            Self::Avg => "avg",
+            Self::Min => "min",
+            Self::Max => "max",
+            Self::Distinct => "distinct",
+        }
+    }
+}
+
+/// An aggregation parsed from an expression like `sum(amount)`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Agg {
+    /// Aggregate function
+    pub func: AggFn,
+
+    /// Column / path the function is applied to, `count()` counts records
+    pub field: Option<String>,
+}
+
+impl Agg {
+    /// Parses a comma-separated list of aggregations like `count(),sum(amount),avg(latency)`,
+    /// fields are columns / paths (e.g. `user.id`).
+    pub fn parse_list(s: &str) -> CtxResult<Vec<Self>> {
+        let mut aggs = Vec::new();
+        let mut rest = s.trim();
+
+        while !rest.is_empty() {
+            let (open, close) = match (rest.find('('), rest.find(')')) {
+                (Some(open), Some(close)) if open < close => (open, close),
+                _ => return Err(invalid_agg(rest)),
+            };
+
+            let func = match rest[..open].trim().to_ascii_lowercase().as_str() {
+                "count" => AggFn::Count,
+                "sum" => AggFn::Sum,
+                "avg" | "mean" => AggFn::Avg,
+                "min" => AggFn::Min,
+                "max" => AggFn::Max,
+                "distinct" | "count_distinct" => AggFn::Distinct,
+                _ => return Err(invalid_agg(&rest[..=close])),
+            };
+
+            let field = Some(rest[open + 1..close].trim())
+                .filter(|f| !f.is_empty() && *f != "*")
+                .map(str::to_string);
+
+            if field.is_none() && func != AggFn::Count {
+                return Err(invalid_agg(&rest[..=close]));
+            }
+
+            aggs.push(Self { func, field });
+
+            rest = rest[close + 1..].trim_start();
+            rest = match rest.strip_prefix(',') {
+                Some(r) => r.trim_start(),
+                None if rest.is_empty() => rest,
+                None => return Err(invalid_agg(rest)),
+            };
+        }
+
+        Ok(aggs)
+    }
+
+    /// Name of the output column, e.g. `count` or `sum_amount`.
+    pub fn name(&self) -> String {
+        match &self.field {
+            Some(field) => format!("{}_{}", self.func.name(), field),
+            None => self.func.name().to_string(),
+        }
+    }
+}
+
+#[inline]
+fn invalid_agg(s: &str) -> CtxResultErr {
+    CtxResultErr::new(
+        "Check the aggregations passed to `--agg`, e.g. `count(),sum(amount)`",
+        format!(
+            "Invalid aggregation: {}, expected count, sum, avg, min, max or distinct with a field",
+            s
+        ),
+    )
+}
+
+/// Options for aggregating records by groups.
+#[derive(Clone, Debug, Default)]
+pub struct AggregateOpts {
+    /// Columns / paths that records are grouped by, all records are one group if it's empty
+    pub group_by: Vec<String>,
+
+    /// Aggregations computed for every group
+    pub aggs: Vec<Agg>,
+}
+
+/// Aggregates records into one record per group with the group keys followed by the aggregations
+/// (e.g. `region,count,sum_amount`), groups are written in the order they're first seen.
+///
+/// Input records are streamed and only the state of every group is kept in memory. Table keys are
+/// matched with header names, keys of other streams are paths (e.g. `user.id`), missing keys and
+/// empty CSV cells are `null` and non-numeric values are skipped by `sum` and `avg`.
+pub fn aggregate_records(
+    data: WriterStreams<Records>,
+    opts: &AggregateOpts,
+) -> CtxResult<WriterStreams<Records>> {
+    let hint = "Check the keys passed to `--group-by` / `--agg`";
+
+    // empty cells are null in tables, like in filters
+    let is_table = matches!(data, WriterStreams::Table { .. });
+
+    let (records, group_by, fields) = match data {
+        WriterStreams::Table { headers, iter } => {
+            let resolve = |name: &String| KeyRef::column(&headers, name, hint);
+
+            let group_by = opts.group_by.iter().map(resolve).collect::<CtxResult<Vec<_>>>()?;
+            let fields = opts
+                .aggs
+                .iter()
+                .map(|a| a.field.as_ref().map(resolve).transpose())
+                .collect::<CtxResult<Vec<_>>>()?;
+
+            (iter, group_by, fields)
+        }
+
+        WriterStreams::Values { iter: records } | WriterStreams::Ndjson { values: records } => {
+            let group_by = opts.group_by.iter().map(|k| KeyRef::path(k)).collect();
+            let fields = opts.aggs.iter().map(|a| a.field.as_deref().map(KeyRef::path)).collect();
+
+            (records, group_by, fields)
+        }
+    };
+
+    let mut groups: IndexMap<Vec<u8>, (Vec<Value>, Vec<State>)> = IndexMap::new();
+    let mut buf = Vec::new();
+
+    for (idx, rec) in records.enumerate() {
+        // invalid records are logged here since only the aggregations are written
+        let Some(rec) = rec.context(ctx!("Failed to aggregate record: {}", idx + 1)).log("[WARN]")
+        else {
+            continue;
+        };
+
+        let get =
+            |key: &KeyRef| key.get(&rec).filter(|v| !(v.is_null() || is_table && is_empty(v)));
+
+        buf.clear();
+
+        for key in &group_by {
+            canonical(get(key).unwrap_or(&Value::Null), &mut buf);
+        }
+
+        let (_, states) = match groups.get_mut(&buf) {
+            Some(group) => group,
+            None => {
+                let keys = group_by.iter().map(|k| get(k).cloned().unwrap_or_default()).collect();
+                let states = opts.aggs.iter().map(|a| State::new(a.func)).collect();
+
+                groups.entry(buf.clone()).or_insert((keys, states))
+            }
+        };
+
+        for (state, field) in states.iter_mut().zip(&fields) {
+            match field {
+                Some(field) => state.update(get(field)),
+                None => state.update(Some(&Value::Null)),
+            }
+        }
+    }
+
+    // a single group of zero records is still written when there's nothing to group by
+    if groups.is_empty() && opts.group_by.is_empty() {
+        groups.insert(
+            Vec::new(),
+            (Vec::new(), opts.aggs.iter().map(|a| State::new(a.func)).collect()),
+        );
+    }
+
+    let headers = opts.group_by.iter().cloned().chain(opts.aggs.iter().map(Agg::name)).collect();
+
+    let iter = groups.into_values().map(|(mut keys, states)| {
+        keys.extend(states.into_iter().map(State::finish));
+        Ok(Value::Array(keys))
+    });
+
+    Ok(WriterStreams::Table { headers, iter: Box::new(iter) })
+}
+
+#[inline]
+fn is_empty(v: &Value) -> bool {
+    match v {
+        Value::String(s) => s.is_empty(),
+        Value::Bytes(b) => b.is_empty(),
+        _ => false,
+    }
+}
+
+/// Running state of an aggregation for a group.
+enum State {
+    Count(i64),
+    Sum { int: i64, float: f64, is_float: bool, any: bool },
+    Avg { sum: f64, count: u64 },
+    Min(Option<Value>),
+    Max(Option<Value>),
+    Distinct(HashSet<Vec<u8>>),
+}
+
+impl State {
+    fn new(func: AggFn) -> Self {
+        match func {
+            AggFn::Count => Self::Count(0),
+            AggFn::Sum => Self::Sum { int: 0, float: 0.0, is_float: false, any: false },
+            AggFn::Avg => Self::Avg { sum: 0.0, count: 0 },
+            AggFn::Min => Self::Min(None),
+            AggFn::Max => Self::Max(None),
+            AggFn::Distinct => Self::Distinct(HashSet::new()),
+        }
+    }
+
+    /// Updates the state with a value, `count()` is updated with `null` for every record and
+    /// `None` is a missing value.
+    fn update(&mut self, value: Option<&Value>) {
+        match self {
+            Self::Count(n) => *n += value.is_some() as i64,
+            Self::Sum { int, float, is_float, any } => {
+                let Some(v) = value else { return };
+
+                match integer(v) {
+                    Some(i) if !*is_float => match int.checked_add(i) {
+                        Some(sum) => *int = sum,
+                        None => {
+                            *is_float = true;
+                            *float = *int as f64 + i as f64;
+                        }
+                    },
+                    _ => {
+                        let Some(f) = as_number(v) else { return };
+
+                        if !*is_float {
+                            *is_float = true;
+                            *float = *int as f64;
+                        }
+
+                        *float += f;
+                    }
+                }
+
+                *any = true;
+            }
+            Self::Avg { sum, count } => {
+                if let Some(f) = value.and_then(as_number) {
+                    *sum += f;
+                    *count += 1;
+                }
+            }
+            Self::Min(cur) => replace_if(cur, value, Ordering::Less),
+            Self::Max(cur) => replace_if(cur, value, Ordering::Greater),
+            Self::Distinct(seen) => {
+                if let Some(v) = value {
+                    let mut buf = Vec::new();
+                    canonical(v, &mut buf);
+                    seen.insert(buf);
+                }
+            }
+        }
+    }
+
+    fn finish(self) -> Value {
+        match self {
+            Self::Count(n) => Value::Int(n),
+            Self::Sum { any: false, .. } => Value::Null,
+            Self::Sum { float, is_float: true, .. } => Value::Float(float),
+            Self::Sum { int, .. } => Value::Int(int),
+            Self::Avg { count: 0, .. } => Value::Null,
+            Self::Avg { sum, count } => Value::Float(sum / count as f64),
+            Self::Min(v) | Self::Max(v) => v.unwrap_or_default(),
+            Self::Distinct(seen) => Value::Int(seen.len() as i64),
+        }
+    }
+}
+
+/// Gets an integer from ints, integral floats and integer strings (e.g. untyped CSV cells).
+#[inline]
+fn integer(v: &Value) -> Option<i64> {
+    match v {
+        Value::Int(i) => Some(*i),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+#[inline]
+fn replace_if(cur: &mut Option<Value>, value: Option<&Value>, ord: Ordering) {
+    let Some(v) = value else { return };
+
+    match cur {
+        Some(c) if compare(v, c) != Some(ord) => {}
+        _ => *cur = Some(v.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_list() {
+        let aggs = Agg::parse_list("count(), sum(amount),distinct(user.id)").unwrap();
+
+        assert_eq!(
+            aggs.iter().map(Agg::name).collect::<Vec<_>>(),
+            ["count", "sum_amount", "distinct_user.id"]
+        );
+
+        assert!(Agg::parse_list("sum()").is_err());
+        assert!(Agg::parse_list("median(x)").is_err());
+        assert!(Agg::parse_list("count() sum(x)").is_err());
+    }
+
+    #[test]
+    fn test_aggregate_groups() {
+        let items = [
+            json!({"region": "eu", "amount": 10, "ms": 1.5, "user": "a"}),
+            json!({"region": "us", "amount": 2.5, "user": "b"}),
+            json!({"region": "eu", "amount": "5", "ms": 2.5, "user": "a"}),
+            json!({"amount": 1}),
+        ];
+
+        let values: Vec<CtxResult<Value>> = items.iter().cloned().map(|v| Ok(v.into())).collect();
+        let data = WriterStreams::Ndjson { values: Box::new(values.into_iter()) as Records };
+
+        let opts = AggregateOpts {
+            group_by: vec!["region".to_string()],
+            aggs: Agg::parse_list("count(),sum(amount),avg(ms),min(user),distinct(user)").unwrap(),
+        };
+
+        let WriterStreams::Table { headers, iter } = aggregate_records(data, &opts).unwrap() else {
+            unreachable!()
+        };
+
+        assert_eq!(
+            headers,
+            ["region", "count", "sum_amount", "avg_ms", "min_user", "distinct_user"]
+        );
+
+        let rows: Vec<Value> = iter.map(Result::unwrap).collect();
+
+        assert_eq!(
+            rows,
+            [
+                json!(["eu", 2, 15, 2.0, "a", 1]),
+                json!(["us", 1, 2.5, null, "b", 1]),
+                json!([null, 1, 1, null, null, 0]),
+            ]
+            .map(Value::from)
+        );
+    }
+}
@@ -0,0 +1,323 @@
+use crate::utils::{
+    CtxResultErr, Log, Map, Records, Value, WriterStreams,
+    flatten::{MAX_INDEX, PathSeg, insert, parse_path},
+};
+
+/// Options for selecting, excluding and renaming columns / keys, nested keys are selected with
+/// paths (e.g. `user.name`, `tags[0]`).
+#[derive(Clone, Debug, Default)]
+pub struct ColumnOpts {
+    /// Columns to keep in the given order, all columns are kept if it's empty
+    pub select: Vec<String>,
+
+    /// Columns to drop
+    pub exclude: Vec<String>,
+
+    /// Columns to rename as `(old, new)` pairs
+    pub rename: Vec<(String, String)>,
+}
+
+impl ColumnOpts {
+    pub fn is_empty(&self) -> bool {
+        self.select.is_empty() && self.exclude.is_empty() && self.rename.is_empty()
+    }
+}
+
+/// Parses an `old=new` column rename.
+pub fn parse_rename(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((old, new)) if !old.is_empty() && !new.is_empty() => {
+            Ok((old.to_string(), new.to_string()))
+        }
+        _ => Err(format!("Invalid rename: {}, expected old=new", s)),
+    }
+}
+
+/// Selects, excludes and renames columns in that order, so `select` and `exclude` use the input
+/// names. Table headers are matched by name or as a prefix of flattened headers (e.g. `user`
+/// matches `user.name`), keys in other streams are matched by path. Nested keys are joined with
+/// `separator` like in flattened headers.
+pub fn select_columns(
+    data: WriterStreams<Records>,
+    opts: &ColumnOpts,
+    separator: &str,
+) -> WriterStreams<Records> {
+    if opts.is_empty() {
+        return data;
+    }
+
+    match data {
+        WriterStreams::Table { headers, iter } => {
+            let (headers, idx) = select_headers(headers, opts, separator);
+
+            let iter = iter.map(move |row| {
+                let Value::Array(mut cells) = row? else {
+                    return Err(CtxResultErr::new(
+                        "Invalid table row",
+                        String::from("Expected cells"),
+                    ));
+                };
+
+                let cells = idx.iter().map(|i| cells.get_mut(*i).map(std::mem::take));
+
+                Ok(Value::Array(cells.map(Option::unwrap_or_default).collect()))
+            });
+
+            WriterStreams::Table { headers, iter: Box::new(iter) }
+        }
+
+        WriterStreams::Values { iter } => {
+            let opts = ParsedOpts::new(opts, separator);
+
+            WriterStreams::Values { iter: Box::new(iter.map(move |v| Ok(opts.apply(v?)))) }
+        }
+
+        WriterStreams::Ndjson { values } => {
+            let opts = ParsedOpts::new(opts, separator);
+
+            WriterStreams::Ndjson { values: Box::new(values.map(move |v| Ok(opts.apply(v?)))) }
+        }
+    }
+}
+
+#[inline]
+fn matches_column(header: &str, name: &str, separator: &str) -> bool {
+    header.strip_prefix(name).is_some_and(|rest| {
+        rest.is_empty()
+            || (!separator.is_empty() && rest.starts_with(separator))
+            || rest.starts_with('[')
+    })
+}
+
+/// Gets the selected headers and their indexes in the input headers.
+fn select_headers(
+    headers: Vec<String>,
+    opts: &ColumnOpts,
+    separator: &str,
+) -> (Vec<String>, Vec<usize>) {
+    let mut idx: Vec<usize> = if opts.select.is_empty() {
+        (0..headers.len()).collect()
+    } else {
+        let mut idx = Vec::with_capacity(opts.select.len());
+
+        for name in &opts.select {
+            let found: Vec<usize> = (0..headers.len())
+                .filter(|i| matches_column(&headers[*i], name, separator) && !idx.contains(i))
+                .collect();
+
+            if found.is_empty() {
+                warn_missing(name, "Check the column names passed to `--select`");
+            }
+
+            idx.extend(found);
+        }
+
+        idx
+    };
+
+    for name in &opts.exclude {
+        let len = idx.len();
+        idx.retain(|i| !matches_column(&headers[*i], name, separator));
+
+        if idx.len() == len {
+            warn_missing(name, "Check the column names passed to `--exclude`");
+        }
+    }
+
+    let mut selected: Vec<String> = idx.iter().map(|i| headers[*i].to_string()).collect();
+
+    for (old, new) in &opts.rename {
+        let mut found = false;
+
+        for h in selected.iter_mut().filter(|h| matches_column(h, old, separator)) {
+            *h = format!("{}{}", new, &h[old.len()..]);
+            found = true;
+        }
+
+        if !found {
+            warn_missing(old, "Check the column names passed to `--rename`");
+        }
+    }
+
+    (selected, idx)
+}
+
+#[inline]
+fn warn_missing(name: &str, hint: &str) {
+    let _ = Err::<(), _>(CtxResultErr::new(
+        hint,
+        format!("Column: {} was not found in the input headers", name),
+    ))
+    .log("[WARN]");
+}
+
+/// Column options with parsed paths.
+struct ParsedOpts {
+    select: Vec<Vec<PathSeg>>,
+    exclude: Vec<Vec<PathSeg>>,
+    rename: Vec<(Vec<PathSeg>, Vec<PathSeg>)>,
+}
+
+impl ParsedOpts {
+    fn new(opts: &ColumnOpts, separator: &str) -> Self {
+        let path = |p: &String| parse_path(p, separator, MAX_INDEX);
+
+        Self {
+            select: opts.select.iter().map(path).collect(),
+            exclude: opts.exclude.iter().map(path).collect(),
+            rename: opts.rename.iter().map(|(old, new)| (path(old), path(new))).collect(),
+        }
+    }
+
+    /// Applies the options to a record, arrays of records (e.g. a top-level YAML sequence) are
+    /// applied to every element and scalars are kept as they are.
+    fn apply(&self, value: Value) -> Value {
+        match value {
+            Value::Array(arr) => Value::Array(arr.into_iter().map(|v| self.apply(v)).collect()),
+            Value::Map(_) => self.apply_record(value),
+            other => other,
+        }
+    }
+
+    fn apply_record(&self, mut value: Value) -> Value {
+        if !self.select.is_empty() {
+            let mut selected = Value::Map(Map::new());
+
+            for path in &self.select {
+                if let Some(v) = take(&mut value, path, false) {
+                    let _ = insert(&mut selected, path, v);
+                }
+            }
+
+            value = selected;
+        }
+
+        for path in &self.exclude {
+            take(&mut value, path, true);
+        }
+
+        for (old, new) in &self.rename {
+            if let Some(v) = take(&mut value, old, false)
+                && let Err(v) = insert(&mut value, new, v)
+            {
+                // keep the value under its old name if the new name is taken
+                let _ = insert(&mut value, old, v);
+            }
+        }
+
+        value
+    }
+}
+
+/// Takes the value at a path out of a record, array elements are removed with `remove` and
+/// replaced with null otherwise so the indexes of other elements don't change.
+fn take(target: &mut Value, path: &[PathSeg], remove: bool) -> Option<Value> {
+    let (seg, rest) = path.split_first()?;
+
+    match (seg, target) {
+        (PathSeg::Key(k), Value::Map(map)) if rest.is_empty() => map.shift_remove(k),
+        (PathSeg::Key(k), Value::Map(map)) => {
+            let taken = take(map.get_mut(k)?, rest, remove);
+
+            // drop objects that were emptied by taking their last key
+            if taken.is_some()
+                && map.get(k).is_some_and(|v| matches!(v, Value::Map(m) if m.is_empty()))
+            {
+                map.shift_remove(k);
+            }
+
+            taken
+        }
+
+        (PathSeg::Index(i), Value::Array(arr)) if rest.is_empty() && *i < arr.len() => {
+            if remove {
+                Some(arr.remove(*i))
+            } else {
+                Some(std::mem::take(&mut arr[*i]))
+            }
+        }
+        (PathSeg::Index(i), Value::Array(arr)) => take(arr.get_mut(*i)?, rest, remove),
+
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn opts(select: &[&str], exclude: &[&str], rename: &[(&str, &str)]) -> ColumnOpts {
+        ColumnOpts {
+            select: select.iter().map(|s| s.to_string()).collect(),
+            exclude: exclude.iter().map(|s| s.to_string()).collect(),
+            rename: rename.iter().map(|(a, b)| (a.to_string(), b.to_string())).collect(),
+        }
+    }
+
+    #[test]
+    fn test_select_headers() {
+        let headers: Vec<String> =
+            ["id", "user.name", "user.age", "tags[0]"].map(String::from).to_vec();
+
+        let (h, idx) = select_headers(
+            headers,
+            &opts(&["user", "id", "tags"], &["user.age"], &[("id", "key")]),
+            ".",
+        );
+
+        assert_eq!(h, ["user.name", "key", "tags[0]"]);
+        assert_eq!(idx, [1, 0, 3]);
+    }
+
+    #[test]
+    fn test_select_with_separator() {
+        let headers: Vec<String> =
+            ["user_name", "user_age", "user.id", "users"].map(String::from).to_vec();
+
+        let (h, idx) = select_headers(headers, &opts(&["user"], &["user_age"], &[]), "_");
+
+        assert_eq!(h, ["user_name"]);
+        assert_eq!(idx, [0]);
+
+        let record: Value = json!({"user": {"age": 3, "name": "x"}, "user.id": 1}).into();
+        let parsed = ParsedOpts::new(&opts(&["user_name", "user.id"], &[], &[]), "_");
+
+        assert_eq!(parsed.apply(record), json!({"user": {"name": "x"}, "user.id": 1}).into());
+    }
+
+    #[test]
+    fn test_select_nested_paths() {
+        let record: Value = json!({"a": 1, "user": {"age": 3, "name": "x"}, "tags": [1, 2]}).into();
+        let parsed = ParsedOpts::new(&opts(&["user.name", "tags[1]", "a"], &[], &[]), ".");
+
+        assert_eq!(
+            parsed.apply(record),
+            json!({"user": {"name": "x"}, "tags": [null, 2], "a": 1}).into()
+        );
+    }
+
+    #[test]
+    fn test_exclude_and_rename_nested_paths() {
+        let record: Value = json!({"a": 1, "b": 2, "user": {"age": 3, "name": "x"}}).into();
+        let parsed =
+            ParsedOpts::new(&opts(&[], &["user.age"], &[("user.name", "name"), ("a", "b")]), ".");
+
+        // `b` is taken so `a` is kept
+        assert_eq!(parsed.apply(record), json!({"a": 1, "b": 2, "name": "x"}).into());
+    }
+
+    #[test]
+    fn test_rename_to_huge_index_stays_flat() {
+        let record: Value = json!({"a": 1, "b": 2}).into();
+        let parsed = ParsedOpts::new(
+            &opts(&[], &[], &[("a", "tags[18446744073709551615]"), ("b", "tags[4000000000]")]),
+            ".",
+        );
+
+        assert_eq!(
+            parsed.apply(record),
+            json!({"tags[18446744073709551615]": 1, "tags[4000000000]": 2}).into()
+        );
+    }
+}
@@ -0,0 +1,194 @@
+use flate2::{read::MultiGzDecoder, write::GzEncoder};
+use std::{
+    ffi::OsStr,
+    io::{Read, Write},
+    path::Path,
+};
+
+use crate::utils::{CtxResult, CtxResultExt, Log};
+
+/// Compression of an input or output file, detected from its last extension (e.g.
+/// `events.ndjson.gz`) or from the magic bytes at the start of the input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Bzip2,
+    Xz,
+}
+
+impl Compression {
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_ascii_lowercase().as_str() {
+            "gz" | "gzip" => Some(Self::Gzip),
+            "zst" | "zstd" => Some(Self::Zstd),
+            "bz2" | "bzip2" => Some(Self::Bzip2),
+            "xz" => Some(Self::Xz),
+            _ => None,
+        }
+    }
+
+    /// Gets the compression of a file from its last extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        Self::from_extension(&path.extension()?.to_string_lossy())
+    }
+
+    /// Detects the compression of input from its first bytes, at least 10 bytes are needed to
+    /// tell every compression apart from plain text.
+    pub fn detect(head: &[u8]) -> Option<Self> {
+        match head {
+            [0x1f, 0x8b, ..] => Some(Self::Gzip),
+            [0x28, 0xb5, 0x2f, 0xfd, ..] => Some(Self::Zstd),
+            [0xfd, b'7', b'z', b'X', b'Z', 0x00, ..] => Some(Self::Xz),
+            // the block or end-of-stream magic after the header, so text starting with "BZh"
+            // isn't mistaken for bzip2
+            [b'B', b'Z', b'h', b'1'..=b'9', rest @ ..]
+                if rest.starts_with(&[0x31, 0x41, 0x59, 0x26, 0x53, 0x59])
+                    || rest.starts_with(&[0x17, 0x72, 0x45, 0x38, 0x50, 0x90]) =>
+            {
+                Some(Self::Bzip2)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Zstd => "zstd",
+            Self::Bzip2 => "bzip2",
+            Self::Xz => "xz",
+        }
+    }
+
+    /// Wraps a reader in a decompressor, concatenated streams (e.g. appended output) are read
+    /// one after another.
+    pub fn decoder<'a>(self, reader: impl Read + 'a) -> CtxResult<Box<dyn Read + 'a>> {
+        Ok(match self {
+            Self::Gzip => Box::new(MultiGzDecoder::new(reader)),
+            Self::Zstd => {
+                Box::new(zstd::Decoder::new(reader).context("Failed to create zstd decompressor")?)
+            }
+            Self::Bzip2 => Box::new(bzip2::read::MultiBzDecoder::new(reader)),
+            Self::Xz => Box::new(liblzma::read::XzDecoder::new_multi_decoder(reader)),
+        })
+    }
+
+    /// Wraps a writer in a compressor with the default level, the stream has to be finished with
+    /// [`Encoder::finish`] (dropping the encoder finishes it too, logging any error).
+    pub fn encoder<W: Write>(self, writer: W) -> CtxResult<Encoder<W>> {
+        Ok(match self {
+            Self::Gzip => Encoder::Gzip(GzEncoder::new(writer, flate2::Compression::default())),
+            Self::Zstd => Encoder::Zstd(
+                zstd::Encoder::new(writer, zstd::DEFAULT_COMPRESSION_LEVEL)
+                    .context("Failed to create zstd compressor")?,
+            ),
+            Self::Bzip2 => {
+                Encoder::Bzip2(bzip2::write::BzEncoder::new(writer, bzip2::Compression::default()))
+            }
+            Self::Xz => Encoder::Xz(liblzma::write::XzEncoder::new(writer, 6)),
+        })
+    }
+}
+
+/// Gets the extension that identifies a file's format, skipping a compression extension (e.g.
+/// `ndjson` for `events.ndjson.gz`).
+pub fn format_extension(path: &Path) -> Option<&OsStr> {
+    match Compression::from_path(path) {
+        Some(_) => Path::new(path.file_stem()?).extension(),
+        None => path.extension(),
+    }
+}
+
+/// Compressing writer returned by [`Compression::encoder`].
+pub enum Encoder<W: Write> {
+    Gzip(GzEncoder<W>),
+    Zstd(zstd::Encoder<'static, W>),
+    Bzip2(bzip2::write::BzEncoder<W>),
+    Xz(liblzma::write::XzEncoder<W>),
+}
+
+impl<W: Write> Encoder<W> {
+    /// Writes the end of the compressed stream, writing after this starts a new stream.
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Gzip(enc) => enc.try_finish(),
+            Self::Zstd(enc) => enc.do_finish(),
+            Self::Bzip2(enc) => enc.try_finish(),
+            Self::Xz(enc) => enc.try_finish(),
+        }
+    }
+
+    fn inner(&mut self) -> &mut dyn Write {
+        match self {
+            Self::Gzip(enc) => enc,
+            Self::Zstd(enc) => enc,
+            Self::Bzip2(enc) => enc,
+            Self::Xz(enc) => enc,
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.inner().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner().flush()
+    }
+}
+
+impl<W: Write> Drop for Encoder<W> {
+    fn drop(&mut self) {
+        // fallback for encoders that weren't finished, finishing twice writes nothing, errors
+        // can't be returned from `drop` so they're logged instead
+        let _ = self.finish().context("Failed to finish compressed output").log("[WARN]");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compression_round_trip_and_detection() {
+        let data = b"{\"a\": 1}\n{\"a\": 2}\n".repeat(100);
+
+        for compression in
+            [Compression::Gzip, Compression::Zstd, Compression::Bzip2, Compression::Xz]
+        {
+            let mut compressed = Vec::new();
+
+            // two streams, like output appended to an existing file
+            for _ in 0..2 {
+                let mut enc = compression.encoder(&mut compressed).unwrap();
+                enc.write_all(&data).unwrap();
+            }
+
+            assert_eq!(Compression::detect(&compressed), Some(compression));
+
+            // dropping a finished encoder doesn't write the end of the stream again
+            let mut finished = Vec::new();
+            let mut enc = compression.encoder(&mut finished).unwrap();
+            enc.write_all(&data).unwrap();
+            enc.finish().unwrap();
+            drop(enc);
+
+            assert_eq!(finished.repeat(2), compressed, "{}", compression.name());
+
+            let mut out = Vec::new();
+            compression.decoder(compressed.as_slice()).unwrap().read_to_end(&mut out).unwrap();
+
+            assert_eq!(out, data.repeat(2), "{}", compression.name());
+        }
+
+        assert_eq!(Compression::detect(b"BZh9 is not bzip2"), None);
+        assert_eq!(Compression::detect(b"{\"a\": 1}"), None);
+
+        assert_eq!(format_extension(Path::new("events.ndjson.gz")), Some(OsStr::new("ndjson")));
+        assert_eq!(format_extension(Path::new("data.json.ZST")), Some(OsStr::new("json")));
+        assert_eq!(format_extension(Path::new("data.csv")), Some(OsStr::new("csv")));
+        assert_eq!(format_extension(Path::new("events.gz")), None);
+    }
+}
@@ -0,0 +1,470 @@
+use std::{
+    fmt,
+    io::{Error, ErrorKind as EK, Read, Write},
+    path::Path,
+    sync::Arc,
+};
+
+use crate::utils::{
+    input_reader::{Input, open_input},
+    *,
+};
+
+/// File formats supported by fiux.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Ndjson,
+    Toml,
+    Csv,
+    Yaml,
+}
+
+impl Format {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Ndjson => "ndjson",
+            Self::Toml => "toml",
+            Self::Csv => "csv",
+            Self::Yaml => "yaml",
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Options for converting between formats.
+#[derive(Clone, Debug)]
+pub struct ConvertOptions {
+    /// Infer column types in table-based input (e.g. CSV) instead of writing cells as strings
+    pub parse_numbers: bool,
+
+    /// Delimiter for CSV input
+    pub input_delimiter: char,
+
+    /// Delimiter for CSV output
+    pub output_delimiter: char,
+
+    /// Path of a nested array in JSON input to stream element by element (e.g. `.data.items`)
+    pub json_path: Option<String>,
+
+    /// Options for flattening nested values into CSV and unflattening them back
+    pub flatten: FlattenOpts,
+
+    /// Options for inferring column types in table-based input
+    pub types: TypeOpts,
+
+    /// Options for selecting, excluding and renaming columns / keys
+    pub columns: ColumnOpts,
+
+    /// Filter for keeping only the records that match an expression
+    pub filter: Option<Filter>,
+
+    /// Steps applied to the records after filtering, in order (e.g. a query then a sort)
+    pub stages: Vec<Stage>,
+
+    /// Number of worker threads for decoding and encoding NDJSON and CSV, `1` runs on the calling
+    /// thread and `0` uses every available core, output is the same for any number of threads
+    pub threads: usize,
+}
+
+impl ConvertOptions {
+    /// Checks if table cells are typed before writing, which is the case when inferring types or
+    /// overriding the types of some columns.
+    pub fn is_typed(&self) -> bool {
+        self.parse_numbers || !self.types.overrides.is_empty()
+    }
+}
+
+impl Default for ConvertOptions {
+    fn default() -> Self {
+        Self {
+            parse_numbers: false,
+            input_delimiter: ',',
+            output_delimiter: ',',
+            json_path: None,
+            flatten: FlattenOpts::default(),
+            types: TypeOpts::default(),
+            columns: ColumnOpts::default(),
+            filter: None,
+            stages: Vec::new(),
+            threads: 1,
+        }
+    }
+}
+
+/// Step of a conversion that transforms the decoded records, steps are applied in the order
+/// they're added to [`ConvertOptions::stages`].
+#[derive(Clone, Debug)]
+pub enum Stage {
+    /// jq-style query whose outputs are written instead of the records
+    Query(Query),
+
+    /// Removes duplicate records
+    Dedupe(DedupeOpts),
+
+    /// Aggregates records by groups, one record is written per group
+    Aggregate(AggregateOpts),
+
+    /// Profiles records, one record of statistics is written per field
+    Stats(StatsOpts),
+
+    /// Infers a JSON Schema, which is written instead of the records
+    Schema(SchemaOpts),
+
+    /// Sorts records by keys
+    Sort(SortOpts),
+}
+
+impl From<Format> for Arc<dyn FileFormat> {
+    /// Gets a built-in format from the global registry, formats registered with the same name
+    /// override the built-in ones.
+    fn from(format: Format) -> Self {
+        find_format(format.name()).unwrap_or_else(|| match format {
+            Format::Json => Arc::new(json_format::JsonFormat),
+            Format::Ndjson => Arc::new(ndjson_format::NdjsonFormat),
+            Format::Toml => Arc::new(toml_format::TomlFormat),
+            Format::Csv => Arc::new(csv_format::CsvFormat),
+            Format::Yaml => Arc::new(yaml_format::YamlFormat),
+        })
+    }
+}
+
+/// Right side of a join: its source, format and the join options.
+type JoinSource = (Box<dyn Read>, Arc<dyn FileFormat>, JoinOpts);
+
+/// Builder for converting a source from one format to another.
+///
+/// # Examples
+///
+/// ```
+/// use fiux::{Converter, Format};
+///
+/// let input = "name,age\nAlice,30\n".as_bytes();
+/// let mut output = Vec::new();
+///
+/// Converter::new(input, Format::Csv, Format::Ndjson)
+///     .parse_numbers(true)
+///     .convert(&mut output)
+///     .unwrap();
+///
+/// assert_eq!(output, b"{\"name\": \"Alice\", \"age\": 30}\n");
+/// ```
+pub struct Converter {
+    source: Input,
+    from: Arc<dyn FileFormat>,
+    to: Arc<dyn FileFormat>,
+    opts: ConvertOptions,
+    join: Option<JoinSource>,
+}
+
+impl Converter {
+    /// Creates a converter from a built-in [`Format`] or any format in the registry (see
+    /// [`find_format`]).
+    pub fn new(
+        source: impl Read + 'static,
+        from: impl Into<Arc<dyn FileFormat>>,
+        to: impl Into<Arc<dyn FileFormat>>,
+    ) -> Self {
+        Self::from_input(Input::Reader(Box::new(source)), from, to)
+    }
+
+    /// Creates a converter for an [`Input`] (see [`open_input`]), memory-mapped input is sliced
+    /// by the NDJSON, CSV and TOML decoders instead of being read through a buffer.
+    pub fn from_input(
+        input: Input,
+        from: impl Into<Arc<dyn FileFormat>>,
+        to: impl Into<Arc<dyn FileFormat>>,
+    ) -> Self {
+        Self {
+            source: input,
+            from: from.into(),
+            to: to.into(),
+            opts: ConvertOptions::default(),
+            join: None,
+        }
+    }
+
+    /// Creates a converter for a file, detecting its format from its extension, `-` reads from
+    /// stdin and large files are memory-mapped.
+    pub fn from_path(
+        path: impl AsRef<Path>,
+        to: impl Into<Arc<dyn FileFormat>>,
+    ) -> CtxResult<Self> {
+        let path = path.as_ref();
+
+        let from = find_format_by_path(path)
+            .ok_or_else(|| Error::new(EK::InvalidFilename, "No supported extension"))
+            .context("Failed to detect input format")?;
+
+        Ok(Self::from_input(open_input(path)?, from, to))
+    }
+
+    pub fn options(mut self, opts: ConvertOptions) -> Self {
+        self.opts = opts;
+        self
+    }
+
+    pub fn parse_numbers(mut self, parse_numbers: bool) -> Self {
+        self.opts.parse_numbers = parse_numbers;
+        self
+    }
+
+    pub fn input_delimiter(mut self, delimiter: char) -> Self {
+        self.opts.input_delimiter = delimiter;
+        self
+    }
+
+    pub fn output_delimiter(mut self, delimiter: char) -> Self {
+        self.opts.output_delimiter = delimiter;
+        self
+    }
+
+    pub fn json_path(mut self, path: impl Into<String>) -> Self {
+        self.opts.json_path = Some(path.into());
+        self
+    }
+
+    pub fn flatten(mut self, opts: FlattenOpts) -> Self {
+        self.opts.flatten = opts;
+        self
+    }
+
+    pub fn types(mut self, opts: TypeOpts) -> Self {
+        self.opts.types = opts;
+        self
+    }
+
+    pub fn columns(mut self, opts: ColumnOpts) -> Self {
+        self.opts.columns = opts;
+        self
+    }
+
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.opts.filter = Some(filter);
+        self
+    }
+
+    /// Adds a step that is applied after the steps added before it.
+    pub fn stage(mut self, stage: Stage) -> Self {
+        self.opts.stages.push(stage);
+        self
+    }
+
+    pub fn query(self, query: Query) -> Self {
+        self.stage(Stage::Query(query))
+    }
+
+    pub fn dedupe(self, opts: DedupeOpts) -> Self {
+        self.stage(Stage::Dedupe(opts))
+    }
+
+    pub fn aggregate(self, opts: AggregateOpts) -> Self {
+        self.stage(Stage::Aggregate(opts))
+    }
+
+    pub fn stats(self, opts: StatsOpts) -> Self {
+        self.stage(Stage::Stats(opts))
+    }
+
+    pub fn schema(self, opts: SchemaOpts) -> Self {
+        self.stage(Stage::Schema(opts))
+    }
+
+    pub fn sort(self, opts: SortOpts) -> Self {
+        self.stage(Stage::Sort(opts))
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.opts.threads = threads;
+        self
+    }
+
+    /// Joins the records of the source (the left side) with the records of another source (the
+    /// right side) before any other step, see [`join_records`].
+    pub fn join(
+        mut self,
+        source: impl Read + 'static,
+        format: impl Into<Arc<dyn FileFormat>>,
+        opts: JoinOpts,
+    ) -> Self {
+        self.join = Some((Box::new(source), format.into(), opts));
+        self
+    }
+
+    /// Converts the source and writes the output into `output`, invalid items are logged and
+    /// skipped, only read / write failures are returned as errors.
+    pub fn convert(self, mut output: impl Write) -> CtxResult<()> {
+        let to = Arc::clone(&self.to);
+        let opts = self.opts.clone();
+
+        let encoder = to.encoder().ok_or_else(|| unsupported(&*to, "encoded"))?;
+        let data = self.records()?;
+
+        encoder.encode(data, &mut output, &opts).context("Serialization failed")
+    }
+
+    /// Decodes the source and applies every step of the conversion except encoding, for consumers
+    /// of the records themselves (e.g. writing them as a text table).
+    pub fn records(self) -> CtxResult<WriterStreams<Records>> {
+        let Self { source, from, to: _, opts, join } = self;
+
+        let decoder = from.decoder().ok_or_else(|| unsupported(&*from, "decoded"))?;
+
+        let mut data = source.decode(decoder, &opts).context("Deserialization failed")?;
+
+        if let Some((source, format, join)) = join {
+            let decoder = format.decoder().ok_or_else(|| unsupported(&*format, "decoded"))?;
+            let right_opts = ConvertOptions {
+                input_delimiter: join.right_delimiter.unwrap_or(opts.input_delimiter),
+                ..opts.clone()
+            };
+
+            let right = decoder.decode(source, &right_opts).context("Deserialization failed")?;
+
+            data = join_records(data, right, &join, &opts).context("Failed to join records")?;
+        }
+
+        // filter before selecting columns so expressions use the input names
+        if let Some(filter) = &opts.filter {
+            data = filter_records(data, filter);
+        }
+
+        for stage in &opts.stages {
+            data = match stage {
+                Stage::Query(query) => query_records(data, query, &opts),
+                Stage::Dedupe(dedupe) => {
+                    dedupe_records(data, dedupe).context("Failed to dedupe records")?
+                }
+                Stage::Aggregate(aggregate) => {
+                    aggregate_records(data, aggregate).context("Failed to aggregate records")?
+                }
+                Stage::Stats(stats) => {
+                    stats_records(data, stats, &opts).context("Failed to profile records")?
+                }
+                Stage::Schema(schema) => {
+                    schema_records(data, schema, &opts).context("Failed to infer schema")?
+                }
+                Stage::Sort(sort) => sort_records(data, sort).context("Failed to sort records")?,
+            };
+        }
+
+        Ok(select_columns(data, &opts.columns, &opts.flatten.separator))
+    }
+}
+
+#[inline]
+fn unsupported(format: &dyn FileFormat, action: &str) -> CtxResultErr {
+    CtxResultErr::new(
+        "Unsupported format",
+        format!("Format: {} can't be {}", format.name(), action),
+    )
+}
+
+/// Validates a source, invalid items are logged and the first error is returned after the whole
+/// source is validated.
+///
+/// # Examples
+///
+/// ```
+/// use fiux::{Format, validate};
+///
+/// assert!(validate(r#"{"a": 1}"#.as_bytes(), Format::Json).is_ok());
+/// ```
+pub fn validate(reader: impl Read, format: impl Into<Arc<dyn FileFormat>>) -> CtxResult<()> {
+    validate_with_delimiter(reader, format, ',')
+}
+
+/// Validates a source like [`validate`] with a custom delimiter for CSV input.
+pub fn validate_with_delimiter(
+    mut reader: impl Read,
+    format: impl Into<Arc<dyn FileFormat>>,
+    delimiter: char,
+) -> CtxResult<()> {
+    let format = format.into();
+    let opts = ConvertOptions { input_delimiter: delimiter, ..Default::default() };
+
+    let validator = format.validator().ok_or_else(|| unsupported(&*format, "validated"))?;
+
+    validator.validate(&mut reader, &opts)
+}
+
+/// Validates an [`Input`] like [`validate_with_delimiter`], memory-mapped TOML is parsed from the
+/// mapping instead of being copied into memory.
+pub fn validate_input(
+    input: Input,
+    format: impl Into<Arc<dyn FileFormat>>,
+    delimiter: char,
+) -> CtxResult<()> {
+    let format = format.into();
+    let opts = ConvertOptions { input_delimiter: delimiter, ..Default::default() };
+
+    let validator = format.validator().ok_or_else(|| unsupported(&*format, "validated"))?;
+
+    input.validate(validator, &opts)
+}
+
+/// Validates the structure of a CSV source and every row against a column-type schema (see
+/// [`CsvSchema`] for the rules).
+///
+/// # Examples
+///
+/// ```
+/// use fiux::{CsvSchema, Value, validate_with_csv_schema};
+///
+/// let schema: Value = serde_json::json!({"age": {"type": "int", "required": true}}).into();
+/// let schema = CsvSchema::new(&schema)?;
+///
+/// assert!(validate_with_csv_schema("name,age\nAlice,30\n".as_bytes(), &schema, ',').is_ok());
+/// assert!(validate_with_csv_schema("name,age\nBob,\n".as_bytes(), &schema, ',').is_err());
+/// # Ok::<(), fiux::CtxResultErr>(())
+/// ```
+pub fn validate_with_csv_schema(
+    reader: impl Read,
+    schema: &CsvSchema,
+    delimiter: char,
+) -> CtxResult<()> {
+    validate_csv_with_schema(reader, delimiter, Some(schema))
+}
+
+/// Validates every record of a source against a JSON Schema (see [`validate_records`]), CSV
+/// columns are typed like with `--parse-numbers` (see [`crate::utils::type_table`]) so schemas
+/// inferred from the same file match, `types` overrides the types of some columns.
+///
+/// # Examples
+///
+/// ```
+/// use fiux::{Format, JsonSchema, TypeOpts, Value, validate_with_schema};
+///
+/// let schema: Value = serde_json::json!({"properties": {"age": {"type": "integer"}}}).into();
+/// let schema = JsonSchema::new(&schema)?;
+/// let csv = "name,age\nAlice,30\n".as_bytes();
+///
+/// assert!(validate_with_schema(csv, Format::Csv, &schema, ',', &TypeOpts::default()).is_ok());
+/// # Ok::<(), fiux::CtxResultErr>(())
+/// ```
+pub fn validate_with_schema(
+    reader: impl Read + 'static,
+    format: impl Into<Arc<dyn FileFormat>>,
+    schema: &JsonSchema,
+    delimiter: char,
+    types: &TypeOpts,
+) -> CtxResult<()> {
+    let format = format.into();
+    let opts = ConvertOptions {
+        input_delimiter: delimiter,
+        parse_numbers: true,
+        types: types.clone(),
+        ..Default::default()
+    };
+
+    let decoder = format.decoder().ok_or_else(|| unsupported(&*format, "decoded"))?;
+    let data = decoder.decode(Box::new(reader), &opts).context("Deserialization failed")?;
+
+    validate_records(data, schema, &opts)
+}
@@ -1,11 +1,16 @@
+use csv_core::ReadRecordResult;
 use resext::ctx;
-use std::{fs::File, io::BufReader};
+use std::io::{Error, ErrorKind as EK, Read};
 
-use crate::utils::{CtxResult, CtxResultExt, DataTypes, WriterStreams};
+use crate::utils::{
+    CtxResult, CtxResultErr, CtxResultExt, Records, Value, WriterStreams,
+    input_reader::Mapped,
+    parallel::{CHUNK_SIZE, Chunk, Chunks, MappedChunks, Ordered, Split},
+};
 
-pub fn csv_decoder(
-    mut reader: csv::Reader<BufReader<File>>,
-) -> CtxResult<WriterStreams<impl Iterator<Item = CtxResult<DataTypes>>>> {
+pub fn csv_decoder<R: Read>(
+    mut reader: csv::Reader<R>,
+) -> CtxResult<WriterStreams<impl Iterator<Item = CtxResult<Value>>>> {
     let headers = reader
         .headers()
         .context("Failed to read input file headers")?
@@ -17,10 +22,265 @@ pub fn csv_decoder(
         let record = rec.context(ctx!("Invalid CSV data at line: {}", line_no + 1));
 
         match record {
-            Ok(ok) => Ok(DataTypes::Csv(ok)),
+            Ok(ok) => Ok(Value::Array(ok.iter().map(csv_cell).collect())),
             Err(err) => Err(err),
         }
     });
 
     Ok(WriterStreams::Table { headers, iter })
 }
+
+/// Decodes CSV like [`csv_decoder`] on `threads` worker threads, the input is split into chunks of
+/// whole records (newlines in quoted fields don't split records) that are decoded in parallel and
+/// yielded in order.
+pub fn csv_decoder_parallel<R: Read>(
+    reader: R,
+    delimiter: char,
+    threads: usize,
+) -> CtxResult<WriterStreams<impl Iterator<Item = CtxResult<Value>>>> {
+    let d = ascii_delimiter(delimiter)?;
+
+    decode_chunks(Chunks::new(reader, Split::Csv(d), CHUNK_SIZE), d, threads)
+}
+
+/// Decodes a memory-mapped file like [`csv_decoder`], records are parsed from the mapping instead
+/// of being copied into a buffer first and `threads` other than `1` decode chunks of the mapping
+/// in parallel like [`csv_decoder_parallel`].
+pub fn csv_decoder_mapped(
+    input: Mapped,
+    delimiter: char,
+    threads: usize,
+) -> CtxResult<WriterStreams<Records>> {
+    let d = ascii_delimiter(delimiter)?;
+
+    if threads != 1 {
+        let chunks = MappedChunks::new(input, Split::Csv(d), CHUNK_SIZE);
+        return Ok(decode_chunks(chunks, d, threads)?.boxed());
+    }
+
+    let mut rdr = MappedReader::new(input, d);
+
+    let headers = match rdr.read_record() {
+        true => rdr.fields().map(|f| String::from_utf8(f.to_vec())).collect(),
+        false => Ok(Vec::new()),
+    }
+    .map_err(|e| CtxResultErr::new("Invalid UTF-8 in headers", e.to_string()))
+    .context("Failed to read input file headers")?;
+
+    let expected = headers.len();
+    let mut record = 0u64;
+
+    let iter = std::iter::from_fn(move || {
+        let (line, byte) = (rdr.core.line(), rdr.pos as u64);
+
+        if !rdr.read_record() {
+            return None;
+        }
+
+        record += 1;
+
+        let row: CtxResult<Value> = match rdr.ends.len() {
+            len if len != expected => {
+                Err(unequal_lengths(record, line, byte, len, expected).into())
+            }
+            _ => Ok(Value::Array(rdr.fields().map(csv_cell).collect())),
+        };
+
+        Some(row.context(ctx!("Invalid CSV data at line: {}", record)))
+    });
+
+    Ok(WriterStreams::Table { headers, iter }.boxed())
+}
+
+fn ascii_delimiter(delimiter: char) -> CtxResult<u8> {
+    if !delimiter.is_ascii() {
+        Err(Error::new(EK::InvalidInput, format!("Input delimiter: {} is not ASCII", delimiter)))
+            .context("Failed to create CSV reader")?;
+    }
+
+    Ok(delimiter as u8)
+}
+
+fn decode_chunks(
+    mut chunks: impl Iterator<Item = CtxResult<Chunk>>,
+    delimiter: u8,
+    threads: usize,
+) -> CtxResult<WriterStreams<impl Iterator<Item = CtxResult<Value>>>> {
+    // headers are read from the first chunk like the single-threaded reader reads them
+    let mut first = match chunks.next().transpose()? {
+        Some(chunk) => chunk,
+        None => Chunk::new(Vec::new(), 1, 0),
+    };
+
+    let mut rdr = csv::ReaderBuilder::new().delimiter(delimiter).from_reader(first.bytes());
+
+    let headers = rdr
+        .headers()
+        .context("Failed to read input file headers")?
+        .iter()
+        .map(|s| s.to_string())
+        .collect::<Vec<String>>();
+
+    let pos = rdr.position().clone();
+    drop(rdr);
+
+    first.skip(pos.byte() as usize);
+    first.first_line = pos.line() as usize;
+
+    let fields = headers.len();
+
+    let rows = Ordered::new(std::iter::once(Ok(first)).chain(chunks), threads, move |chunk| {
+        decode_chunk(chunk, delimiter, fields)
+    })?;
+
+    // records are numbered here since chunks don't know how many records came before them
+    let iter = rows.flatten().enumerate().map(move |(idx, row)| {
+        let record = idx as u64 + 1;
+
+        let row: CtxResult<Value> = match row {
+            Row::Cells(cells) => Ok(cells),
+            Row::Unequal { line, byte, len } => {
+                Err(unequal_lengths(record, line, byte, len, fields).into())
+            }
+            Row::Failed(err) => Err(err),
+        };
+
+        row.context(ctx!("Invalid CSV data at line: {}", record))
+    });
+
+    Ok(WriterStreams::Table { headers, iter })
+}
+
+/// Row decoded on a worker thread.
+enum Row {
+    Cells(Value),
+    /// Row with a different number of fields than the headers at a line / byte of the input
+    Unequal {
+        line: u64,
+        byte: u64,
+        len: usize,
+    },
+    Failed(CtxResultErr),
+}
+
+/// Decodes the rows of a chunk, rows with a different number of fields than the headers are
+/// errors like in the single-threaded reader.
+fn decode_chunk(chunk: CtxResult<Chunk>, delimiter: u8, fields: usize) -> Vec<Row> {
+    let chunk = match chunk {
+        Ok(chunk) => chunk,
+        Err(err) => return vec![Row::Failed(err)],
+    };
+
+    let mut rdr = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(chunk.bytes());
+
+    let mut rows = Vec::new();
+    let mut rec = csv::ByteRecord::new();
+
+    loop {
+        match rdr.read_byte_record(&mut rec) {
+            Ok(false) => break,
+            Ok(true) if rec.len() != fields => {
+                let (line, byte) = rec.position().map_or((1, 0), |p| (p.line(), p.byte()));
+
+                rows.push(Row::Unequal {
+                    line: chunk.first_line as u64 + line - 1,
+                    byte: chunk.first_byte + byte,
+                    len: rec.len(),
+                });
+            }
+            Ok(true) => rows.push(Row::Cells(Value::Array(rec.iter().map(csv_cell).collect()))),
+            Err(err) => rows.push(Row::Failed(CtxResultErr::new("Failed to read CSV record", err))),
+        }
+    }
+
+    rows
+}
+
+/// Same error as the one the `csv` crate returns for rows with a different number of fields than
+/// the headers, so logs are the same however the input was read.
+fn unequal_lengths(record: u64, line: u64, byte: u64, len: usize, expected: usize) -> csv::Error {
+    csv::Error::from(Error::new(
+        EK::InvalidData,
+        format!(
+            "CSV error: record {} (line: {}, byte: {}): found record with {} fields, but the previous record has {} fields",
+            record, line, byte, len, expected
+        ),
+    ))
+}
+
+/// CSV reader that parses records straight from a memory-mapped file.
+struct MappedReader {
+    input: Mapped,
+    core: csv_core::Reader,
+    pos: usize,
+    output: Vec<u8>,
+    ends: Vec<usize>,
+    done: bool,
+}
+
+impl MappedReader {
+    fn new(input: Mapped, delimiter: u8) -> Self {
+        Self {
+            input,
+            core: csv_core::ReaderBuilder::new().delimiter(delimiter).build(),
+            pos: 0,
+            output: vec![0; 4096],
+            ends: Vec::new(),
+            done: false,
+        }
+    }
+
+    /// Reads the next record into `output` / `ends`, returns `false` at the end of the input.
+    fn read_record(&mut self) -> bool {
+        let (mut out, mut nends) = (0, 0);
+        let mut ends = std::mem::take(&mut self.ends);
+        ends.resize(ends.capacity().max(16), 0);
+
+        while !self.done {
+            // an empty input tells `csv_core` that the input has ended
+            let input = &self.input.as_slice()[self.pos..];
+            let (res, nin, nout, nend) =
+                self.core.read_record(input, &mut self.output[out..], &mut ends[nends..]);
+
+            self.pos += nin;
+            out += nout;
+            nends += nend;
+
+            match res {
+                ReadRecordResult::InputEmpty => {}
+                ReadRecordResult::OutputFull => self.output.resize(self.output.len() * 2, 0),
+                ReadRecordResult::OutputEndsFull => ends.resize(ends.len() * 2, 0),
+                ReadRecordResult::Record => {
+                    ends.truncate(nends);
+                    self.ends = ends;
+                    return true;
+                }
+                ReadRecordResult::End => self.done = true,
+            }
+        }
+
+        ends.clear();
+        self.ends = ends;
+        false
+    }
+
+    /// Gets the fields of the last record.
+    fn fields(&self) -> impl Iterator<Item = &[u8]> {
+        let starts = std::iter::once(0).chain(self.ends.iter().copied());
+
+        starts.zip(self.ends.iter()).map(|(start, &end)| &self.output[start..end])
+    }
+}
+
+/// Cells are kept as strings, cells that aren't valid UTF-8 are kept as bytes.
+#[inline(always)]
+fn csv_cell(cell: &[u8]) -> Value {
+    match std::str::from_utf8(cell) {
+        Ok(s) => Value::String(s.to_string()),
+        Err(_) => Value::Bytes(cell.to_vec()),
+    }
+}
@@ -0,0 +1,375 @@
+use resext::ctx;
+use std::io::BufRead;
+
+use crate::utils::{CtxResult, CtxResultErr, CtxResultExt, Value, WriterStreams, json};
+
+/// Decoder that streams the elements of a JSON array one by one instead of deserializing the
+/// whole array at once, `path` selects a nested array (e.g. `.data.items`), `.` selects the
+/// top-level array.
+pub fn json_array_decoder<R: BufRead>(
+    reader: R,
+    path: &str,
+) -> CtxResult<WriterStreams<impl Iterator<Item = CtxResult<Value>> + use<R>>> {
+    let mut stream = JsonArrayStream::new(reader);
+
+    let keys = path.split('.').filter(|k| !k.is_empty());
+    let is_top_level = path.split('.').all(|k| k.is_empty());
+
+    for key in keys {
+        stream.find_key(key).context(ctx!("Failed to find JSON path: {}", path))?;
+    }
+
+    stream.expect(b'[').context(ctx!("Value at JSON path: {} is not an array", path))?;
+
+    stream.is_top_level = is_top_level;
+
+    Ok(WriterStreams::Ndjson { values: stream })
+}
+
+/// Checks if the input starts with a top-level JSON array without consuming it.
+#[inline]
+pub fn is_json_array<R: BufRead>(reader: &mut R) -> CtxResult<bool> {
+    let chunk = reader.fill_buf().context("Failed to read input file")?;
+
+    Ok(chunk.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'['))
+}
+
+struct JsonArrayStream<R: BufRead> {
+    reader: R,
+    buf: Vec<u8>,
+    line_no: usize,
+    idx: usize,
+    is_top_level: bool,
+    done: bool,
+}
+
+impl<R: BufRead> JsonArrayStream<R> {
+    fn new(reader: R) -> Self {
+        Self { reader, buf: Vec::new(), line_no: 1, idx: 0, is_top_level: true, done: false }
+    }
+
+    #[inline]
+    fn syntax_err(&self, msg: &str) -> CtxResultErr {
+        CtxResultErr::new(
+            "Invalid JSON data in input file",
+            format!("{} at line: {}", msg, self.line_no),
+        )
+    }
+
+    /// Skips whitespace and returns the next byte without consuming it.
+    fn peek(&mut self) -> CtxResult<Option<u8>> {
+        loop {
+            let chunk = self.reader.fill_buf().context("Failed to read input file")?;
+
+            if chunk.is_empty() {
+                return Ok(None);
+            }
+
+            let ws = chunk.iter().take_while(|b| b.is_ascii_whitespace()).count();
+            let newlines = chunk[..ws].iter().filter(|b| **b == b'\n').count();
+            let has_value = ws < chunk.len();
+            let next = chunk.get(ws).copied();
+
+            self.line_no += newlines;
+            self.reader.consume(ws);
+
+            if has_value {
+                return Ok(next);
+            }
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> CtxResult<()> {
+        match self.peek()? {
+            Some(b) if b == byte => {
+                self.reader.consume(1);
+                Ok(())
+            }
+            Some(b) => Err(self.syntax_err(&format!(
+                "Expected `{}`, found `{}`",
+                byte as char,
+                b.escape_ascii()
+            ))),
+            None => Err(self.syntax_err(&format!("Expected `{}`, found EOF", byte as char))),
+        }
+    }
+
+    /// Scans the next complete JSON value and passes its bytes to `f`, a value is sliced from the
+    /// reader's buffer when it ends in it (memory-mapped input is one buffer) and copied into
+    /// `self.buf` when it spans reads.
+    fn with_value<T>(&mut self, f: impl FnOnce(&[u8]) -> T) -> CtxResult<T> {
+        if self.peek()?.is_none() {
+            return Err(self.syntax_err("Expected value, found EOF"));
+        }
+
+        let mut scan = Scan::default();
+        self.buf.clear();
+
+        loop {
+            let chunk = self.reader.fill_buf().context("Failed to read input file")?;
+
+            if chunk.is_empty() {
+                if scan.is_open() {
+                    return Err(self.syntax_err("Unexpected EOF"));
+                }
+
+                break;
+            }
+
+            let end = scan.end(chunk);
+            let used = end.unwrap_or(chunk.len());
+
+            self.line_no += chunk[..used].iter().filter(|b| **b == b'\n').count();
+
+            if end.is_some() && self.buf.is_empty() {
+                let out = f(&chunk[..used]);
+                self.reader.consume(used);
+
+                return Ok(out);
+            }
+
+            self.buf.extend_from_slice(&chunk[..used]);
+            self.reader.consume(used);
+
+            if end.is_some() {
+                break;
+            }
+        }
+
+        Ok(f(&self.buf))
+    }
+
+    /// Skips the next complete JSON value without copying it, so large values that aren't on
+    /// the path aren't held in memory.
+    fn skip_value(&mut self) -> CtxResult<()> {
+        if self.peek()?.is_none() {
+            return Err(self.syntax_err("Expected value, found EOF"));
+        }
+
+        let mut scan = Scan::default();
+
+        loop {
+            let chunk = self.reader.fill_buf().context("Failed to read input file")?;
+
+            if chunk.is_empty() {
+                if scan.is_open() {
+                    return Err(self.syntax_err("Unexpected EOF"));
+                }
+
+                return Ok(());
+            }
+
+            let end = scan.end(chunk);
+            let used = end.unwrap_or(chunk.len());
+
+            self.line_no += chunk[..used].iter().filter(|b| **b == b'\n').count();
+            self.reader.consume(used);
+
+            if end.is_some() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Moves into the object at the current position up to the value of `key`.
+    fn find_key(&mut self, key: &str) -> CtxResult<()> {
+        self.expect(b'{')?;
+
+        if self.peek()? == Some(b'}') {
+            return Err(self.syntax_err(&format!("Key: {} not found", key)));
+        }
+
+        loop {
+            if self.peek()? != Some(b'"') {
+                return Err(self.syntax_err("Expected object key"));
+            }
+
+            let line_no = self.line_no;
+            let k = self
+                .with_value(json::from_slice::<String>)?
+                .context(ctx!("Invalid object key at line: {}", line_no))?;
+
+            self.expect(b':')?;
+
+            if k == key {
+                return Ok(());
+            }
+
+            self.skip_value()?;
+
+            match self.peek()? {
+                Some(b',') => self.reader.consume(1),
+                _ => return Err(self.syntax_err(&format!("Key: {} not found", key))),
+            }
+        }
+    }
+
+    /// Returns the next element, the outer result is for structural errors which can't be
+    /// recovered from, the inner result is for elements with valid boundaries but invalid contents.
+    fn next_element(&mut self) -> CtxResult<Option<CtxResult<Value>>> {
+        match self.peek()? {
+            Some(b']') => {
+                self.reader.consume(1);
+                self.done = true;
+
+                if self.is_top_level && self.peek()?.is_some() {
+                    return Err(self.syntax_err("Trailing data after top-level array"));
+                }
+
+                return Ok(None);
+            }
+            Some(b',') if self.idx > 0 => self.reader.consume(1),
+            Some(_) if self.idx == 0 => {}
+            Some(b) => {
+                return Err(
+                    self.syntax_err(&format!("Expected `,` or `]`, found `{}`", b.escape_ascii()))
+                );
+            }
+            None => return Err(self.syntax_err("Expected `,` or `]`, found EOF")),
+        }
+
+        self.idx += 1;
+        let line_no = self.line_no;
+
+        let value = self.with_value(json::from_slice)?;
+
+        Ok(Some(value.context(ctx!(
+            "Invalid JSON array element: {} at line: {}",
+            self.idx,
+            line_no
+        ))))
+    }
+}
+
+/// State of the scan for the end of a JSON value, which tracks depth and strings across reads.
+#[derive(Default)]
+struct Scan {
+    depth: usize,
+    in_str: bool,
+    escaped: bool,
+}
+
+impl Scan {
+    /// Finds the end of the value in `chunk`, `None` if it continues past the chunk.
+    fn end(&mut self, chunk: &[u8]) -> Option<usize> {
+        for (i, &b) in chunk.iter().enumerate() {
+            if self.in_str {
+                if self.escaped {
+                    self.escaped = false;
+                } else if b == b'\\' {
+                    self.escaped = true;
+                } else if b == b'"' {
+                    self.in_str = false;
+
+                    if self.depth == 0 {
+                        return Some(i + 1);
+                    }
+                }
+
+                continue;
+            }
+
+            match b {
+                b'"' => self.in_str = true,
+                b'{' | b'[' => self.depth += 1,
+                b'}' | b']' if self.depth == 0 => return Some(i),
+                b'}' | b']' => {
+                    self.depth -= 1;
+
+                    if self.depth == 0 {
+                        return Some(i + 1);
+                    }
+                }
+                b',' if self.depth == 0 => return Some(i),
+                b if self.depth == 0 && b.is_ascii_whitespace() => return Some(i),
+                _ => {}
+            }
+        }
+
+        None
+    }
+
+    #[inline(always)]
+    fn is_open(&self) -> bool {
+        self.depth > 0 || self.in_str
+    }
+}
+
+impl<R: BufRead> Iterator for JsonArrayStream<R> {
+    type Item = CtxResult<Value>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match self.next_element() {
+            Ok(Some(Ok(val))) => Some(Ok(val)),
+            Ok(Some(Err(err))) => Some(Err(err)),
+            Ok(None) => None,
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufReader;
+
+    #[test]
+    fn test_nested_path_skips_siblings_without_buffering() {
+        let big = format!("[{}]", vec!["{\"x\": \"a]b\\\"}\"}"; 10_000].join(", "));
+        let input = format!(
+            "{{\"meta\": {}, \"data\": {{\"skip\": \"}}\", \"items\": [1, {{\"a\": 2}}]}}}}",
+            big
+        );
+
+        let mut stream = JsonArrayStream::new(input.as_bytes());
+
+        for key in ["data", "items"] {
+            stream.find_key(key).unwrap();
+        }
+
+        stream.expect(b'[').unwrap();
+        stream.is_top_level = false;
+
+        // only keys are buffered while walking the path, never the skipped values
+        assert!(stream.buf.capacity() < 64);
+
+        let values = stream.map(Result::unwrap).collect::<Vec<_>>();
+        let expected: Vec<Value> = serde_json::from_str("[1, {\"a\": 2}]").unwrap();
+
+        assert_eq!(values, expected);
+    }
+
+    #[test]
+    fn test_large_array_matches_serde_json() {
+        let items = [
+            "{\"a\": [1, -0, 2.5e3, true], \"b\": \"x\\ny\"}",
+            "-0",
+            "\"\\ud83d\\ude00\"",
+            "18446744073709551616",
+            "null",
+            "[[], {\"c\": \"]\"}]",
+        ];
+
+        let elements: Vec<&str> = (0..20_000).map(|i| items[i % items.len()]).collect();
+        let input = format!("[{}]\n", elements.join(",\n  "));
+        let expected: Vec<Value> = serde_json::from_str(&input).unwrap();
+
+        // elements that span reads are copied, the rest are sliced like memory-mapped input
+        for capacity in [5, 4096, input.len()] {
+            let reader = BufReader::with_capacity(capacity, input.as_bytes());
+
+            let WriterStreams::Ndjson { values } = json_array_decoder(reader, ".").unwrap() else {
+                unreachable!()
+            };
+
+            assert_eq!(values.map(Result::unwrap).collect::<Vec<_>>(), expected, "{}", capacity);
+        }
+    }
+}
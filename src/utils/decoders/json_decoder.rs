@@ -1,18 +1,12 @@
-use std::{fs::File, io::BufReader};
+use std::io::BufRead;
 
-use crate::utils::{CtxResult, CtxResultExt, DataTypes, WriterStreams};
+use crate::utils::{CtxResult, CtxResultExt, Value, WriterStreams, json};
 
-pub fn json_decoder(
-    reader: serde_json::Deserializer<serde_json::de::IoRead<BufReader<File>>>,
-) -> CtxResult<WriterStreams<impl Iterator<Item = CtxResult<DataTypes>>>> {
-    let iter = reader.into_iter::<serde_json::Value>().map(move |obj| {
-        let obj = obj.context("Invalid JSON data in input file");
-
-        match obj {
-            Ok(ok) => Ok(DataTypes::Json(ok)),
-            Err(err) => Err(err),
-        }
-    });
+pub fn json_decoder<R: BufRead>(
+    reader: R,
+) -> CtxResult<WriterStreams<impl Iterator<Item = CtxResult<Value>>>> {
+    let iter = json::stream::<Value, _>(reader)
+        .map(move |obj| obj.context("Invalid JSON data in input file"));
 
     Ok(WriterStreams::Values { iter })
 }
@@ -1,4 +1,6 @@
 pub mod csv_decoder;
+pub mod json_array_decoder;
 pub mod json_decoder;
 pub mod ndjson_decoder;
 pub mod toml_decoder;
+pub mod yaml_decoder;
@@ -1,15 +1,19 @@
 use resext::ctx;
 use std::{
-    fs::File,
-    io::{BufRead, BufReader},
+    io::{BufRead, Read},
     iter::from_fn,
 };
 
-use crate::utils::{CtxResult, CtxResultExt, DataTypes, Log, WriterStreams};
+use crate::utils::{
+    CtxResult, CtxResultExt, Log, Records, Value, WriterStreams,
+    input_reader::Mapped,
+    json,
+    parallel::{CHUNK_SIZE, Chunk, Chunks, MappedChunks, Ordered, Split},
+};
 
-pub fn ndjson_decoder(
-    mut reader: BufReader<File>,
-) -> CtxResult<WriterStreams<impl Iterator<Item = CtxResult<DataTypes>>>> {
+pub fn ndjson_decoder<R: BufRead>(
+    mut reader: R,
+) -> CtxResult<WriterStreams<impl Iterator<Item = CtxResult<Value>>>> {
     let mut buf = Vec::new();
     let mut line_no = 0usize;
 
@@ -26,25 +30,88 @@ pub fn ndjson_decoder(
                 return None;
             } else if bytes.is_none() {
                 continue;
-            } else {
-                while buf.last() == Some(&b'\n') || buf.last() == Some(&b'\r') {
-                    buf.pop();
-                }
-
-                if buf.is_empty() {
-                    continue;
-                };
-
-                let ndjson_obj = serde_json::from_slice(buf.as_slice())
-                    .context("Failed to deserialize file")
-                    .context(ctx!("Invalid NDJSON values at line: {}", line_no));
-                return match ndjson_obj {
-                    Ok(ok) => Some(Ok(DataTypes::Json(ok))),
-                    Err(err) => Some(Err(err)),
-                };
+            } else if let Some(ndjson_obj) = decode_line(&buf, line_no) {
+                return Some(ndjson_obj);
             }
         }
     });
 
     Ok(WriterStreams::Ndjson { values: iter })
 }
+
+/// Decodes NDJSON like [`ndjson_decoder`] on `threads` worker threads, the input is split into
+/// chunks of whole lines that are decoded in parallel and yielded in order.
+pub fn ndjson_decoder_parallel<R: Read>(
+    reader: R,
+    threads: usize,
+) -> CtxResult<WriterStreams<impl Iterator<Item = CtxResult<Value>>>> {
+    decode_chunks(Chunks::new(reader, Split::Lines, CHUNK_SIZE), threads)
+}
+
+/// Decodes a memory-mapped file like [`ndjson_decoder`], lines are sliced from the mapping instead
+/// of being copied into a buffer and `threads` other than `1` decode chunks of the mapping in
+/// parallel like [`ndjson_decoder_parallel`].
+pub fn ndjson_decoder_mapped(input: Mapped, threads: usize) -> CtxResult<WriterStreams<Records>> {
+    if threads != 1 {
+        let chunks = MappedChunks::new(input, Split::Lines, CHUNK_SIZE);
+        return Ok(decode_chunks(chunks, threads)?.boxed());
+    }
+
+    let mut pos = 0usize;
+    let mut line_no = 0usize;
+
+    let iter = from_fn(move || {
+        let bytes = input.as_slice();
+
+        while pos < bytes.len() {
+            line_no += 1;
+
+            let end = memchr::memchr(b'\n', &bytes[pos..]).map_or(bytes.len(), |n| pos + n + 1);
+            let line = &bytes[pos..end];
+            pos = end;
+
+            if let Some(ndjson_obj) = decode_line(line, line_no) {
+                return Some(ndjson_obj);
+            }
+        }
+
+        None
+    });
+
+    Ok(WriterStreams::Ndjson { values: iter }.boxed())
+}
+
+fn decode_chunks(
+    chunks: impl Iterator<Item = CtxResult<Chunk>>,
+    threads: usize,
+) -> CtxResult<WriterStreams<impl Iterator<Item = CtxResult<Value>>>> {
+    let values = Ordered::new(chunks, threads, |chunk: CtxResult<Chunk>| match chunk {
+        Ok(chunk) => chunk
+            .bytes()
+            .split(|b| *b == b'\n')
+            .enumerate()
+            .filter_map(|(idx, line)| decode_line(line, chunk.first_line + idx))
+            .collect(),
+        Err(err) => vec![Err(err)],
+    })?;
+
+    Ok(WriterStreams::Ndjson { values: values.flatten() })
+}
+
+/// Decodes one line, empty lines are skipped.
+#[inline]
+fn decode_line(mut line: &[u8], line_no: usize) -> Option<CtxResult<Value>> {
+    while let [rest @ .., b'\n' | b'\r'] = line {
+        line = rest;
+    }
+
+    if line.is_empty() {
+        return None;
+    }
+
+    let ndjson_obj = json::from_slice::<Value>(line)
+        .context("Failed to deserialize file")
+        .context(ctx!("Invalid NDJSON values at line: {}", line_no));
+
+    Some(ndjson_obj)
+}
@@ -1,15 +1,12 @@
-use crate::utils::{CtxResult, CtxResultExt, DataTypes, WriterStreams};
+use crate::utils::{CtxResult, CtxResultExt, Value, WriterStreams};
 
+/// Decodes a TOML document from bytes in memory (e.g. read by `toml_reader` or a memory-mapped
+/// file).
 pub fn toml_decoder(
-    content: Vec<u8>,
-) -> CtxResult<WriterStreams<impl Iterator<Item = CtxResult<DataTypes>>>> {
+    content: impl AsRef<[u8]>,
+) -> CtxResult<WriterStreams<impl Iterator<Item = CtxResult<Value>>>> {
     let iter = [content].into_iter().map(move |c| {
-        let toml_val = toml::from_slice(c.as_slice()).context("Invalid TOML values in input file");
-
-        match toml_val {
-            Ok(ok) => Ok(DataTypes::Toml(ok)),
-            Err(err) => Err(err),
-        }
+        toml::from_slice::<Value>(c.as_ref()).context("Invalid TOML values in input file")
     });
 
     Ok(WriterStreams::Values { iter })
@@ -0,0 +1,35 @@
+use resext::ctx;
+use serde::Deserialize;
+
+use crate::utils::{CtxResult, CtxResultExt, Value, WriterStreams};
+
+pub fn yaml_decoder(
+    reader: serde_yaml::Deserializer<'_>,
+) -> CtxResult<WriterStreams<impl Iterator<Item = CtxResult<Value>>>> {
+    let mut docs = reader
+        .enumerate()
+        .map(move |(idx, doc)| {
+            Value::deserialize(doc).context(ctx!("Invalid YAML values in document: {}", idx + 1))
+        })
+        // YAML syntax errors can't be recovered from, so stop after the first one
+        .scan(false, |failed, doc| {
+            if *failed {
+                return None;
+            }
+
+            *failed = doc.is_err();
+            Some(doc)
+        })
+        .peekable();
+
+    // multi-document streams (separated by `---`) are treated like NDJSON
+    let first = docs.next();
+    let is_multi_doc = docs.peek().is_some();
+    let iter = first.into_iter().chain(docs);
+
+    if is_multi_doc {
+        Ok(WriterStreams::Ndjson { values: iter })
+    } else {
+        Ok(WriterStreams::Values { iter })
+    }
+}
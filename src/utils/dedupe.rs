@@ -0,0 +1,356 @@
+use resext::ctx;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    hash::{BuildHasher, Hasher, RandomState},
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+};
+
+use crate::utils::{
+    CtxResult, CtxResultExt, Log, Records, Value, WriterStreams,
+    sort::{KeyRef, read_spilled, write_spilled},
+};
+
+/// Which record of a group of duplicates is kept.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum KeepMode {
+    /// Keep the first record, duplicates are dropped as they're read
+    #[default]
+    First,
+
+    /// Keep the last record, in the position of the last record
+    Last,
+}
+
+/// Options for removing duplicate records.
+#[derive(Clone, Debug, Default)]
+pub struct DedupeOpts {
+    /// Columns / paths that identify a record (e.g. `id`, `user.id`), whole records are compared
+    /// if it's empty
+    pub keys: Vec<String>,
+
+    /// Which record of a group of duplicates is kept
+    pub keep: KeepMode,
+
+    /// Track seen records in temporary files instead of memory, for inputs with more unique
+    /// records than fit in memory
+    pub on_disk: bool,
+}
+
+/// Number of temporary files that seen record hashes are partitioned into with `on_disk`.
+const BUCKETS: usize = 64;
+
+/// Removes duplicate records by keys or by a hash of the whole record, records are compared with
+/// 128-bit hashes of their canonical form so key order in objects doesn't matter.
+///
+/// Table keys are matched with header names, keys of other streams are paths (e.g. `user.id`) and
+/// missing keys are treated as `null`.
+pub fn dedupe_records(
+    data: WriterStreams<Records>,
+    opts: &DedupeOpts,
+) -> CtxResult<WriterStreams<Records>> {
+    match data {
+        WriterStreams::Table { headers, iter } => {
+            let mut keys = Vec::with_capacity(opts.keys.len());
+
+            for key in &opts.keys {
+                keys.push(KeyRef::column(&headers, key, "Check the keys passed to `--key`")?);
+            }
+
+            let iter = dedupe(iter, Fingerprint::new(keys), opts)?;
+
+            Ok(WriterStreams::Table { headers, iter })
+        }
+
+        WriterStreams::Values { iter } => {
+            Ok(WriterStreams::Values { iter: dedupe(iter, Fingerprint::paths(opts), opts)? })
+        }
+
+        WriterStreams::Ndjson { values } => {
+            Ok(WriterStreams::Ndjson { values: dedupe(values, Fingerprint::paths(opts), opts)? })
+        }
+    }
+}
+
+/// Hashes records by their keys.
+struct Fingerprint {
+    keys: Vec<KeyRef>,
+    /// Two SipHash keys, created once so every record is hashed with the same keys
+    states: [RandomState; 2],
+}
+
+impl Fingerprint {
+    fn new(keys: Vec<KeyRef>) -> Self {
+        Self { keys, states: [RandomState::new(), RandomState::new()] }
+    }
+
+    fn paths(opts: &DedupeOpts) -> Self {
+        Self::new(opts.keys.iter().map(|k| KeyRef::path(k)).collect())
+    }
+
+    fn of(&self, record: &Value, buf: &mut Vec<u8>) -> u128 {
+        buf.clear();
+
+        if self.keys.is_empty() {
+            canonical(record, buf);
+        } else {
+            for key in &self.keys {
+                canonical(key.get(record).unwrap_or(&Value::Null), buf);
+            }
+        }
+
+        // two 64-bit SipHashes with independent random keys act as one 128-bit hash, so the
+        // chance of any collision among a billion records is around 10^-21
+        let [a, b] = self.states.each_ref().map(|state| {
+            let mut hasher = state.build_hasher();
+            hasher.write(buf);
+            hasher.finish()
+        });
+
+        ((a as u128) << 64) | b as u128
+    }
+}
+
+/// Writes a canonical form of a value, object keys are sorted and integral floats are written as
+/// integers so `{"a": 1, "b": 2.0}` and `{"b": 2, "a": 1}` are equal.
+pub(crate) fn canonical(value: &Value, out: &mut Vec<u8>) {
+    let bytes = |tag: u8, b: &[u8], out: &mut Vec<u8>| {
+        out.push(tag);
+        out.extend_from_slice(&(b.len() as u64).to_le_bytes());
+        out.extend_from_slice(b);
+    };
+
+    match value {
+        Value::Null => out.push(0),
+        Value::Bool(b) => out.extend_from_slice(&[1, *b as u8]),
+        Value::Int(i) => {
+            out.push(2);
+            out.extend_from_slice(&i.to_le_bytes());
+        }
+        Value::Float(f) if f.fract() == 0.0 && f.abs() < i64::MAX as f64 => {
+            out.push(2);
+            out.extend_from_slice(&(*f as i64).to_le_bytes());
+        }
+        Value::Float(f) => {
+            out.push(3);
+            out.extend_from_slice(&f.to_le_bytes());
+        }
+        Value::Decimal(s) => bytes(4, s.as_bytes(), out),
+        Value::String(s) => bytes(5, s.as_bytes(), out),
+        Value::Bytes(b) => bytes(5, b, out),
+        Value::DateTime(s) => bytes(6, s.as_bytes(), out),
+        Value::Array(arr) => {
+            out.push(7);
+            out.extend_from_slice(&(arr.len() as u64).to_le_bytes());
+
+            for v in arr {
+                canonical(v, out);
+            }
+        }
+        Value::Map(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+
+            out.push(8);
+            out.extend_from_slice(&(entries.len() as u64).to_le_bytes());
+
+            for (k, v) in entries {
+                bytes(5, k.as_bytes(), out);
+                canonical(v, out);
+            }
+        }
+    }
+}
+
+fn dedupe(records: Records, fp: Fingerprint, opts: &DedupeOpts) -> CtxResult<Records> {
+    if opts.on_disk {
+        return dedupe_on_disk(records, fp, opts.keep);
+    }
+
+    let mut buf = Vec::new();
+
+    match opts.keep {
+        KeepMode::First => {
+            let mut seen: HashSet<u128> = HashSet::new();
+
+            // invalid records are passed through so writers can log them
+            Ok(Box::new(records.filter(move |rec| match rec {
+                Ok(v) => seen.insert(fp.of(v, &mut buf)),
+                Err(_) => true,
+            })))
+        }
+
+        KeepMode::Last => {
+            let mut kept: Vec<Option<CtxResult<Value>>> = Vec::new();
+            let mut last: HashMap<u128, usize> = HashMap::new();
+
+            for rec in records {
+                if let Ok(v) = &rec
+                    && let Some(prev) = last.insert(fp.of(v, &mut buf), kept.len())
+                {
+                    kept[prev] = None;
+                }
+
+                kept.push(Some(rec));
+            }
+
+            Ok(Box::new(kept.into_iter().flatten()))
+        }
+    }
+}
+
+/// Dedupes records without keeping them or their hashes in memory: records are spilled into a
+/// temporary file while their hashes are partitioned into buckets, then each bucket is deduped on
+/// its own into a bitmap of kept records that the spilled records are filtered with.
+fn dedupe_on_disk(records: Records, fp: Fingerprint, keep: KeepMode) -> CtxResult<Records> {
+    let mut spill = tempfile_writer()?;
+    let mut buckets = Vec::with_capacity(BUCKETS);
+
+    for _ in 0..BUCKETS {
+        buckets.push(tempfile_writer()?);
+    }
+
+    let mut buf = Vec::new();
+    let mut hash_buf = Vec::new();
+    let mut count: u64 = 0;
+
+    for (idx, rec) in records.enumerate() {
+        // invalid records are logged here since they can't be written into the temporary file
+        let Some(rec) = rec.context(ctx!("Failed to dedupe record: {}", idx + 1)).log("[WARN]")
+        else {
+            continue;
+        };
+
+        let hash = fp.of(&rec, &mut hash_buf);
+        let bucket = &mut buckets[(hash >> (128 - BUCKETS.trailing_zeros())) as usize];
+
+        bucket
+            .write_all(&hash.to_le_bytes())
+            .and_then(|_| bucket.write_all(&count.to_le_bytes()))
+            .context("Failed to write hash into temporary file")?;
+
+        write_spilled(&mut spill, &rec, &mut buf)?;
+        count += 1;
+    }
+
+    let mut kept = vec![0u64; count.div_ceil(64) as usize];
+
+    for bucket in buckets {
+        let mut rdr = BufReader::with_capacity(64 * 1024, rewind(bucket)?);
+        let mut seen: HashMap<u128, u64> = HashMap::new();
+        let mut entry = [0u8; 24];
+
+        loop {
+            match rdr.read_exact(&mut entry) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(e) => Err(e).context("Failed to read hash from temporary file")?,
+            }
+
+            let hash = u128::from_le_bytes(entry[..16].try_into().unwrap_or_default());
+            let idx = u64::from_le_bytes(entry[16..].try_into().unwrap_or_default());
+
+            // indexes in a bucket are increasing so the first / last insert wins
+            match keep {
+                KeepMode::First => {
+                    seen.entry(hash).or_insert(idx);
+                }
+                KeepMode::Last => {
+                    seen.insert(hash, idx);
+                }
+            }
+        }
+
+        for idx in seen.into_values() {
+            kept[(idx / 64) as usize] |= 1 << (idx % 64);
+        }
+    }
+
+    let mut rdr = BufReader::with_capacity(256 * 1024, rewind(spill)?);
+    let mut idx: u64 = 0;
+
+    let iter = std::iter::from_fn(move || {
+        loop {
+            let rec = match read_spilled(&mut rdr) {
+                Ok(Some(rec)) => rec,
+                Ok(None) => return None,
+                Err(e) => return Some(Err(e)),
+            };
+
+            let is_kept = kept[(idx / 64) as usize] & (1 << (idx % 64)) != 0;
+            idx += 1;
+
+            if is_kept {
+                return Some(Ok(rec));
+            }
+        }
+    });
+
+    Ok(Box::new(iter))
+}
+
+#[inline]
+fn tempfile_writer() -> CtxResult<BufWriter<File>> {
+    let file = tempfile::tempfile().context("Failed to create temporary file")?;
+
+    Ok(BufWriter::with_capacity(64 * 1024, file))
+}
+
+#[inline]
+fn rewind(wtr: BufWriter<File>) -> CtxResult<File> {
+    let mut file =
+        wtr.into_inner().map_err(|e| e.into_error()).context("Failed to flush temporary file")?;
+
+    file.seek(SeekFrom::Start(0)).context("Failed to rewind temporary file")?;
+
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn run(items: &[serde_json::Value], opts: &DedupeOpts) -> Vec<Value> {
+        let values: Vec<CtxResult<Value>> = items.iter().cloned().map(|v| Ok(v.into())).collect();
+        let data = WriterStreams::Ndjson { values: Box::new(values.into_iter()) as Records };
+
+        match dedupe_records(data, opts).unwrap() {
+            WriterStreams::Ndjson { values } => values.map(Result::unwrap).collect(),
+            _ => unreachable!(),
+        }
+    }
+
+    fn values(items: &[serde_json::Value]) -> Vec<Value> {
+        items.iter().cloned().map(Value::from).collect()
+    }
+
+    #[test]
+    fn test_whole_record_ignores_key_order() {
+        let items = [json!({"a": 1, "b": 2.0}), json!({"b": 2, "a": 1}), json!({"a": 1})];
+
+        for on_disk in [false, true] {
+            let opts = DedupeOpts { on_disk, ..Default::default() };
+            assert_eq!(run(&items, &opts), values(&[items[0].clone(), items[2].clone()]));
+        }
+    }
+
+    #[test]
+    fn test_keys_keep_last() {
+        let items = [
+            json!({"id": 1, "v": "a"}),
+            json!({"id": 2, "v": "b"}),
+            json!({"id": 1, "v": "c"}),
+            json!({"v": "d"}),
+        ];
+
+        for on_disk in [false, true] {
+            let opts = DedupeOpts { keys: vec!["id".to_string()], keep: KeepMode::Last, on_disk };
+
+            assert_eq!(
+                run(&items, &opts),
+                values(&[items[1].clone(), items[2].clone(), items[3].clone()])
+            );
+        }
+    }
+}
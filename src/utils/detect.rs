@@ -0,0 +1,336 @@
+use resext::ctx;
+use serde::de::IgnoredAny;
+use std::{
+    fmt,
+    fs::File,
+    io::{BufRead, Read, stdin},
+    path::Path,
+};
+
+use crate::utils::{Compression, CtxResult, CtxResultExt, Format, is_std_stream};
+
+/// Number of bytes (after decompression) that formats are detected from.
+pub const SNIFF_SIZE: usize = 64 * 1024;
+
+/// Number of lines that CSV delimiters are detected from.
+const SNIFF_LINES: usize = 50;
+
+/// CSV delimiters tried when sniffing, in order of preference for ties.
+const DELIMITERS: [u8; 4] = [b',', b'\t', b';', b'|'];
+
+/// Format of an input detected from its content by [`detect_format`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Detection {
+    pub format: Format,
+    /// Delimiter of CSV input
+    pub delimiter: Option<char>,
+    pub compression: Option<Compression>,
+    /// How likely the detected format is right, from `0.0` to `1.0`
+    pub confidence: f32,
+}
+
+impl fmt::Display for Detection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "format: {}", self.format)?;
+
+        if let Some(d) = self.delimiter {
+            writeln!(f, "delimiter: {:?}", d)?;
+        }
+
+        if let Some(compression) = self.compression {
+            writeln!(f, "compression: {}", compression.name())?;
+        }
+
+        write!(f, "confidence: {:.2}", self.confidence)
+    }
+}
+
+/// Detects the format of a file from its first [`SNIFF_SIZE`] bytes, `-` peeks at stdin without
+/// consuming it so it can still be decoded afterwards.
+pub fn detect_path(path: &Path) -> CtxResult<Option<Detection>> {
+    let mut head = Vec::with_capacity(SNIFF_SIZE);
+
+    if is_std_stream(path) {
+        let mut stdin = stdin().lock();
+        let buf = stdin.fill_buf().context("Failed to read stdin")?;
+
+        // stdin can't be read further without consuming it, so its buffer is never complete
+        head.extend_from_slice(&buf[..buf.len().min(SNIFF_SIZE)]);
+        return Ok(sniff(&head, false));
+    }
+
+    File::open(path)
+        .context("Failed to open input file")
+        .context(ctx!("Input file: {}", path.to_string_lossy()))?
+        .take(SNIFF_SIZE as u64)
+        .read_to_end(&mut head)
+        .context("Failed to read input file")?;
+
+    Ok(detect_format(&head))
+}
+
+/// Detects the format of input from its first bytes (e.g. the first [`SNIFF_SIZE`] bytes of a
+/// file), compressed input is decompressed first. `None` if it doesn't look like any format.
+///
+/// Formats are detected from a UTF-8 BOM, the first non-whitespace byte, whether every line is a
+/// JSON value (NDJSON), TOML `key = value` / `[table]` lines, YAML `key: value` / `- item` lines
+/// and the delimiter that splits the first lines into the same number of CSV fields.
+pub fn detect_format(head: &[u8]) -> Option<Detection> {
+    let complete = head.len() < SNIFF_SIZE;
+
+    sniff(head, complete)
+}
+
+/// Sniffs the format of `head`, `complete` is `false` if the input continues after it.
+fn sniff(head: &[u8], complete: bool) -> Option<Detection> {
+    let Some(compression) = Compression::detect(head) else {
+        let (format, delimiter, confidence) = sniff_text(head, complete)?;
+
+        return Some(Detection { format, delimiter, compression: None, confidence });
+    };
+
+    let mut text = Vec::with_capacity(SNIFF_SIZE);
+
+    // the head can cut the compressed stream off, everything decoded before that is used
+    let decoded = compression.decoder(head).ok()?.take(SNIFF_SIZE as u64).read_to_end(&mut text);
+    let complete = complete && decoded.is_ok() && text.len() < SNIFF_SIZE;
+
+    let (format, delimiter, confidence) = sniff_text(&text, complete)?;
+
+    Some(Detection { format, delimiter, compression: Some(compression), confidence })
+}
+
+type Sniffed = (Format, Option<char>, f32);
+
+fn sniff_text(text: &[u8], complete: bool) -> Option<Sniffed> {
+    // UTF-16 isn't supported by any decoder
+    if text.starts_with(b"\xff\xfe") || text.starts_with(b"\xfe\xff") {
+        return None;
+    }
+
+    let text = text.strip_prefix(b"\xef\xbb\xbf").unwrap_or(text);
+    let first = *text.iter().find(|b| !b.is_ascii_whitespace())?;
+
+    let mut lines: Vec<&[u8]> = text.split(|&b| b == b'\n').collect();
+
+    // the last line is cut off in the middle unless the input is complete or ends with a newline
+    if !complete && !text.ends_with(b"\n") {
+        lines.pop();
+    }
+
+    let lines: Vec<&[u8]> =
+        lines.into_iter().map(|l| l.trim_ascii()).filter(|l| !l.is_empty()).collect();
+
+    // input that parses as JSON values is JSON even if its lines look like CSV (e.g. commas)
+    if matches!(first, b'{' | b'[')
+        && let Some(json) = sniff_json(text, &lines, complete)
+    {
+        return Some(json);
+    }
+
+    [sniff_toml(text, &lines, complete), sniff_yaml(&lines), sniff_csv(text, complete)]
+        .into_iter()
+        .flatten()
+        .max_by(|a, b| a.2.total_cmp(&b.2))
+}
+
+/// NDJSON if every line is a JSON value, JSON if the input is a stream of JSON values.
+fn sniff_json(text: &[u8], lines: &[&[u8]], complete: bool) -> Option<Sniffed> {
+    let is_value = |line: &&[u8]| serde_json::from_slice::<IgnoredAny>(line).is_ok();
+
+    if lines.len() >= 2 && lines.iter().all(is_value) {
+        let confidence = if lines.len() >= 3 { 0.95 } else { 0.8 };
+        return Some((Format::Ndjson, None, confidence));
+    }
+
+    for value in serde_json::Deserializer::from_slice(text).into_iter::<IgnoredAny>() {
+        match value {
+            Ok(_) => {}
+            // values cut off at the end of the head are fine if the input continues
+            Err(err) if err.is_eof() && !complete => return Some((Format::Json, None, 0.8)),
+            Err(_) => return None,
+        }
+    }
+
+    // a single line is also a valid NDJSON file, JSON is more likely
+    let confidence = if lines.len() == 1 { 0.75 } else { 0.95 };
+
+    Some((Format::Json, None, confidence))
+}
+
+fn sniff_toml(text: &[u8], lines: &[&[u8]], complete: bool) -> Option<Sniffed> {
+    let lines: Vec<&[u8]> = lines.iter().copied().filter(|l| !l.starts_with(b"#")).collect();
+
+    if !is_toml_line(lines.first()?) {
+        return None;
+    }
+
+    if complete && std::str::from_utf8(text).is_ok_and(|s| s.parse::<toml::Table>().is_ok()) {
+        return Some((Format::Toml, None, 0.95));
+    }
+
+    // multi-line arrays and strings have lines that are neither keys nor tables
+    let ratio = lines.iter().filter(|l| is_toml_line(l)).count() as f32 / lines.len() as f32;
+
+    (ratio >= 0.8).then_some((Format::Toml, None, 0.9 * ratio))
+}
+
+/// `key = value`, `a.b = value`, `"quoted key" = value`, `[table]` or `[[array.of.tables]]`.
+fn is_toml_line(line: &[u8]) -> bool {
+    if let Some(inner) = line.strip_prefix(b"[") {
+        let inner = inner.strip_prefix(b"[").unwrap_or(inner);
+        let end = inner.iter().position(|&b| b == b']');
+
+        return end.is_some_and(|end| end > 0 && inner[..end].iter().copied().all(is_key_byte));
+    }
+
+    let key_len = line.iter().position(|&b| b == b'=');
+
+    key_len.is_some_and(|len| {
+        let key = line[..len].trim_ascii();
+        !key.is_empty() && key.iter().copied().all(is_key_byte)
+    })
+}
+
+#[inline(always)]
+fn is_key_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || matches!(b, b'_' | b'-' | b'.' | b'"' | b'\'' | b' ')
+}
+
+fn sniff_yaml(lines: &[&[u8]]) -> Option<Sniffed> {
+    let lines: Vec<&[u8]> = lines.iter().copied().filter(|l| !l.starts_with(b"#")).collect();
+    let first = *lines.first()?;
+
+    if first == b"---" || first.starts_with(b"%YAML") {
+        return Some((Format::Yaml, None, 0.9));
+    }
+
+    if !is_yaml_line(first) {
+        return None;
+    }
+
+    let ratio = lines.iter().filter(|l| is_yaml_line(l)).count() as f32 / lines.len() as f32;
+
+    (ratio >= 0.8).then_some((Format::Yaml, None, 0.85 * ratio))
+}
+
+/// `key: value`, `key:` or `- item` (lines are trimmed, so nested lines look the same).
+fn is_yaml_line(line: &[u8]) -> bool {
+    if line == b"-" || line.starts_with(b"- ") || line == b"---" {
+        return true;
+    }
+
+    let Some(colon) = line.iter().position(|&b| b == b':') else {
+        return false;
+    };
+
+    // commas in keys are more likely CSV cells (e.g. `id,12:30`)
+    let key = &line[..colon];
+    let after = line.get(colon + 1);
+
+    !key.is_empty()
+        && !key.contains(&b',')
+        && !key.starts_with(b"{")
+        && after.is_none_or(|b| b.is_ascii_whitespace())
+}
+
+/// CSV with the delimiter that splits the first lines into the most fields consistently.
+fn sniff_csv(text: &[u8], complete: bool) -> Option<Sniffed> {
+    let mut best: Option<(u8, f32, usize)> = None;
+
+    for d in DELIMITERS {
+        let mut rdr = csv::ReaderBuilder::new()
+            .delimiter(d)
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(text);
+
+        let mut counts: Vec<usize> = rdr
+            .byte_records()
+            .take(SNIFF_LINES + 1)
+            .map_while(Result::ok)
+            .map(|rec| rec.len())
+            .collect();
+
+        // the last record is cut off in the middle unless the input is complete
+        if !complete || counts.len() > SNIFF_LINES {
+            counts.pop();
+        }
+
+        let Some(&fields) = counts.first() else {
+            continue;
+        };
+
+        if fields < 2 {
+            continue;
+        }
+
+        let same = counts.iter().filter(|&&c| c == fields).count();
+        let consistency = same as f32 / counts.len() as f32;
+
+        if best.is_none_or(|(_, c, f)| (consistency, fields) > (c, f)) {
+            best = Some((d, consistency, fields));
+        }
+    }
+
+    let (d, consistency, _) = best?;
+
+    if consistency < 0.8 {
+        return None;
+    }
+
+    // a header alone could be anything with the delimiter in it
+    let rows = text.split(|&b| b == b'\n').filter(|l| !l.trim_ascii().is_empty()).count();
+    let confidence = if rows >= 2 { 0.9 } else { 0.5 };
+
+    Some((Format::Csv, Some(d as char), confidence * consistency))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn detect(text: &str) -> Option<(Format, Option<char>)> {
+        detect_format(text.as_bytes()).map(|d| (d.format, d.delimiter))
+    }
+
+    #[test]
+    fn test_detect_format() {
+        let ndjson = "{\"a\": 1, \"b\": \"x,y\"}\n{\"a\": 2, \"b\": 3}\n[3, 4]\n";
+        let json = "{\n  \"a\": [1, 2],\n  \"b\": {\"c\": \"d\"}\n}\n";
+        let toml = "# config\ntitle = \"x\"\n\n[server]\nhost = \"a\"\nports = [\n  1,\n  2,\n]\n";
+        let yaml = "name: fiux\nitems:\n  - a\n  - b\nnested:\n  key: value\n";
+        let tsv = "id\tname\tnote\n1\tAlice\ta,b\n2\tBob\t\"x\ty\"\n";
+        let csv = "id,time,note\n1,12:30,\"a;b\"\n2,13:00,\"multi\nline\"\n";
+
+        assert_eq!(detect(ndjson), Some((Format::Ndjson, None)));
+        assert_eq!(
+            detect("{\"a\": 1, \"b\": 2}\n{\"a\": 3, \"b\": 4}"),
+            Some((Format::Ndjson, None))
+        );
+        assert_eq!(detect(json), Some((Format::Json, None)));
+        assert_eq!(detect("\u{feff}[1, 2, 3]"), Some((Format::Json, None)));
+        assert_eq!(detect(toml), Some((Format::Toml, None)));
+        assert_eq!(detect("[[servers]]\nname = \"a\"\n"), Some((Format::Toml, None)));
+        assert_eq!(detect(yaml), Some((Format::Yaml, None)));
+        assert_eq!(detect("---\na: 1\n"), Some((Format::Yaml, None)));
+        assert_eq!(detect(tsv), Some((Format::Csv, Some('\t'))));
+        assert_eq!(detect(csv), Some((Format::Csv, Some(','))));
+        assert_eq!(detect("a;b\n1;2\n"), Some((Format::Csv, Some(';'))));
+
+        assert_eq!(detect("just some text\n"), None);
+        assert_eq!(detect("  \n"), None);
+
+        let mut gz = Vec::new();
+        Compression::Gzip.encoder(&mut gz).unwrap().write_all(ndjson.as_bytes()).unwrap();
+
+        // the end of the stream is cut off like in a head of a larger file
+        for head in [&gz[..], &gz[..gz.len() - 4]] {
+            let detection = detect_format(head).unwrap();
+
+            assert_eq!(detection.format, Format::Ndjson);
+            assert_eq!(detection.compression, Some(Compression::Gzip));
+        }
+    }
+}
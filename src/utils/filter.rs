@@ -0,0 +1,594 @@
+use regex::Regex;
+use std::{borrow::Cow, cmp::Ordering};
+
+use crate::utils::{
+    CtxResult, CtxResultErr, Records, Value, WriterStreams,
+    flatten::{PathSeg, get_path, parse_header},
+};
+
+/// Record filter parsed from an expression like `level == "error" && latency_ms > 500`.
+///
+/// Expressions support:
+/// - Field paths, nested with `.` and `[idx]` (e.g. `user.name`, `tags[0]`), keys with spaces are
+///   quoted with backticks (e.g. `` `first name` ``), missing fields are `null`
+/// - Literals: numbers, strings in double / single quotes, `true`, `false` and `null`
+/// - Comparisons: `==`, `!=`, `<`, `<=`, `>`, `>=`, numeric strings are compared as numbers
+/// - Regex matches: `=~` and `!~` with a string pattern (e.g. `email =~ "@example\.com$"`)
+/// - Boolean logic: `&&` / `and`, `||` / `or`, `!` / `not` and parentheses
+/// - Null checks: `field == null` and `field != null`, empty CSV cells are `null`
+///
+/// # Examples
+///
+/// ```
+/// use fiux::{Converter, Filter, Format};
+///
+/// let input = "{\"level\": \"error\", \"ms\": 900}\n{\"level\": \"info\", \"ms\": 5}\n";
+/// let mut output = Vec::new();
+///
+/// Converter::new(input.as_bytes(), Format::Ndjson, Format::Ndjson)
+///     .filter(Filter::parse(r#"level == "error" && ms > 500"#)?)
+///     .convert(&mut output)?;
+///
+/// assert_eq!(output, b"{\"level\":\"error\",\"ms\":900}\n");
+/// # Ok::<(), fiux::CtxResultErr>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct Filter {
+    expr: Expr,
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Lit(Value),
+    Path(Path),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Cmp(CmpOp, Box<Expr>, Box<Expr>),
+    Match(Box<Expr>, Regex, bool),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Debug)]
+struct Path {
+    text: String,
+    segs: Vec<PathSeg>,
+
+    /// Index of the matching column for table rows
+    col: Option<usize>,
+}
+
+static NULL: Value = Value::Null;
+
+impl Filter {
+    /// Parses a filter expression, invalid expressions are returned as errors with the column of
+    /// the invalid token.
+    pub fn parse(expr: &str) -> CtxResult<Self> {
+        let tokens = lex(expr)?;
+        let mut parser = Parser { tokens, pos: 0 };
+
+        let parsed = parser.or()?;
+
+        if let Some((tok, col)) = parser.tokens.get(parser.pos) {
+            return Err(syntax_err(&format!("Unexpected token: {}", tok.describe()), *col));
+        }
+
+        Ok(Self { expr: parsed })
+    }
+
+    /// Checks if a record matches the filter.
+    pub fn matches(&self, record: &Value) -> bool {
+        truthy(&eval(&self.expr, &|p: &Path| get_path(record, &p.segs)))
+    }
+
+    /// Resolves paths to table columns by their header names (e.g. `user.name`).
+    fn bind(&mut self, headers: &[String]) {
+        fn bind_expr(expr: &mut Expr, headers: &[String]) {
+            match expr {
+                Expr::Path(p) => p.col = headers.iter().position(|h| *h == p.text),
+                Expr::Not(e) | Expr::Match(e, ..) => bind_expr(e, headers),
+                Expr::And(a, b) | Expr::Or(a, b) | Expr::Cmp(_, a, b) => {
+                    bind_expr(a, headers);
+                    bind_expr(b, headers);
+                }
+                Expr::Lit(_) => {}
+            }
+        }
+
+        bind_expr(&mut self.expr, headers);
+    }
+
+    fn matches_row(&self, cells: &[Value]) -> bool {
+        let get = |p: &Path| match p.col.and_then(|i| cells.get(i)) {
+            Some(Value::String(s)) if s.is_empty() => Some(&NULL),
+            other => other,
+        };
+
+        truthy(&eval(&self.expr, &get))
+    }
+}
+
+/// Keeps only the records that match a filter, invalid records are kept so writers can log them.
+/// Top-level arrays in `Values` streams are filtered element by element, other values are
+/// filtered like records.
+pub fn filter_records(data: WriterStreams<Records>, filter: &Filter) -> WriterStreams<Records> {
+    let mut filter = filter.clone();
+
+    match data {
+        WriterStreams::Table { headers, iter } => {
+            filter.bind(&headers);
+
+            let iter = iter.filter(move |row| match row {
+                Ok(Value::Array(cells)) => filter.matches_row(cells),
+                _ => true,
+            });
+
+            WriterStreams::Table { headers, iter: Box::new(iter) }
+        }
+
+        WriterStreams::Values { iter } => {
+            let iter = iter.filter_map(move |item| match item {
+                Ok(Value::Array(arr)) => {
+                    Some(Ok(Value::Array(arr.into_iter().filter(|v| filter.matches(v)).collect())))
+                }
+                // other values are records themselves (e.g. concatenated JSON objects, YAML
+                // documents or a TOML file)
+                Ok(v) => filter.matches(&v).then_some(Ok(v)),
+                Err(err) => Some(Err(err)),
+            });
+
+            WriterStreams::Values { iter: Box::new(iter) }
+        }
+
+        WriterStreams::Ndjson { values } => {
+            let values = values.filter(move |item| match item {
+                Ok(v) => filter.matches(v),
+                Err(_) => true,
+            });
+
+            WriterStreams::Ndjson { values: Box::new(values) }
+        }
+    }
+}
+
+fn eval<'a>(expr: &'a Expr, get: &dyn Fn(&Path) -> Option<&'a Value>) -> Cow<'a, Value> {
+    let b = |v: bool| Cow::Owned(Value::Bool(v));
+
+    match expr {
+        Expr::Lit(v) => Cow::Borrowed(v),
+        Expr::Path(p) => Cow::Borrowed(get(p).unwrap_or(&NULL)),
+        Expr::Not(e) => b(!truthy(&eval(e, get))),
+        Expr::And(l, r) => b(truthy(&eval(l, get)) && truthy(&eval(r, get))),
+        Expr::Or(l, r) => b(truthy(&eval(l, get)) || truthy(&eval(r, get))),
+        Expr::Match(e, re, negate) => {
+            let v = eval(e, get);
+            b(!v.is_null() && re.is_match(&v.to_cell()) != *negate)
+        }
+        Expr::Cmp(op, l, r) => b(op.apply(&eval(l, get), &eval(r, get))),
+    }
+}
+
+impl CmpOp {
+    /// Gets a comparison operator from its symbol (e.g. `==`).
+    pub(crate) fn from_symbol(op: &str) -> Option<Self> {
+        match op {
+            "==" => Some(Self::Eq),
+            "!=" => Some(Self::Ne),
+            "<" => Some(Self::Lt),
+            "<=" => Some(Self::Le),
+            ">" => Some(Self::Gt),
+            ">=" => Some(Self::Ge),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn apply(self, l: &Value, r: &Value) -> bool {
+        match self {
+            Self::Eq => equals(l, r),
+            Self::Ne => !equals(l, r),
+            Self::Lt => compare(l, r) == Some(Ordering::Less),
+            Self::Le => matches!(compare(l, r), Some(Ordering::Less | Ordering::Equal)),
+            Self::Gt => compare(l, r) == Some(Ordering::Greater),
+            Self::Ge => matches!(compare(l, r), Some(Ordering::Greater | Ordering::Equal)),
+        }
+    }
+}
+
+/// Checks if a value counts as true, `null`, `false`, `""` and `"false"` (e.g. from CSV cells)
+/// don't.
+#[inline]
+pub(crate) fn truthy(v: &Value) -> bool {
+    match v {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::String(s) => !s.is_empty() && s != "false",
+        _ => true,
+    }
+}
+
+#[inline]
+pub(crate) fn as_number(v: &Value) -> Option<f64> {
+    match v {
+        Value::Int(i) => Some(*i as f64),
+        Value::Float(f) => Some(*f),
+        Value::Decimal(s) | Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+#[inline]
+fn is_number(v: &Value) -> bool {
+    matches!(v, Value::Int(_) | Value::Float(_) | Value::Decimal(_))
+}
+
+fn equals(l: &Value, r: &Value) -> bool {
+    match (l, r) {
+        (Value::Null, Value::Null) => true,
+        (Value::Null, _) | (_, Value::Null) => false,
+        (Value::Array(_), _) | (_, Value::Array(_)) | (Value::Map(_), _) | (_, Value::Map(_)) => {
+            l == r
+        }
+        _ => compare(l, r) == Some(Ordering::Equal),
+    }
+}
+
+/// Compares two values, numbers (and numeric strings compared with numbers) are compared
+/// numerically, booleans with booleans and everything else as strings.
+pub(crate) fn compare(l: &Value, r: &Value) -> Option<Ordering> {
+    match (l, r) {
+        (Value::Null, _) | (_, Value::Null) => None,
+        (Value::Array(_) | Value::Map(_), _) | (_, Value::Array(_) | Value::Map(_)) => None,
+        _ if is_number(l) || is_number(r) => as_number(l)?.partial_cmp(&as_number(r)?),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::String(s)) => s.parse::<bool>().ok().map(|b| a.cmp(&b)),
+        (Value::String(s), Value::Bool(b)) => s.parse::<bool>().ok().map(|a| a.cmp(b)),
+        (Value::Bool(_), _) | (_, Value::Bool(_)) => None,
+        _ => Some(l.to_cell().cmp(&r.to_cell())),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Path(String),
+    Lit(Value),
+    Op(&'static str),
+    LParen,
+    RParen,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Path(p) => format!("`{}`", p),
+            Token::Lit(v) => format!("`{}`", v.to_cell()),
+            Token::Op(op) => format!("`{}`", op),
+            Token::LParen => "`(`".to_string(),
+            Token::RParen => "`)`".to_string(),
+        }
+    }
+}
+
+#[inline]
+fn syntax_err(msg: &str, col: usize) -> CtxResultErr {
+    CtxResultErr::new("Invalid filter expression", format!("{} at column: {}", msg, col))
+}
+
+const OPS: [&str; 14] =
+    ["==", "!=", "<=", ">=", "=~", "!~", "&&", "||", "<", ">", "!", "and", "or", "not"];
+
+fn lex(src: &str) -> CtxResult<Vec<(Token, usize)>> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    let is_path_char = |c: char| c.is_alphanumeric() || matches!(c, '_' | '$' | '.' | '[' | ']');
+
+    while i < chars.len() {
+        let c = chars[i];
+        let col = i + 1;
+
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        if c == '(' || c == ')' {
+            tokens.push((if c == '(' { Token::LParen } else { Token::RParen }, col));
+            i += 1;
+            continue;
+        }
+
+        if c == '"' || c == '\'' || c == '`' {
+            let mut s = String::new();
+            i += 1;
+
+            loop {
+                match chars.get(i) {
+                    None => return Err(syntax_err("Unterminated string", col)),
+                    Some(&q) if q == c => break,
+                    Some('\\') if c != '`' => {
+                        i += 1;
+
+                        match chars.get(i) {
+                            Some('n') => s.push('\n'),
+                            Some('t') => s.push('\t'),
+                            Some('r') => s.push('\r'),
+                            // unknown escapes are kept for regexes (e.g. `\.`)
+                            Some(&e) if e == c || e == '\\' => s.push(e),
+                            Some(&e) => {
+                                s.push('\\');
+                                s.push(e);
+                            }
+                            None => return Err(syntax_err("Unterminated string", col)),
+                        }
+                    }
+                    Some(&ch) => s.push(ch),
+                }
+
+                i += 1;
+            }
+
+            i += 1;
+
+            let tok = if c == '`' {
+                // keys are kept as a single path segment
+                Token::Path(format!("`{}`", s))
+            } else {
+                Token::Lit(Value::String(s))
+            };
+
+            tokens.push((tok, col));
+            continue;
+        }
+
+        if c.is_ascii_digit()
+            || (c == '-' && chars.get(i + 1).is_some_and(|n| n.is_ascii_digit() || *n == '.'))
+        {
+            let start = i;
+            i += 1;
+
+            while chars.get(i).is_some_and(|n| {
+                n.is_ascii_digit()
+                    || matches!(n, '.' | 'e' | 'E')
+                    || (matches!(n, '-' | '+') && matches!(chars[i - 1], 'e' | 'E'))
+            }) {
+                i += 1;
+            }
+
+            let num: String = chars[start..i].iter().collect();
+
+            let lit = if let Ok(n) = num.parse::<i64>() {
+                Value::Int(n)
+            } else if let Ok(f) = num.parse::<f64>() {
+                Value::Float(f)
+            } else {
+                return Err(syntax_err(&format!("Invalid number: {}", num), col));
+            };
+
+            tokens.push((Token::Lit(lit), col));
+            continue;
+        }
+
+        if is_path_char(c) {
+            let start = i;
+
+            while chars.get(i).is_some_and(|n| is_path_char(*n)) {
+                i += 1;
+            }
+
+            let word: String = chars[start..i].iter().collect();
+
+            let tok = match word.as_str() {
+                "true" => Token::Lit(Value::Bool(true)),
+                "false" => Token::Lit(Value::Bool(false)),
+                "null" => Token::Lit(Value::Null),
+                "and" => Token::Op("&&"),
+                "or" => Token::Op("||"),
+                "not" => Token::Op("!"),
+                _ => Token::Path(word),
+            };
+
+            tokens.push((tok, col));
+            continue;
+        }
+
+        let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+
+        match OPS.iter().find(|op| !op.chars().all(char::is_alphabetic) && rest.starts_with(*op)) {
+            Some(op) => {
+                tokens.push((Token::Op(op), col));
+                i += op.len();
+            }
+            None => return Err(syntax_err(&format!("Unexpected character: `{}`", c), col)),
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(t, _)| t)
+    }
+
+    fn col(&self) -> usize {
+        self.tokens.get(self.pos).or(self.tokens.last()).map_or(1, |(_, c)| *c)
+    }
+
+    fn eat_op(&mut self, op: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Op(o)) if *o == op) {
+            self.pos += 1;
+            return true;
+        }
+
+        false
+    }
+
+    fn or(&mut self) -> CtxResult<Expr> {
+        let mut lhs = self.and()?;
+
+        while self.eat_op("||") {
+            lhs = Expr::Or(Box::new(lhs), Box::new(self.and()?));
+        }
+
+        Ok(lhs)
+    }
+
+    fn and(&mut self) -> CtxResult<Expr> {
+        let mut lhs = self.not()?;
+
+        while self.eat_op("&&") {
+            lhs = Expr::And(Box::new(lhs), Box::new(self.not()?));
+        }
+
+        Ok(lhs)
+    }
+
+    fn not(&mut self) -> CtxResult<Expr> {
+        if self.eat_op("!") {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+
+        self.cmp()
+    }
+
+    fn cmp(&mut self) -> CtxResult<Expr> {
+        let lhs = self.primary()?;
+
+        let op = match self.peek() {
+            Some(Token::Op(op)) if !matches!(*op, "&&" | "||" | "!") => *op,
+            _ => return Ok(lhs),
+        };
+
+        let col = self.col();
+        self.pos += 1;
+
+        let rhs = self.primary()?;
+
+        match CmpOp::from_symbol(op) {
+            Some(cmp) => Ok(Expr::Cmp(cmp, Box::new(lhs), Box::new(rhs))),
+            None => {
+                let Expr::Lit(Value::String(pattern)) = &rhs else {
+                    return Err(syntax_err(
+                        &format!("Expected a string pattern after `{}`", op),
+                        col,
+                    ));
+                };
+
+                let re = Regex::new(pattern)
+                    .map_err(|e| syntax_err(&format!("Invalid regex: {}", e), col))?;
+
+                Ok(Expr::Match(Box::new(lhs), re, op == "!~"))
+            }
+        }
+    }
+
+    fn primary(&mut self) -> CtxResult<Expr> {
+        let col = self.col();
+
+        let Some((tok, _)) = self.tokens.get(self.pos).cloned() else {
+            return Err(syntax_err("Unexpected end of expression", col));
+        };
+
+        self.pos += 1;
+
+        match tok {
+            Token::Lit(v) => Ok(Expr::Lit(v)),
+            Token::Path(text) => Ok(Expr::Path(parse_path(text))),
+            Token::LParen => {
+                let expr = self.or()?;
+
+                match self.peek() {
+                    Some(Token::RParen) => {
+                        self.pos += 1;
+                        Ok(expr)
+                    }
+                    _ => Err(syntax_err("Expected `)`", self.col())),
+                }
+            }
+            other => Err(syntax_err(&format!("Unexpected token: {}", other.describe()), col)),
+        }
+    }
+}
+
+fn parse_path(text: String) -> Path {
+    let text = match text.strip_prefix('`').and_then(|t| t.strip_suffix('`')) {
+        Some(key) => key.to_string(),
+        None => text.strip_prefix('.').unwrap_or(&text).to_string(),
+    };
+
+    let segs = if text.contains(['.', '[']) {
+        parse_header(&text, ".")
+    } else {
+        vec![PathSeg::Key(text.to_string())]
+    };
+
+    Path { text, segs, col: None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn check(expr: &str, record: serde_json::Value) -> bool {
+        Filter::parse(expr).unwrap().matches(&record.into())
+    }
+
+    #[test]
+    fn test_comparisons_and_logic() {
+        let rec = json!({"level": "error", "latency_ms": 620, "ok": false});
+
+        assert!(check(r#"level == "error" && latency_ms > 500"#, rec.clone()));
+        assert!(check("latency_ms >= 620 and not ok", rec.clone()));
+        assert!(check("(latency_ms < 10 || level != 'info') && !(ok == true)", rec.clone()));
+        assert!(!check("latency_ms <= 500 or ok", rec));
+    }
+
+    #[test]
+    fn test_nested_paths_and_nulls() {
+        let rec = json!({"user": {"name": "a", "tags": ["x", "y"]}, "n": null});
+
+        assert!(check(r#"user.name == "a" && user.tags[1] == "y""#, rec.clone()));
+        assert!(check("n == null && missing == null && user != null", rec.clone()));
+        assert!(!check("user.tags[5] != null", rec));
+    }
+
+    #[test]
+    fn test_regex_and_numeric_strings() {
+        let rec = json!({"email": "a@example.com", "ms": "620"});
+
+        assert!(check(r#"email =~ "@example\.com$" && email !~ "^b""#, rec.clone()));
+        assert!(check("ms > 500 && ms == 620.0", rec));
+    }
+
+    #[test]
+    fn test_table_rows() {
+        let mut f = Filter::parse("`user name` == 'a' && age == null").unwrap();
+        f.bind(&["user name".to_string(), "age".to_string()]);
+
+        assert!(f.matches_row(&[Value::from("a"), Value::from("")]));
+        assert!(!f.matches_row(&[Value::from("b"), Value::from("")]));
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        for expr in ["a ==", "(a == 1", "a == 1 b", "a =~ 1", "a =~ '('", "a # 1", "'abc"] {
+            assert!(Filter::parse(expr).is_err(), "{}", expr);
+        }
+    }
+}
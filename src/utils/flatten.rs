@@ -0,0 +1,416 @@
+use crate::utils::{Map, Value};
+
+/// How arrays are written when flattening nested values into columns.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ArrayMode {
+    /// One column per element with indexed keys (e.g. `tags[0]`, `tags[1]`)
+    #[default]
+    Index,
+
+    /// One column per array with the whole array encoded as JSON
+    Json,
+}
+
+/// Options for flattening nested values into table columns.
+#[derive(Clone, Debug)]
+pub struct FlattenOpts {
+    /// Separator for joining nested object keys (e.g. `.` for `user.address.city`)
+    pub separator: String,
+
+    /// How arrays are flattened
+    pub arrays: ArrayMode,
+
+    /// Number of records to read for discovering headers
+    pub sample_size: usize,
+
+    /// Discover headers from all records by spilling them to a temporary file first
+    pub two_pass: bool,
+
+    /// Rebuild nested values from flattened table headers instead of writing them as flat keys
+    pub unflatten: bool,
+}
+
+impl Default for FlattenOpts {
+    fn default() -> Self {
+        Self {
+            separator: ".".to_string(),
+            arrays: ArrayMode::Index,
+            sample_size: 1000,
+            two_pass: false,
+            unflatten: false,
+        }
+    }
+}
+
+/// Flattens a value into `(column, cell)` pairs, scalars that aren't in an object are written into
+/// a `value` column.
+#[inline]
+pub fn flatten(value: &Value, opts: &FlattenOpts, out: &mut Vec<(String, String)>) {
+    match value {
+        Value::Map(map) if map.is_empty() => {}
+        Value::Map(_) | Value::Array(_) => flatten_into(value, String::new(), opts, out),
+        _ => flatten_into(value, "value".to_string(), opts, out),
+    }
+}
+
+fn flatten_into(
+    value: &Value,
+    prefix: String,
+    opts: &FlattenOpts,
+    out: &mut Vec<(String, String)>,
+) {
+    match value {
+        Value::Map(map) if !map.is_empty() => {
+            for (k, v) in map {
+                let key = if prefix.is_empty() {
+                    k.to_string()
+                } else {
+                    format!("{}{}{}", prefix, opts.separator, k)
+                };
+
+                flatten_into(v, key, opts, out);
+            }
+        }
+
+        Value::Array(arr) if !arr.is_empty() && opts.arrays == ArrayMode::Index => {
+            for (idx, v) in arr.iter().enumerate() {
+                flatten_into(v, format!("{}[{}]", prefix, idx), opts, out);
+            }
+        }
+
+        _ => out.push((prefix, value.to_cell().into_owned())),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum PathSeg {
+    Key(String),
+    Index(usize),
+}
+
+/// Gets the value at a path in a nested value.
+pub(crate) fn get_path<'a>(value: &'a Value, path: &[PathSeg]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |v, seg| match (seg, v) {
+        (PathSeg::Key(k), Value::Map(map)) => map.get(k),
+        (PathSeg::Index(i), Value::Array(arr)) => arr.get(*i),
+        _ => None,
+    })
+}
+
+/// Rebuilds nested values from flattened table headers (e.g. `user.name`, `user.tags[0]`), the
+/// inverse of [`flatten`].
+pub struct Unflattener {
+    headers: Vec<String>,
+    paths: Vec<Vec<PathSeg>>,
+    typed: bool,
+    null_literal: bool,
+}
+
+impl Unflattener {
+    /// Creates an unflattener for table headers, `typed` is for rows with cells that were already
+    /// typed (see [`crate::utils::type_table`]) so only JSON arrays / objects in string cells are
+    /// parsed.
+    pub fn new(headers: &[String], separator: &str, typed: bool) -> Self {
+        // a row can't fill more array elements than it has cells
+        let paths = headers.iter().map(|h| parse_path(h, separator, headers.len())).collect();
+
+        Self { headers: headers.to_vec(), paths, typed, null_literal: true }
+    }
+
+    /// Keeps untyped `null` cells as strings like flat TOML rows do, since TOML has no null.
+    pub fn without_null_literal(mut self) -> Self {
+        self.null_literal = false;
+        self
+    }
+
+    /// Builds a nested object from a row, empty cells in nested columns are skipped so missing
+    /// keys and array elements stay missing, cells that are already typed are kept as they are.
+    pub fn unflatten(&self, row: &[Value]) -> Value {
+        let mut root = Value::Map(Map::with_capacity(self.paths.len()));
+
+        for ((header, path), cell) in self.headers.iter().zip(&self.paths).zip(row) {
+            let value = match cell {
+                Value::String(s) if s.is_empty() && path.len() > 1 => continue,
+                Value::Null if path.len() > 1 => continue,
+                Value::String(s) if !self.null_literal && s == "null" => cell.clone(),
+                Value::String(s) => parse_cell(s.as_bytes(), !self.typed),
+                Value::Bytes(b) => parse_cell(b, !self.typed),
+                other => other.clone(),
+            };
+
+            if let Err(value) = insert(&mut root, path, value)
+                && let Value::Map(map) = &mut root
+            {
+                // paths that conflict with earlier columns (e.g. `a` and `a.b`) are kept flat
+                map.insert(header.to_string(), value);
+            }
+        }
+
+        root
+    }
+}
+
+/// Largest number of array elements a path of `--select` / `--exclude` / `--rename` can address.
+pub(crate) const MAX_INDEX: usize = 1 << 16;
+
+pub(crate) fn parse_header(header: &str, separator: &str) -> Vec<PathSeg> {
+    parse_path(header, separator, usize::MAX)
+}
+
+/// Parses a header into a path like [`parse_header`] for inserting values, indexes of at least
+/// `width` are kept in the key (e.g. `tags[4000000000]`) so a header can't allocate a huge array.
+pub(crate) fn parse_path(header: &str, separator: &str, width: usize) -> Vec<PathSeg> {
+    let mut path = Vec::new();
+
+    let keys: Vec<&str> =
+        if separator.is_empty() { vec![header] } else { header.split(separator).collect() };
+
+    for key in keys {
+        // split trailing indexes from keys, e.g. `tags[0][1]` into `tags`, `0`, `1`
+        let mut rest = key;
+        let mut indexes = Vec::new();
+
+        while let Some(stripped) = rest.strip_suffix(']')
+            && let Some(open) = stripped.rfind('[')
+            && let Ok(idx) = stripped[open + 1..].parse::<usize>()
+            && idx < width
+        {
+            indexes.push(idx);
+            rest = &stripped[..open];
+        }
+
+        if rest.is_empty() && !path.is_empty() {
+            // keys like `a.[0]` are treated as indexes on the previous key
+        } else if rest.is_empty() {
+            return vec![PathSeg::Key(header.to_string())];
+        } else {
+            path.push(PathSeg::Key(rest.to_string()));
+        }
+
+        path.extend(indexes.into_iter().rev().map(PathSeg::Index));
+    }
+
+    if path.is_empty() {
+        path.push(PathSeg::Key(header.to_string()));
+    }
+
+    path
+}
+
+pub(crate) fn insert(target: &mut Value, path: &[PathSeg], value: Value) -> Result<(), Value> {
+    let Some((seg, rest)) = path.split_first() else {
+        return if target.is_null() {
+            *target = value;
+            Ok(())
+        } else {
+            Err(value)
+        };
+    };
+
+    match seg {
+        PathSeg::Key(k) => {
+            if target.is_null() {
+                *target = Value::Map(Map::new());
+            }
+
+            match target {
+                Value::Map(map) => {
+                    insert(map.entry(k.to_string()).or_insert(Value::Null), rest, value)
+                }
+                _ => Err(value),
+            }
+        }
+
+        PathSeg::Index(idx) => {
+            if target.is_null() {
+                *target = Value::Array(Vec::new());
+            }
+
+            match target {
+                Value::Array(arr) => {
+                    if arr.len() <= *idx {
+                        let Some(len) = idx.checked_add(1) else {
+                            return Err(value);
+                        };
+
+                        arr.resize(len, Value::Null);
+                    }
+
+                    insert(&mut arr[*idx], rest, value)
+                }
+                _ => Err(value),
+            }
+        }
+    }
+}
+
+/// Parses a table cell into a value, JSON arrays / objects are always parsed and `true`, `false`
+/// and `null` are only parsed with `literals`.
+#[inline]
+pub fn parse_cell(cell: &[u8], literals: bool) -> Value {
+    match cell {
+        b"true" if literals => return Value::Bool(true),
+        b"false" if literals => return Value::Bool(false),
+        b"null" if literals => return Value::Null,
+        [b'[', .., b']'] | [b'{', .., b'}'] => {
+            if let Ok(val) = serde_json::from_slice::<Value>(cell) {
+                return val;
+            }
+        }
+        _ => {}
+    }
+
+    match std::str::from_utf8(cell) {
+        Ok(s) => Value::String(s.to_string()),
+        Err(_) => Value::Bytes(cell.to_vec()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::ColumnType;
+    use serde_json::json;
+
+    fn flat(value: serde_json::Value, opts: &FlattenOpts) -> Vec<(String, String)> {
+        let mut out = Vec::new();
+        flatten(&value.into(), opts, &mut out);
+        out
+    }
+
+    fn row(cells: &[&str]) -> Vec<Value> {
+        cells.iter().map(|c| Value::from(*c)).collect()
+    }
+
+    #[test]
+    fn test_flatten_nested_objects() {
+        let out = flat(
+            json!({"n": 1, "user": {"address": {"city": "b"}, "name": "a"}}),
+            &FlattenOpts::default(),
+        );
+
+        assert_eq!(
+            out,
+            vec![
+                ("n".to_string(), "1".to_string()),
+                ("user.address.city".to_string(), "b".to_string()),
+                ("user.name".to_string(), "a".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_flatten_arrays_indexed() {
+        let out = flat(json!({"tags": ["x", {"y": null}]}), &FlattenOpts::default());
+
+        assert_eq!(
+            out,
+            vec![
+                ("tags[0]".to_string(), "x".to_string()),
+                ("tags[1].y".to_string(), String::new())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_flatten_arrays_json_and_separator() {
+        let opts = FlattenOpts {
+            separator: "_".to_string(),
+            arrays: ArrayMode::Json,
+            ..Default::default()
+        };
+        let out = flat(json!({"a": {"b": [1, 2]}}), &opts);
+
+        assert_eq!(out, vec![("a_b".to_string(), "[1,2]".to_string())]);
+    }
+
+    #[test]
+    fn test_unflatten_nested_headers() {
+        let headers: Vec<String> = ["id", "user.name", "user.tags[0]", "user.tags[1]", "m[0][1]"]
+            .map(String::from)
+            .to_vec();
+
+        let u = Unflattener::new(&headers, ".", false);
+        let out = u.unflatten(&row(&["1", "a", "x", "", "true"]));
+
+        assert_eq!(
+            out,
+            json!({"id": "1", "user": {"name": "a", "tags": ["x"]}, "m": [[null, true]]}).into()
+        );
+    }
+
+    #[test]
+    fn test_unflatten_untyped_literals() {
+        let headers: Vec<String> = ["a", "b", "c", "d.e"].map(String::from).to_vec();
+        let cells = row(&["true", "false", "null", "null"]);
+
+        // the same literals as flat rows, see the table branches of the writers
+        let u = Unflattener::new(&headers, ".", false);
+        assert_eq!(
+            u.unflatten(&cells),
+            json!({"a": true, "b": false, "c": null, "d": {"e": null}}).into()
+        );
+
+        let u = Unflattener::new(&headers, ".", false).without_null_literal();
+        assert_eq!(
+            u.unflatten(&cells),
+            json!({"a": true, "b": false, "c": "null", "d": {"e": "null"}}).into()
+        );
+
+        let u = Unflattener::new(&headers, ".", true);
+        assert_eq!(
+            u.unflatten(&cells),
+            json!({"a": "true", "b": "false", "c": "null", "d": {"e": "null"}}).into()
+        );
+    }
+
+    #[test]
+    fn test_unflatten_conflicting_headers() {
+        let headers: Vec<String> = ["a", "a.b"].map(String::from).to_vec();
+
+        let u = Unflattener::new(&headers, ".", false);
+        let out = u.unflatten(&row(&["1", "2"]));
+
+        assert_eq!(out, json!({"a": "1", "a.b": "2"}).into());
+    }
+
+    #[test]
+    fn test_unflatten_huge_indexes_stay_flat() {
+        let headers: Vec<String> = ["tags[18446744073709551615]", "tags[4000000000]", "tags[1]"]
+            .map(String::from)
+            .to_vec();
+
+        let u = Unflattener::new(&headers, ".", false);
+        let out = u.unflatten(&row(&["a", "b", "c"]));
+
+        assert_eq!(
+            out,
+            json!({"tags[18446744073709551615]": "a", "tags[4000000000]": "b", "tags": [null, "c"]})
+                .into()
+        );
+
+        let mut arr = Value::Array(Vec::new());
+        assert!(insert(&mut arr, &[PathSeg::Index(usize::MAX)], Value::Null).is_err());
+    }
+
+    #[test]
+    fn test_flatten_roundtrip() {
+        let value = json!({"a": {"b": [1, {"c": "x"}]}, "d": false});
+        let out = flat(value.clone(), &FlattenOpts::default());
+
+        let (headers, cells): (Vec<String>, Vec<String>) = out.into_iter().unzip();
+        let u = Unflattener::new(&headers, ".", true);
+
+        let cells: Vec<Value> =
+            cells.iter().map(|c| ColumnType::infer(c).convert(c).unwrap_or_default()).collect();
+
+        assert_eq!(u.unflatten(&cells), value.into());
+    }
+
+    #[test]
+    fn test_flatten_scalar() {
+        let out = flat(json!(true), &FlattenOpts::default());
+
+        assert_eq!(out, vec![("value".to_string(), "true".to_string())]);
+    }
+}
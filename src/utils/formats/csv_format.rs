@@ -0,0 +1,90 @@
+use std::io::{Read, Write};
+
+use crate::utils::{
+    ConvertOptions, CtxResult, FileFormat, FormatDecoder, FormatEncoder, FormatValidator, Records,
+    WriterStreams, csv_decoder, csv_reader, csv_validator, csv_writer, input_reader::Mapped,
+    type_table,
+};
+
+/// Built-in CSV format, also used for CSV-like formats with custom delimiters (e.g. TSV, PSV).
+pub struct CsvFormat;
+
+impl FormatDecoder for CsvFormat {
+    fn decode(
+        &self,
+        reader: Box<dyn Read>,
+        opts: &ConvertOptions,
+    ) -> CtxResult<WriterStreams<Records>> {
+        let data = if opts.threads != 1 {
+            csv_decoder::csv_decoder_parallel(reader, opts.input_delimiter, opts.threads)?.boxed()
+        } else {
+            let rdr = csv_reader::csv_reader(reader, opts.input_delimiter)?;
+            csv_decoder::csv_decoder(rdr)?.boxed()
+        };
+
+        if opts.is_typed() { type_table(data, opts.parse_numbers, &opts.types) } else { Ok(data) }
+    }
+
+    fn decode_mapped(
+        &self,
+        input: Mapped,
+        opts: &ConvertOptions,
+    ) -> CtxResult<WriterStreams<Records>> {
+        let data = csv_decoder::csv_decoder_mapped(input, opts.input_delimiter, opts.threads)?;
+
+        if opts.is_typed() { type_table(data, opts.parse_numbers, &opts.types) } else { Ok(data) }
+    }
+}
+
+impl FormatEncoder for CsvFormat {
+    fn encode(
+        &self,
+        data: WriterStreams<Records>,
+        output: &mut dyn Write,
+        opts: &ConvertOptions,
+    ) -> CtxResult<()> {
+        if opts.threads != 1 {
+            return csv_writer::csv_writer_parallel(
+                data,
+                output,
+                opts.output_delimiter,
+                &opts.flatten,
+                opts.threads,
+            );
+        }
+
+        csv_writer::csv_writer(data, output, opts.output_delimiter, &opts.flatten)
+    }
+}
+
+impl FormatValidator for CsvFormat {
+    fn validate(&self, reader: &mut dyn Read, opts: &ConvertOptions) -> CtxResult<()> {
+        csv_validator::validate_csv(reader, opts.input_delimiter)
+    }
+}
+
+impl FileFormat for CsvFormat {
+    fn name(&self) -> &str {
+        "csv"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["csv"]
+    }
+
+    fn description(&self) -> &str {
+        "Comma-separated values, nested values are flattened into columns"
+    }
+
+    fn decoder(&self) -> Option<&dyn FormatDecoder> {
+        Some(self)
+    }
+
+    fn encoder(&self) -> Option<&dyn FormatEncoder> {
+        Some(self)
+    }
+
+    fn validator(&self) -> Option<&dyn FormatValidator> {
+        Some(self)
+    }
+}
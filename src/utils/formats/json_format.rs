@@ -0,0 +1,91 @@
+use std::io::{BufRead, Read, Write};
+
+use crate::utils::{
+    ConvertOptions, CtxResult, FileFormat, FormatDecoder, FormatEncoder, FormatValidator, Records,
+    WriterStreams, input_reader::Mapped, json_array_decoder, json_decoder, json_reader,
+    json_validator, write_json,
+};
+
+/// Built-in JSON format, top-level arrays and arrays at `--json-path` are streamed element by
+/// element.
+pub struct JsonFormat;
+
+impl FormatDecoder for JsonFormat {
+    fn decode(
+        &self,
+        reader: Box<dyn Read>,
+        opts: &ConvertOptions,
+    ) -> CtxResult<WriterStreams<Records>> {
+        decode(json_reader::json_reader(reader), opts)
+    }
+
+    fn decode_mapped(
+        &self,
+        input: Mapped,
+        opts: &ConvertOptions,
+    ) -> CtxResult<WriterStreams<Records>> {
+        decode(input, opts)
+    }
+}
+
+/// Values and array elements are parsed one by one from the chunks of the reader, memory-mapped
+/// input is one chunk so they're sliced from the mapping.
+fn decode<R: BufRead + 'static>(
+    mut reader: R,
+    opts: &ConvertOptions,
+) -> CtxResult<WriterStreams<Records>> {
+    if opts.json_path.is_some() || json_array_decoder::is_json_array(&mut reader)? {
+        let path = opts.json_path.as_deref().unwrap_or(".");
+
+        Ok(json_array_decoder::json_array_decoder(reader, path)?.boxed())
+    } else {
+        Ok(json_decoder::json_decoder(reader)?.boxed())
+    }
+}
+
+impl FormatEncoder for JsonFormat {
+    fn encode(
+        &self,
+        data: WriterStreams<Records>,
+        output: &mut dyn Write,
+        opts: &ConvertOptions,
+    ) -> CtxResult<()> {
+        write_json::write_json(data, output, opts.is_typed(), &opts.flatten)
+    }
+}
+
+impl FormatValidator for JsonFormat {
+    fn validate(&self, reader: &mut dyn Read, _opts: &ConvertOptions) -> CtxResult<()> {
+        json_validator::validate_json(reader)
+    }
+
+    fn validate_mapped(&self, input: &[u8], _opts: &ConvertOptions) -> CtxResult<()> {
+        json_validator::validate_json_slice(input)
+    }
+}
+
+impl FileFormat for JsonFormat {
+    fn name(&self) -> &str {
+        "json"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["json"]
+    }
+
+    fn description(&self) -> &str {
+        "JSON values, arrays are streamed element by element"
+    }
+
+    fn decoder(&self) -> Option<&dyn FormatDecoder> {
+        Some(self)
+    }
+
+    fn encoder(&self) -> Option<&dyn FormatEncoder> {
+        Some(self)
+    }
+
+    fn validator(&self) -> Option<&dyn FormatValidator> {
+        Some(self)
+    }
+}
@@ -0,0 +1,6 @@
+pub mod csv_format;
+pub mod json_format;
+pub mod ndjson_format;
+pub mod registry;
+pub mod toml_format;
+pub mod yaml_format;
@@ -0,0 +1,85 @@
+use std::io::{Read, Write};
+
+use crate::utils::{
+    ConvertOptions, CtxResult, FileFormat, FormatDecoder, FormatEncoder, FormatValidator, Records,
+    WriterStreams, input_reader::Mapped, ndjson_decoder, ndjson_reader, ndjson_validator,
+    ndjson_writer,
+};
+
+/// Built-in newline-delimited JSON format.
+pub struct NdjsonFormat;
+
+impl FormatDecoder for NdjsonFormat {
+    fn decode(
+        &self,
+        reader: Box<dyn Read>,
+        opts: &ConvertOptions,
+    ) -> CtxResult<WriterStreams<Records>> {
+        if opts.threads != 1 {
+            return Ok(ndjson_decoder::ndjson_decoder_parallel(reader, opts.threads)?.boxed());
+        }
+
+        Ok(ndjson_decoder::ndjson_decoder(ndjson_reader::ndjson_reader(reader))?.boxed())
+    }
+
+    fn decode_mapped(
+        &self,
+        input: Mapped,
+        opts: &ConvertOptions,
+    ) -> CtxResult<WriterStreams<Records>> {
+        ndjson_decoder::ndjson_decoder_mapped(input, opts.threads)
+    }
+}
+
+impl FormatEncoder for NdjsonFormat {
+    fn encode(
+        &self,
+        data: WriterStreams<Records>,
+        output: &mut dyn Write,
+        opts: &ConvertOptions,
+    ) -> CtxResult<()> {
+        if opts.threads != 1 {
+            return ndjson_writer::ndjson_writer_parallel(
+                data,
+                output,
+                opts.is_typed(),
+                &opts.flatten,
+                opts.threads,
+            );
+        }
+
+        ndjson_writer::ndjson_writer(data, output, opts.is_typed(), &opts.flatten)
+    }
+}
+
+impl FormatValidator for NdjsonFormat {
+    fn validate(&self, reader: &mut dyn Read, _opts: &ConvertOptions) -> CtxResult<()> {
+        ndjson_validator::validate_ndjson(reader)
+    }
+}
+
+impl FileFormat for NdjsonFormat {
+    fn name(&self) -> &str {
+        "ndjson"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ndjson", "jsonl"]
+    }
+
+    fn description(&self) -> &str {
+        "Newline-delimited JSON, one value per line"
+    }
+
+    fn decoder(&self) -> Option<&dyn FormatDecoder> {
+        Some(self)
+    }
+
+    fn encoder(&self) -> Option<&dyn FormatEncoder> {
+        Some(self)
+    }
+
+    fn validator(&self) -> Option<&dyn FormatValidator> {
+        Some(self)
+    }
+}
@@ -0,0 +1,277 @@
+use std::{
+    io::{Read, Write},
+    path::Path,
+    sync::{Arc, LazyLock, RwLock},
+};
+
+use crate::utils::{
+    ConvertOptions, CtxResult, Records, WriterStreams, format_extension, formats::*,
+    input_reader::Mapped,
+};
+
+/// Reads a source into a stream of records.
+pub trait FormatDecoder: Send + Sync {
+    fn decode(
+        &self,
+        reader: Box<dyn Read>,
+        opts: &ConvertOptions,
+    ) -> CtxResult<WriterStreams<Records>>;
+
+    /// Decodes a memory-mapped file, formats that can slice records from the mapping override
+    /// this to avoid copying the file, it's read like any other source by default.
+    fn decode_mapped(
+        &self,
+        input: Mapped,
+        opts: &ConvertOptions,
+    ) -> CtxResult<WriterStreams<Records>> {
+        self.decode(Box::new(input), opts)
+    }
+}
+
+/// Writes a stream of records into an output.
+pub trait FormatEncoder: Send + Sync {
+    fn encode(
+        &self,
+        data: WriterStreams<Records>,
+        output: &mut dyn Write,
+        opts: &ConvertOptions,
+    ) -> CtxResult<()>;
+}
+
+/// Validates a source, logging every invalid item.
+pub trait FormatValidator: Send + Sync {
+    fn validate(&self, reader: &mut dyn Read, opts: &ConvertOptions) -> CtxResult<()>;
+
+    /// Validates the bytes of a memory-mapped file, they're read like any other source by
+    /// default.
+    fn validate_mapped(&self, mut input: &[u8], opts: &ConvertOptions) -> CtxResult<()> {
+        self.validate(&mut input, opts)
+    }
+}
+
+/// A self-describing file format, with optional support for decoding, encoding and validation.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::{Read, Write};
+/// use fiux::*;
+///
+/// struct Lines;
+///
+/// impl FormatEncoder for Lines {
+///     fn encode(
+///         &self,
+///         data: WriterStreams<Records>,
+///         output: &mut dyn Write,
+///         _opts: &ConvertOptions,
+///     ) -> CtxResult<()> {
+///         let (WriterStreams::Values { iter } | WriterStreams::Ndjson { values: iter }) = data
+///         else {
+///             return Ok(());
+///         };
+///
+///         for item in iter {
+///             writeln!(output, "{}", serde_json::to_string(&item?).context("Invalid record")?)
+///                 .context("Failed to write record")?;
+///         }
+///
+///         Ok(())
+///     }
+/// }
+///
+/// impl FileFormat for Lines {
+///     fn name(&self) -> &str {
+///         "lines"
+///     }
+///
+///     fn extensions(&self) -> &[&str] {
+///         &["lines"]
+///     }
+///
+///     fn encoder(&self) -> Option<&dyn FormatEncoder> {
+///         Some(self)
+///     }
+/// }
+///
+/// register_format(Lines);
+///
+/// let mut out = Vec::new();
+/// Converter::new("{\"a\": 1}\n".as_bytes(), Format::Ndjson, find_format("lines").unwrap())
+///     .convert(&mut out)?;
+///
+/// assert_eq!(out, b"{\"a\":1}\n");
+/// # Ok::<(), CtxResultErr>(())
+/// ```
+pub trait FileFormat: Send + Sync {
+    /// Unique name of the format used by `--from` / `--to` (e.g. `json`)
+    fn name(&self) -> &str;
+
+    /// File extensions of the format without the leading dot (e.g. `yaml`, `yml`)
+    fn extensions(&self) -> &[&str];
+
+    fn description(&self) -> &str {
+        ""
+    }
+
+    fn decoder(&self) -> Option<&dyn FormatDecoder> {
+        None
+    }
+
+    fn encoder(&self) -> Option<&dyn FormatEncoder> {
+        None
+    }
+
+    fn validator(&self) -> Option<&dyn FormatValidator> {
+        None
+    }
+}
+
+/// Formats keyed by name and extensions, formats registered later override earlier ones with the
+/// same name or extension.
+pub struct Registry {
+    formats: Vec<Arc<dyn FileFormat>>,
+}
+
+impl Registry {
+    /// Creates a registry without any formats.
+    pub fn empty() -> Self {
+        Self { formats: Vec::new() }
+    }
+
+    /// Creates a registry with fiux's built-in formats.
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::empty();
+
+        registry.register(json_format::JsonFormat);
+        registry.register(ndjson_format::NdjsonFormat);
+        registry.register(toml_format::TomlFormat);
+        registry.register(csv_format::CsvFormat);
+        registry.register(yaml_format::YamlFormat);
+
+        registry
+    }
+
+    pub fn register(&mut self, format: impl FileFormat + 'static) {
+        self.formats.push(Arc::new(format));
+    }
+
+    /// Finds a format by its name or one of its extensions, case-insensitive.
+    pub fn find(&self, name: &str) -> Option<Arc<dyn FileFormat>> {
+        let by_name = self.formats.iter().rev().find(|f| f.name().eq_ignore_ascii_case(name));
+
+        by_name
+            .or_else(|| {
+                self.formats
+                    .iter()
+                    .rev()
+                    .find(|f| f.extensions().iter().any(|e| e.eq_ignore_ascii_case(name)))
+            })
+            .cloned()
+    }
+
+    /// Finds a format by a file's extension, skipping a compression extension.
+    pub fn find_by_path(&self, path: &Path) -> Option<Arc<dyn FileFormat>> {
+        self.find(&format_extension(path)?.to_string_lossy())
+    }
+
+    /// Lists formats in order of registration, skipping overridden formats.
+    pub fn formats(&self) -> Vec<Arc<dyn FileFormat>> {
+        let mut formats: Vec<Arc<dyn FileFormat>> = Vec::with_capacity(self.formats.len());
+
+        for (idx, format) in self.formats.iter().enumerate() {
+            let is_overridden = self.formats[idx + 1..]
+                .iter()
+                .any(|f| f.name().eq_ignore_ascii_case(format.name()));
+
+            if !is_overridden {
+                formats.push(Arc::clone(format));
+            }
+        }
+
+        formats
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::with_builtins()
+    }
+}
+
+static REGISTRY: LazyLock<RwLock<Registry>> =
+    LazyLock::new(|| RwLock::new(Registry::with_builtins()));
+
+/// Registers a format in the global registry used by [`crate::Converter`] and the CLI.
+pub fn register_format(format: impl FileFormat + 'static) {
+    match REGISTRY.write() {
+        Ok(mut registry) => registry.register(format),
+        Err(poisoned) => poisoned.into_inner().register(format),
+    }
+}
+
+/// Finds a format in the global registry by its name or one of its extensions.
+pub fn find_format(name: &str) -> Option<Arc<dyn FileFormat>> {
+    match REGISTRY.read() {
+        Ok(registry) => registry.find(name),
+        Err(poisoned) => poisoned.into_inner().find(name),
+    }
+}
+
+/// Finds a format in the global registry by a file's extension.
+pub fn find_format_by_path(path: &Path) -> Option<Arc<dyn FileFormat>> {
+    match REGISTRY.read() {
+        Ok(registry) => registry.find_by_path(path),
+        Err(poisoned) => poisoned.into_inner().find_by_path(path),
+    }
+}
+
+/// Lists the formats in the global registry.
+pub fn formats() -> Vec<Arc<dyn FileFormat>> {
+    match REGISTRY.read() {
+        Ok(registry) => registry.formats(),
+        Err(poisoned) => poisoned.into_inner().formats(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Custom(&'static str);
+
+    impl FileFormat for Custom {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn extensions(&self) -> &[&str] {
+            &["yml", "cst"]
+        }
+    }
+
+    #[test]
+    fn test_registry_find_by_name_and_extension() {
+        let registry = Registry::with_builtins();
+
+        assert_eq!(registry.find("YAML").map(|f| f.name().to_string()), Some("yaml".into()));
+        assert_eq!(registry.find("jsonl").map(|f| f.name().to_string()), Some("ndjson".into()));
+        assert_eq!(
+            registry.find_by_path(Path::new("a/b.yml")).map(|f| f.name().to_string()),
+            Some("yaml".into())
+        );
+        assert!(registry.find("xml").is_none());
+    }
+
+    #[test]
+    fn test_registry_later_formats_override() {
+        let mut registry = Registry::with_builtins();
+        registry.register(Custom("yaml"));
+
+        let yaml = registry.find("yml").unwrap();
+        assert!(yaml.decoder().is_none());
+
+        let names: Vec<String> = registry.formats().iter().map(|f| f.name().to_string()).collect();
+        assert_eq!(names, ["json", "ndjson", "toml", "csv", "yaml"]);
+    }
+}
@@ -0,0 +1,74 @@
+use std::io::{Read, Write};
+
+use crate::utils::{
+    ConvertOptions, CtxResult, FileFormat, FormatDecoder, FormatEncoder, FormatValidator, Records,
+    WriterStreams, input_reader::Mapped, toml_decoder, toml_reader, toml_validator, toml_writer,
+};
+
+/// Built-in TOML format, input is loaded into memory since TOML can't be streamed.
+pub struct TomlFormat;
+
+impl FormatDecoder for TomlFormat {
+    fn decode(
+        &self,
+        reader: Box<dyn Read>,
+        _opts: &ConvertOptions,
+    ) -> CtxResult<WriterStreams<Records>> {
+        Ok(toml_decoder::toml_decoder(toml_reader::toml_reader(reader)?)?.boxed())
+    }
+
+    fn decode_mapped(
+        &self,
+        input: Mapped,
+        _opts: &ConvertOptions,
+    ) -> CtxResult<WriterStreams<Records>> {
+        Ok(toml_decoder::toml_decoder(input)?.boxed())
+    }
+}
+
+impl FormatEncoder for TomlFormat {
+    fn encode(
+        &self,
+        data: WriterStreams<Records>,
+        output: &mut dyn Write,
+        opts: &ConvertOptions,
+    ) -> CtxResult<()> {
+        toml_writer::toml_writer(data, output, opts.is_typed(), &opts.flatten)
+    }
+}
+
+impl FormatValidator for TomlFormat {
+    fn validate(&self, reader: &mut dyn Read, _opts: &ConvertOptions) -> CtxResult<()> {
+        toml_validator::validate_toml(reader)
+    }
+
+    fn validate_mapped(&self, input: &[u8], _opts: &ConvertOptions) -> CtxResult<()> {
+        toml_validator::validate_toml_slice(input)
+    }
+}
+
+impl FileFormat for TomlFormat {
+    fn name(&self) -> &str {
+        "toml"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["toml"]
+    }
+
+    fn description(&self) -> &str {
+        "TOML documents, loaded into memory"
+    }
+
+    fn decoder(&self) -> Option<&dyn FormatDecoder> {
+        Some(self)
+    }
+
+    fn encoder(&self) -> Option<&dyn FormatEncoder> {
+        Some(self)
+    }
+
+    fn validator(&self) -> Option<&dyn FormatValidator> {
+        Some(self)
+    }
+}
@@ -0,0 +1,62 @@
+use std::io::{Read, Write};
+
+use crate::utils::{
+    ConvertOptions, CtxResult, FileFormat, FormatDecoder, FormatEncoder, FormatValidator, Records,
+    WriterStreams, yaml_decoder, yaml_reader, yaml_validator, yaml_writer,
+};
+
+/// Built-in YAML format, multi-document input is streamed document by document.
+pub struct YamlFormat;
+
+impl FormatDecoder for YamlFormat {
+    fn decode(
+        &self,
+        reader: Box<dyn Read>,
+        _opts: &ConvertOptions,
+    ) -> CtxResult<WriterStreams<Records>> {
+        Ok(yaml_decoder::yaml_decoder(yaml_reader::yaml_reader(reader))?.boxed())
+    }
+}
+
+impl FormatEncoder for YamlFormat {
+    fn encode(
+        &self,
+        data: WriterStreams<Records>,
+        output: &mut dyn Write,
+        opts: &ConvertOptions,
+    ) -> CtxResult<()> {
+        yaml_writer::yaml_writer(data, output, opts.is_typed(), &opts.flatten)
+    }
+}
+
+impl FormatValidator for YamlFormat {
+    fn validate(&self, reader: &mut dyn Read, _opts: &ConvertOptions) -> CtxResult<()> {
+        yaml_validator::validate_yaml(reader)
+    }
+}
+
+impl FileFormat for YamlFormat {
+    fn name(&self) -> &str {
+        "yaml"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["yaml", "yml"]
+    }
+
+    fn description(&self) -> &str {
+        "YAML documents, multiple documents are streamed one by one"
+    }
+
+    fn decoder(&self) -> Option<&dyn FormatDecoder> {
+        Some(self)
+    }
+
+    fn encoder(&self) -> Option<&dyn FormatEncoder> {
+        Some(self)
+    }
+
+    fn validator(&self) -> Option<&dyn FormatValidator> {
+        Some(self)
+    }
+}
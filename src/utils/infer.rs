@@ -0,0 +1,442 @@
+use resext::ctx;
+use std::{
+    collections::HashSet,
+    fmt,
+    io::{Seek, SeekFrom},
+    str::FromStr,
+};
+
+use crate::utils::{CtxResult, CtxResultErr, CtxResultExt, Log, Records, Value, WriterStreams};
+
+/// Types of table columns, inferred from their cells or set with `--type col=type`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColumnType {
+    Null,
+    Bool,
+    Int,
+    Float,
+    Date,
+    DateTime,
+    String,
+}
+
+impl ColumnType {
+    /// Infers the type of a single cell, empty cells and `null` are nulls.
+    pub fn infer(cell: &str) -> Self {
+        match cell {
+            "" | "null" => Self::Null,
+            "true" | "false" => Self::Bool,
+            _ if is_int(cell) => Self::Int,
+            _ if is_float(cell) => Self::Float,
+            _ if is_date(cell) => Self::Date,
+            _ if is_datetime(cell) => Self::DateTime,
+            _ => Self::String,
+        }
+    }
+
+    /// Gets the narrowest type that fits cells of both types.
+    pub fn merge(self, other: Self) -> Self {
+        use ColumnType::*;
+
+        match (self, other) {
+            (a, b) if a == b => a,
+            (Null, t) | (t, Null) => t,
+            (Int, Float) | (Float, Int) => Float,
+            (Date, DateTime) | (DateTime, Date) => DateTime,
+            _ => String,
+        }
+    }
+
+    /// Converts a cell into a value of this type, returns `None` if the cell doesn't fit it.
+    pub fn convert(self, cell: &str) -> Option<Value> {
+        if self != Self::String && matches!(cell, "" | "null") {
+            return Some(Value::Null);
+        }
+
+        match self {
+            Self::Null => None,
+            Self::Bool => match cell {
+                "true" => Some(Value::Bool(true)),
+                "false" => Some(Value::Bool(false)),
+                _ => None,
+            },
+            Self::Int if is_int(cell) => Some(match cell.parse::<i64>() {
+                Ok(i) => Value::Int(i),
+                Err(_) => Value::Decimal(cell.to_string()),
+            }),
+            Self::Float if is_int(cell) || is_float(cell) => {
+                let digits = cell.bytes().take_while(|b| *b != b'e' && *b != b'E');
+
+                // keep numbers that don't fit into an f64 as they are
+                if digits.filter(u8::is_ascii_digit).count() > 17 {
+                    Some(Value::Decimal(cell.to_string()))
+                } else {
+                    cell.parse::<f64>().ok().map(Value::Float)
+                }
+            }
+            Self::Date if is_date(cell) => Some(Value::DateTime(cell.to_string())),
+            Self::DateTime if is_date(cell) || is_datetime(cell) => {
+                Some(Value::DateTime(cell.to_string()))
+            }
+            Self::String => Some(Value::String(cell.to_string())),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Null => "null",
+            Self::Bool => "bool",
+            Self::Int => "int",
+            Self::Float => "float",
+            Self::Date => "date",
+            Self::DateTime => "datetime",
+            Self::String => "string",
+        }
+    }
+}
+
+impl FromStr for ColumnType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "null" => Ok(Self::Null),
+            "bool" | "boolean" => Ok(Self::Bool),
+            "int" | "integer" => Ok(Self::Int),
+            "float" | "number" => Ok(Self::Float),
+            "date" => Ok(Self::Date),
+            "datetime" => Ok(Self::DateTime),
+            "string" | "str" => Ok(Self::String),
+            _ => Err(format!(
+                "Unknown type: {}, expected one of: null, bool, int, float, date, datetime, string",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Parses a `col=type` column type override.
+pub fn parse_type_override(s: &str) -> Result<(String, ColumnType), String> {
+    let (col, ty) = s
+        .rsplit_once('=')
+        .ok_or_else(|| format!("Invalid type override: {}, expected col=type", s))?;
+
+    Ok((col.to_string(), ty.parse()?))
+}
+
+/// Options for inferring the types of table columns.
+#[derive(Clone, Debug)]
+pub struct TypeOpts {
+    /// Number of rows to read for inferring column types
+    pub sample_size: usize,
+
+    /// Infer column types from all rows by spilling them to a temporary file first
+    pub whole_file: bool,
+
+    /// Column types that are used instead of inferring them
+    pub overrides: Vec<(String, ColumnType)>,
+}
+
+impl Default for TypeOpts {
+    fn default() -> Self {
+        Self { sample_size: 1000, whole_file: false, overrides: Vec::new() }
+    }
+}
+
+/// Converts the string cells of a table into typed values, column types are inferred from a sample
+/// of rows (or all rows with `whole_file`) when `infer` is set, otherwise only overridden columns
+/// are converted. Cells that don't fit their column's type are kept as strings.
+pub fn type_table(
+    data: WriterStreams<Records>,
+    infer: bool,
+    opts: &TypeOpts,
+) -> CtxResult<WriterStreams<Records>> {
+    let WriterStreams::Table { headers, mut iter } = data else {
+        return Ok(data);
+    };
+
+    let mut types: Vec<Option<ColumnType>> = vec![None; headers.len()];
+
+    let rows: Records = if !infer {
+        iter
+    } else if opts.whole_file {
+        let (spilled, inferred) = spill_rows(iter, headers.len())?;
+        types = inferred.into_iter().map(Some).collect();
+
+        spilled
+    } else {
+        let sample: Vec<CtxResult<Value>> = iter.by_ref().take(opts.sample_size.max(1)).collect();
+        let mut inferred = vec![ColumnType::Null; headers.len()];
+
+        for row in sample.iter().flatten() {
+            infer_row(row, &mut inferred);
+        }
+
+        types = inferred.into_iter().map(Some).collect();
+
+        Box::new(sample.into_iter().chain(iter))
+    };
+
+    for (col, ty) in &opts.overrides {
+        match headers.iter().position(|h| h == col) {
+            Some(idx) => types[idx] = Some(*ty),
+            None => {
+                let _ = Err::<(), _>(CtxResultErr::new(
+                    "Check the column name passed to `--type`",
+                    format!("Column: {} was not found in the input headers", col),
+                ))
+                .log("[WARN]");
+            }
+        }
+    }
+
+    let mut mismatched: HashSet<usize> = HashSet::new();
+    let names = headers.clone();
+
+    let iter = rows.map(move |row| {
+        let Value::Array(cells) = row? else {
+            return Err(CtxResultErr::new("Invalid table row", String::from("Expected cells")));
+        };
+
+        let cells = cells
+            .into_iter()
+            .enumerate()
+            .map(|(idx, cell)| {
+                let (Some(Some(ty)), Value::String(s)) = (types.get(idx), &cell) else {
+                    return cell;
+                };
+
+                match ty.convert(s) {
+                    Some(val) => val,
+                    None => {
+                        if mismatched.insert(idx) {
+                            let _ = Err::<(), _>(CtxResultErr::new(
+                                "Increase `--infer-rows`, use `--infer-all` or `--type` to fix it",
+                                format!(
+                                    "Column: {} has values that aren't of its type: {}, they're kept as strings",
+                                    names[idx], ty
+                                ),
+                            ))
+                            .log("[WARN]");
+                        }
+
+                        cell
+                    }
+                }
+            })
+            .collect();
+
+        Ok(Value::Array(cells))
+    });
+
+    Ok(WriterStreams::Table { headers, iter: Box::new(iter) })
+}
+
+#[inline]
+fn infer_row(row: &Value, types: &mut [ColumnType]) {
+    let Value::Array(cells) = row else {
+        return;
+    };
+
+    for (ty, cell) in types.iter_mut().zip(cells) {
+        let cell_ty = match cell {
+            Value::String(s) => ColumnType::infer(s),
+            _ => ColumnType::String,
+        };
+
+        *ty = ty.merge(cell_ty);
+    }
+}
+
+/// Writes rows into a temporary CSV file while inferring column types, then streams them back.
+fn spill_rows(rows: Records, width: usize) -> CtxResult<(Records, Vec<ColumnType>)> {
+    let spill = tempfile::tempfile().context("Failed to create temporary file")?;
+    let mut wtr = csv::WriterBuilder::new().has_headers(false).flexible(true).from_writer(spill);
+    let mut types = vec![ColumnType::Null; width];
+
+    for (line_no, row) in rows.enumerate() {
+        // invalid rows are logged here since they can't be written into the temporary file
+        let Some(row) = row.context(ctx!("Failed to read row: {}", line_no + 1)).log("[WARN]")
+        else {
+            continue;
+        };
+
+        infer_row(&row, &mut types);
+
+        if let Value::Array(cells) = row {
+            wtr.write_record(cells.iter().map(|c| c.to_cell_bytes()))
+                .context("Failed to write row into temporary file")?;
+        }
+    }
+
+    let mut spill =
+        wtr.into_inner().map_err(|e| e.into_error()).context("Failed to flush temporary file")?;
+
+    spill.seek(SeekFrom::Start(0)).context("Failed to rewind temporary file")?;
+
+    let rdr = csv::ReaderBuilder::new().has_headers(false).flexible(true).from_reader(spill);
+
+    let rows = rdr.into_byte_records().map(|rec| {
+        let rec = rec.context("Failed to read row from temporary file")?;
+
+        Ok(Value::Array(
+            rec.iter()
+                .map(|cell| match std::str::from_utf8(cell) {
+                    Ok(s) => Value::String(s.to_string()),
+                    Err(_) => Value::Bytes(cell.to_vec()),
+                })
+                .collect(),
+        ))
+    });
+
+    Ok((Box::new(rows), types))
+}
+
+#[inline]
+fn is_digits(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Integers without a `+` sign or leading zeros, so zip codes (e.g. `01234`) and phone numbers
+/// (e.g. `+1555`) stay strings.
+fn is_int(s: &str) -> bool {
+    let digits = s.strip_prefix('-').unwrap_or(s);
+
+    is_digits(digits) && (digits == "0" || !digits.starts_with('0'))
+}
+
+/// Floats with a fraction and / or an exponent, with the same sign and leading zero rules as
+/// integers.
+fn is_float(s: &str) -> bool {
+    let (mantissa, exp) = match s.find(['e', 'E']) {
+        Some(idx) => (&s[..idx], Some(&s[idx + 1..])),
+        None => (s, None),
+    };
+
+    if let Some(exp) = exp
+        && !is_digits(exp.strip_prefix(['-', '+']).unwrap_or(exp))
+    {
+        return false;
+    }
+
+    match mantissa.split_once('.') {
+        Some((int, frac)) => is_int(int) && is_digits(frac),
+        None => exp.is_some() && is_int(mantissa),
+    }
+}
+
+/// Dates in the `YYYY-MM-DD` format.
+fn is_date(s: &str) -> bool {
+    let b = s.as_bytes();
+
+    if b.len() != 10 || b[4] != b'-' || b[7] != b'-' {
+        return false;
+    }
+
+    let (Ok(month), Ok(day)) = (s[5..7].parse::<u8>(), s[8..10].parse::<u8>()) else {
+        return false;
+    };
+
+    is_digits(&s[..4]) && (1..=12).contains(&month) && (1..=31).contains(&day)
+}
+
+/// Datetimes in the RFC 3339 format (e.g. `2024-01-31T10:00:00Z`), a space can be used instead of
+/// `T` and seconds, fractions and offsets are optional.
+fn is_datetime(s: &str) -> bool {
+    if s.len() < 16 || !s.is_char_boundary(10) || !is_date(&s[..10]) {
+        return false;
+    }
+
+    let rest = match s.as_bytes()[10] {
+        b'T' | b't' | b' ' => &s[11..],
+        _ => return false,
+    };
+
+    let is_time = |t: &str| {
+        let parts: Vec<&str> = t.split(':').collect();
+
+        (parts.len() == 2 || parts.len() == 3)
+            && parts.iter().all(|p| p.len() == 2 && is_digits(p))
+            && parts[0] < "24"
+            && parts[1] < "60"
+            && parts.get(2).is_none_or(|s| *s < "61")
+    };
+
+    let (time, offset) = match rest.find(['Z', 'z', '+', '-']) {
+        Some(idx) => (&rest[..idx], &rest[idx..]),
+        None => (rest, ""),
+    };
+
+    let time = match time.split_once('.') {
+        Some((hms, frac)) if is_digits(frac) => hms,
+        Some(_) => return false,
+        None => time,
+    };
+
+    let valid_offset = match offset {
+        "" | "Z" | "z" => true,
+        _ => offset.len() == 6 && is_time(&offset[1..]),
+    };
+
+    is_time(time) && valid_offset
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_infer_cells() {
+        assert_eq!(ColumnType::infer(""), ColumnType::Null);
+        assert_eq!(ColumnType::infer("false"), ColumnType::Bool);
+        assert_eq!(ColumnType::infer("-42"), ColumnType::Int);
+        assert_eq!(ColumnType::infer("0"), ColumnType::Int);
+        assert_eq!(ColumnType::infer("1.5e-3"), ColumnType::Float);
+        assert_eq!(ColumnType::infer("2024-02-29"), ColumnType::Date);
+        assert_eq!(ColumnType::infer("2024-02-29T10:00:00.5+02:00"), ColumnType::DateTime);
+        assert_eq!(ColumnType::infer("2024-02-29 10:00"), ColumnType::DateTime);
+    }
+
+    #[test]
+    fn test_infer_keeps_ids_as_strings() {
+        for cell in ["01234", "+1555", "1.", ".5", "1e", "NaN", "2024-13-01", "12:00"] {
+            assert_eq!(ColumnType::infer(cell), ColumnType::String, "{}", cell);
+        }
+    }
+
+    #[test]
+    fn test_merge_types() {
+        use ColumnType::*;
+
+        assert_eq!(Null.merge(Int), Int);
+        assert_eq!(Int.merge(Float), Float);
+        assert_eq!(Date.merge(DateTime), DateTime);
+        assert_eq!(Bool.merge(Int), String);
+    }
+
+    #[test]
+    fn test_convert_cells() {
+        assert_eq!(ColumnType::Float.convert("2"), Some(Value::Float(2.0)));
+        assert_eq!(ColumnType::Int.convert(""), Some(Value::Null));
+        assert_eq!(ColumnType::Int.convert("x"), None);
+        assert_eq!(
+            ColumnType::Int.convert("99999999999999999999"),
+            Some(Value::Decimal("99999999999999999999".to_string()))
+        );
+        assert_eq!(ColumnType::String.convert(""), Some(Value::from("")));
+    }
+
+    #[test]
+    fn test_parse_type_override() {
+        assert_eq!(parse_type_override("a=b=string"), Ok(("a=b".to_string(), ColumnType::String)));
+        assert!(parse_type_override("zip").is_err());
+        assert!(parse_type_override("zip=text").is_err());
+    }
+}
@@ -0,0 +1,562 @@
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, VecDeque},
+    iter::Peekable,
+};
+
+use crate::utils::{
+    ConvertOptions, CtxResult, CtxResultErr, CtxResultExt, Log, Map, Records, SortKey, SortOpts,
+    Value, WriterStreams,
+    flatten::{PathSeg, get_path, parse_header},
+    sort::approx_size,
+    sort_records,
+};
+
+/// Kinds of joins, named like their SQL counterparts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum JoinKind {
+    /// Only records with a match on both sides
+    #[default]
+    Inner,
+
+    /// Every left record, with the fields of matching right records if there are any
+    Left,
+
+    /// Every right record, with the fields of matching left records if there are any
+    Right,
+
+    /// Every record of both sides
+    Full,
+}
+
+/// Options for joining two inputs.
+#[derive(Clone, Debug)]
+pub struct JoinOpts {
+    /// Keys to join on as `(left, right)` pairs of columns / paths
+    pub on: Vec<(String, String)>,
+
+    /// Kind of join
+    pub kind: JoinKind,
+
+    /// Approximate number of bytes of right records kept in memory for a hash join, larger
+    /// inputs are joined by sorting both sides with spilling and merging them
+    pub memory_limit: usize,
+
+    /// Delimiter for CSV right input, the input delimiter is used if it's not set
+    pub right_delimiter: Option<char>,
+}
+
+impl Default for JoinOpts {
+    fn default() -> Self {
+        Self {
+            on: Vec::new(),
+            kind: JoinKind::Inner,
+            memory_limit: 256 * 1024 * 1024,
+            right_delimiter: None,
+        }
+    }
+}
+
+/// Parses a join key, `id` joins on `id` on both sides and `user_id=id` joins the left `user_id`
+/// with the right `id`.
+pub fn parse_join_key(s: &str) -> Result<(String, String), String> {
+    let (left, right) = s.split_once('=').unwrap_or((s, s));
+
+    if left.is_empty() || right.is_empty() {
+        return Err(format!("Invalid join key: {}, expected col or left_col=right_col", s));
+    }
+
+    Ok((left.to_string(), right.to_string()))
+}
+
+/// Joins the records of two streams on keys, table rows are turned into objects keyed by their
+/// headers first (see [`WriterStreams::into_records`]).
+///
+/// The right side is loaded into memory for a hash join that streams left records in their order,
+/// unless it takes more than `memory_limit` bytes, then both sides are sorted by their keys with
+/// spilling (see [`sort_records`]) and merged, which writes records in key order.
+///
+/// Keys are compared as text so CSV cells match JSON numbers (e.g. `"1"` and `1`), records with
+/// missing, null or empty keys never match. Joined records have the left fields followed by the
+/// right fields, right fields with the same name as a left field get a `_right` suffix and right
+/// keys with the same name as their left key are only written once.
+pub fn join_records(
+    left: WriterStreams<Records>,
+    right: WriterStreams<Records>,
+    opts: &JoinOpts,
+    convert_opts: &ConvertOptions,
+) -> CtxResult<WriterStreams<Records>> {
+    if opts.on.is_empty() {
+        return Err(CtxResultErr::new(
+            "Pass the keys to join on with `--on`",
+            String::from("No join keys"),
+        ));
+    }
+
+    let keys = Keys::new(opts);
+    let left = left.into_records(convert_opts);
+    let mut right = right.into_records(convert_opts);
+
+    let mut table: Vec<Value> = Vec::new();
+    let mut size = 0;
+
+    while size < opts.memory_limit {
+        let Some(rec) = right.next() else {
+            let join = HashJoin::new(left, table, keys, opts.kind);
+            return Ok(WriterStreams::Ndjson { values: Box::new(join) });
+        };
+
+        // invalid right records are logged here since they're never written
+        let Some(rec) = expect_map(rec).context("Failed to join right record").log("[WARN]") else {
+            continue;
+        };
+
+        size += approx_size(&rec);
+        table.push(rec);
+    }
+
+    // the right side doesn't fit in memory so both sides are sorted and merged
+    let right: Records = Box::new(table.into_iter().map(Ok).chain(right));
+    let join = SortMergeJoin::new(left, right, keys, opts)?;
+
+    Ok(WriterStreams::Ndjson { values: Box::new(join) })
+}
+
+/// Checks that a record is an object since only objects can be merged.
+fn expect_map(rec: CtxResult<Value>) -> CtxResult<Value> {
+    match rec? {
+        Value::Map(map) => Ok(Value::Map(map)),
+        other => Err(CtxResultErr::new(
+            "Only objects can be joined",
+            format!("Expected an object, found: {}", other.type_name()),
+        )),
+    }
+}
+
+type Key = Vec<String>;
+
+/// Join keys of both sides.
+struct Keys {
+    left: Vec<Vec<PathSeg>>,
+    right: Vec<Vec<PathSeg>>,
+    names: Vec<(String, String)>,
+}
+
+impl Keys {
+    fn new(opts: &JoinOpts) -> Self {
+        Self {
+            left: opts.on.iter().map(|(l, _)| parse_header(l, ".")).collect(),
+            right: opts.on.iter().map(|(_, r)| parse_header(r, ".")).collect(),
+            names: opts.on.clone(),
+        }
+    }
+
+    fn of(paths: &[Vec<PathSeg>], record: &Value) -> Option<Key> {
+        paths
+            .iter()
+            .map(|p| match get_path(record, p)? {
+                Value::Null => None,
+                Value::Float(f) if f.fract() == 0.0 && f.abs() < i64::MAX as f64 => {
+                    Some((*f as i64).to_string())
+                }
+                v => Some(v.to_cell().into_owned()).filter(|s| !s.is_empty()),
+            })
+            .collect()
+    }
+
+    /// Merges a left and a right record, either side can be missing in outer joins.
+    fn merge(&self, left: Option<&Value>, right: Option<&Value>) -> Value {
+        let mut out = match left {
+            Some(Value::Map(map)) => map.clone(),
+            _ => Map::new(),
+        };
+
+        if left.is_none()
+            && let Some(right) = right
+        {
+            // unmatched right records keep their keys under the left names
+            for ((l, _), path) in self.names.iter().zip(&self.right) {
+                if let Some(v) = get_path(right, path) {
+                    out.insert(l.to_string(), v.clone());
+                }
+            }
+        }
+
+        if let Some(Value::Map(right)) = right {
+            for (k, v) in right {
+                if self.names.iter().any(|(l, r)| r == k && l == k) {
+                    continue;
+                }
+
+                let key = if out.contains_key(k) { format!("{}_right", k) } else { k.to_string() };
+                out.insert(key, v.clone());
+            }
+        }
+
+        Value::Map(out)
+    }
+}
+
+/// Hash join of streamed left records with right records in memory.
+struct HashJoin {
+    left: Records,
+    right: Vec<Value>,
+    table: HashMap<Key, Vec<usize>>,
+    matched: Vec<bool>,
+    keys: Keys,
+    kind: JoinKind,
+    pending: VecDeque<CtxResult<Value>>,
+    tail: Option<usize>,
+}
+
+impl HashJoin {
+    fn new(left: Records, right: Vec<Value>, keys: Keys, kind: JoinKind) -> Self {
+        let mut table: HashMap<Key, Vec<usize>> = HashMap::new();
+
+        for (idx, rec) in right.iter().enumerate() {
+            if let Some(key) = Keys::of(&keys.right, rec) {
+                table.entry(key).or_default().push(idx);
+            }
+        }
+
+        let matched = vec![false; right.len()];
+
+        Self { left, right, table, matched, keys, kind, pending: VecDeque::new(), tail: None }
+    }
+}
+
+impl Iterator for HashJoin {
+    type Item = CtxResult<Value>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.pending.pop_front() {
+                return Some(item);
+            }
+
+            // unmatched right records are written after all left records
+            if let Some(start) = self.tail {
+                if !keeps_right(self.kind) {
+                    return None;
+                }
+
+                let idx = (start..self.right.len()).find(|i| !self.matched[*i])?;
+                self.tail = Some(idx + 1);
+
+                return Some(Ok(self.keys.merge(None, Some(&self.right[idx]))));
+            }
+
+            let left = match self.left.next().map(expect_map) {
+                Some(Ok(rec)) => rec,
+                Some(Err(e)) => return Some(Err(e)),
+                None => {
+                    self.tail = Some(0);
+                    continue;
+                }
+            };
+
+            match Keys::of(&self.keys.left, &left).and_then(|k| self.table.get(&k)) {
+                Some(found) => {
+                    for i in found {
+                        self.matched[*i] = true;
+
+                        let joined = self.keys.merge(Some(&left), Some(&self.right[*i]));
+                        self.pending.push_back(Ok(joined));
+                    }
+                }
+                None if keeps_left(self.kind) => {
+                    return Some(Ok(self.keys.merge(Some(&left), None)));
+                }
+                None => {}
+            }
+        }
+    }
+}
+
+#[inline]
+fn keeps_left(kind: JoinKind) -> bool {
+    matches!(kind, JoinKind::Left | JoinKind::Full)
+}
+
+#[inline]
+fn keeps_right(kind: JoinKind) -> bool {
+    matches!(kind, JoinKind::Right | JoinKind::Full)
+}
+
+/// Sorts records by their keys, records are wrapped as `{"k": [keys], "r": record}` so both sides
+/// are sorted by their keys as text, records without keys are sorted last.
+fn sort_by_key(records: Records, paths: &[Vec<PathSeg>], opts: &JoinOpts) -> CtxResult<Records> {
+    let paths = paths.to_vec();
+
+    let wrapped = records.map(move |rec| {
+        let rec = expect_map(rec)?;
+        let key = Keys::of(&paths, &rec)
+            .map_or(Value::Null, |k| Value::Array(k.into_iter().map(Value::String).collect()));
+
+        let mut map = Map::with_capacity(2);
+        map.insert("k".to_string(), key);
+        map.insert("r".to_string(), rec);
+
+        Ok(Value::Map(map))
+    });
+
+    let sort = SortOpts {
+        keys: (0..opts.on.len())
+            .map(|i| SortKey { column: format!("k[{}]", i), desc: false, numeric: false })
+            .collect(),
+        memory_limit: opts.memory_limit,
+    };
+
+    match sort_records(WriterStreams::Ndjson { values: Box::new(wrapped) }, &sort)? {
+        WriterStreams::Ndjson { values } => Ok(values),
+        _ => unreachable!("sorting keeps the kind of stream"),
+    }
+}
+
+/// Unwraps a record sorted with [`sort_by_key`] into its key and the record.
+fn unwrap_sorted(value: Value) -> (Option<Key>, Value) {
+    let Value::Map(mut map) = value else {
+        return (None, Value::Null);
+    };
+
+    let key = match map.shift_remove("k") {
+        Some(Value::Array(parts)) => Some(parts.iter().map(|p| p.to_cell().into_owned()).collect()),
+        _ => None,
+    };
+
+    (key, map.shift_remove("r").unwrap_or_default())
+}
+
+/// Compares the key of a sorted record with a key, records without keys are sorted last.
+#[inline]
+fn cmp_key(sorted: &Value, key: &Key) -> Ordering {
+    match sorted.get("k") {
+        Some(Value::Array(parts)) => {
+            parts.iter().map(|p| p.to_cell()).cmp(key.iter().map(|k| k.as_str().into()))
+        }
+        _ => Ordering::Greater,
+    }
+}
+
+/// Sort-merge join of both sides sorted by their keys, right records with the same key are kept
+/// in memory while they're joined.
+struct SortMergeJoin {
+    left: Records,
+    right: Peekable<Records>,
+    keys: Keys,
+    kind: JoinKind,
+    group_key: Option<Key>,
+    group: Vec<Value>,
+    group_matched: bool,
+    pending: VecDeque<CtxResult<Value>>,
+}
+
+impl SortMergeJoin {
+    fn new(left: Records, right: Records, keys: Keys, opts: &JoinOpts) -> CtxResult<Self> {
+        Ok(Self {
+            left: sort_by_key(left, &keys.left, opts)?,
+            right: sort_by_key(right, &keys.right, opts)?.peekable(),
+            keys,
+            kind: opts.kind,
+            group_key: None,
+            group: Vec::new(),
+            group_matched: false,
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// Writes the current group of right records if none of them matched.
+    fn flush_group(&mut self) {
+        let group = std::mem::take(&mut self.group);
+
+        if !self.group_matched && keeps_right(self.kind) {
+            for rec in &group {
+                self.pending.push_back(Ok(self.keys.merge(None, Some(rec))));
+            }
+        }
+
+        self.group_key = None;
+        self.group_matched = false;
+    }
+
+    /// Reads the right records with a key into the current group, right records with smaller
+    /// keys never match so they're written if the join keeps them.
+    fn load_group(&mut self, key: Key) {
+        self.flush_group();
+
+        loop {
+            let ord = match self.right.peek() {
+                None => break,
+                Some(Ok(sorted)) => cmp_key(sorted, &key),
+                Some(Err(_)) => Ordering::Less,
+            };
+
+            if ord == Ordering::Greater {
+                break;
+            }
+
+            match self.right.next() {
+                Some(Ok(sorted)) => {
+                    let (_, rec) = unwrap_sorted(sorted);
+
+                    if ord == Ordering::Equal {
+                        self.group.push(rec);
+                    } else if keeps_right(self.kind) {
+                        self.pending.push_back(Ok(self.keys.merge(None, Some(&rec))));
+                    }
+                }
+                Some(Err(e)) => self.pending.push_back(Err(e)),
+                None => break,
+            }
+        }
+
+        self.group_key = Some(key);
+    }
+}
+
+impl Iterator for SortMergeJoin {
+    type Item = CtxResult<Value>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.pending.pop_front() {
+                return Some(item);
+            }
+
+            let Some(item) = self.left.next() else {
+                // the rest of the right records are unmatched once left records are done
+                self.flush_group();
+
+                if !self.pending.is_empty() {
+                    continue;
+                }
+
+                let item = self.right.next()?;
+
+                if !keeps_right(self.kind) {
+                    return None;
+                }
+
+                return Some(
+                    item.map(|sorted| self.keys.merge(None, Some(&unwrap_sorted(sorted).1))),
+                );
+            };
+
+            let (key, left) = match item {
+                Ok(sorted) => unwrap_sorted(sorted),
+                Err(e) => return Some(Err(e)),
+            };
+
+            let Some(key) = key else {
+                // records without keys never match
+                if keeps_left(self.kind) {
+                    return Some(Ok(self.keys.merge(Some(&left), None)));
+                }
+
+                continue;
+            };
+
+            if self.group_key.as_ref() != Some(&key) {
+                self.load_group(key);
+            }
+
+            if self.group.is_empty() {
+                if keeps_left(self.kind) {
+                    self.pending.push_back(Ok(self.keys.merge(Some(&left), None)));
+                }
+
+                continue;
+            }
+
+            self.group_matched = true;
+
+            for rec in &self.group {
+                self.pending.push_back(Ok(self.keys.merge(Some(&left), Some(rec))));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn stream(items: &[serde_json::Value]) -> WriterStreams<Records> {
+        let values: Vec<CtxResult<Value>> = items.iter().cloned().map(|v| Ok(v.into())).collect();
+
+        WriterStreams::Ndjson { values: Box::new(values.into_iter()) }
+    }
+
+    fn join(kind: JoinKind, memory_limit: usize) -> Vec<Value> {
+        let left = [
+            json!({"id": 1, "name": "a"}),
+            json!({"id": 2, "name": "b"}),
+            json!({"name": "no id"}),
+            json!({"id": 1, "name": "c"}),
+        ];
+
+        let right = [
+            json!({"id": "1", "name": "x", "v": true}),
+            json!({"id": "3", "v": false}),
+            json!({"id": "1", "v": null}),
+        ];
+
+        let opts = JoinOpts {
+            on: vec![parse_join_key("id").unwrap()],
+            kind,
+            memory_limit,
+            ..Default::default()
+        };
+
+        match join_records(stream(&left), stream(&right), &opts, &ConvertOptions::default()) {
+            Ok(WriterStreams::Ndjson { values }) => values.map(Result::unwrap).collect(),
+            _ => unreachable!(),
+        }
+    }
+
+    fn sorted(mut values: Vec<Value>) -> Vec<String> {
+        let mut out: Vec<String> =
+            values.drain(..).map(|v| serde_json::to_string(&v).unwrap()).collect();
+        out.sort();
+        out
+    }
+
+    #[test]
+    fn test_hash_join_kinds() {
+        let inner = join(JoinKind::Inner, usize::MAX);
+
+        assert_eq!(
+            inner,
+            [
+                json!({"id": 1, "name": "a", "name_right": "x", "v": true}),
+                json!({"id": 1, "name": "a", "v": null}),
+                json!({"id": 1, "name": "c", "name_right": "x", "v": true}),
+                json!({"id": 1, "name": "c", "v": null}),
+            ]
+            .map(Value::from)
+        );
+
+        assert_eq!(join(JoinKind::Left, usize::MAX).len(), 6);
+        assert_eq!(join(JoinKind::Right, usize::MAX).len(), 5);
+        assert_eq!(
+            join(JoinKind::Full, usize::MAX).last(),
+            Some(&json!({"id": "3", "v": false}).into())
+        );
+    }
+
+    #[test]
+    fn test_sort_merge_join_matches_hash_join() {
+        for kind in [JoinKind::Inner, JoinKind::Left, JoinKind::Right, JoinKind::Full] {
+            // a memory limit of 0 makes the join sort both sides
+            assert_eq!(sorted(join(kind, 0)), sorted(join(kind, usize::MAX)), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn test_parse_join_key() {
+        assert_eq!(parse_join_key("a=b"), Ok(("a".to_string(), "b".to_string())));
+        assert_eq!(parse_join_key("id"), Ok(("id".to_string(), "id".to_string())));
+        assert!(parse_join_key("=b").is_err());
+    }
+}
@@ -0,0 +1,566 @@
+//! JSON parsing shared by the JSON and NDJSON decoders / validators.
+//!
+//! With the `simd-json` feature, input is parsed with `simd-json` on CPUs with the instructions it
+//! needs (AVX2 or SSE4.2 on x86, NEON on ARM) and with `serde_json` everywhere else. Input that
+//! `simd-json` rejects or treats differently (e.g. lone surrogate escapes, `-0` or nesting past
+//! `serde_json`'s recursion limit) is parsed again with `serde_json`, so values and error locations
+//! are the same on both paths. Streams of values are parsed one value at a time, the rest of a
+//! stream is parsed by `serde_json` from the first value `simd-json` can't parse.
+
+use serde::de::DeserializeOwned;
+use serde_json::{StreamDeserializer, de::IoRead};
+use std::io::BufRead;
+
+/// Parses one JSON value from a slice, trailing whitespace is allowed.
+#[inline]
+pub(crate) fn from_slice<T: DeserializeOwned>(bytes: &[u8]) -> serde_json::Result<T> {
+    #[cfg(feature = "simd-json")]
+    if simd::supported()
+        && let Some(value) = simd::from_slice(bytes)
+    {
+        return Ok(value);
+    }
+
+    serde_json::from_slice(bytes)
+}
+
+/// Parses a stream of whitespace separated JSON values like [`serde_json::StreamDeserializer`],
+/// values are parsed one by one from the chunks of the reader.
+pub(crate) fn stream<T: DeserializeOwned, R: BufRead>(reader: R) -> Stream<R, T> {
+    #[cfg(feature = "simd-json")]
+    if simd::supported() {
+        return Stream::Simd(simd::Stream::new(reader));
+    }
+
+    Stream::Scalar(serde_json::Deserializer::from_reader(reader).into_iter())
+}
+
+/// Stream of JSON values returned by [`stream`].
+pub(crate) enum Stream<R: BufRead, T> {
+    Scalar(StreamDeserializer<'static, IoRead<R>, T>),
+    #[cfg(feature = "simd-json")]
+    Simd(simd::Stream<R, T>),
+}
+
+impl<R: BufRead, T: DeserializeOwned> Iterator for Stream<R, T> {
+    type Item = serde_json::Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::Scalar(iter) => iter.next(),
+            #[cfg(feature = "simd-json")]
+            Self::Simd(iter) => iter.next(),
+        }
+    }
+}
+
+#[cfg(feature = "simd-json")]
+mod simd {
+    use serde::de::DeserializeOwned;
+    use serde_json::{StreamDeserializer, de::IoRead};
+    use std::{
+        cell::RefCell,
+        io::{self, BufRead, Chain, Cursor, Read, Repeat, Take},
+    };
+
+    /// Values nested this deep are left to `serde_json`, which stops at 128 levels.
+    const MAX_DEPTH: usize = 127;
+
+    /// Checks whether the CPU has the instructions `simd-json` needs.
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    pub(super) fn supported() -> bool {
+        is_x86_feature_detected!("avx2") || is_x86_feature_detected!("sse4.2")
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    pub(super) fn supported() -> bool {
+        std::arch::is_aarch64_feature_detected!("neon")
+    }
+
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+    pub(super) fn supported() -> bool {
+        false
+    }
+
+    /// Parses a slice holding one value, `None` when the caller should parse it with `serde_json`.
+    pub(super) fn from_slice<T: DeserializeOwned>(bytes: &[u8]) -> Option<T> {
+        let start = skip_whitespace(bytes, 0);
+        let end = value_end(bytes, start, true).ok()?;
+
+        if skip_whitespace(bytes, end) != bytes.len() {
+            return None;
+        }
+
+        parse(&bytes[start..end])
+    }
+
+    fn parse<T: DeserializeOwned>(bytes: &[u8]) -> Option<T> {
+        thread_local! {
+            static SCRATCH: RefCell<(Vec<u8>, simd_json::Buffers)> = RefCell::default();
+        }
+
+        // `simd-json` parses in place, so values are copied into a buffer that is reused
+        SCRATCH.with_borrow_mut(|(buf, buffers)| {
+            buf.clear();
+            buf.extend_from_slice(bytes);
+
+            simd_json::serde::from_slice_with_buffers(buf, buffers).ok()
+        })
+    }
+
+    /// Stream of values parsed one by one from the chunks of a reader, values are sliced from
+    /// the chunk they're in (memory-mapped input is a single chunk) and only copied into a buffer
+    /// when they span chunks. From the first value that `simd-json` can't parse, the rest of the
+    /// input is parsed by `serde_json`.
+    pub(crate) struct Stream<R: BufRead, T> {
+        source: Source<R, T>,
+    }
+
+    enum Source<R: BufRead, T> {
+        Chunks(Chunks<R>),
+        Fallback(StreamDeserializer<'static, IoRead<Resume<R>>, T>),
+        Done,
+    }
+
+    /// Rest of the input parsed by `serde_json`, behind whitespace that moves it to the line and
+    /// column the rest starts at so error locations are counted from the start of the input.
+    type Resume<R> = Chain<Chain<Take<Repeat>, Take<Repeat>>, Chain<Cursor<Vec<u8>>, R>>;
+
+    struct Chunks<R> {
+        reader: R,
+        /// Bytes of values that span chunks, parsed from `offset`
+        buf: Vec<u8>,
+        offset: usize,
+        /// Number of unparsed bytes `buf` needs before it's scanned again, doubled every time the
+        /// value in it is incomplete so large values aren't scanned once per chunk
+        want: usize,
+        /// Line and column of the first unparsed byte, counted like `serde_json` does
+        line: u64,
+        column: u64,
+    }
+
+    enum Step<T> {
+        Value(T),
+        Fallback,
+        End,
+    }
+
+    impl<R: BufRead, T: DeserializeOwned> Stream<R, T> {
+        pub(super) fn new(reader: R) -> Self {
+            let chunks = Chunks { reader, buf: Vec::new(), offset: 0, want: 0, line: 0, column: 0 };
+
+            Self { source: Source::Chunks(chunks) }
+        }
+
+        /// Parses the rest of the input with `serde_json`, starting from the first unparsed byte.
+        fn fallback(&mut self) -> Option<serde_json::Result<T>> {
+            let Source::Chunks(mut chunks) = std::mem::replace(&mut self.source, Source::Done)
+            else {
+                return None;
+            };
+
+            let position =
+                io::repeat(b'\n').take(chunks.line).chain(io::repeat(b' ').take(chunks.column));
+            let rest = Cursor::new(chunks.buf.split_off(chunks.offset)).chain(chunks.reader);
+
+            let mut iter = serde_json::Deserializer::from_reader(position.chain(rest)).into_iter();
+            let next = iter.next();
+
+            self.source = Source::Fallback(iter);
+            next
+        }
+    }
+
+    impl<R: BufRead, T: DeserializeOwned> Iterator for Stream<R, T> {
+        type Item = serde_json::Result<T>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            let step = match &mut self.source {
+                Source::Chunks(chunks) => chunks.next(),
+                Source::Fallback(iter) => return iter.next(),
+                Source::Done => return None,
+            };
+
+            match step {
+                Ok(Step::Value(value)) => Some(Ok(value)),
+                Ok(Step::Fallback) => self.fallback(),
+                Ok(Step::End) => {
+                    self.source = Source::Done;
+                    None
+                }
+                Err(err) => {
+                    self.source = Source::Done;
+                    Some(Err(serde_json::Error::io(err)))
+                }
+            }
+        }
+    }
+
+    impl<R: BufRead> Chunks<R> {
+        fn next<T: DeserializeOwned>(&mut self) -> io::Result<Step<T>> {
+            loop {
+                if self.offset < self.buf.len() {
+                    match self.next_buffered()? {
+                        Some(step) => return Ok(step),
+                        None => continue,
+                    }
+                }
+
+                let chunk = self.reader.fill_buf()?;
+
+                if chunk.is_empty() {
+                    return Ok(Step::End);
+                }
+
+                let start = skip_whitespace(chunk, 0);
+
+                if start == chunk.len() {
+                    let len = chunk.len();
+
+                    advance(chunk, &mut self.line, &mut self.column);
+                    self.reader.consume(len);
+                    continue;
+                }
+
+                // bytes up to `parsed` are parsed, bytes up to `used` are consumed from the reader
+                let (parsed, used, step) = match value_end(chunk, start, false) {
+                    // values must be separated by whitespace, anything else is left to `serde_json`
+                    Ok(end) if end < chunk.len() && is_whitespace(chunk[end]) => {
+                        match parse(&chunk[start..end]) {
+                            Some(value) => (end, end, Some(Step::Value(value))),
+                            None => (start, start, Some(Step::Fallback)),
+                        }
+                    }
+                    Ok(end) if end < chunk.len() => (start, start, Some(Step::Fallback)),
+                    Err(Stop::Unsupported) => (start, start, Some(Step::Fallback)),
+                    // the value continues in the next chunk
+                    _ => {
+                        self.buf.clear();
+                        self.buf.extend_from_slice(&chunk[start..]);
+                        self.offset = 0;
+                        self.want = 2 * self.buf.len();
+
+                        (start, chunk.len(), None)
+                    }
+                };
+
+                advance(&chunk[..parsed], &mut self.line, &mut self.column);
+                self.reader.consume(used);
+
+                if let Some(step) = step {
+                    return Ok(step);
+                }
+            }
+        }
+
+        /// Parses the next value in `buf`, reading chunks into it until it holds `want` unparsed
+        /// bytes. `None` when the value is still incomplete or only whitespace is left.
+        fn next_buffered<T: DeserializeOwned>(&mut self) -> io::Result<Option<Step<T>>> {
+            let ws = skip_whitespace(&self.buf, self.offset);
+
+            advance(&self.buf[self.offset..ws], &mut self.line, &mut self.column);
+            self.offset = ws;
+
+            if self.offset == self.buf.len() {
+                self.buf.clear();
+                self.offset = 0;
+
+                return Ok(None);
+            }
+
+            let mut eof = false;
+
+            while self.buf.len() - self.offset < self.want && !eof {
+                let chunk = self.reader.fill_buf()?;
+                let len = chunk.len();
+
+                eof = len == 0;
+
+                self.buf.drain(..self.offset);
+                self.buf.extend_from_slice(chunk);
+                self.offset = 0;
+
+                self.reader.consume(len);
+            }
+
+            let rest = &self.buf[self.offset..];
+
+            let step = match value_end(rest, 0, eof) {
+                Ok(end) if end < rest.len() && !is_whitespace(rest[end]) => Some(Step::Fallback),
+                Ok(end) if end < rest.len() || eof => match parse(&rest[..end]) {
+                    Some(value) => {
+                        advance(&rest[..end], &mut self.line, &mut self.column);
+                        self.offset += end;
+
+                        Some(Step::Value(value))
+                    }
+                    None => Some(Step::Fallback),
+                },
+                Err(Stop::Incomplete) if eof => Some(Step::Fallback),
+                Err(Stop::Unsupported) => Some(Step::Fallback),
+                // the value (or the whitespace after it) continues in the next chunk
+                _ => None,
+            };
+
+            self.want = match step {
+                Some(_) => 0,
+                None => 2 * (self.buf.len() - self.offset),
+            };
+
+            Ok(step)
+        }
+    }
+
+    /// Moves a line and column past `bytes`.
+    fn advance(bytes: &[u8], line: &mut u64, column: &mut u64) {
+        match memchr::memrchr(b'\n', bytes) {
+            Some(last) => {
+                *line += memchr::memchr_iter(b'\n', bytes).count() as u64;
+                *column = (bytes.len() - last - 1) as u64;
+            }
+            None => *column += bytes.len() as u64,
+        }
+    }
+
+    #[inline(always)]
+    fn is_whitespace(b: u8) -> bool {
+        matches!(b, b' ' | b'\t' | b'\n' | b'\r')
+    }
+
+    fn skip_whitespace(bytes: &[u8], mut pos: usize) -> usize {
+        while pos < bytes.len() && is_whitespace(bytes[pos]) {
+            pos += 1;
+        }
+
+        pos
+    }
+
+    /// Reason the end of a value wasn't found.
+    enum Stop {
+        /// The value continues past the end of the bytes
+        Incomplete,
+        /// `simd-json` would parse the value differently from `serde_json`
+        Unsupported,
+    }
+
+    /// Finds the end of the value starting at `start`, scalars that run up to the end of the bytes
+    /// are only complete if the bytes are the rest of the input.
+    fn value_end(bytes: &[u8], start: usize, complete: bool) -> Result<usize, Stop> {
+        match bytes.get(start) {
+            None => Err(Stop::Incomplete),
+            Some(b'"') => string_end(bytes, start + 1),
+            Some(b'{' | b'[') => container_end(bytes, start),
+            Some(_) => scalar_end(bytes, start, complete),
+        }
+    }
+
+    fn container_end(bytes: &[u8], start: usize) -> Result<usize, Stop> {
+        let (mut pos, mut depth) = (start, 0);
+
+        while pos < bytes.len() {
+            match bytes[pos] {
+                b'"' => {
+                    pos = string_end(bytes, pos + 1)?;
+                    continue;
+                }
+                b'{' | b'[' => {
+                    depth += 1;
+
+                    if depth > MAX_DEPTH {
+                        return Err(Stop::Unsupported);
+                    }
+                }
+                b'}' | b']' => {
+                    depth -= 1;
+
+                    if depth == 0 {
+                        return Ok(pos + 1);
+                    }
+                }
+                // a number at the end of the bytes is checked once the rest is read
+                b'-' if pos + 2 < bytes.len() && negative_zero(bytes, pos) => {
+                    return Err(Stop::Unsupported);
+                }
+                _ => {}
+            }
+
+            pos += 1;
+        }
+
+        Err(Stop::Incomplete)
+    }
+
+    /// Finds the end of a string starting after its opening quote, strings with surrogate escapes
+    /// are left to `serde_json` since `simd-json` accepts lone surrogates.
+    fn string_end(bytes: &[u8], mut pos: usize) -> Result<usize, Stop> {
+        loop {
+            let rest = bytes.get(pos..).ok_or(Stop::Incomplete)?;
+            pos += memchr::memchr2(b'"', b'\\', rest).ok_or(Stop::Incomplete)?;
+
+            if bytes[pos] == b'"' {
+                return Ok(pos + 1);
+            }
+
+            match bytes.get(pos + 1..pos + 4) {
+                Some([b'u', b'd' | b'D', b'8'..=b'9' | b'a'..=b'f' | b'A'..=b'F']) => {
+                    return Err(Stop::Unsupported);
+                }
+                // the escape is checked once the rest is read
+                None if bytes.get(pos + 1) == Some(&b'u') => return Err(Stop::Incomplete),
+                _ => pos += 2,
+            }
+        }
+    }
+
+    fn scalar_end(bytes: &[u8], start: usize, complete: bool) -> Result<usize, Stop> {
+        let len = bytes[start..]
+            .iter()
+            .position(|&b| is_whitespace(b) || b"{}[],:\"".contains(&b))
+            .unwrap_or(bytes.len() - start);
+
+        if start + len == bytes.len() && !complete {
+            return Err(Stop::Incomplete);
+        }
+
+        if len == 0 || negative_zero(bytes, start) {
+            return Err(Stop::Unsupported);
+        }
+
+        Ok(start + len)
+    }
+
+    /// `serde_json` parses `-0` as a float and `simd-json` as an integer.
+    #[inline(always)]
+    fn negative_zero(bytes: &[u8], pos: usize) -> bool {
+        bytes[pos] == b'-'
+            && bytes.get(pos + 1) == Some(&b'0')
+            && !matches!(bytes.get(pos + 2), Some(b'.' | b'e' | b'E'))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::Value;
+    use serde::de::IgnoredAny;
+    use std::io::BufReader;
+
+    const CASES: &[&str] = &[
+        "{\"a\": [1, -2, 3.5, 1e3, -0.0, true, null], \"b\": {\"c\": \"d\\n\\u00e9\"}}",
+        "  [1, 2]\r\n",
+        "-0",
+        "[-0, 1]",
+        "18446744073709551616",
+        "-9223372036854775809",
+        "0.30000000000000004",
+        "\"\\ud83d\\ude00\"",
+        "\"\\ud800\"",
+        "{\"a\": \"\\udc00\"}",
+        "[1, 2]x",
+        "[1,]",
+        "{\"a\" 1}",
+        "\"a\tb\"",
+        "1e400",
+        "tru",
+        "",
+        "   ",
+        "\u{feff}1",
+    ];
+
+    fn debug<T: std::fmt::Debug>(res: serde_json::Result<T>) -> String {
+        match res {
+            Ok(value) => format!("{:?}", value),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_from_slice_matches_serde_json() {
+        let deep = [127, 128, 200].map(|n| format!("{}{}", "[".repeat(n), "]".repeat(n)));
+
+        for case in CASES.iter().copied().chain(deep.iter().map(String::as_str)) {
+            let bytes = case.as_bytes();
+
+            assert_eq!(
+                debug(from_slice::<Value>(bytes)),
+                debug(serde_json::from_slice::<Value>(bytes)),
+                "{}",
+                case
+            );
+            assert_eq!(
+                from_slice::<IgnoredAny>(bytes).is_ok(),
+                serde_json::from_slice::<IgnoredAny>(bytes).is_ok(),
+                "{}",
+                case
+            );
+        }
+    }
+
+    #[cfg(feature = "simd-json")]
+    #[test]
+    fn test_simd_parses_plain_values() {
+        if !simd::supported() {
+            return;
+        }
+
+        for case in &CASES[..2] {
+            let value = simd::from_slice::<Value>(case.as_bytes());
+            assert_eq!(value, serde_json::from_str::<Value>(case).ok(), "{}", case);
+        }
+
+        for case in ["-0", "[-0, 1]", "\"\\ud800\""] {
+            assert_eq!(simd::from_slice::<Value>(case.as_bytes()), None, "{}", case);
+        }
+    }
+
+    #[test]
+    fn test_stream_matches_serde_json() {
+        let inputs = [
+            CASES.join("\n"),
+            "{\"a\": 1}\n[2] 3\n\"4\"".to_string(),
+            "{\"a\": 1}{\"b\": 2} 3".to_string(),
+            "1 2\n{\"a\": -0}\n[3]".to_string(),
+            "{\"a\": 1}\n{\"b\": ".to_string(),
+        ];
+
+        for input in inputs {
+            let expected = serde_json::Deserializer::from_reader(input.as_bytes())
+                .into_iter::<Value>()
+                .map(debug)
+                .collect::<Vec<_>>();
+
+            let values = stream::<Value, _>(input.as_bytes()).map(debug).collect::<Vec<_>>();
+
+            assert_eq!(values, expected, "{}", input);
+
+            // values that span reads are parsed once they're complete
+            for capacity in [1, 3, 16] {
+                let reader = BufReader::with_capacity(capacity, input.as_bytes());
+                let values = stream::<Value, _>(reader).map(debug).collect::<Vec<_>>();
+
+                assert_eq!(values, expected, "{} in reads of {}", input, capacity);
+            }
+        }
+    }
+
+    #[test]
+    fn test_large_stream_matches_serde_json() {
+        let values = ["{\"a\": [1, 2.5, \"\\u00e9\"]}", "3", "\"x y\"", "{\"b\": null}"];
+        let input = (0..20_000).map(|i| values[i % values.len()]).collect::<Vec<_>>().join("\n");
+
+        // the rest of the input is parsed by `serde_json` from the first `-0`
+        let input = format!("{}\n-0 [1]\n\"\\ud800\"\n{{\"c\": ]", input);
+
+        let expected = serde_json::Deserializer::from_reader(input.as_bytes())
+            .into_iter::<Value>()
+            .map(debug)
+            .collect::<Vec<_>>();
+
+        for capacity in [64, 8192, input.len()] {
+            let reader = BufReader::with_capacity(capacity, input.as_bytes());
+            let values = stream::<Value, _>(reader).map(debug).collect::<Vec<_>>();
+
+            assert_eq!(values, expected, "{}", capacity);
+        }
+    }
+}
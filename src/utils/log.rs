@@ -1,7 +1,8 @@
 use std::{
-    fs::{File, OpenOptions},
-    io::{BufWriter, Error, Write},
-    sync::{LazyLock, Mutex},
+    fs::OpenOptions,
+    io::{BufWriter, Error, Write, stderr},
+    path::PathBuf,
+    sync::{LazyLock, Mutex, OnceLock},
 };
 
 use owo_colors::OwoColorize;
@@ -14,56 +15,49 @@ pub enum FiuxErr {
     TomlDeserialize(toml::de::Error),
     TomlSerialize(toml::ser::Error),
     Csv(csv::Error),
+    Yaml(serde_yaml::Error),
     IoError(Error),
     Custom(String),
 }
 
-static LOGGER: LazyLock<Mutex<BufWriter<File>>> = LazyLock::new(|| {
-    let args = &*crate::ARGS;
+static LOG_FILE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Sets the file that recoverable errors are logged into, this has to be called before anything
+/// is logged, otherwise logs are written into stderr. Returns `false` if it was already set.
+pub fn set_log_file(path: impl Into<PathBuf>) -> bool {
+    LOG_FILE.set(path.into()).is_ok()
+}
+
+static LOGGER: LazyLock<Mutex<BufWriter<Box<dyn Write + Send>>>> = LazyLock::new(|| {
     let mut open = OpenOptions::new();
 
     open.create(true).append(true);
 
-    match &args.log_file {
-        Some(path) => {
-            let res = open.open(path);
-
-            match res {
-                Ok(file) => Mutex::new(BufWriter::with_capacity(64 * 1024, file)),
-                Err(err) => {
-                    eprintln!("{} {}", "[WARN]".yellow(), err);
-
-                    Mutex::new(BufWriter::with_capacity(
-                        64 * 1024,
-                        open.open("fiux.log")
-                            .inspect_err(|e| {
-                                eprintln!(
-                                    "{} Failed to open log file: ./fiux.log\nError: {}",
-                                    "[FATAL]".red().bold(),
-                                    e
-                                );
-                                std::process::exit(1);
-                            })
-                            .unwrap(),
-                    ))
+    let sink: Box<dyn Write + Send> = match LOG_FILE.get() {
+        Some(path) => match open.open(path) {
+            Ok(file) => Box::new(file),
+            Err(err) => {
+                eprintln!("{} {}", "[WARN]".yellow(), err);
+
+                match open.open("fiux.log") {
+                    Ok(file) => Box::new(file),
+                    Err(e) => {
+                        eprintln!(
+                            "{} Failed to open log file: ./fiux.log, logging into stderr instead\nError: {}",
+                            "[WARN]".yellow(),
+                            e
+                        );
+
+                        Box::new(stderr())
+                    }
                 }
             }
-        }
+        },
 
-        None => Mutex::new(BufWriter::with_capacity(
-            64 * 1024,
-            open.open("fiux.log")
-                .inspect_err(|e| {
-                    eprintln!(
-                        "{} Failed to open log file: ./fiux.log\nError: {}",
-                        "[FATAL]".red().bold(),
-                        e
-                    );
-                    std::process::exit(1);
-                })
-                .unwrap(),
-        )),
-    }
+        None => Box::new(stderr()),
+    };
+
+    Mutex::new(BufWriter::with_capacity(64 * 1024, sink))
 });
 
 pub trait Log<T> {
@@ -1,15 +1,49 @@
-pub mod cli;
+pub mod aggregate;
+pub mod columns;
+pub mod compression;
+pub mod converter;
 pub mod decoders;
+pub mod dedupe;
+pub mod detect;
+pub mod filter;
+pub mod flatten;
+pub mod formats;
+pub mod infer;
+pub mod join;
+pub mod json;
 pub mod log;
+pub mod parallel;
+pub mod query;
 pub mod readers;
+pub mod schema;
+pub mod sort;
+pub mod stats;
 pub mod utilities;
 pub mod validators;
+pub mod value;
 pub mod writers;
 
-pub use cli::*;
+pub use aggregate::*;
+pub use columns::*;
+pub use compression::*;
+pub use converter::*;
 pub use decoders::*;
+pub use dedupe::*;
+pub use detect::*;
+pub use filter::*;
+pub use flatten::*;
+pub use formats::registry::*;
+pub use formats::*;
+pub use infer::*;
+pub use join::*;
 pub use log::*;
+pub use parallel::thread_count;
+pub use query::*;
 pub use readers::*;
+pub use schema::*;
+pub use sort::*;
+pub use stats::*;
 pub use utilities::*;
 pub use validators::*;
+pub use value::*;
 pub use writers::*;
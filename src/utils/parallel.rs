@@ -0,0 +1,480 @@
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::{
+    collections::BTreeMap,
+    io::{ErrorKind as EK, Read, Write},
+    iter::Fuse,
+    ops::Range,
+    sync::{Arc, Mutex, PoisonError, mpsc},
+};
+
+use crate::utils::{
+    CtxResult, CtxResultErr, CtxResultExt, Value, WriterStreams, input_reader::Mapped,
+};
+
+/// Size of the chunks that line-oriented input is split into for decoding on worker threads.
+pub(crate) const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Number of records serialized by one job on a worker thread.
+pub(crate) const BATCH_SIZE: usize = 4096;
+
+/// Gets the number of worker threads for `--threads`, `0` uses every available core.
+pub fn thread_count(threads: usize) -> usize {
+    match threads {
+        0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    }
+}
+
+/// Gets a pool with `threads` worker threads, shared by every step of a conversion.
+fn pool(threads: usize) -> CtxResult<Arc<ThreadPool>> {
+    static POOL: Mutex<Option<(usize, Arc<ThreadPool>)>> = Mutex::new(None);
+
+    let mut cached = POOL.lock().unwrap_or_else(PoisonError::into_inner);
+
+    if let Some((n, pool)) = &*cached
+        && *n == threads
+    {
+        return Ok(Arc::clone(pool));
+    }
+
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .thread_name(|idx| format!("fiux-worker-{}", idx))
+        .build()
+        .map_err(|e| CtxResultErr::new("Failed to create thread pool", e.to_string()))?;
+
+    let pool = Arc::new(pool);
+    *cached = Some((threads, Arc::clone(&pool)));
+
+    Ok(pool)
+}
+
+/// Runs jobs on a thread pool and yields their outputs in the order of the jobs, jobs are pulled
+/// from the source lazily and only a few of them per thread are in flight at once to bound memory.
+pub(crate) struct Ordered<I: Iterator, T> {
+    jobs: Fuse<I>,
+    work: Arc<dyn Fn(I::Item) -> T + Send + Sync>,
+    pool: Arc<ThreadPool>,
+    /// Dropped once every job is spawned, so the channel closes when the last worker is done
+    tx: Option<mpsc::Sender<(usize, T)>>,
+    rx: mpsc::Receiver<(usize, T)>,
+    done: BTreeMap<usize, T>,
+    next: usize,
+    spawned: usize,
+    in_flight: usize,
+}
+
+impl<I, T> Ordered<I, T>
+where
+    I: Iterator,
+    I::Item: Send + 'static,
+    T: Send + 'static,
+{
+    pub(crate) fn new(
+        jobs: I,
+        threads: usize,
+        work: impl Fn(I::Item) -> T + Send + Sync + 'static,
+    ) -> CtxResult<Self> {
+        let threads = thread_count(threads);
+        let (tx, rx) = mpsc::channel();
+
+        Ok(Self {
+            jobs: jobs.fuse(),
+            work: Arc::new(work),
+            pool: pool(threads)?,
+            tx: Some(tx),
+            rx,
+            done: BTreeMap::new(),
+            next: 0,
+            spawned: 0,
+            in_flight: threads * 2,
+        })
+    }
+}
+
+impl<I, T> Iterator for Ordered<I, T>
+where
+    I: Iterator,
+    I::Item: Send + 'static,
+    T: Send + 'static,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        while self.spawned - self.next < self.in_flight
+            && let Some(tx) = &self.tx
+        {
+            let Some(job) = self.jobs.next() else {
+                self.tx = None;
+                break;
+            };
+
+            let (work, tx, idx) = (Arc::clone(&self.work), tx.clone(), self.spawned);
+
+            self.pool.spawn(move || {
+                let _ = tx.send((idx, work(job)));
+            });
+
+            self.spawned += 1;
+        }
+
+        if self.next == self.spawned {
+            return None;
+        }
+
+        loop {
+            if let Some(out) = self.done.remove(&self.next) {
+                self.next += 1;
+                return Some(out);
+            }
+
+            // `self.tx` is dropped once every job is spawned, so this fails instead of blocking if
+            // a job is dropped without sending its output (panics in jobs abort the process)
+            let (idx, out) =
+                self.rx.recv().expect("Worker thread stopped before sending the output of its job");
+            self.done.insert(idx, out);
+        }
+    }
+}
+
+/// Where a chunk of input can be split without splitting a record.
+#[derive(Clone, Copy)]
+pub(crate) enum Split {
+    /// After any newline (e.g. NDJSON)
+    Lines,
+    /// After newlines outside of quoted CSV fields with the delimiter
+    Csv(u8),
+}
+
+/// Chunk of whole records and the line it starts at, the records are a range of a buffer or a
+/// memory-mapped file shared with the other chunks.
+pub(crate) struct Chunk {
+    source: Arc<dyn AsRef<[u8]> + Send + Sync>,
+    range: Range<usize>,
+    pub first_line: usize,
+    pub first_byte: u64,
+}
+
+impl Chunk {
+    pub(crate) fn new(data: Vec<u8>, first_line: usize, first_byte: u64) -> Self {
+        Self { range: 0..data.len(), source: Arc::new(data), first_line, first_byte }
+    }
+
+    #[inline(always)]
+    pub(crate) fn bytes(&self) -> &[u8] {
+        &(*self.source).as_ref()[self.range.clone()]
+    }
+
+    /// Drops the first `n` bytes of the chunk.
+    pub(crate) fn skip(&mut self, n: usize) {
+        let n = n.min(self.range.len());
+
+        self.range.start += n;
+        self.first_byte += n as u64;
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum CsvState {
+    Start,
+    Unquoted,
+    Quoted,
+    QuoteInQuoted,
+}
+
+/// Finds record boundaries in input that's scanned piece by piece.
+struct Boundaries {
+    split: Split,
+    state: CsvState,
+}
+
+impl Boundaries {
+    fn new(split: Split) -> Self {
+        Self { split, state: CsvState::Start }
+    }
+
+    /// Scans the next piece of input and gets the end of the last record in it.
+    fn scan(&mut self, bytes: &[u8]) -> Option<usize> {
+        match self.split {
+            Split::Lines => memchr::memrchr(b'\n', bytes).map(|pos| pos + 1),
+            // same states as `csv_core`, quotes only start quoted fields at the start of fields
+            Split::Csv(d) => {
+                let mut boundary = None;
+
+                for (idx, &b) in bytes.iter().enumerate() {
+                    self.state = match (self.state, b) {
+                        (CsvState::Quoted, b'"') => CsvState::QuoteInQuoted,
+                        (CsvState::Quoted, _) => CsvState::Quoted,
+                        (CsvState::QuoteInQuoted, b'"') => CsvState::Quoted,
+                        (CsvState::Start, b'"') => CsvState::Quoted,
+                        (_, b'\n') => {
+                            boundary = Some(idx + 1);
+                            CsvState::Start
+                        }
+                        (_, b'\r') => CsvState::Start,
+                        (_, _) if b == d => CsvState::Start,
+                        _ => CsvState::Unquoted,
+                    };
+                }
+
+                boundary
+            }
+        }
+    }
+}
+
+#[inline]
+fn count_lines(bytes: &[u8]) -> usize {
+    memchr::memchr_iter(b'\n', bytes).count()
+}
+
+/// Reads a source in chunks of at least `size` bytes that end at record boundaries, records larger
+/// than `size` are kept whole.
+pub(crate) struct Chunks<R> {
+    reader: R,
+    boundaries: Boundaries,
+    size: usize,
+    buf: Vec<u8>,
+    scanned: usize,
+    /// End of the last record in `buf`, `0` if there's no whole record yet
+    boundary: usize,
+    line: usize,
+    byte: u64,
+    eof: bool,
+}
+
+impl<R: Read> Chunks<R> {
+    pub(crate) fn new(reader: R, split: Split, size: usize) -> Self {
+        Self {
+            reader,
+            boundaries: Boundaries::new(split),
+            size: size.max(1),
+            buf: Vec::new(),
+            scanned: 0,
+            boundary: 0,
+            line: 1,
+            byte: 0,
+            eof: false,
+        }
+    }
+
+    fn scan(&mut self) {
+        if let Some(end) = self.boundaries.scan(&self.buf[self.scanned..]) {
+            self.boundary = self.scanned + end;
+        }
+
+        self.scanned = self.buf.len();
+    }
+
+    fn take(&mut self, end: usize) -> Chunk {
+        let rest = self.buf.split_off(end);
+        let data = std::mem::replace(&mut self.buf, rest);
+
+        self.scanned -= end;
+        self.boundary = 0;
+
+        let (first_line, first_byte) = (self.line, self.byte);
+        self.line += count_lines(&data);
+        self.byte += data.len() as u64;
+
+        Chunk::new(data, first_line, first_byte)
+    }
+}
+
+impl<R: Read> Iterator for Chunks<R> {
+    type Item = CtxResult<Chunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.scan();
+
+            if self.eof {
+                return (!self.buf.is_empty()).then(|| Ok(self.take(self.buf.len())));
+            }
+
+            if self.boundary > 0 && self.buf.len() >= self.size {
+                return Some(Ok(self.take(self.boundary)));
+            }
+
+            let len = self.buf.len();
+            self.buf.resize(len + self.size, 0);
+
+            match self.reader.read(&mut self.buf[len..]) {
+                Ok(n) => {
+                    self.buf.truncate(len + n);
+                    self.eof = n == 0;
+                }
+                Err(e) if e.kind() == EK::Interrupted => self.buf.truncate(len),
+                Err(e) => {
+                    self.buf.clear();
+                    self.eof = true;
+
+                    return Some(Err(e).context("Failed to read input file"));
+                }
+            }
+        }
+    }
+}
+
+/// Splits a memory-mapped file into chunks like [`Chunks`], chunks are ranges of the mapping so
+/// nothing is copied.
+pub(crate) struct MappedChunks {
+    source: Arc<Mapped>,
+    boundaries: Boundaries,
+    size: usize,
+    start: usize,
+    scanned: usize,
+    /// End of the last record scanned so far
+    boundary: usize,
+    line: usize,
+}
+
+impl MappedChunks {
+    pub(crate) fn new(input: Mapped, split: Split, size: usize) -> Self {
+        Self {
+            source: Arc::new(input),
+            boundaries: Boundaries::new(split),
+            size: size.max(1),
+            start: 0,
+            scanned: 0,
+            boundary: 0,
+            line: 1,
+        }
+    }
+}
+
+impl Iterator for MappedChunks {
+    type Item = CtxResult<Chunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let bytes = self.source.as_slice();
+
+        if self.start == bytes.len() {
+            return None;
+        }
+
+        while self.scanned < bytes.len()
+            && (self.boundary <= self.start || self.scanned - self.start < self.size)
+        {
+            let end = (self.scanned + self.size).min(bytes.len());
+
+            if let Some(boundary) = self.boundaries.scan(&bytes[self.scanned..end]) {
+                self.boundary = self.scanned + boundary;
+            }
+
+            self.scanned = end;
+        }
+
+        let end = if self.scanned == bytes.len() { bytes.len() } else { self.boundary };
+        let range = self.start..end;
+
+        let first_line = self.line;
+        self.line += count_lines(&bytes[range.clone()]);
+        self.start = end;
+
+        let first_byte = range.start as u64;
+
+        Some(Ok(Chunk { source: Arc::clone(&self.source) as _, range, first_line, first_byte }))
+    }
+}
+
+/// Splits records into batches for serializing on worker threads.
+pub(crate) fn batches<T>(mut iter: impl Iterator<Item = T>) -> impl Iterator<Item = Vec<T>> {
+    std::iter::from_fn(move || {
+        let batch: Vec<T> = iter.by_ref().take(BATCH_SIZE).collect();
+
+        (!batch.is_empty()).then_some(batch)
+    })
+}
+
+/// Kind of a stream with its headers, for rebuilding batches of its records into streams.
+#[derive(Clone)]
+pub(crate) enum StreamKind {
+    Values,
+    Table(Vec<String>),
+    Ndjson,
+}
+
+impl StreamKind {
+    pub(crate) fn of<I: Iterator<Item = CtxResult<Value>>>(data: &WriterStreams<I>) -> Self {
+        match data {
+            WriterStreams::Values { .. } => Self::Values,
+            WriterStreams::Table { headers, .. } => Self::Table(headers.clone()),
+            WriterStreams::Ndjson { .. } => Self::Ndjson,
+        }
+    }
+
+    pub(crate) fn stream(
+        &self,
+        batch: Vec<Value>,
+    ) -> WriterStreams<impl Iterator<Item = CtxResult<Value>> + use<>> {
+        let iter = batch.into_iter().map(Ok);
+
+        match self {
+            Self::Values => WriterStreams::Values { iter },
+            Self::Table(headers) => WriterStreams::Table { headers: headers.clone(), iter },
+            Self::Ndjson => WriterStreams::Ndjson { values: iter },
+        }
+    }
+}
+
+/// Serializes batches on worker threads and writes their output in order, a batch that fails
+/// still has the output of its records before the failure written.
+pub(crate) fn write_ordered<B: Send + 'static>(
+    wtr: &mut impl Write,
+    batches: impl Iterator<Item = B>,
+    threads: usize,
+    work: impl Fn(B) -> (Vec<u8>, CtxResult<()>) + Send + Sync + 'static,
+) -> CtxResult<()> {
+    for (out, res) in Ordered::new(batches, threads, work)? {
+        wtr.write_all(&out).context("Failed to write into output file")?;
+        res?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(input: &str, split: Split, size: usize) -> Vec<(String, usize)> {
+        Chunks::new(input.as_bytes(), split, size)
+            .map(|c| c.map(|c| (String::from_utf8(c.bytes().to_vec()).unwrap(), c.first_line)))
+            .collect::<CtxResult<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_chunks_end_at_records() {
+        assert_eq!(
+            split("a\nbb\n\nccc\nd", Split::Lines, 2),
+            [("a\n", 1), ("bb\n\n", 2), ("ccc\n", 4), ("d", 5)].map(|(s, l)| (s.to_string(), l))
+        );
+
+        let csv = "h\n\"x\ny\",\"q\"\"\n\"\nb\"\nz\n";
+
+        assert_eq!(
+            split(csv, Split::Csv(b','), 1),
+            [("h\n", 1), ("\"x\ny\",\"q\"\"\n\"\n", 2), ("b\"\n", 5), ("z\n", 6)]
+                .map(|(s, l)| (s.to_string(), l))
+        );
+    }
+
+    #[test]
+    fn test_ordered_keeps_job_order() {
+        let mut ordered = Ordered::new(0..100usize, 4, |n| {
+            std::thread::sleep(std::time::Duration::from_micros((100 - n as u64) * 10));
+            n * 2
+        })
+        .unwrap();
+
+        let out: Vec<usize> = ordered.by_ref().collect();
+
+        assert_eq!(out, (0..100).map(|n| n * 2).collect::<Vec<_>>());
+
+        // the sender is dropped once every job is spawned so a lost job can't block forever
+        assert!(ordered.tx.is_none());
+        assert_eq!(ordered.next(), None);
+    }
+}
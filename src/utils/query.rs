@@ -0,0 +1,865 @@
+use crate::utils::{
+    ConvertOptions, CtxResult, CtxResultErr, Map, Records, Value, WriterStreams,
+    filter::{CmpOp, truthy},
+};
+
+/// jq-style query parsed from an expression like `.servers[] | select(.port > 1024) | .host`.
+///
+/// Expressions support:
+/// - Paths: `.` (the record itself), `.key`, `."key with spaces"`, `.[0]`, `.[-1]` and slices like
+///   `.[2:4]`, `.key?` ignores errors (e.g. indexing a number)
+/// - Iteration: `.[]` outputs every element of an array or every value of an object
+/// - Pipes and multiple outputs: `.a | .b`, `.a, .b`
+/// - Construction: arrays (`[.items[].id]`) and objects (`{id, name: .user.name}`)
+/// - Comparisons and logic: `==`, `!=`, `<`, `<=`, `>`, `>=`, `and`, `or`, `//` (alternative)
+///   with the same rules as [`crate::Filter`]
+/// - Functions: `select(f)`, `map(f)`, `has(key)`, `length`, `keys`, `not` and `empty`
+///
+/// # Examples
+///
+/// ```
+/// use fiux::{Converter, Format, Query};
+///
+/// let input = "[[servers]]\nhost = \"a\"\nport = 80\n\n[[servers]]\nhost = \"b\"\nport = 8080\n";
+/// let mut output = Vec::new();
+///
+/// Converter::new(input.as_bytes(), Format::Toml, Format::Csv)
+///     .query(Query::parse(".servers[] | select(.port > 1024) | {host}")?)
+///     .convert(&mut output)?;
+///
+/// assert_eq!(output, b"host\nb\n");
+/// # Ok::<(), fiux::CtxResultErr>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct Query {
+    expr: Expr,
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Identity,
+    Lit(Value),
+    Index(Box<Expr>, Box<Expr>),
+    Slice(Box<Expr>, Option<Box<Expr>>, Option<Box<Expr>>),
+    Iterate(Box<Expr>),
+    Try(Box<Expr>),
+    Pipe(Box<Expr>, Box<Expr>),
+    Comma(Box<Expr>, Box<Expr>),
+    Alt(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Cmp(CmpOp, Box<Expr>, Box<Expr>),
+    Array(Option<Box<Expr>>),
+    Object(Vec<(Expr, Expr)>),
+    Call(Func, Option<Box<Expr>>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Func {
+    Select,
+    Map,
+    Has,
+    Length,
+    Keys,
+    Not,
+    Empty,
+}
+
+impl Func {
+    /// Gets a function and whether it takes an argument from its name.
+    fn from_name(name: &str) -> Option<(Self, bool)> {
+        match name {
+            "select" => Some((Self::Select, true)),
+            "map" => Some((Self::Map, true)),
+            "has" => Some((Self::Has, true)),
+            "length" => Some((Self::Length, false)),
+            "keys" => Some((Self::Keys, false)),
+            "not" => Some((Self::Not, false)),
+            "empty" => Some((Self::Empty, false)),
+            _ => None,
+        }
+    }
+}
+
+impl Query {
+    /// Parses a query expression, invalid expressions are returned as errors with the column of
+    /// the invalid token.
+    pub fn parse(expr: &str) -> CtxResult<Self> {
+        let tokens = lex(expr)?;
+        let mut parser = Parser { tokens, pos: 0 };
+
+        let parsed = parser.pipe()?;
+
+        if let Some((tok, col)) = parser.tokens.get(parser.pos) {
+            return Err(syntax_err(&format!("Unexpected token: {}", tok.describe()), *col));
+        }
+
+        Ok(Self { expr: parsed })
+    }
+
+    /// Runs the query on a record and returns its outputs, a query can output any number of
+    /// values (e.g. `.[]`).
+    pub fn run(&self, input: &Value) -> CtxResult<Vec<Value>> {
+        let mut out = Vec::new();
+
+        eval(&self.expr, input, &mut out)
+            .map_err(|e| CtxResultErr::new("Failed to run query on record", e))?;
+
+        Ok(out)
+    }
+}
+
+/// Runs a query on every record and streams its outputs as records, table rows are turned into
+/// objects first (see [`WriterStreams::into_records`]). Records that the query fails on are logged by the
+/// writers and skipped.
+pub fn query_records(
+    data: WriterStreams<Records>,
+    query: &Query,
+    opts: &ConvertOptions,
+) -> WriterStreams<Records> {
+    let records = data.into_records(opts);
+
+    let query = query.clone();
+
+    let values = records.flat_map(move |rec| {
+        let outputs: Vec<CtxResult<Value>> = match rec.and_then(|v| query.run(&v)) {
+            Ok(values) => values.into_iter().map(Ok).collect(),
+            Err(e) => vec![Err(e)],
+        };
+
+        outputs
+    });
+
+    WriterStreams::Ndjson { values: Box::new(values) }
+}
+
+type EvalResult = Result<(), String>;
+
+/// Evaluates an expression into `out`, runtime errors (e.g. iterating over a number) are returned
+/// as messages.
+fn eval(expr: &Expr, input: &Value, out: &mut Vec<Value>) -> EvalResult {
+    match expr {
+        Expr::Identity => out.push(input.clone()),
+        Expr::Lit(v) => out.push(v.clone()),
+
+        Expr::Index(target, key) => {
+            let keys = outputs(key, input)?;
+
+            for t in outputs(target, input)? {
+                for k in &keys {
+                    out.push(index(&t, k)?);
+                }
+            }
+        }
+
+        Expr::Slice(target, from, to) => {
+            let bound = |e: &Option<Box<Expr>>| -> Result<Option<Value>, String> {
+                match e {
+                    Some(e) => Ok(outputs(e, input)?.into_iter().next()),
+                    None => Ok(None),
+                }
+            };
+
+            let (from, to) = (bound(from)?, bound(to)?);
+
+            for t in outputs(target, input)? {
+                out.push(slice(&t, from.as_ref(), to.as_ref())?);
+            }
+        }
+
+        Expr::Iterate(target) => {
+            for t in outputs(target, input)? {
+                match t {
+                    Value::Array(arr) => out.extend(arr),
+                    Value::Map(map) => out.extend(map.into_values()),
+                    other => return Err(format!("Cannot iterate over {}", other.type_name())),
+                }
+            }
+        }
+
+        Expr::Try(e) => {
+            // outputs before the error are kept
+            let _ = eval(e, input, out);
+        }
+
+        Expr::Pipe(l, r) => {
+            for v in outputs(l, input)? {
+                eval(r, &v, out)?;
+            }
+        }
+
+        Expr::Comma(l, r) => {
+            eval(l, input, out)?;
+            eval(r, input, out)?;
+        }
+
+        Expr::Alt(l, r) => {
+            let mut left = Vec::new();
+            let _ = eval(l, input, &mut left);
+
+            left.retain(|v| !matches!(v, Value::Null | Value::Bool(false)));
+
+            if left.is_empty() {
+                eval(r, input, out)?;
+            } else {
+                out.extend(left);
+            }
+        }
+
+        Expr::And(l, r) | Expr::Or(l, r) => {
+            let is_and = matches!(expr, Expr::And(..));
+
+            for lv in outputs(l, input)? {
+                // short-circuits like jq, `false and _` is false and `true or _` is true
+                if truthy(&lv) != is_and {
+                    out.push(Value::Bool(!is_and));
+                    continue;
+                }
+
+                for rv in outputs(r, input)? {
+                    out.push(Value::Bool(truthy(&rv)));
+                }
+            }
+        }
+
+        Expr::Cmp(op, l, r) => {
+            let rights = outputs(r, input)?;
+
+            for lv in outputs(l, input)? {
+                for rv in &rights {
+                    out.push(Value::Bool(op.apply(&lv, rv)));
+                }
+            }
+        }
+
+        Expr::Array(e) => {
+            let items = match e {
+                Some(e) => outputs(e, input)?,
+                None => Vec::new(),
+            };
+
+            out.push(Value::Array(items));
+        }
+
+        Expr::Object(entries) => {
+            let mut objects = vec![Map::new()];
+
+            for (k, v) in entries {
+                let keys = outputs(k, input)?;
+                let values = outputs(v, input)?;
+                let mut next = Vec::with_capacity(objects.len() * keys.len() * values.len());
+
+                for obj in &objects {
+                    for key in &keys {
+                        let Value::String(key) = key else {
+                            return Err(format!(
+                                "Object keys must be strings, got {}",
+                                key.type_name()
+                            ));
+                        };
+
+                        for value in &values {
+                            let mut obj = obj.clone();
+                            obj.insert(key.to_string(), value.clone());
+                            next.push(obj);
+                        }
+                    }
+                }
+
+                objects = next;
+            }
+
+            out.extend(objects.into_iter().map(Value::Map));
+        }
+
+        Expr::Call(func, arg) => call(*func, arg.as_deref(), input, out)?,
+    }
+
+    Ok(())
+}
+
+#[inline]
+fn outputs(expr: &Expr, input: &Value) -> Result<Vec<Value>, String> {
+    let mut out = Vec::new();
+    eval(expr, input, &mut out)?;
+
+    Ok(out)
+}
+
+fn call(func: Func, arg: Option<&Expr>, input: &Value, out: &mut Vec<Value>) -> EvalResult {
+    let arg = arg.unwrap_or(&Expr::Identity);
+
+    match func {
+        Func::Select => {
+            for cond in outputs(arg, input)? {
+                if truthy(&cond) {
+                    out.push(input.clone());
+                }
+            }
+        }
+
+        Func::Map => {
+            let items: Vec<&Value> = match input {
+                Value::Array(arr) => arr.iter().collect(),
+                Value::Map(map) => map.values().collect(),
+                other => return Err(format!("Cannot iterate over {}", other.type_name())),
+            };
+
+            let mut mapped = Vec::with_capacity(items.len());
+
+            for item in items {
+                eval(arg, item, &mut mapped)?;
+            }
+
+            out.push(Value::Array(mapped));
+        }
+
+        Func::Has => {
+            for key in outputs(arg, input)? {
+                let has = match (input, &key) {
+                    (Value::Map(map), Value::String(k)) => map.contains_key(k),
+                    (Value::Array(arr), Value::Int(i)) => *i >= 0 && (*i as usize) < arr.len(),
+                    _ => {
+                        return Err(format!(
+                            "Cannot check if {} has a {} key",
+                            input.type_name(),
+                            key.type_name()
+                        ));
+                    }
+                };
+
+                out.push(Value::Bool(has));
+            }
+        }
+
+        Func::Length => out.push(match input {
+            Value::Null => Value::Int(0),
+            Value::Int(i) => Value::Int(i.saturating_abs()),
+            Value::Float(f) => Value::Float(f.abs()),
+            Value::String(s) => Value::Int(s.chars().count() as i64),
+            Value::Bytes(b) => Value::Int(b.len() as i64),
+            Value::Array(arr) => Value::Int(arr.len() as i64),
+            Value::Map(map) => Value::Int(map.len() as i64),
+            other => return Err(format!("{} has no length", other.type_name())),
+        }),
+
+        Func::Keys => out.push(match input {
+            Value::Map(map) => {
+                let mut keys: Vec<&String> = map.keys().collect();
+                keys.sort();
+
+                Value::Array(keys.into_iter().map(|k| Value::String(k.to_string())).collect())
+            }
+            Value::Array(arr) => Value::Array((0..arr.len() as i64).map(Value::Int).collect()),
+            other => return Err(format!("{} has no keys", other.type_name())),
+        }),
+
+        Func::Not => out.push(Value::Bool(!truthy(input))),
+        Func::Empty => {}
+    }
+
+    Ok(())
+}
+
+fn index(target: &Value, key: &Value) -> Result<Value, String> {
+    match (target, key) {
+        (Value::Map(map), Value::String(k)) => Ok(map.get(k).cloned().unwrap_or_default()),
+        (Value::Array(arr), Value::Int(i)) => {
+            let i = if *i < 0 { arr.len() as i64 + i } else { *i };
+
+            Ok(usize::try_from(i).ok().and_then(|i| arr.get(i)).cloned().unwrap_or_default())
+        }
+        (Value::Null, Value::String(_) | Value::Int(_)) => Ok(Value::Null),
+        _ => Err(format!("Cannot index {} with {}", target.type_name(), key.type_name())),
+    }
+}
+
+fn slice(target: &Value, from: Option<&Value>, to: Option<&Value>) -> Result<Value, String> {
+    let len = match target {
+        Value::Null => return Ok(Value::Null),
+        Value::Array(arr) => arr.len(),
+        Value::String(s) => s.chars().count(),
+        other => return Err(format!("Cannot slice {}", other.type_name())),
+    };
+
+    let bound = |v: Option<&Value>, default: usize| match v {
+        None | Some(Value::Null) => Ok(default),
+        Some(Value::Int(i)) if *i < 0 => Ok(len.saturating_sub(i.unsigned_abs() as usize)),
+        Some(Value::Int(i)) => Ok((*i as usize).min(len)),
+        Some(other) => Err(format!("Cannot slice with {}", other.type_name())),
+    };
+
+    let (from, to) = (bound(from, 0)?, bound(to, len)?);
+    let to = to.max(from);
+
+    Ok(match target {
+        Value::Array(arr) => Value::Array(arr[from..to].to_vec()),
+        Value::String(s) => Value::String(s.chars().skip(from).take(to - from).collect()),
+        _ => unreachable!(),
+    })
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Num(Value),
+    Punct(&'static str),
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Ident(s) => format!("`{}`", s),
+            Token::Str(s) => format!("`\"{}\"`", s),
+            Token::Num(v) => format!("`{}`", v.to_cell()),
+            Token::Punct(p) => format!("`{}`", p),
+        }
+    }
+}
+
+#[inline]
+fn syntax_err(msg: &str, col: usize) -> CtxResultErr {
+    CtxResultErr::new("Invalid query expression", format!("{} at column: {}", msg, col))
+}
+
+const PUNCT: [&str; 18] =
+    ["==", "!=", "<=", ">=", "//", "<", ">", ".", "[", "]", "{", "}", "(", ")", ":", ",", "|", "?"];
+
+fn lex(src: &str) -> CtxResult<Vec<(Token, usize)>> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let col = i + 1;
+
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        if c == '"' {
+            let mut s = String::new();
+            i += 1;
+
+            loop {
+                match chars.get(i) {
+                    None => return Err(syntax_err("Unterminated string", col)),
+                    Some('"') => break,
+                    Some('\\') => {
+                        i += 1;
+
+                        match chars.get(i) {
+                            Some('n') => s.push('\n'),
+                            Some('t') => s.push('\t'),
+                            Some('r') => s.push('\r'),
+                            Some(&e) => s.push(e),
+                            None => return Err(syntax_err("Unterminated string", col)),
+                        }
+                    }
+                    Some(&ch) => s.push(ch),
+                }
+
+                i += 1;
+            }
+
+            i += 1;
+            tokens.push((Token::Str(s), col));
+            continue;
+        }
+
+        if c.is_ascii_digit() || (c == '-' && chars.get(i + 1).is_some_and(char::is_ascii_digit)) {
+            let start = i;
+            i += 1;
+
+            while chars.get(i).is_some_and(|n| {
+                n.is_ascii_digit()
+                    || matches!(n, 'e' | 'E')
+                    || (*n == '.' && chars.get(i + 1).is_some_and(char::is_ascii_digit))
+                    || (matches!(n, '-' | '+') && matches!(chars[i - 1], 'e' | 'E'))
+            }) {
+                i += 1;
+            }
+
+            let num: String = chars[start..i].iter().collect();
+
+            let lit = if let Ok(n) = num.parse::<i64>() {
+                Value::Int(n)
+            } else if let Ok(f) = num.parse::<f64>() {
+                Value::Float(f)
+            } else {
+                return Err(syntax_err(&format!("Invalid number: {}", num), col));
+            };
+
+            tokens.push((Token::Num(lit), col));
+            continue;
+        }
+
+        if c.is_alphabetic() || c == '_' {
+            let start = i;
+
+            while chars.get(i).is_some_and(|n| n.is_alphanumeric() || *n == '_') {
+                i += 1;
+            }
+
+            tokens.push((Token::Ident(chars[start..i].iter().collect()), col));
+            continue;
+        }
+
+        let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+
+        match PUNCT.iter().find(|p| rest.starts_with(*p)) {
+            Some(p) => {
+                tokens.push((Token::Punct(p), col));
+                i += p.len();
+            }
+            None => return Err(syntax_err(&format!("Unexpected character: `{}`", c), col)),
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(t, _)| t)
+    }
+
+    fn col(&self) -> usize {
+        self.tokens.get(self.pos).or(self.tokens.last()).map_or(1, |(_, c)| *c)
+    }
+
+    fn is(&self, punct: &str) -> bool {
+        matches!(self.peek(), Some(Token::Punct(p)) if *p == punct)
+    }
+
+    fn is_ident(&self, ident: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(i)) if i == ident)
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        let found = self.is(punct);
+
+        if found {
+            self.pos += 1;
+        }
+
+        found
+    }
+
+    fn expect(&mut self, punct: &str) -> CtxResult<()> {
+        if self.eat(punct) {
+            Ok(())
+        } else {
+            Err(syntax_err(&format!("Expected `{}`", punct), self.col()))
+        }
+    }
+
+    fn pipe(&mut self) -> CtxResult<Expr> {
+        let mut lhs = self.comma()?;
+
+        while self.eat("|") {
+            lhs = Expr::Pipe(Box::new(lhs), Box::new(self.comma()?));
+        }
+
+        Ok(lhs)
+    }
+
+    fn comma(&mut self) -> CtxResult<Expr> {
+        let mut lhs = self.alt()?;
+
+        while self.eat(",") {
+            lhs = Expr::Comma(Box::new(lhs), Box::new(self.alt()?));
+        }
+
+        Ok(lhs)
+    }
+
+    fn alt(&mut self) -> CtxResult<Expr> {
+        let mut lhs = self.or()?;
+
+        while self.eat("//") {
+            lhs = Expr::Alt(Box::new(lhs), Box::new(self.or()?));
+        }
+
+        Ok(lhs)
+    }
+
+    fn or(&mut self) -> CtxResult<Expr> {
+        let mut lhs = self.and()?;
+
+        while self.is_ident("or") {
+            self.pos += 1;
+            lhs = Expr::Or(Box::new(lhs), Box::new(self.and()?));
+        }
+
+        Ok(lhs)
+    }
+
+    fn and(&mut self) -> CtxResult<Expr> {
+        let mut lhs = self.cmp()?;
+
+        while self.is_ident("and") {
+            self.pos += 1;
+            lhs = Expr::And(Box::new(lhs), Box::new(self.cmp()?));
+        }
+
+        Ok(lhs)
+    }
+
+    fn cmp(&mut self) -> CtxResult<Expr> {
+        let lhs = self.postfix()?;
+
+        let op = match self.peek() {
+            Some(Token::Punct(p)) => CmpOp::from_symbol(p),
+            _ => None,
+        };
+
+        let Some(op) = op else {
+            return Ok(lhs);
+        };
+
+        self.pos += 1;
+
+        Ok(Expr::Cmp(op, Box::new(lhs), Box::new(self.postfix()?)))
+    }
+
+    fn postfix(&mut self) -> CtxResult<Expr> {
+        let mut expr = self.term()?;
+
+        loop {
+            if self.eat("?") {
+                expr = Expr::Try(Box::new(expr));
+            } else if self.eat("[") {
+                expr = self.bracket(expr)?;
+            } else if self.is(".") {
+                self.pos += 1;
+
+                expr = match self.tokens.get(self.pos).cloned() {
+                    Some((Token::Ident(k) | Token::Str(k), _)) => {
+                        self.pos += 1;
+                        Expr::Index(Box::new(expr), Box::new(Expr::Lit(Value::String(k))))
+                    }
+                    Some((Token::Punct("["), _)) => {
+                        self.pos += 1;
+                        self.bracket(expr)?
+                    }
+                    _ => return Err(syntax_err("Expected a key after `.`", self.col())),
+                };
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    /// Parses the rest of `[...]` after a value, the opening bracket is already consumed.
+    fn bracket(&mut self, target: Expr) -> CtxResult<Expr> {
+        let target = Box::new(target);
+
+        if self.eat("]") {
+            return Ok(Expr::Iterate(target));
+        }
+
+        if self.eat(":") {
+            let to = self.pipe()?;
+            self.expect("]")?;
+
+            return Ok(Expr::Slice(target, None, Some(Box::new(to))));
+        }
+
+        let key = self.pipe()?;
+
+        if self.eat(":") {
+            let to = if self.is("]") { None } else { Some(Box::new(self.pipe()?)) };
+            self.expect("]")?;
+
+            return Ok(Expr::Slice(target, Some(Box::new(key)), to));
+        }
+
+        self.expect("]")?;
+
+        Ok(Expr::Index(target, Box::new(key)))
+    }
+
+    fn term(&mut self) -> CtxResult<Expr> {
+        let col = self.col();
+
+        let Some((tok, _)) = self.tokens.get(self.pos).cloned() else {
+            return Err(syntax_err("Unexpected end of expression", col));
+        };
+
+        self.pos += 1;
+
+        match tok {
+            Token::Num(v) => Ok(Expr::Lit(v)),
+            Token::Str(s) => Ok(Expr::Lit(Value::String(s))),
+
+            Token::Punct(".") => match self.tokens.get(self.pos).cloned() {
+                Some((Token::Ident(k) | Token::Str(k), _)) => {
+                    self.pos += 1;
+                    Ok(Expr::Index(Box::new(Expr::Identity), Box::new(Expr::Lit(Value::String(k)))))
+                }
+                Some((Token::Punct("["), _)) => {
+                    self.pos += 1;
+                    self.bracket(Expr::Identity)
+                }
+                _ => Ok(Expr::Identity),
+            },
+
+            Token::Punct("(") => {
+                let expr = self.pipe()?;
+                self.expect(")")?;
+
+                Ok(expr)
+            }
+
+            Token::Punct("[") => {
+                if self.eat("]") {
+                    return Ok(Expr::Array(None));
+                }
+
+                let expr = self.pipe()?;
+                self.expect("]")?;
+
+                Ok(Expr::Array(Some(Box::new(expr))))
+            }
+
+            Token::Punct("{") => self.object(),
+
+            Token::Ident(name) => match name.as_str() {
+                "true" => Ok(Expr::Lit(Value::Bool(true))),
+                "false" => Ok(Expr::Lit(Value::Bool(false))),
+                "null" => Ok(Expr::Lit(Value::Null)),
+                _ => {
+                    let Some((func, takes_arg)) = Func::from_name(&name) else {
+                        return Err(syntax_err(&format!("Unknown function: {}", name), col));
+                    };
+
+                    if !takes_arg {
+                        return Ok(Expr::Call(func, None));
+                    }
+
+                    self.expect("(")?;
+                    let arg = self.pipe()?;
+                    self.expect(")")?;
+
+                    Ok(Expr::Call(func, Some(Box::new(arg))))
+                }
+            },
+
+            other => Err(syntax_err(&format!("Unexpected token: {}", other.describe()), col)),
+        }
+    }
+
+    /// Parses an object after its opening brace, keys without values (e.g. `{id}`) take the value
+    /// of the key with the same name.
+    fn object(&mut self) -> CtxResult<Expr> {
+        let mut entries = Vec::new();
+
+        while !self.eat("}") {
+            if !entries.is_empty() {
+                self.expect(",")?;
+            }
+
+            let col = self.col();
+
+            let key = match self.tokens.get(self.pos).cloned() {
+                Some((Token::Ident(k) | Token::Str(k), _)) => {
+                    self.pos += 1;
+                    Expr::Lit(Value::String(k))
+                }
+                Some((Token::Punct("("), _)) => {
+                    self.pos += 1;
+                    let key = self.pipe()?;
+                    self.expect(")")?;
+
+                    key
+                }
+                _ => return Err(syntax_err("Expected an object key", col)),
+            };
+
+            let value = if self.eat(":") {
+                self.alt()?
+            } else {
+                match &key {
+                    Expr::Lit(_) => Expr::Index(Box::new(Expr::Identity), Box::new(key.clone())),
+                    _ => return Err(syntax_err("Expected `:` after a computed key", col)),
+                }
+            };
+
+            entries.push((key, value));
+        }
+
+        Ok(Expr::Object(entries))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn run(expr: &str, input: serde_json::Value) -> Vec<Value> {
+        Query::parse(expr).unwrap().run(&input.into()).unwrap()
+    }
+
+    fn values(items: &[serde_json::Value]) -> Vec<Value> {
+        items.iter().cloned().map(Value::from).collect()
+    }
+
+    #[test]
+    fn test_paths_iteration_and_slices() {
+        let doc = json!({"servers": [{"host": "a"}, {"host": "b"}, {"host": "c"}], "my key": 1});
+
+        assert_eq!(
+            run(".servers[].host", doc.clone()),
+            values(&[json!("a"), json!("b"), json!("c")])
+        );
+        assert_eq!(
+            run(".servers[-1].host, .\"my key\"", doc.clone()),
+            values(&[json!("c"), json!(1)])
+        );
+        assert_eq!(run(".servers[1:] | length", doc.clone()), values(&[json!(2)]));
+        assert_eq!(run(".servers[0].host[:1]", doc.clone()), values(&[json!("a")]));
+        assert_eq!(run(".missing.key, .missing[0]", doc), values(&[json!(null), json!(null)]));
+    }
+
+    #[test]
+    fn test_select_map_and_construction() {
+        let doc = json!([{"id": 1, "tags": ["x"]}, {"id": 7, "tags": []}]);
+
+        assert_eq!(
+            run(".[] | select(.id > 2 and (.tags | length) == 0) | {id, n: .tags}", doc.clone()),
+            values(&[json!({"id": 7, "n": []})])
+        );
+        assert_eq!(run("map(.id)", doc.clone()), values(&[json!([1, 7])]));
+        assert_eq!(run("[.[] | .id] | .[0]", doc.clone()), values(&[json!(1)]));
+        assert_eq!(
+            run(".[0] | keys, has(\"id\")", doc),
+            values(&[json!(["id", "tags"]), json!(true)])
+        );
+    }
+
+    #[test]
+    fn test_alternative_and_errors() {
+        let doc = json!({"a": null, "b": 2, "n": 5});
+
+        assert_eq!(run(".a // .b", doc.clone()), values(&[json!(2)]));
+        assert_eq!(run(".n[]?, .b", doc.clone()), values(&[json!(2)]));
+        assert!(Query::parse(".n[]").unwrap().run(&doc.into()).is_err());
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        for expr in [".[", ".a |", "foo", "select .a", "{(.a)}", ".a ==", "\"abc", ".a.", "{a b}"] {
+            assert!(Query::parse(expr).is_err(), "{}", expr);
+        }
+    }
+}
@@ -1,20 +1,17 @@
-use std::{fs::File, io::BufReader, path::PathBuf};
+use std::io::{BufReader, Error, ErrorKind as EK, Read};
 
-#[inline]
-pub fn csv_reader(path: &PathBuf, delimiter: char) -> csv::Reader<BufReader<File>> {
-    let file = File::open(path).unwrap_or_else(|e| {
-        eprintln!("Failed to open input file\nError: {}", e);
-        std::process::exit(1);
-    });
+use crate::utils::{CtxResult, CtxResultExt};
 
-    let buffered_reader = BufReader::with_capacity(256 * 1024, file);
+#[inline]
+pub fn csv_reader<R: Read>(reader: R, delimiter: char) -> CtxResult<csv::Reader<BufReader<R>>> {
+    let buffered_reader = BufReader::with_capacity(256 * 1024, reader);
 
     if !delimiter.is_ascii() {
-        eprintln!("Input delimiter: {} is not valid UTF-8", delimiter);
-        std::process::exit(1);
+        Err(Error::new(EK::InvalidInput, format!("Input delimiter: {} is not ASCII", delimiter)))
+            .context("Failed to create CSV reader")?;
     }
 
     let d = delimiter as u8;
 
-    csv::ReaderBuilder::new().delimiter(d).from_reader(buffered_reader)
+    Ok(csv::ReaderBuilder::new().delimiter(d).from_reader(buffered_reader))
 }
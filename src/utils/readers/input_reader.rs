@@ -0,0 +1,245 @@
+use memmap2::Mmap;
+use resext::ctx;
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read, stdin},
+    path::Path,
+    sync::Arc,
+};
+
+use crate::utils::{
+    Compression, ConvertOptions, CtxResult, CtxResultExt, FormatDecoder, FormatValidator, Records,
+    WriterStreams, is_std_stream,
+};
+
+/// UTF-8 byte order mark, skipped at the start of input.
+const BOM: &[u8] = b"\xef\xbb\xbf";
+
+/// Regular files at least this large are memory-mapped by [`open_input`] instead of being read
+/// through a buffer.
+pub const MMAP_THRESHOLD: u64 = 4 * 1024 * 1024;
+
+/// Opens the input for reading, `-` reads from stdin instead of a file. Compressed input is
+/// decompressed while it's read.
+#[inline]
+pub fn input_reader(path: &Path) -> CtxResult<Box<dyn Read>> {
+    if is_std_stream(path) {
+        return decompressed(stdin().lock(), path);
+    }
+
+    let file = File::open(path)
+        .context("Failed to open input file")
+        .context(ctx!("Input file: {}", path.to_string_lossy()))?;
+
+    decompressed(BufReader::new(file), path)
+}
+
+/// Opens the input like [`input_reader`], regular files of at least [`MMAP_THRESHOLD`] bytes are
+/// memory-mapped so decoders can slice records from the mapping. Stdin, pipes, files that can't
+/// be mapped and compressed files are read through a buffer.
+pub fn open_input(path: &Path) -> CtxResult<Input> {
+    if is_std_stream(path) {
+        return Ok(Input::Reader(decompressed(stdin().lock(), path)?));
+    }
+
+    let file = File::open(path)
+        .context("Failed to open input file")
+        .context(ctx!("Input file: {}", path.to_string_lossy()))?;
+
+    let mappable = file.metadata().is_ok_and(|m| m.is_file() && m.len() >= MMAP_THRESHOLD);
+
+    if mappable && let Ok(mut mapped) = Mapped::new(&file) {
+        if let Some(compression) = compression(path, mapped.as_slice()) {
+            let mut reader = BufReader::new(compression.decoder(mapped)?);
+            skip_bom(&mut reader)?;
+
+            return Ok(Input::Reader(Box::new(reader)));
+        }
+
+        skip_bom(&mut mapped)?;
+        return Ok(Input::Mapped(mapped));
+    }
+
+    Ok(Input::Reader(decompressed(BufReader::new(file), path)?))
+}
+
+/// Gets the compression of input from its magic bytes, falling back to its extension so
+/// corrupt compressed files fail with the decompressor's error.
+fn compression(path: &Path, head: &[u8]) -> Option<Compression> {
+    Compression::detect(head).or_else(|| Compression::from_path(path))
+}
+
+/// Wraps input in a decompressor if it's compressed, the first bytes are peeked from the buffer
+/// so nothing has to be read twice. A UTF-8 BOM at the start of the (decompressed) input is
+/// skipped.
+fn decompressed(mut reader: impl BufRead + 'static, path: &Path) -> CtxResult<Box<dyn Read>> {
+    let head = reader.fill_buf().context("Failed to read input file")?;
+
+    if let Some(compression) = compression(path, head) {
+        let mut reader = BufReader::new(compression.decoder(reader)?);
+        skip_bom(&mut reader)?;
+
+        return Ok(Box::new(reader));
+    }
+
+    skip_bom(&mut reader)?;
+    Ok(Box::new(reader))
+}
+
+/// Skips a UTF-8 BOM, which none of the decoders expect before the first value / header.
+fn skip_bom(reader: &mut impl BufRead) -> CtxResult<()> {
+    if reader.fill_buf().context("Failed to read input file")?.starts_with(BOM) {
+        reader.consume(BOM.len());
+    }
+
+    Ok(())
+}
+
+/// Source of a conversion or validation.
+pub enum Input {
+    /// Stream read through a buffer (e.g. stdin, pipes or small files)
+    Reader(Box<dyn Read>),
+    /// File mapped into memory
+    Mapped(Mapped),
+}
+
+impl Input {
+    /// Decodes the input, mapped input is sliced by decoders that support it.
+    pub(crate) fn decode(
+        self,
+        decoder: &dyn FormatDecoder,
+        opts: &ConvertOptions,
+    ) -> CtxResult<WriterStreams<Records>> {
+        match self {
+            Self::Reader(reader) => decoder.decode(reader, opts),
+            Self::Mapped(mapped) => decoder.decode_mapped(mapped, opts),
+        }
+    }
+
+    /// Validates the input, mapped input is sliced by validators that support it.
+    pub(crate) fn validate(
+        self,
+        validator: &dyn FormatValidator,
+        opts: &ConvertOptions,
+    ) -> CtxResult<()> {
+        match self {
+            Self::Reader(mut reader) => validator.validate(&mut reader, opts),
+            Self::Mapped(mapped) => validator.validate_mapped(mapped.as_slice(), opts),
+        }
+    }
+}
+
+impl Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Reader(reader) => reader.read(buf),
+            Self::Mapped(mapped) => mapped.read(buf),
+        }
+    }
+}
+
+/// Read-only memory map of a file that can be shared between threads, reading from it advances
+/// through the mapping without copying it into a buffer first.
+///
+/// The file must not be truncated while it's mapped, like any other memory-mapped file.
+#[derive(Clone)]
+pub struct Mapped {
+    map: Arc<Mmap>,
+    pos: usize,
+}
+
+impl Mapped {
+    /// Maps a whole file into memory.
+    pub fn new(file: &File) -> CtxResult<Self> {
+        // SAFETY: the mapping is read-only and only read through slices, the same caveat as
+        // every mmap applies (the file must not be truncated by another process while mapped)
+        let map = unsafe { Mmap::map(file) }.context("Failed to map input file into memory")?;
+
+        Ok(Self { map: Arc::new(map), pos: 0 })
+    }
+
+    /// Gets the bytes that haven't been read yet.
+    #[inline(always)]
+    pub fn as_slice(&self) -> &[u8] {
+        &self.map[self.pos..]
+    }
+}
+
+impl AsRef<[u8]> for Mapped {
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl Read for Mapped {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.as_slice().read(buf)?;
+        self.pos += n;
+
+        Ok(n)
+    }
+}
+
+impl BufRead for Mapped {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        Ok(self.as_slice())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.map.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{csv_decoder, csv_reader, ndjson_decoder, ndjson_reader};
+    use std::io::Write;
+
+    fn collect(data: WriterStreams<Records>) -> Vec<String> {
+        let rows = match data {
+            WriterStreams::Table { iter, .. } => iter,
+            WriterStreams::Ndjson { values } => values,
+            WriterStreams::Values { iter } => iter,
+        };
+
+        rows.map(|row| match row {
+            Ok(value) => format!("{:?}", value),
+            Err(err) => err.to_string(),
+        })
+        .collect()
+    }
+
+    fn mapped(content: &str) -> Mapped {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+
+        Mapped::new(&file).unwrap()
+    }
+
+    #[test]
+    fn test_mapped_decoders_match_readers() {
+        let csv = "id,note\r\n1,\"a\nb\"\n2\n\n3,\"q\"\"\"\n4,x";
+
+        let expected = collect(
+            csv_decoder::csv_decoder(csv_reader::csv_reader(csv.as_bytes(), ',').unwrap())
+                .unwrap()
+                .boxed(),
+        );
+
+        for threads in [1, 2] {
+            let data = csv_decoder::csv_decoder_mapped(mapped(csv), ',', threads).unwrap();
+            assert_eq!(collect(data), expected);
+        }
+
+        let ndjson = "{\"a\": 1}\r\n\n{broken\n[1, 2]";
+
+        let reader = ndjson_reader::ndjson_reader(ndjson.as_bytes());
+        let expected = collect(ndjson_decoder::ndjson_decoder(reader).unwrap().boxed());
+
+        for threads in [1, 2] {
+            let data = ndjson_decoder::ndjson_decoder_mapped(mapped(ndjson), threads).unwrap();
+            assert_eq!(collect(data), expected);
+        }
+    }
+}
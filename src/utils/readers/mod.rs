@@ -1,4 +1,6 @@
 pub mod csv_reader;
+pub mod input_reader;
 pub mod json_reader;
 pub mod ndjson_reader;
 pub mod toml_reader;
+pub mod yaml_reader;
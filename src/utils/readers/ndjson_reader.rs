@@ -1,11 +1,6 @@
-use std::{fs::File, io::BufReader, path::PathBuf};
+use std::io::{BufReader, Read};
 
 #[inline]
-pub fn ndjson_reader(path: &PathBuf) -> BufReader<File> {
-    let file = File::open(path).unwrap_or_else(|e| {
-        eprintln!("Failed to open input file\nError: {}", e);
-        std::process::exit(1);
-    });
-
-    BufReader::with_capacity(256 * 1024, file)
+pub fn ndjson_reader<R: Read>(reader: R) -> BufReader<R> {
+    BufReader::with_capacity(256 * 1024, reader)
 }
@@ -1,7 +1,12 @@
+use std::io::Read;
+
+use crate::utils::{CtxResult, CtxResultExt};
+
 #[inline]
-pub fn toml_reader(path: &std::path::PathBuf) -> Vec<u8> {
-    std::fs::read(path).unwrap_or_else(|e| {
-        eprintln!("Failed to read input file\nError: {}", e);
-        std::process::exit(1);
-    })
+pub fn toml_reader<R: Read>(mut reader: R) -> CtxResult<Vec<u8>> {
+    let mut content = Vec::new();
+
+    reader.read_to_end(&mut content).context("Failed to read input file")?;
+
+    Ok(content)
 }
@@ -0,0 +1,8 @@
+use std::io::{BufReader, Read};
+
+#[inline]
+pub fn yaml_reader<'de, R: Read + 'de>(reader: R) -> serde_yaml::Deserializer<'de> {
+    let buffered = BufReader::with_capacity(256 * 1024, reader);
+
+    serde_yaml::Deserializer::from_reader(buffered)
+}
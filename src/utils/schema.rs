@@ -0,0 +1,354 @@
+use indexmap::IndexMap;
+use resext::ctx;
+
+use crate::utils::{
+    ColumnType, ConvertOptions, CtxResult, CtxResultExt, Log, Map, Records, Value, WriterStreams,
+};
+
+/// URI of the JSON Schema dialect of inferred schemas.
+pub const SCHEMA_DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// Options for inferring a JSON Schema.
+#[derive(Clone, Debug)]
+pub struct SchemaOpts {
+    /// Largest number of distinct strings written as an `enum`, `0` never writes enums
+    pub enum_limit: usize,
+}
+
+impl Default for SchemaOpts {
+    fn default() -> Self {
+        Self { enum_limit: 10 }
+    }
+}
+
+/// Infers a JSON Schema (draft 2020-12) that every record matches, walking every record and
+/// merging their types, nested objects and array items.
+///
+/// - Properties that are in every object are `required`, values of different types are written as
+///   a list of types (e.g. `["string", "null"]`) and integers mixed with floats are `number`
+/// - Strings get a `format` when every value matches it (`date-time`, `date`, `email` or `uri`)
+/// - Strings with few distinct values (up to `enum_limit`, each seen at least twice on average)
+///   are written as an `enum`
+///
+/// Table rows are turned into objects keyed by their headers (see
+/// [`WriterStreams::into_records`]), cells of untyped tables (e.g. CSV without `--parse-numbers`)
+/// are typed like [`ColumnType::infer`] and empty cells are `null`.
+pub fn infer_schema(
+    data: WriterStreams<Records>,
+    opts: &SchemaOpts,
+    convert_opts: &ConvertOptions,
+) -> CtxResult<Value> {
+    let infer = matches!(data, WriterStreams::Table { .. }) && !convert_opts.is_typed();
+    let mut root = Node::default();
+
+    for (idx, rec) in data.into_records(convert_opts).enumerate() {
+        let Some(rec) =
+            rec.context(ctx!("Failed to infer schema of record: {}", idx + 1)).log("[WARN]")
+        else {
+            continue;
+        };
+
+        root.update(&rec, infer, opts.enum_limit);
+    }
+
+    let Value::Map(schema) = root.finish(opts.enum_limit) else {
+        unreachable!("schemas are objects")
+    };
+
+    let mut out = Map::with_capacity(schema.len() + 1);
+    out.insert("$schema".to_string(), Value::String(SCHEMA_DIALECT.to_string()));
+    out.extend(schema);
+
+    Ok(Value::Map(out))
+}
+
+/// Infers a schema of records as a stream of one value, see [`infer_schema`].
+pub fn schema_records(
+    data: WriterStreams<Records>,
+    opts: &SchemaOpts,
+    convert_opts: &ConvertOptions,
+) -> CtxResult<WriterStreams<Records>> {
+    let schema = infer_schema(data, opts, convert_opts)?;
+
+    Ok(WriterStreams::Values { iter: Box::new(std::iter::once(Ok(schema))) })
+}
+
+const NULL: u8 = 1;
+const BOOLEAN: u8 = 1 << 1;
+const INTEGER: u8 = 1 << 2;
+const NUMBER: u8 = 1 << 3;
+const STRING: u8 = 1 << 4;
+const ARRAY: u8 = 1 << 5;
+const OBJECT: u8 = 1 << 6;
+
+const TYPE_NAMES: [(u8, &str); 7] = [
+    (OBJECT, "object"),
+    (ARRAY, "array"),
+    (STRING, "string"),
+    (INTEGER, "integer"),
+    (NUMBER, "number"),
+    (BOOLEAN, "boolean"),
+    (NULL, "null"),
+];
+
+/// Format of every string of a node, `Mixed` once strings with different formats are seen.
+#[derive(Clone, Copy, Default, PartialEq)]
+enum StrFormat {
+    #[default]
+    Unset,
+    Some(&'static str),
+    Mixed,
+}
+
+/// Merged schema of every value seen at one place in records.
+#[derive(Default)]
+struct Node {
+    types: u8,
+    seen: u64,
+    strings: u64,
+    format: StrFormat,
+    enum_values: Option<Vec<String>>,
+    objects: u64,
+    properties: IndexMap<String, Node>,
+    items: Option<Box<Node>>,
+}
+
+impl Node {
+    fn update(&mut self, value: &Value, infer: bool, enum_limit: usize) {
+        self.seen += 1;
+
+        match value {
+            Value::String(s) if infer => match ColumnType::infer(s).convert(s) {
+                Some(Value::String(_)) | None => self.update_string(s, enum_limit),
+                Some(typed) => {
+                    self.seen -= 1;
+                    self.update(&typed, false, enum_limit);
+                }
+            },
+            Value::Null => self.types |= NULL,
+            Value::Bool(_) => self.types |= BOOLEAN,
+            Value::Int(_) => self.types |= INTEGER,
+            Value::Decimal(d) if !d.contains(['.', 'e', 'E']) => self.types |= INTEGER,
+            Value::Float(_) | Value::Decimal(_) => self.types |= NUMBER,
+            Value::String(s) | Value::DateTime(s) => self.update_string(s, enum_limit),
+            Value::Bytes(b) => self.update_string(&String::from_utf8_lossy(b), enum_limit),
+            Value::Array(items) => {
+                self.types |= ARRAY;
+
+                let node = self.items.get_or_insert_default();
+
+                for item in items {
+                    node.update(item, infer, enum_limit);
+                }
+            }
+            Value::Map(map) => {
+                self.types |= OBJECT;
+                self.objects += 1;
+
+                for (k, v) in map {
+                    self.properties.entry(k.to_string()).or_default().update(v, infer, enum_limit);
+                }
+            }
+        }
+    }
+
+    fn update_string(&mut self, s: &str, enum_limit: usize) {
+        self.types |= STRING;
+        self.strings += 1;
+
+        self.format = match (self.format, string_format(s)) {
+            (StrFormat::Unset, Some(f)) => StrFormat::Some(f),
+            (StrFormat::Some(a), Some(b)) if a == b => StrFormat::Some(a),
+            _ => StrFormat::Mixed,
+        };
+
+        if self.strings == 1 && enum_limit > 0 {
+            self.enum_values = Some(Vec::new());
+        }
+
+        if let Some(values) = &mut self.enum_values
+            && !values.iter().any(|v| v == s)
+        {
+            if values.len() < enum_limit {
+                values.push(s.to_string());
+            } else {
+                self.enum_values = None;
+            }
+        }
+    }
+
+    fn finish(self, enum_limit: usize) -> Value {
+        let mut schema = Map::new();
+
+        // integers are numbers so both are written as `number`
+        let types = if self.types & NUMBER != 0 { self.types & !INTEGER } else { self.types };
+        let names: Vec<Value> = TYPE_NAMES
+            .iter()
+            .filter(|(t, _)| types & t != 0)
+            .map(|(_, name)| Value::String(name.to_string()))
+            .collect();
+
+        match names.len() {
+            0 => {}
+            1 => {
+                schema.insert("type".to_string(), names.into_iter().next().unwrap_or_default());
+            }
+            _ => {
+                schema.insert("type".to_string(), Value::Array(names));
+            }
+        }
+
+        if let StrFormat::Some(format) = self.format {
+            schema.insert("format".to_string(), Value::String(format.to_string()));
+        } else if let Some(values) = self.enum_values
+            && types & !(STRING | NULL) == 0
+            && values.len() <= enum_limit
+            && values.len() as u64 * 2 <= self.strings
+        {
+            let mut values: Vec<Value> = values.into_iter().map(Value::String).collect();
+
+            if types & NULL != 0 {
+                values.push(Value::Null);
+            }
+
+            schema.insert("enum".to_string(), Value::Array(values));
+        }
+
+        if types & OBJECT != 0 {
+            let objects = self.objects;
+            let mut required = Vec::new();
+            let mut properties = Map::with_capacity(self.properties.len());
+
+            for (k, node) in self.properties {
+                if node.seen >= objects {
+                    required.push(Value::String(k.to_string()));
+                }
+
+                properties.insert(k, node.finish(enum_limit));
+            }
+
+            schema.insert("properties".to_string(), Value::Map(properties));
+
+            if !required.is_empty() {
+                schema.insert("required".to_string(), Value::Array(required));
+            }
+        }
+
+        if let Some(items) = self.items
+            && items.seen > 0
+        {
+            schema.insert("items".to_string(), items.finish(enum_limit));
+        }
+
+        Value::Map(schema)
+    }
+}
+
+/// Gets the JSON Schema format of a string, dates are `YYYY-MM-DD` and datetimes are full RFC 3339
+/// timestamps with seconds and an offset (e.g. `2024-01-31T10:00:00Z`).
+fn string_format(s: &str) -> Option<&'static str> {
+    match ColumnType::infer(s) {
+        ColumnType::Date => return Some("date"),
+        ColumnType::DateTime
+            if s.as_bytes()[10].eq_ignore_ascii_case(&b't')
+                && s.as_bytes().get(16) == Some(&b':')
+                && (s.ends_with(['Z', 'z'])
+                    || s.len() > 6 && matches!(s.as_bytes()[s.len() - 6], b'+' | b'-')) =>
+        {
+            return Some("date-time");
+        }
+        _ => {}
+    }
+
+    if is_email(s) {
+        Some("email")
+    } else if is_uri(s) {
+        Some("uri")
+    } else {
+        None
+    }
+}
+
+fn is_email(s: &str) -> bool {
+    let Some((local, domain)) = s.split_once('@') else {
+        return false;
+    };
+
+    !local.is_empty()
+        && !s.contains(char::is_whitespace)
+        && !domain.contains('@')
+        && domain.split('.').count() >= 2
+        && domain.split('.').all(|part| !part.is_empty())
+}
+
+fn is_uri(s: &str) -> bool {
+    let Some((scheme, rest)) = s.split_once("://") else {
+        return false;
+    };
+
+    scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+        && !rest.is_empty()
+        && !s.contains(char::is_whitespace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn infer(items: &[serde_json::Value], opts: &SchemaOpts) -> serde_json::Value {
+        let values: Vec<CtxResult<Value>> = items.iter().cloned().map(|v| Ok(v.into())).collect();
+        let data = WriterStreams::Ndjson { values: Box::new(values.into_iter()) as Records };
+
+        let schema = infer_schema(data, opts, &ConvertOptions::default()).unwrap();
+
+        serde_json::to_value(schema).unwrap()
+    }
+
+    #[test]
+    fn test_infer_nested_schema() {
+        let items = [
+            json!({"id": 1, "user": {"email": "a@x.io"}, "tags": ["a"], "at": "2024-01-31T10:00:00Z"}),
+            json!({"id": 2.5, "user": {"email": "b@y.org", "age": 3}, "tags": [], "at": null}),
+        ];
+
+        assert_eq!(
+            infer(&items, &SchemaOpts::default()),
+            json!({
+                "$schema": SCHEMA_DIALECT,
+                "type": "object",
+                "properties": {
+                    "id": {"type": "number"},
+                    "user": {
+                        "type": "object",
+                        "properties": {
+                            "email": {"type": "string", "format": "email"},
+                            "age": {"type": "integer"}
+                        },
+                        "required": ["email"]
+                    },
+                    "tags": {"type": "array", "items": {"type": "string"}},
+                    "at": {"type": ["string", "null"], "format": "date-time"}
+                },
+                "required": ["at", "id", "tags", "user"]
+            })
+        );
+    }
+
+    #[test]
+    fn test_enums_for_low_cardinality_strings() {
+        let items: Vec<_> =
+            ["info", "warn", "info", "error", "info", "warn"].map(|l| json!({"level": l})).into();
+
+        assert_eq!(
+            infer(&items, &SchemaOpts::default())["properties"]["level"],
+            json!({"type": "string", "enum": ["info", "warn", "error"]})
+        );
+
+        assert_eq!(
+            infer(&items, &SchemaOpts { enum_limit: 2 })["properties"]["level"],
+            json!({"type": "string"})
+        );
+    }
+}
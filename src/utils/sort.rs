@@ -0,0 +1,583 @@
+use resext::ctx;
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+    fs::File,
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+};
+
+use crate::utils::{
+    CtxResult, CtxResultErr, CtxResultExt, Log, Map, Records, Value, WriterStreams,
+    flatten::{PathSeg, get_path, parse_header},
+};
+
+/// Key for sorting records, parsed from `col[:desc][:num]`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SortKey {
+    /// Column name or path of a nested key (e.g. `user.age`)
+    pub column: String,
+
+    /// Sort in descending order
+    pub desc: bool,
+
+    /// Compare values as numbers, values that aren't numbers are sorted last
+    pub numeric: bool,
+}
+
+/// Parses a `col[:desc][:num]` sort key, `:asc` is also accepted for clarity.
+pub fn parse_sort_key(s: &str) -> Result<SortKey, String> {
+    let mut key = SortKey { column: s.to_string(), desc: false, numeric: false };
+
+    loop {
+        if let Some(col) = key.column.strip_suffix(":desc") {
+            key.desc = true;
+            key.column = col.to_string();
+        } else if let Some(col) = key.column.strip_suffix(":asc") {
+            key.column = col.to_string();
+        } else if let Some(col) = key.column.strip_suffix(":num") {
+            key.numeric = true;
+            key.column = col.to_string();
+        } else {
+            break;
+        }
+    }
+
+    if key.column.is_empty() {
+        return Err(format!("Invalid sort key: {}, expected col[:desc][:num]", s));
+    }
+
+    Ok(key)
+}
+
+/// Options for sorting records.
+#[derive(Clone, Debug)]
+pub struct SortOpts {
+    /// Keys to sort by in order of priority
+    pub keys: Vec<SortKey>,
+
+    /// Approximate number of bytes of records kept in memory before sorted chunks are spilled
+    /// into temporary files and merged
+    pub memory_limit: usize,
+}
+
+impl Default for SortOpts {
+    fn default() -> Self {
+        Self { keys: Vec::new(), memory_limit: 256 * 1024 * 1024 }
+    }
+}
+
+/// Sorts records by keys with an external merge sort, records are sorted in memory until they
+/// reach `memory_limit`, then sorted chunks are spilled into temporary files and merged. Sorting
+/// is stable and nulls / missing keys are sorted last.
+///
+/// Table keys are matched with header names, keys of other streams are paths (e.g. `user.age`).
+/// Invalid records are logged and skipped since they can't be sorted.
+pub fn sort_records(
+    data: WriterStreams<Records>,
+    opts: &SortOpts,
+) -> CtxResult<WriterStreams<Records>> {
+    if opts.keys.is_empty() {
+        return Ok(data);
+    }
+
+    match data {
+        WriterStreams::Table { headers, iter } => {
+            let mut cols = Vec::with_capacity(opts.keys.len());
+
+            for key in &opts.keys {
+                let col =
+                    KeyRef::column(&headers, &key.column, "Check the keys passed to `--key`")?;
+                cols.push((col, key.desc, key.numeric));
+            }
+
+            let iter = external_sort(iter, Sorter { keys: cols }, opts.memory_limit)?;
+
+            Ok(WriterStreams::Table { headers, iter })
+        }
+
+        WriterStreams::Values { iter } => Ok(WriterStreams::Values {
+            iter: external_sort(iter, Sorter::paths(opts), opts.memory_limit)?,
+        }),
+
+        WriterStreams::Ndjson { values } => Ok(WriterStreams::Ndjson {
+            values: external_sort(values, Sorter::paths(opts), opts.memory_limit)?,
+        }),
+    }
+}
+
+/// Key of a record, a table column or a path in other records.
+#[derive(Clone, Debug)]
+pub(crate) enum KeyRef {
+    Column(usize),
+    Path(Vec<PathSeg>),
+}
+
+impl KeyRef {
+    /// Resolves a key to the table column with the same header name, `hint` names the flag the
+    /// key was passed to.
+    pub(crate) fn column(headers: &[String], name: &str, hint: &'static str) -> CtxResult<Self> {
+        match headers.iter().position(|h| h == name) {
+            Some(idx) => Ok(Self::Column(idx)),
+            None => Err(CtxResultErr::new(
+                hint,
+                format!("Column: {} was not found in the input headers", name),
+            )),
+        }
+    }
+
+    pub(crate) fn path(name: &str) -> Self {
+        Self::Path(parse_header(name, "."))
+    }
+
+    pub(crate) fn get<'a>(&self, record: &'a Value) -> Option<&'a Value> {
+        match (self, record) {
+            (Self::Column(i), Value::Array(cells)) => cells.get(*i),
+            (Self::Path(path), _) => get_path(record, path),
+            _ => None,
+        }
+    }
+}
+
+/// Extracts sort keys from records.
+struct Sorter {
+    keys: Vec<(KeyRef, bool, bool)>,
+}
+
+impl Sorter {
+    fn paths(opts: &SortOpts) -> Self {
+        let keys = opts.keys.iter().map(|k| (KeyRef::path(&k.column), k.desc, k.numeric)).collect();
+
+        Self { keys }
+    }
+
+    fn key(&self, record: &Value) -> Vec<KeyPart> {
+        self.keys
+            .iter()
+            .map(|(key, desc, numeric)| KeyPart {
+                val: KeyVal::new(key.get(record), *numeric),
+                desc: *desc,
+            })
+            .collect()
+    }
+}
+
+/// Comparable key value, numbers are sorted before strings.
+#[derive(Debug, PartialEq)]
+enum KeyVal {
+    Num(f64),
+    Str(String),
+    Null,
+}
+
+impl KeyVal {
+    fn new(value: Option<&Value>, numeric: bool) -> Self {
+        let num = |s: &str| s.trim().parse::<f64>().ok().filter(|f| !f.is_nan());
+
+        match value {
+            None | Some(Value::Null) => Self::Null,
+            Some(Value::String(s)) if s.is_empty() => Self::Null,
+            Some(Value::Int(i)) => Self::Num(*i as f64),
+            Some(Value::Float(f)) if !f.is_nan() => Self::Num(*f),
+            Some(Value::Decimal(s)) => num(s).map_or(Self::Null, Self::Num),
+            Some(v) if numeric => num(&v.to_cell()).map_or(Self::Null, Self::Num),
+            Some(v) => Self::Str(v.to_cell().into_owned()),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct KeyPart {
+    val: KeyVal,
+    desc: bool,
+}
+
+impl Eq for KeyPart {}
+
+impl PartialOrd for KeyPart {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for KeyPart {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let ord = match (&self.val, &other.val) {
+            // nulls are sorted last in both orders
+            (KeyVal::Null, KeyVal::Null) => return Ordering::Equal,
+            (KeyVal::Null, _) => return Ordering::Greater,
+            (_, KeyVal::Null) => return Ordering::Less,
+            (KeyVal::Num(a), KeyVal::Num(b)) => a.total_cmp(b),
+            (KeyVal::Str(a), KeyVal::Str(b)) => a.cmp(b),
+            (KeyVal::Num(_), KeyVal::Str(_)) => Ordering::Less,
+            (KeyVal::Str(_), KeyVal::Num(_)) => Ordering::Greater,
+        };
+
+        if self.desc { ord.reverse() } else { ord }
+    }
+}
+
+fn external_sort(records: Records, sorter: Sorter, memory_limit: usize) -> CtxResult<Records> {
+    let mut chunk: Vec<(Vec<KeyPart>, Value)> = Vec::new();
+    let mut chunk_size = 0;
+    let mut spilled: Vec<File> = Vec::new();
+
+    for (idx, rec) in records.enumerate() {
+        let Some(rec) = rec.context(ctx!("Failed to sort record: {}", idx + 1)).log("[WARN]")
+        else {
+            continue;
+        };
+
+        chunk_size += approx_size(&rec);
+        chunk.push((sorter.key(&rec), rec));
+
+        if chunk_size >= memory_limit {
+            spilled.push(spill_chunk(&mut chunk)?);
+            chunk_size = 0;
+        }
+    }
+
+    // stable sort keeps the input order of equal records
+    chunk.sort_by(|a, b| a.0.cmp(&b.0));
+
+    if spilled.is_empty() {
+        return Ok(Box::new(chunk.into_iter().map(|(_, v)| Ok(v))));
+    }
+
+    if !chunk.is_empty() {
+        spilled.push(spill_chunk(&mut chunk)?);
+    }
+
+    let mut merge =
+        Merge { sources: Vec::with_capacity(spilled.len()), heap: BinaryHeap::new(), sorter };
+
+    for (idx, file) in spilled.into_iter().enumerate() {
+        merge.sources.push(BufReader::with_capacity(64 * 1024, file));
+        merge.refill(idx)?;
+    }
+
+    Ok(Box::new(merge))
+}
+
+/// Sorts a chunk and writes it into a temporary file.
+fn spill_chunk(chunk: &mut Vec<(Vec<KeyPart>, Value)>) -> CtxResult<File> {
+    chunk.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let spill = tempfile::tempfile().context("Failed to create temporary file")?;
+    let mut wtr = BufWriter::with_capacity(256 * 1024, spill);
+    let mut buf = Vec::new();
+
+    for (_, rec) in chunk.drain(..) {
+        write_spilled(&mut wtr, &rec, &mut buf)?;
+    }
+
+    let mut spill =
+        wtr.into_inner().map_err(|e| e.into_error()).context("Failed to flush temporary file")?;
+
+    spill.seek(SeekFrom::Start(0)).context("Failed to rewind temporary file")?;
+
+    Ok(spill)
+}
+
+/// Head of a sorted chunk, ordered by its key then its chunk so equal records keep their order.
+struct Head {
+    key: Vec<KeyPart>,
+    source: usize,
+    value: Value,
+}
+
+impl PartialEq for Head {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Head {}
+
+impl PartialOrd for Head {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Head {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key.cmp(&other.key).then(self.source.cmp(&other.source))
+    }
+}
+
+/// K-way merge of sorted chunks.
+struct Merge {
+    sources: Vec<BufReader<File>>,
+    heap: BinaryHeap<Reverse<Head>>,
+    sorter: Sorter,
+}
+
+impl Merge {
+    /// Reads the next record of a chunk into the heap.
+    fn refill(&mut self, source: usize) -> CtxResult<()> {
+        let Some(value) = read_spilled(&mut self.sources[source])? else {
+            return Ok(());
+        };
+
+        self.heap.push(Reverse(Head { key: self.sorter.key(&value), source, value }));
+
+        Ok(())
+    }
+}
+
+impl Iterator for Merge {
+    type Item = CtxResult<Value>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse(head) = self.heap.pop()?;
+
+        if let Err(e) = self.refill(head.source) {
+            return Some(Err(e));
+        }
+
+        Some(Ok(head.value))
+    }
+}
+
+/// Writes a length-prefixed record into a temporary file, `buf` is reused between records.
+pub(crate) fn write_spilled(wtr: &mut impl Write, rec: &Value, buf: &mut Vec<u8>) -> CtxResult<()> {
+    buf.clear();
+    encode(rec, buf);
+
+    wtr.write_all(&(buf.len() as u64).to_le_bytes())
+        .and_then(|_| wtr.write_all(buf))
+        .context("Failed to write record into temporary file")
+}
+
+/// Reads a record written with [`write_spilled`], `None` is returned at the end of the file.
+pub(crate) fn read_spilled(rdr: &mut impl Read) -> CtxResult<Option<Value>> {
+    let mut len = [0u8; 8];
+
+    match rdr.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => Err(e).context("Failed to read record from temporary file")?,
+    }
+
+    let mut buf = vec![0u8; u64::from_le_bytes(len) as usize];
+    rdr.read_exact(&mut buf).context("Failed to read record from temporary file")?;
+
+    decode(&mut buf.as_slice()).map(Some).ok_or_else(|| {
+        CtxResultErr::new(
+            "Failed to read record from temporary file",
+            String::from("Corrupted record"),
+        )
+    })
+}
+
+/// Approximate number of bytes a value takes in memory.
+pub(crate) fn approx_size(value: &Value) -> usize {
+    std::mem::size_of::<Value>()
+        + match value {
+            Value::Decimal(s) | Value::String(s) | Value::DateTime(s) => s.len(),
+            Value::Bytes(b) => b.len(),
+            Value::Array(arr) => arr.iter().map(approx_size).sum(),
+            Value::Map(map) => map.iter().map(|(k, v)| k.len() + 16 + approx_size(v)).sum(),
+            _ => 0,
+        }
+}
+
+/// Encodes a value into a compact binary form for spilling, unlike the text formats it keeps
+/// every type (e.g. bytes and datetimes) as it is.
+fn encode(value: &Value, out: &mut Vec<u8>) {
+    let bytes = |b: &[u8], out: &mut Vec<u8>| {
+        out.extend_from_slice(&(b.len() as u64).to_le_bytes());
+        out.extend_from_slice(b);
+    };
+
+    match value {
+        Value::Null => out.push(0),
+        Value::Bool(b) => out.extend_from_slice(&[1, *b as u8]),
+        Value::Int(i) => {
+            out.push(2);
+            out.extend_from_slice(&i.to_le_bytes());
+        }
+        Value::Float(f) => {
+            out.push(3);
+            out.extend_from_slice(&f.to_le_bytes());
+        }
+        Value::Decimal(s) => {
+            out.push(4);
+            bytes(s.as_bytes(), out);
+        }
+        Value::String(s) => {
+            out.push(5);
+            bytes(s.as_bytes(), out);
+        }
+        Value::Bytes(b) => {
+            out.push(6);
+            bytes(b, out);
+        }
+        Value::DateTime(s) => {
+            out.push(7);
+            bytes(s.as_bytes(), out);
+        }
+        Value::Array(arr) => {
+            out.push(8);
+            out.extend_from_slice(&(arr.len() as u64).to_le_bytes());
+
+            for v in arr {
+                encode(v, out);
+            }
+        }
+        Value::Map(map) => {
+            out.push(9);
+            out.extend_from_slice(&(map.len() as u64).to_le_bytes());
+
+            for (k, v) in map {
+                bytes(k.as_bytes(), out);
+                encode(v, out);
+            }
+        }
+    }
+}
+
+fn decode(buf: &mut &[u8]) -> Option<Value> {
+    fn take<'a>(buf: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+        if buf.len() < n {
+            return None;
+        }
+
+        let (head, rest) = buf.split_at(n);
+        *buf = rest;
+
+        Some(head)
+    }
+
+    fn u64_le(buf: &mut &[u8]) -> Option<u64> {
+        Some(u64::from_le_bytes(take(buf, 8)?.try_into().ok()?))
+    }
+
+    fn string(buf: &mut &[u8]) -> Option<String> {
+        let len = u64_le(buf)? as usize;
+        String::from_utf8(take(buf, len)?.to_vec()).ok()
+    }
+
+    let tag = take(buf, 1)?[0];
+
+    Some(match tag {
+        0 => Value::Null,
+        1 => Value::Bool(take(buf, 1)?[0] != 0),
+        2 => Value::Int(i64::from_le_bytes(take(buf, 8)?.try_into().ok()?)),
+        3 => Value::Float(f64::from_le_bytes(take(buf, 8)?.try_into().ok()?)),
+        4 => Value::Decimal(string(buf)?),
+        5 => Value::String(string(buf)?),
+        6 => {
+            let len = u64_le(buf)? as usize;
+            Value::Bytes(take(buf, len)?.to_vec())
+        }
+        7 => Value::DateTime(string(buf)?),
+        8 => {
+            let len = u64_le(buf)? as usize;
+            let mut arr = Vec::with_capacity(len.min(buf.len()));
+
+            for _ in 0..len {
+                arr.push(decode(buf)?);
+            }
+
+            Value::Array(arr)
+        }
+        9 => {
+            let len = u64_le(buf)? as usize;
+            let mut map = Map::with_capacity(len.min(buf.len()));
+
+            for _ in 0..len {
+                let k = string(buf)?;
+                map.insert(k, decode(buf)?);
+            }
+
+            Value::Map(map)
+        }
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn ndjson(items: &[serde_json::Value]) -> WriterStreams<Records> {
+        let values: Vec<CtxResult<Value>> = items.iter().cloned().map(|v| Ok(v.into())).collect();
+
+        WriterStreams::Ndjson { values: Box::new(values.into_iter()) }
+    }
+
+    fn collect(data: WriterStreams<Records>) -> Vec<Value> {
+        match data {
+            WriterStreams::Ndjson { values } => values.map(Result::unwrap).collect(),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_parse_sort_key() {
+        assert_eq!(
+            parse_sort_key("a:b:desc:num"),
+            Ok(SortKey { column: "a:b".to_string(), desc: true, numeric: true })
+        );
+        assert!(parse_sort_key(":num").is_err());
+    }
+
+    #[test]
+    fn test_sort_spilled_chunks_is_stable() {
+        let items: Vec<serde_json::Value> = (0..50)
+            .map(|i| json!({"g": (i * 7) % 5, "i": i, "v": {"n": format!("{}", i % 3)}}))
+            .collect();
+
+        let opts = SortOpts {
+            keys: vec![parse_sort_key("g:desc").unwrap(), parse_sort_key("v.n:num").unwrap()],
+            memory_limit: 512,
+        };
+
+        let sorted = collect(sort_records(ndjson(&items), &opts).unwrap());
+
+        let mut expected = items.clone();
+        expected.sort_by_key(|v| {
+            (Reverse(v["g"].as_i64().unwrap()), v["v"]["n"].as_str().unwrap().to_string())
+        });
+
+        assert_eq!(sorted, expected.into_iter().map(Value::from).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_nulls_sort_last() {
+        let items = [json!({"a": "10"}), json!({}), json!({"a": "9"}), json!({"a": "x"})];
+
+        let opts =
+            SortOpts { keys: vec![parse_sort_key("a:num:desc").unwrap()], ..Default::default() };
+        let sorted = collect(sort_records(ndjson(&items), &opts).unwrap());
+
+        let order: Vec<Value> =
+            [json!({"a": "10"}), json!({"a": "9"}), json!({}), json!({"a": "x"})]
+                .into_iter()
+                .map(Value::from)
+                .collect();
+
+        assert_eq!(sorted, order);
+    }
+
+    #[test]
+    fn test_encode_roundtrip() {
+        let mut map = Map::new();
+        map.insert("d".to_string(), Value::DateTime("2024-01-01T00:00:00Z".to_string()));
+        map.insert("b".to_string(), Value::Bytes(vec![0xff, 0]));
+
+        let value = Value::Array(vec![
+            Value::Map(map),
+            Value::Decimal("18446744073709551615".to_string()),
+            Value::Float(1.5),
+            Value::Null,
+        ]);
+
+        let mut buf = Vec::new();
+        encode(&value, &mut buf);
+
+        assert_eq!(decode(&mut buf.as_slice()), Some(value));
+    }
+}
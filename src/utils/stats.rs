@@ -0,0 +1,662 @@
+use indexmap::IndexMap;
+use resext::ctx;
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hasher},
+    io::Write,
+};
+
+use crate::utils::{
+    ColumnType, ConvertOptions, CtxResult, CtxResultExt, Log, Map, Records, Value, WriterStreams,
+    dedupe::canonical, filter::compare, into_row,
+};
+
+/// Options for profiling records.
+#[derive(Clone, Debug)]
+pub struct StatsOpts {
+    /// Number of most frequent values reported per field
+    pub top_k: usize,
+}
+
+impl Default for StatsOpts {
+    fn default() -> Self {
+        Self { top_k: 5 }
+    }
+}
+
+/// Headers of the records written by [`stats_records`].
+pub const STATS_HEADERS: [&str; 12] = [
+    "field",
+    "type",
+    "count",
+    "nulls",
+    "distinct",
+    "min",
+    "max",
+    "mean",
+    "stddev",
+    "min_length",
+    "max_length",
+    "top",
+];
+
+/// Profiles records in one pass and writes one record per column / field (see [`STATS_HEADERS`])
+/// with its inferred type, number of non-null values, number of nulls (including records where the
+/// field is missing), estimated number of distinct values, min / max, mean / standard deviation of
+/// numbers, range of string lengths and most frequent values.
+///
+/// Nested objects are profiled by their dotted paths (e.g. `user.name`), arrays are profiled as
+/// single values. Cells of untyped tables (e.g. CSV without `--parse-numbers`) are typed like
+/// [`ColumnType::infer`] and empty cells are `null`, a column whose cells don't share a type is
+/// profiled as a string column. Mean / standard deviation are only reported for numeric fields.
+///
+/// Distinct values are estimated with a HyperLogLog sketch (about 1.6% error) and the most frequent
+/// values are tracked with the Space-Saving algorithm, so memory stays bounded per field and counts
+/// of top values are exact unless a field has many more distinct values than tracked.
+pub fn stats_records(
+    data: WriterStreams<Records>,
+    opts: &StatsOpts,
+    convert_opts: &ConvertOptions,
+) -> CtxResult<WriterStreams<Records>> {
+    let mut fields: IndexMap<String, FieldStats> = IndexMap::new();
+    let mut records: i64 = 0;
+
+    let capacity = opts.top_k.saturating_mul(16).max(64);
+    let new_field = |name: &str, fields: &mut IndexMap<String, FieldStats>| {
+        if !fields.contains_key(name) {
+            fields.insert(name.to_string(), FieldStats::new(capacity));
+        }
+    };
+
+    match data {
+        WriterStreams::Table { headers, iter } => {
+            let infer = !convert_opts.is_typed();
+
+            for h in &headers {
+                new_field(h, &mut fields);
+            }
+
+            for (idx, row) in iter.enumerate() {
+                let Some(cells) =
+                    into_row(row).context(ctx!("Failed to profile row: {}", idx + 1)).log("[WARN]")
+                else {
+                    continue;
+                };
+
+                records += 1;
+
+                for (stats, cell) in fields.values_mut().zip(cells) {
+                    match cell {
+                        Value::String(s) if infer => stats.update_cell(s),
+                        Value::String(s) if s.is_empty() => {}
+                        cell => stats.update(cell),
+                    }
+                }
+            }
+        }
+
+        WriterStreams::Values { iter: values } | WriterStreams::Ndjson { values } => {
+            let separator = &convert_opts.flatten.separator;
+            let mut leaves = Vec::new();
+
+            for (idx, rec) in values.enumerate() {
+                let Some(rec) =
+                    rec.context(ctx!("Failed to profile record: {}", idx + 1)).log("[WARN]")
+                else {
+                    continue;
+                };
+
+                records += 1;
+
+                leaves.clear();
+                collect_leaves(rec, String::new(), separator, &mut leaves);
+
+                for (name, value) in leaves.drain(..) {
+                    new_field(&name, &mut fields);
+
+                    if let Some(stats) = fields.get_mut(&name) {
+                        stats.update(value);
+                    }
+                }
+            }
+        }
+    }
+
+    let top_k = opts.top_k;
+    let iter = fields.into_iter().map(move |(name, stats)| Ok(stats.finish(name, records, top_k)));
+
+    Ok(WriterStreams::Table {
+        headers: STATS_HEADERS.iter().map(|h| h.to_string()).collect(),
+        iter: Box::new(iter),
+    })
+}
+
+/// Collects the leaves of nested objects with their dotted paths, scalars that aren't in an object
+/// are collected into a `value` field.
+fn collect_leaves(value: Value, prefix: String, separator: &str, out: &mut Vec<(String, Value)>) {
+    match value {
+        Value::Map(map) if !map.is_empty() => {
+            for (k, v) in map {
+                let key =
+                    if prefix.is_empty() { k } else { format!("{}{}{}", prefix, separator, k) };
+
+                collect_leaves(v, key, separator, out);
+            }
+        }
+        value if prefix.is_empty() => out.push(("value".to_string(), value)),
+        value => out.push((prefix, value)),
+    }
+}
+
+/// Type of a field, merged from the types of its values.
+#[derive(Clone, Copy, PartialEq)]
+enum FieldType {
+    Scalar(ColumnType),
+    Array,
+    Object,
+    Mixed,
+}
+
+impl FieldType {
+    fn of(value: &Value) -> Self {
+        match value {
+            Value::Null => Self::Scalar(ColumnType::Null),
+            Value::Bool(_) => Self::Scalar(ColumnType::Bool),
+            Value::Int(_) => Self::Scalar(ColumnType::Int),
+            Value::Float(_) | Value::Decimal(_) => Self::Scalar(ColumnType::Float),
+            Value::DateTime(s) if s.len() == 10 => Self::Scalar(ColumnType::Date),
+            Value::DateTime(_) => Self::Scalar(ColumnType::DateTime),
+            Value::String(_) | Value::Bytes(_) => Self::Scalar(ColumnType::String),
+            Value::Array(_) => Self::Array,
+            Value::Map(_) => Self::Object,
+        }
+    }
+
+    fn merge(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Scalar(ColumnType::Null), t) | (t, Self::Scalar(ColumnType::Null)) => t,
+            (Self::Scalar(a), Self::Scalar(b)) => match a.merge(b) {
+                // strings mixed with other scalars are kept apart from plain string fields
+                ColumnType::String if a != b => Self::Mixed,
+                t => Self::Scalar(t),
+            },
+            (a, b) if a == b => a,
+            _ => Self::Mixed,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Scalar(t) => t.name(),
+            Self::Array => "array",
+            Self::Object => "object",
+            Self::Mixed => "mixed",
+        }
+    }
+}
+
+/// Running statistics of a field.
+struct FieldStats {
+    count: i64,
+    ty: FieldType,
+    distinct: HyperLogLog,
+    top: SpaceSaving,
+    min: Option<Value>,
+    max: Option<Value>,
+    numbers: u64,
+    mean: f64,
+    m2: f64,
+    min_len: Option<usize>,
+    max_len: usize,
+    cells: Option<CellRange>,
+    buf: Vec<u8>,
+}
+
+/// Range of the raw cells of an untyped column, reported instead of the typed range when the
+/// cells don't share a type.
+#[derive(Default)]
+struct CellRange {
+    min: Option<String>,
+    max: Option<String>,
+    min_len: Option<usize>,
+    max_len: usize,
+}
+
+impl FieldStats {
+    fn new(capacity: usize) -> Self {
+        Self {
+            count: 0,
+            ty: FieldType::Scalar(ColumnType::Null),
+            distinct: HyperLogLog::new(),
+            top: SpaceSaving::new(capacity),
+            min: None,
+            max: None,
+            numbers: 0,
+            mean: 0.0,
+            m2: 0.0,
+            min_len: None,
+            max_len: 0,
+            cells: None,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Updates the stats with a cell of an untyped table, typed like [`ColumnType::infer`] while
+    /// keeping the range of the raw cells.
+    fn update_cell(&mut self, cell: String) {
+        if cell.is_empty() {
+            return;
+        }
+
+        let range = self.cells.get_or_insert_default();
+        let len = cell.chars().count();
+
+        range.min_len = Some(range.min_len.map_or(len, |m| m.min(len)));
+        range.max_len = range.max_len.max(len);
+
+        if range.min.as_ref().is_none_or(|m| cell < *m) {
+            range.min = Some(cell.clone());
+        }
+
+        if range.max.as_ref().is_none_or(|m| cell > *m) {
+            range.max = Some(cell.clone());
+        }
+
+        let value = ColumnType::infer(&cell).convert(&cell).unwrap_or(Value::String(cell));
+        self.update(value);
+    }
+
+    fn update(&mut self, value: Value) {
+        if value.is_null() {
+            return;
+        }
+
+        self.count += 1;
+        self.ty = self.ty.merge(FieldType::of(&value));
+
+        self.buf.clear();
+        canonical(&value, &mut self.buf);
+
+        let mut hasher = DefaultHasher::new();
+        hasher.write(&self.buf);
+        self.distinct.insert(hasher.finish());
+
+        // Welford's algorithm keeps the mean and variance stable over long streams
+        let number = match &value {
+            Value::Int(i) => Some(*i as f64),
+            Value::Float(f) => Some(*f),
+            _ => None,
+        };
+
+        if let Some(x) = number {
+            self.numbers += 1;
+
+            let delta = x - self.mean;
+            self.mean += delta / self.numbers as f64;
+            self.m2 += delta * (x - self.mean);
+        }
+
+        if let Value::String(s) = &value {
+            let len = s.chars().count();
+
+            self.min_len = Some(self.min_len.map_or(len, |m| m.min(len)));
+            self.max_len = self.max_len.max(len);
+        }
+
+        if !matches!(value, Value::Array(_) | Value::Map(_)) {
+            if self.min.as_ref().is_none_or(|m| compare(&value, m).is_some_and(|o| o.is_lt())) {
+                self.min = Some(value.clone());
+            }
+
+            if self.max.as_ref().is_none_or(|m| compare(&value, m).is_some_and(|o| o.is_gt())) {
+                self.max = Some(value.clone());
+            }
+        }
+
+        self.top.insert(&self.buf, value);
+    }
+
+    fn finish(mut self, name: String, records: i64, top_k: usize) -> Value {
+        let numeric = matches!(self.ty, FieldType::Scalar(ColumnType::Int | ColumnType::Float));
+
+        let (mean, stddev) = match self.numbers {
+            n if n == 0 || !numeric => (Value::Null, Value::Null),
+            1 => (Value::Float(self.mean), Value::Null),
+            n => (Value::Float(self.mean), Value::Float((self.m2 / (n - 1) as f64).sqrt())),
+        };
+
+        // values of different types can't be ordered, untyped columns fall back to their raw cells
+        let is_string_column = self.ty == FieldType::Mixed && self.cells.is_some();
+
+        if self.ty == FieldType::Mixed {
+            let cells = self.cells.take().unwrap_or_default();
+
+            self.min = cells.min.map(Value::String);
+            self.max = cells.max.map(Value::String);
+
+            if is_string_column {
+                self.ty = FieldType::Scalar(ColumnType::String);
+                self.min_len = cells.min_len;
+                self.max_len = cells.max_len;
+            }
+        }
+
+        let top = self
+            .top
+            .top(top_k)
+            .into_iter()
+            .map(|(value, count)| {
+                let value = match value {
+                    value if is_string_column => Value::String(value.to_cell().into_owned()),
+                    value => value,
+                };
+
+                let mut entry = Map::with_capacity(2);
+                entry.insert("value".to_string(), value);
+                entry.insert("count".to_string(), Value::Int(count as i64));
+
+                Value::Map(entry)
+            })
+            .collect();
+
+        Value::Array(vec![
+            Value::String(name),
+            Value::String(self.ty.name().to_string()),
+            Value::Int(self.count),
+            Value::Int(records - self.count),
+            Value::Int(self.distinct.estimate().min(self.count as u64) as i64),
+            self.min.unwrap_or_default(),
+            self.max.unwrap_or_default(),
+            mean,
+            stddev,
+            self.min_len.map_or(Value::Null, |l| Value::Int(l as i64)),
+            self.min_len.map_or(Value::Null, |_| Value::Int(self.max_len as i64)),
+            Value::Array(top),
+        ])
+    }
+}
+
+/// Number of bits of a hash used to pick a HyperLogLog register.
+const HLL_BITS: u32 = 12;
+
+/// HyperLogLog sketch for estimating the number of distinct values in 4 KiB.
+struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    fn new() -> Self {
+        Self { registers: vec![0; 1 << HLL_BITS] }
+    }
+
+    fn insert(&mut self, hash: u64) {
+        let idx = (hash >> (64 - HLL_BITS)) as usize;
+        let rank = ((hash << HLL_BITS) | (1 << (HLL_BITS - 1))).leading_zeros() as u8 + 1;
+
+        self.registers[idx] = self.registers[idx].max(rank);
+    }
+
+    fn estimate(&self) -> u64 {
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+
+        let sum: f64 = self.registers.iter().map(|r| 2f64.powi(-(*r as i32))).sum();
+        let zeros = self.registers.iter().filter(|r| **r == 0).count();
+
+        let raw = alpha * m * m / sum;
+
+        // linear counting is more accurate for small cardinalities
+        if raw <= 2.5 * m && zeros > 0 {
+            (m * (m / zeros as f64).ln()).round() as u64
+        } else {
+            raw.round() as u64
+        }
+    }
+}
+
+/// Space-Saving sketch for the most frequent values, when it's full a new value replaces the least
+/// frequent one and inherits its count.
+struct SpaceSaving {
+    capacity: usize,
+    counts: HashMap<Vec<u8>, (Value, u64, u64)>,
+}
+
+impl SpaceSaving {
+    fn new(capacity: usize) -> Self {
+        Self { capacity, counts: HashMap::new() }
+    }
+
+    fn insert(&mut self, key: &[u8], value: Value) {
+        if let Some((_, count, _)) = self.counts.get_mut(key) {
+            *count += 1;
+            return;
+        }
+
+        if self.counts.len() < self.capacity {
+            self.counts.insert(key.to_vec(), (value, 1, 0));
+            return;
+        }
+
+        let Some((min_key, min_count)) = self
+            .counts
+            .iter()
+            .min_by_key(|(_, (_, c, _))| *c)
+            .map(|(k, (_, c, _))| (k.clone(), *c))
+        else {
+            return;
+        };
+
+        self.counts.remove(&min_key);
+        self.counts.insert(key.to_vec(), (value, min_count + 1, min_count));
+    }
+
+    /// Gets the `k` most frequent values with their counts, ties are ordered by value so output
+    /// doesn't depend on hashing.
+    fn top(self, k: usize) -> Vec<(Value, u64)> {
+        let mut entries: Vec<_> = self.counts.into_values().collect();
+
+        entries.sort_by(|a, b| {
+            b.1.cmp(&a.1)
+                .then_with(|| a.2.cmp(&b.2))
+                .then_with(|| a.0.to_cell().cmp(&b.0.to_cell()))
+        });
+
+        entries.into_iter().take(k).map(|(value, count, _)| (value, count)).collect()
+    }
+}
+
+/// Writes records as an aligned text table for terminals, e.g. the output of [`stats_records`].
+///
+/// Floats are rounded to 2 decimals, arrays of `{"value", "count"}` objects are written as
+/// `value (count)` lists and long cells are truncated.
+pub fn write_text_table(data: WriterStreams<Records>, mut out: impl Write) -> CtxResult<()> {
+    const MAX_WIDTH: usize = 40;
+
+    let (headers, rows): (Vec<String>, Records) = match data {
+        WriterStreams::Table { headers, iter } => (headers, iter),
+        WriterStreams::Values { iter: rows } | WriterStreams::Ndjson { values: rows } => {
+            (vec!["value".to_string()], Box::new(rows.map(|r| Ok(Value::Array(vec![r?])))))
+        }
+    };
+
+    let mut table = vec![headers];
+
+    for (idx, row) in rows.enumerate() {
+        let Some(cells) =
+            into_row(row).context(ctx!("Failed to write row: {}", idx + 1)).log("[WARN]")
+        else {
+            continue;
+        };
+
+        table.push(cells.iter().map(text_cell).collect());
+    }
+
+    let mut widths = vec![0; table[0].len()];
+
+    for row in &mut table {
+        for (cell, width) in row.iter_mut().zip(&mut widths) {
+            if cell.chars().count() > MAX_WIDTH {
+                *cell = cell.chars().take(MAX_WIDTH - 1).chain(['…']).collect();
+            }
+
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    for row in &table {
+        let line: Vec<String> =
+            row.iter().zip(&widths).map(|(cell, w)| format!("{:<w$}", cell, w = *w)).collect();
+
+        writeln!(out, "{}", line.join("  ").trim_end()).context("Failed to write table")?;
+    }
+
+    out.flush().context("Failed to flush table")
+}
+
+fn text_cell(value: &Value) -> String {
+    match value {
+        Value::Float(f) => format!("{:.2}", f),
+        Value::Array(items) => items
+            .iter()
+            .map(|item| match (item.get("value"), item.get("count")) {
+                (Some(v), Some(c)) => format!("{} ({})", v.to_cell(), c.to_cell()),
+                _ => item.to_cell().into_owned(),
+            })
+            .collect::<Vec<_>>()
+            .join(", "),
+        v => v.to_cell().into_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_hyperloglog_estimate() {
+        for n in [10u64, 1_000, 100_000] {
+            let mut hll = HyperLogLog::new();
+
+            for i in 0..n {
+                let mut hasher = DefaultHasher::new();
+                hasher.write_u64(i);
+                hll.insert(hasher.finish());
+            }
+
+            let error = (hll.estimate() as f64 - n as f64).abs() / n as f64;
+            assert!(error < 0.05, "{} estimated as {}", n, hll.estimate());
+        }
+    }
+
+    #[test]
+    fn test_stats_csv_and_nested_records() {
+        let rows: Vec<CtxResult<Value>> =
+            [json!(["1", "a"]), json!(["", "bb"]), json!(["3.5", "a"])]
+                .into_iter()
+                .map(|v| Ok(v.into()))
+                .collect();
+
+        let data = WriterStreams::Table {
+            headers: vec!["n".to_string(), "s".to_string()],
+            iter: Box::new(rows.into_iter()) as Records,
+        };
+
+        let WriterStreams::Table { iter, .. } =
+            stats_records(data, &StatsOpts { top_k: 1 }, &ConvertOptions::default()).unwrap()
+        else {
+            unreachable!()
+        };
+
+        let stats: Vec<Value> = iter.map(Result::unwrap).collect();
+
+        assert_eq!(stats, [
+            json!(["n", "float", 2, 1, 2, 1, 3.5, 2.25, 1.7677669529663689, null, null, [{"value": 1, "count": 1}]]),
+            json!(["s", "string", 3, 0, 2, "a", "bb", null, null, 1, 2, [{"value": "a", "count": 2}]]),
+        ]
+        .map(Value::from));
+
+        let values: Vec<CtxResult<Value>> =
+            [json!({"user": {"id": 1}}), json!({"user": {"id": 2}, "tags": [1]})]
+                .into_iter()
+                .map(|v| Ok(v.into()))
+                .collect();
+
+        let data = WriterStreams::Ndjson { values: Box::new(values.into_iter()) as Records };
+
+        let WriterStreams::Table { iter, .. } =
+            stats_records(data, &StatsOpts::default(), &ConvertOptions::default()).unwrap()
+        else {
+            unreachable!()
+        };
+
+        let fields: Vec<(String, String, i64)> = iter
+            .map(|r| match r.unwrap() {
+                Value::Array(cells) => (
+                    cells[0].to_cell().into(),
+                    cells[1].to_cell().into(),
+                    match cells[3] {
+                        Value::Int(i) => i,
+                        _ => -1,
+                    },
+                ),
+                _ => unreachable!(),
+            })
+            .collect();
+
+        assert_eq!(
+            fields,
+            [
+                ("user.id".to_string(), "int".to_string(), 0),
+                ("tags".to_string(), "array".to_string(), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stats_mixed_columns() {
+        let rows: Vec<CtxResult<Value>> =
+            [json!(["10", "9"]), json!(["x", "2"]), json!(["3", "n/a"])]
+                .into_iter()
+                .map(|v| Ok(v.into()))
+                .collect();
+
+        let data = WriterStreams::Table {
+            headers: vec!["a".to_string(), "b".to_string()],
+            iter: Box::new(rows.into_iter()) as Records,
+        };
+
+        let WriterStreams::Table { iter, .. } =
+            stats_records(data, &StatsOpts { top_k: 1 }, &ConvertOptions::default()).unwrap()
+        else {
+            unreachable!()
+        };
+
+        let stats: Vec<Value> = iter.map(Result::unwrap).collect();
+
+        // mixed columns are strings without numeric stats, ordered like strings
+        assert_eq!(stats, [
+            json!(["a", "string", 3, 0, 3, "10", "x", null, null, 1, 2, [{"value": "10", "count": 1}]]),
+            json!(["b", "string", 3, 0, 3, "2", "n/a", null, null, 1, 3, [{"value": "2", "count": 1}]]),
+        ]
+        .map(Value::from));
+
+        let values: Vec<CtxResult<Value>> = [json!({"v": 1}), json!({"v": "a"}), json!({"v": 2.5})]
+            .into_iter()
+            .map(|v| Ok(v.into()))
+            .collect();
+
+        let data = WriterStreams::Ndjson { values: Box::new(values.into_iter()) as Records };
+
+        let WriterStreams::Table { mut iter, .. } =
+            stats_records(data, &StatsOpts { top_k: 0 }, &ConvertOptions::default()).unwrap()
+        else {
+            unreachable!()
+        };
+
+        assert_eq!(
+            iter.next().unwrap().unwrap(),
+            json!(["v", "mixed", 3, 0, 3, null, null, null, null, 1, 1, []]).into()
+        );
+    }
+}
@@ -1,12 +1,10 @@
-use csv::ByteRecord;
-
-use serde::Serialize;
-
-use crate::utils::{CtxResult, CtxResultExt};
+use crate::utils::{ConvertOptions, CtxResult, CtxResultErr, CtxResultExt, Unflattener, Value};
 
+/// Streams of records passed from decoders to writers, rows of `Table` streams are arrays of cells
+/// in the same order as `headers`.
 pub enum WriterStreams<I>
 where
-    I: Iterator<Item = CtxResult<DataTypes>>,
+    I: Iterator<Item = CtxResult<Value>>,
 {
     Values { iter: I },
 
@@ -15,37 +13,68 @@ where
     Ndjson { values: I },
 }
 
-pub enum DataTypes {
-    Json(serde_json::Value),
-
-    Toml(toml::Value),
+/// Type-erased stream of records passed between format decoders and encoders.
+pub type Records = Box<dyn Iterator<Item = CtxResult<Value>>>;
 
-    Csv(ByteRecord),
+impl WriterStreams<Records> {
+    /// Turns a stream into records, rows of `Table` streams are turned into objects keyed by their
+    /// headers (nested with `opts.flatten.unflatten`).
+    pub fn into_records(self, opts: &ConvertOptions) -> Records {
+        match self {
+            Self::Table { headers, iter } => {
+                let u = opts
+                    .flatten
+                    .unflatten
+                    .then(|| Unflattener::new(&headers, &opts.flatten.separator, opts.is_typed()));
+
+                Box::new(iter.map(move |row| {
+                    let cells = into_row(row)?;
+
+                    Ok(match &u {
+                        Some(u) => u.unflatten(&cells),
+                        None => Value::Map(headers.iter().cloned().zip(cells).collect()),
+                    })
+                }))
+            }
+
+            Self::Values { iter } => iter,
+            Self::Ndjson { values } => values,
+        }
+    }
 }
 
-impl Serialize for DataTypes {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
+impl<I> WriterStreams<I>
+where
+    I: Iterator<Item = CtxResult<Value>> + 'static,
+{
+    /// Erases the iterator type of a stream.
+    pub fn boxed(self) -> WriterStreams<Records> {
         match self {
-            DataTypes::Json(j) => j.serialize(serializer),
-            DataTypes::Toml(t) => t.serialize(serializer),
-            DataTypes::Csv(_) => unreachable!(),
+            Self::Values { iter } => WriterStreams::Values { iter: Box::new(iter) },
+            Self::Table { headers, iter } => WriterStreams::Table { headers, iter: Box::new(iter) },
+            Self::Ndjson { values } => WriterStreams::Ndjson { values: Box::new(values) },
         }
     }
 }
 
+/// Gets the cells of a table row, rows that aren't arrays are returned as errors.
 #[inline(always)]
-pub fn into_byte_record(brec: CtxResult<DataTypes>) -> CtxResult<ByteRecord> {
-    let rec = brec.context("Failed to unwrap record")?;
-
-    match rec {
-        DataTypes::Csv(csv) => Ok(csv),
-        _ => unreachable!(),
+pub fn into_row(rec: CtxResult<Value>) -> CtxResult<Vec<Value>> {
+    match rec.context("Failed to unwrap record")? {
+        Value::Array(cells) => Ok(cells),
+        other => Err(CtxResultErr::new(
+            "Invalid table row",
+            format!("Expected an array of cells, found: {}", other.type_name()),
+        )),
     }
 }
 
+/// Checks if a path is `-`, which is used for stdin / stdout.
+#[inline(always)]
+pub fn is_std_stream(path: &std::path::Path) -> bool {
+    path.as_os_str() == "-"
+}
+
 const NEEDS_ESCAPE: [bool; 256] = {
     let mut table = [false; 256];
     table[b'\\' as usize] = true;
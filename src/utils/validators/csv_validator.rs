@@ -1,40 +1,640 @@
+use regex::Regex;
 use resext::ctx;
-use std::{fs::File, io::BufReader, path::PathBuf};
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Error, ErrorKind as EK, Read},
+    sync::Arc,
+};
 
-use crate::utils::{CtxResult, CtxResultErr, CtxResultExt, Log};
+use crate::utils::{
+    ColumnType, CtxResult, CtxResultErr, CtxResultExt, FileFormat, Log, Value,
+    validators::schema_validator::read_schema,
+};
+
+/// Rules for one column of a [`CsvSchema`], empty cells are only checked by `required`.
+#[derive(Clone, Debug)]
+pub struct CsvColumnRule {
+    /// Header of the column
+    pub name: String,
+    /// Type every cell must fit (see [`ColumnType::convert`])
+    pub ty: Option<ColumnType>,
+    /// Regex every cell must match as a whole
+    pub regex: Option<Regex>,
+    /// Whether the column must be in the headers and every cell must be non-empty
+    pub required: bool,
+}
+
+/// Column-type schema for CSV files, a map of headers to `type`, `regex` and `required` rules in
+/// any format that can be decoded (e.g. TOML, JSON, YAML):
+///
+/// ```toml
+/// [id]
+/// type = "int"
+/// required = true
+///
+/// [email]
+/// regex = '[^@\s]+@[^@\s]+'
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CsvSchema {
+    pub columns: Vec<CsvColumnRule>,
+}
+
+impl CsvSchema {
+    /// Reads a schema from a map of headers to rules, returns an error on unknown rules, types or
+    /// invalid regexes.
+    pub fn new(schema: &Value) -> CtxResult<Self> {
+        let Value::Map(map) = schema else {
+            return Err(invalid_schema(String::from("Expected a map of headers to column rules")));
+        };
+
+        let mut columns = Vec::with_capacity(map.len());
+
+        for (name, rules) in map {
+            let Value::Map(rules) = rules else {
+                return Err(invalid_schema(format!("Rules of column: {} aren't a map", name)));
+            };
+
+            let mut col =
+                CsvColumnRule { name: name.to_string(), ty: None, regex: None, required: false };
+
+            for (rule, value) in rules {
+                match (rule.as_str(), value) {
+                    ("type", Value::String(ty)) => {
+                        col.ty = Some(ty.parse().map_err(invalid_schema)?);
+                    }
+                    ("regex", Value::String(re)) => {
+                        let re = Regex::new(&format!("^(?:{})$", re)).map_err(|e| {
+                            invalid_schema(format!("Invalid regex of column: {}: {}", name, e))
+                        })?;
+
+                        col.regex = Some(re);
+                    }
+                    ("required", Value::Bool(b)) => col.required = *b,
+                    _ => {
+                        return Err(invalid_schema(format!(
+                            "Invalid rule: {} of column: {}, expected `type` (string), `regex` (string) or `required` (bool)",
+                            rule, name
+                        )));
+                    }
+                }
+            }
+
+            columns.push(col);
+        }
+
+        Ok(Self { columns })
+    }
+
+    /// Reads a schema in any format that can be decoded, the first record of the source is the
+    /// schema.
+    pub fn from_reader(
+        reader: impl Read + 'static,
+        format: impl Into<Arc<dyn FileFormat>>,
+    ) -> CtxResult<Self> {
+        Self::new(&read_schema(reader, format.into(), "--csv-schema")?)
+    }
+}
+
+fn invalid_schema(msg: String) -> CtxResultErr {
+    CtxResultErr::new("Check the schema passed to `--csv-schema`", msg)
+}
 
 #[inline(always)]
-pub fn validate_csv(path: &PathBuf, delimiter: char) -> CtxResult<()> {
-    let file = File::open(path)
-        .context("Failed to validate file")
-        .context(ctx!("Failed to open file: {}", &path.to_string_lossy()))?;
+pub fn validate_csv<R: Read>(reader: R, delimiter: char) -> CtxResult<()> {
+    validate_csv_with_schema(reader, delimiter, None)
+}
 
-    let buf = BufReader::with_capacity(256 * 1024, file);
+/// Validates the structure of a CSV file, each problem is logged with its line and column:
+///
+/// - Blank and duplicate headers, a blank last header is also reported as a trailing delimiter
+/// - Ragged rows with more or fewer fields than headers, rows with one extra empty field are
+///   reported as trailing delimiters
+/// - Cells that aren't valid UTF-8
+/// - Line endings that differ from the first line ending (e.g. CRLF in a LF file)
+/// - Unbalanced quotes (quotes inside unquoted fields, data after closing quotes and quotes that
+///   are never closed)
+///
+/// Rows are also checked against the rules of a [`CsvSchema`] if one is passed. Returns an error
+/// after the whole file is validated if any problem is found.
+pub fn validate_csv_with_schema<R: Read>(
+    reader: R,
+    delimiter: char,
+    schema: Option<&CsvSchema>,
+) -> CtxResult<()> {
+    match check_csv(reader, delimiter, schema)? {
+        0 => Ok(()),
+        n => {
+            Err(CtxResultErr::new("Input file is invalid", format!("{} problem(s) in CSV data", n)))
+        }
+    }
+}
 
+fn check_csv<R: Read>(reader: R, delimiter: char, schema: Option<&CsvSchema>) -> CtxResult<usize> {
     if !delimiter.is_ascii() {
-        eprintln!("Input delimiter: {} is not valid UTF-8", delimiter);
-        std::process::exit(1);
+        Err(Error::new(EK::InvalidInput, format!("Input delimiter: {} is not ASCII", delimiter)))
+            .context("Failed to validate file")?;
+    }
+
+    let mut buf = BufReader::with_capacity(256 * 1024, reader);
+    let mut scanner = Scanner::new(delimiter as u8, schema);
+
+    loop {
+        let chunk = buf.fill_buf().context("Failed to read input file")?;
+
+        if chunk.is_empty() {
+            break;
+        }
+
+        let n = chunk.len();
+
+        for &b in chunk {
+            scanner.push(b);
+        }
+
+        buf.consume(n);
+    }
+
+    scanner.finish();
+
+    Ok(scanner.checks.problems)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    /// Start of a field
+    Start,
+    Unquoted,
+    Quoted,
+    /// Quote in a quoted field, either closing it or escaping another quote
+    QuoteInQuoted,
+    /// Unquoted data after a closing quote or a stray quote, already reported
+    Malformed,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum LineEnding {
+    Lf,
+    CrLf,
+    Cr,
+}
+
+impl LineEnding {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Lf => "LF",
+            Self::CrLf => "CRLF",
+            Self::Cr => "CR",
+        }
+    }
+}
+
+/// Fields of the current record, stored in one buffer like `csv::ByteRecord`.
+#[derive(Default)]
+struct Record {
+    buf: Vec<u8>,
+    ends: Vec<usize>,
+    /// Line each field starts at
+    lines: Vec<u64>,
+    last_quoted: bool,
+}
+
+impl Record {
+    fn cell(&self, idx: usize) -> &[u8] {
+        let start = if idx == 0 { 0 } else { self.ends[idx - 1] };
+        &self.buf[start..self.ends[idx]]
+    }
+
+    fn clear(&mut self) {
+        self.buf.clear();
+        self.ends.clear();
+        self.lines.clear();
+    }
+}
+
+/// Byte by byte CSV parser that reports quoting problems and line endings while parsing and hands
+/// every record to [`Checks`].
+struct Scanner<'a> {
+    delimiter: u8,
+    state: State,
+    line: u64,
+    field_line: u64,
+    quoted: bool,
+    prev: u8,
+    /// Line and column of a CR outside of quotes, CRLF if the next byte is LF
+    pending_cr: Option<(u64, usize)>,
+    record: Record,
+    checks: Checks<'a>,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(delimiter: u8, schema: Option<&'a CsvSchema>) -> Self {
+        Self {
+            delimiter,
+            state: State::Start,
+            line: 1,
+            field_line: 1,
+            quoted: false,
+            prev: 0,
+            pending_cr: None,
+            record: Record::default(),
+            checks: Checks { schema, ..Default::default() },
+        }
+    }
+
+    #[inline]
+    fn column(&self) -> usize {
+        self.record.ends.len() + 1
+    }
+
+    #[inline]
+    fn push(&mut self, b: u8) {
+        if let Some((line, col)) = self.pending_cr.take() {
+            if b == b'\n' {
+                self.checks.line_ending(LineEnding::CrLf, line, col);
+                self.prev = b;
+                return;
+            }
+
+            self.checks.line_ending(LineEnding::Cr, line, col);
+        }
+
+        match self.state {
+            State::Quoted => {
+                if b == b'"' {
+                    self.state = State::QuoteInQuoted;
+                } else {
+                    if b == b'\r' || (b == b'\n' && self.prev != b'\r') {
+                        self.line += 1;
+                    }
+
+                    self.record.buf.push(b);
+                }
+            }
+            State::QuoteInQuoted if b == b'"' => {
+                self.record.buf.push(b);
+                self.state = State::Quoted;
+            }
+            State::QuoteInQuoted if b != self.delimiter && b != b'\r' && b != b'\n' => {
+                self.checks.report(
+                    self.line,
+                    self.column(),
+                    "Unbalanced quotes",
+                    String::from("Data after the closing quote of a field"),
+                );
+
+                self.record.buf.push(b);
+                self.state = State::Malformed;
+            }
+            _ => self.unquoted(b),
+        }
+
+        self.prev = b;
+    }
+
+    #[inline]
+    fn unquoted(&mut self, b: u8) {
+        match b {
+            _ if b == self.delimiter => self.end_field(),
+            b'\r' | b'\n' => {
+                let col = self.column();
+
+                self.end_record();
+
+                if b == b'\r' {
+                    self.pending_cr = Some((self.line, col));
+                } else {
+                    self.checks.line_ending(LineEnding::Lf, self.line, col);
+                }
+
+                self.line += 1;
+                self.field_line = self.line;
+            }
+            b'"' if self.state == State::Start => {
+                self.state = State::Quoted;
+                self.quoted = true;
+            }
+            b'"' if self.state == State::Unquoted => {
+                self.checks.report(
+                    self.line,
+                    self.column(),
+                    "Unbalanced quotes",
+                    String::from("Quote inside an unquoted field"),
+                );
+
+                self.record.buf.push(b);
+                self.state = State::Malformed;
+            }
+            _ => {
+                self.record.buf.push(b);
+
+                if self.state == State::Start {
+                    self.state = State::Unquoted;
+                }
+            }
+        }
     }
 
-    let d = delimiter as u8;
+    fn end_field(&mut self) {
+        self.record.ends.push(self.record.buf.len());
+        self.record.lines.push(self.field_line);
+        self.record.last_quoted = self.quoted;
 
-    let mut reader = csv::ReaderBuilder::new().delimiter(d).from_reader(buf);
+        self.state = State::Start;
+        self.quoted = false;
+        self.field_line = self.line;
+    }
+
+    fn end_record(&mut self) {
+        // empty lines are skipped like the `csv` crate does
+        let is_empty = self.record.ends.is_empty()
+            && self.record.buf.is_empty()
+            && self.state == State::Start
+            && !self.quoted;
 
-    let mut res = Ok(());
+        if !is_empty {
+            self.end_field();
+            self.checks.record(&self.record);
+        }
 
-    reader
-        .byte_headers()
-        .context(ctx!("Input file: {} is invalid", &path.to_string_lossy()))
-        .context("Failed to read input file headers")?;
+        self.record.clear();
+    }
 
-    for (idx, rec) in reader.byte_records().enumerate() {
-        let opt =
-            rec.context(ctx!("Invalid CSV data at record: {}", idx + 1)).log("[WARN]").is_none();
+    fn finish(&mut self) {
+        if let Some((line, col)) = self.pending_cr.take() {
+            self.checks.line_ending(LineEnding::Cr, line, col);
+        }
 
-        if opt && res.is_ok() {
-            res = Err(CtxResultErr::new("Input file is invalid", String::from("Invalid CSV data")));
+        if self.state == State::Quoted {
+            self.checks.report(
+                self.field_line,
+                self.column(),
+                "Unbalanced quotes",
+                String::from("Quote is never closed"),
+            );
         }
+
+        self.end_record();
     }
+}
+
+/// Checks of whole records, counting every reported problem.
+#[derive(Default)]
+struct Checks<'a> {
+    schema: Option<&'a CsvSchema>,
+    headers: Option<Vec<String>>,
+    /// Index of the column of each schema rule
+    rule_columns: Vec<Option<usize>>,
+    ending: Option<LineEnding>,
+    mixed_endings: bool,
+    problems: usize,
+}
+
+impl Checks<'_> {
+    fn report(&mut self, line: u64, column: usize, msg: &str, detail: String) {
+        self.problems += 1;
+
+        let _ = Err::<(), _>(CtxResultErr::new(msg, detail))
+            .context(ctx!("Invalid CSV data at line: {}, column: {}", line, column))
+            .log("[WARN]");
+    }
+
+    fn line_ending(&mut self, ending: LineEnding, line: u64, column: usize) {
+        match self.ending {
+            None => self.ending = Some(ending),
+            // only the first mismatch is reported, every line after it would be reported too
+            Some(first) if first != ending && !self.mixed_endings => {
+                self.mixed_endings = true;
+
+                self.report(
+                    line,
+                    column,
+                    "Mixed line endings",
+                    format!(
+                        "Line ends with {} but previous lines end with {}",
+                        ending.name(),
+                        first.name()
+                    ),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    fn record(&mut self, rec: &Record) {
+        match self.headers {
+            None => self.check_headers(rec),
+            Some(_) => self.check_row(rec),
+        }
+    }
+
+    fn check_headers(&mut self, rec: &Record) {
+        let count = rec.ends.len();
+        let mut headers = Vec::with_capacity(count);
+        let mut seen: HashMap<String, usize> = HashMap::with_capacity(count);
+
+        for idx in 0..count {
+            let (line, col) = (rec.lines[idx], idx + 1);
+            let mut cell = rec.cell(idx);
+
+            if idx == 0 {
+                cell = cell.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(cell);
+            }
+
+            let name = match std::str::from_utf8(cell) {
+                Ok(name) => name.to_string(),
+                Err(e) => {
+                    self.report(line, col, "Invalid UTF-8", utf8_detail(e));
+                    headers.push(String::from_utf8_lossy(cell).into_owned());
+                    continue;
+                }
+            };
+
+            if name.is_empty() && col == count && col > 1 && !rec.last_quoted {
+                self.report(
+                    line,
+                    col,
+                    "Trailing delimiter",
+                    String::from("Headers end with a delimiter"),
+                );
+            }
+
+            // the trailing delimiter still adds a column without a name
+            if name.trim().is_empty() {
+                self.report(line, col, "Blank header", String::from("Header is empty"));
+            } else if let Some(first) = seen.get(&name) {
+                self.report(
+                    line,
+                    col,
+                    "Duplicate header",
+                    format!("Header: {} is already the header of column: {}", name, first),
+                );
+            } else {
+                seen.insert(name.clone(), col);
+            }
+
+            headers.push(name);
+        }
+
+        if let Some(schema) = self.schema {
+            let line = rec.lines.first().copied().unwrap_or(1);
+
+            for rule in &schema.columns {
+                let idx = headers.iter().position(|h| *h == rule.name);
+
+                if idx.is_none() && rule.required {
+                    self.report(
+                        line,
+                        count + 1,
+                        "Missing required column",
+                        format!("Column: {} is required by the schema", rule.name),
+                    );
+                }
 
-    res
+                self.rule_columns.push(idx);
+            }
+        }
+
+        self.headers = Some(headers);
+    }
+
+    fn check_row(&mut self, rec: &Record) {
+        let expected = self.headers.as_ref().map_or(0, Vec::len);
+        let count = rec.ends.len();
+
+        if count == expected + 1 && !rec.last_quoted && rec.cell(count - 1).is_empty() {
+            self.report(
+                rec.lines[count - 1],
+                count,
+                "Trailing delimiter",
+                format!("Row ends with a delimiter, expected {} fields", expected),
+            );
+        } else if count != expected {
+            self.report(
+                rec.lines[0],
+                count.min(expected) + 1,
+                "Ragged row",
+                format!("Expected {} fields, found {}", expected, count),
+            );
+        }
+
+        let mut cells = Vec::with_capacity(count);
+
+        for idx in 0..count {
+            match std::str::from_utf8(rec.cell(idx)) {
+                Ok(cell) => cells.push(Some(cell)),
+                Err(e) => {
+                    self.report(rec.lines[idx], idx + 1, "Invalid UTF-8", utf8_detail(e));
+                    cells.push(None);
+                }
+            }
+        }
+
+        let Some(schema) = self.schema else {
+            return;
+        };
+
+        let rule_columns = std::mem::take(&mut self.rule_columns);
+
+        for (rule, &idx) in schema.columns.iter().zip(&rule_columns) {
+            let Some(idx) = idx else {
+                continue;
+            };
+
+            // missing cells of ragged rows are empty, invalid UTF-8 is already reported
+            let cell = match cells.get(idx) {
+                Some(Some(cell)) => *cell,
+                Some(None) => continue,
+                None => "",
+            };
+
+            let line = rec.lines.get(idx).copied().unwrap_or(rec.lines[0]);
+
+            if cell.is_empty() {
+                if rule.required {
+                    self.report(
+                        line,
+                        idx + 1,
+                        "Missing required value",
+                        format!("Column: {} is required by the schema", rule.name),
+                    );
+                }
+
+                continue;
+            }
+
+            if let Some(ty) = rule.ty
+                && ty.convert(cell).is_none()
+            {
+                self.report(
+                    line,
+                    idx + 1,
+                    "Invalid type",
+                    format!("Expected {} in column: {}, found: {}", ty, rule.name, cell),
+                );
+            }
+
+            if let Some(re) = &rule.regex
+                && !re.is_match(cell)
+            {
+                self.report(
+                    line,
+                    idx + 1,
+                    "Regex mismatch",
+                    format!("Value: {} of column: {} doesn't match: {}", cell, rule.name, re),
+                );
+            }
+        }
+
+        self.rule_columns = rule_columns;
+    }
+}
+
+fn utf8_detail(e: std::str::Utf8Error) -> String {
+    format!("Cell has an invalid UTF-8 byte at offset: {}", e.valid_up_to())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn problems(input: &str, schema: Option<&CsvSchema>) -> usize {
+        check_csv(input.as_bytes(), ',', schema).unwrap()
+    }
+
+    #[test]
+    fn test_structural_problems() {
+        assert_eq!(problems("a,b\n1,2\r\n\"3\",\"x\"\"y\"\n\n", None), 1);
+        assert_eq!(problems("a,b,a,\n1,2,3,\n", None), 3);
+        assert_eq!(problems("a,b\n1,2,\n1\n1,2,3\n", None), 3);
+        assert_eq!(problems("a,b\n1,x\"y\n\"1\"x,2\n1,\"open\n", None), 3);
+        assert_eq!(check_csv(&b"a,b\n1,\xFF\n"[..], ',', None).unwrap(), 1);
+        assert!(validate_csv("a,\"b\nc\"\r\n1,2\r\n".as_bytes(), ',').is_ok());
+    }
+
+    #[test]
+    fn test_blank_trailing_header() {
+        // reported as a trailing delimiter and as a blank header, rows with the extra field match
+        assert_eq!(problems("a,b,\n1,2,3\n", None), 2);
+        assert_eq!(problems("a,b,\"\"\n1,2,3\n", None), 1);
+        assert_eq!(problems("a,b, \n1,2,3\n", None), 1);
+    }
+
+    #[test]
+    fn test_csv_schema() {
+        let schema = CsvSchema::new(
+            &json!({
+                "id": {"type": "int", "required": true},
+                "email": {"regex": "[^@]+@[^@]+"},
+                "missing": {"required": true}
+            })
+            .into(),
+        )
+        .unwrap();
+
+        assert_eq!(problems("id,email\n1,a@x.io\n,\nx,nope\n", Some(&schema)), 4);
+        assert!(CsvSchema::new(&json!({"id": {"type": "uuid"}}).into()).is_err());
+        assert!(CsvSchema::new(&json!({"id": {"max": 1}}).into()).is_err());
+    }
 }
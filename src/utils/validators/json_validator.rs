@@ -1,19 +1,22 @@
-use resext::ctx;
 use serde::de::IgnoredAny;
-use serde_json::Deserializer;
-use std::{fs::File, io::BufReader, path::PathBuf};
+use std::io::{BufRead, BufReader, Read};
 
-use crate::utils::{CtxResult, CtxResultErr, CtxResultExt, Log};
+use crate::utils::{CtxResult, CtxResultErr, CtxResultExt, Log, json};
 
 #[inline(always)]
-pub fn validate_json(path: &PathBuf) -> CtxResult<()> {
-    let file = File::open(path)
-        .context("Failed to validate file")
-        .context(ctx!("Failed to open file: {}", &path.to_string_lossy()))?;
+pub fn validate_json<R: Read>(reader: R) -> CtxResult<()> {
+    check_json(BufReader::with_capacity(256 * 1024, reader))
+}
 
-    let reader = BufReader::with_capacity(256 * 1024, file);
+/// Validates JSON like [`validate_json`], values are parsed straight from the slice (e.g. a
+/// memory-mapped file) instead of being read through a buffer.
+#[inline(always)]
+pub fn validate_json_slice(input: &[u8]) -> CtxResult<()> {
+    check_json(input)
+}
 
-    let file_stream = Deserializer::from_reader(reader).into_iter::<IgnoredAny>();
+fn check_json<R: BufRead>(reader: R) -> CtxResult<()> {
+    let file_stream = json::stream::<IgnoredAny, _>(reader);
 
     let mut res = Ok(());
 
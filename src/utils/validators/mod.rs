@@ -1,4 +1,9 @@
 pub mod csv_validator;
 pub mod json_validator;
 pub mod ndjson_validator;
+pub mod schema_validator;
 pub mod toml_validator;
+pub mod yaml_validator;
+
+pub use csv_validator::{CsvColumnRule, CsvSchema, validate_csv_with_schema};
+pub use schema_validator::{JsonSchema, validate_records};
@@ -1,21 +1,13 @@
 use resext::ctx;
-use std::{
-    fs::File,
-    io::{BufRead, BufReader},
-    path::PathBuf,
-};
+use std::io::{BufRead, BufReader, Read};
 
 use serde::de::IgnoredAny;
 
-use crate::utils::{CtxResult, CtxResultErr, CtxResultExt, Log};
+use crate::utils::{CtxResult, CtxResultErr, CtxResultExt, Log, json};
 
 #[inline(always)]
-pub fn validate_ndjson(path: &PathBuf) -> CtxResult<()> {
-    let file = File::open(path)
-        .context("Failed to validate file")
-        .context(ctx!("Failed to open file: {}", &path.to_string_lossy()))?;
-
-    let mut reader = BufReader::with_capacity(256 * 1024, file);
+pub fn validate_ndjson<R: Read>(reader: R) -> CtxResult<()> {
+    let mut reader = BufReader::with_capacity(256 * 1024, reader);
 
     // read lines one by one and deserialize them to check for errors
     let mut buf: Vec<u8> = Vec::new();
@@ -32,7 +24,7 @@ pub fn validate_ndjson(path: &PathBuf) -> CtxResult<()> {
         };
 
         // check line validity
-        let opt = serde_json::from_slice::<IgnoredAny>(&buf)
+        let opt = json::from_slice::<IgnoredAny>(&buf)
             .context(ctx!("Invalid NDJSON values at line: {}", idx))
             .log("[WARN]")
             .is_none();
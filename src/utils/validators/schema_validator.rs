@@ -0,0 +1,184 @@
+use resext::ctx;
+use std::{fmt, io::Read, sync::Arc};
+
+use crate::utils::{
+    ConvertOptions, CtxResult, CtxResultErr, CtxResultExt, FileFormat, Log, Records, Value,
+    WriterStreams, type_table,
+};
+
+/// JSON Schema (draft 2020-12) compiled for validating records, `format` keywords (e.g.
+/// `date-time`, `email`) are checked too.
+pub struct JsonSchema {
+    validator: jsonschema::Validator,
+}
+
+impl fmt::Debug for JsonSchema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JsonSchema").finish_non_exhaustive()
+    }
+}
+
+impl JsonSchema {
+    /// Compiles a schema, returns an error if it isn't a valid draft 2020-12 schema.
+    pub fn new(schema: &Value) -> CtxResult<Self> {
+        let schema = serde_json::to_value(schema).context("Failed to convert schema into JSON")?;
+
+        let validator = jsonschema::draft202012::options()
+            .should_validate_formats(true)
+            .build(&schema)
+            .map_err(|e| CtxResultErr::new("Check the schema passed to `--schema`", e.to_string()))
+            .context("Invalid JSON Schema")?;
+
+        Ok(Self { validator })
+    }
+
+    /// Reads a schema in any format that can be decoded (e.g. JSON, YAML), the first record of the
+    /// source is the schema.
+    pub fn from_reader(
+        reader: impl Read + 'static,
+        format: impl Into<Arc<dyn FileFormat>>,
+    ) -> CtxResult<Self> {
+        Self::new(&read_schema(reader, format.into(), "--schema")?)
+    }
+
+    /// Checks if a value matches the schema.
+    pub fn is_valid(&self, value: &Value) -> bool {
+        serde_json::to_value(value).is_ok_and(|v| self.validator.is_valid(&v))
+    }
+}
+
+/// Validates every record of a stream against a schema, each violation is logged with the line
+/// (NDJSON lines, CSV rows) or record number (JSON array elements, YAML documents, TOML documents),
+/// the JSON pointer of the failing value and the failing schema keyword. Returns an error after
+/// every record is validated if any record is invalid.
+///
+/// Table rows are validated as objects keyed by their headers (see
+/// [`WriterStreams::into_records`]), columns of untyped tables (e.g. CSV without `--parse-numbers`)
+/// are typed like [`type_table`] does.
+pub fn validate_records(
+    data: WriterStreams<Records>,
+    schema: &JsonSchema,
+    opts: &ConvertOptions,
+) -> CtxResult<()> {
+    // CSV rows are counted from the line after the headers
+    let (unit, first) = match &data {
+        WriterStreams::Table { .. } => ("Line", 2),
+        WriterStreams::Ndjson { .. } => ("Line", 1),
+        WriterStreams::Values { .. } => ("Record", 1),
+    };
+
+    // untyped columns are typed like with `--parse-numbers`, so a schema inferred from the same
+    // table matches it
+    let data = if opts.is_typed() { data } else { type_table(data, true, &opts.types)? };
+
+    let mut invalid: usize = 0;
+
+    for (idx, rec) in data.into_records(opts).enumerate() {
+        let loc = idx + first;
+
+        let Some(rec) = rec
+            .and_then(|r| serde_json::to_value(&r).context("Failed to convert record into JSON"))
+            .context(ctx!("Invalid record at {}: {}", unit.to_lowercase(), loc))
+            .log("[WARN]")
+        else {
+            invalid += 1;
+            continue;
+        };
+
+        let mut is_valid = true;
+
+        for err in schema.validator.iter_errors(&rec) {
+            is_valid = false;
+
+            let _ = Err::<(), _>(CtxResultErr::new("Schema violation", err.to_string()))
+                .context(ctx!(
+                    "{}: {}, at: {} (schema: {})",
+                    unit,
+                    loc,
+                    pointer(err.instance_path().as_str()),
+                    pointer(err.schema_path().as_str())
+                ))
+                .log("[WARN]");
+        }
+
+        invalid += !is_valid as usize;
+    }
+
+    match invalid {
+        0 => Ok(()),
+        n => Err(CtxResultErr::new(
+            "Input file is invalid",
+            format!("{} record(s) don't match the schema", n),
+        )),
+    }
+}
+
+/// Reads the first record of a schema file passed to `flag`.
+pub(crate) fn read_schema(
+    reader: impl Read + 'static,
+    format: Arc<dyn FileFormat>,
+    flag: &str,
+) -> CtxResult<Value> {
+    let opts = ConvertOptions::default();
+
+    let decoder = format.decoder().ok_or_else(|| {
+        CtxResultErr::new(
+            "Unsupported format",
+            format!("Format: {} can't be decoded", format.name()),
+        )
+    })?;
+
+    let schema = match decoder.decode(Box::new(reader), &opts).context("Failed to read schema")? {
+        WriterStreams::Values { mut iter } | WriterStreams::Ndjson { values: mut iter } => {
+            iter.next()
+        }
+        WriterStreams::Table { .. } => None,
+    };
+
+    match schema {
+        Some(schema) => schema.context("Failed to read schema"),
+        None => Err(CtxResultErr::new(
+            &format!("Check the schema passed to `{}`", flag),
+            String::from("Schema file is empty or isn't a JSON / YAML / TOML document"),
+        )),
+    }
+}
+
+#[inline]
+fn pointer(path: &str) -> &str {
+    if path.is_empty() { "/" } else { path }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_validate_records() {
+        let schema = JsonSchema::new(
+            &json!({
+                "type": "object",
+                "properties": {"id": {"type": "integer"}, "at": {"format": "date-time"}},
+                "required": ["id"]
+            })
+            .into(),
+        )
+        .unwrap();
+
+        assert!(schema.is_valid(&json!({"id": 1, "at": "2024-01-31T10:00:00Z"}).into()));
+        assert!(!schema.is_valid(&json!({"id": 1, "at": "yesterday"}).into()));
+        assert!(!schema.is_valid(&json!({"id": "1"}).into()));
+
+        let values: Vec<CtxResult<Value>> =
+            [json!({"id": 1}), json!({})].into_iter().map(|v| Ok(v.into())).collect();
+        let data = WriterStreams::Ndjson { values: Box::new(values.into_iter()) as Records };
+
+        assert!(validate_records(data, &schema, &ConvertOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_invalid_schema() {
+        assert!(JsonSchema::new(&json!({"type": "nope"}).into()).is_err());
+    }
+}
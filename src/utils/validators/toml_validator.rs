@@ -1,21 +1,23 @@
-use resext::ctx;
-use std::path::PathBuf;
+use std::io::Read;
 
 use crate::utils::{CtxResult, CtxResultExt};
 
 #[inline(always)]
-pub fn validate_toml(path: &PathBuf) -> CtxResult<()> {
-    let file_bytes = std::fs::read(path)
-        .context("Failed to validate file")
-        .context(ctx!("Failed to open file: {}", &path.to_string_lossy()))?;
+pub fn validate_toml<R: Read>(mut reader: R) -> CtxResult<()> {
+    let mut file_bytes = Vec::new();
 
-    let res = toml::from_slice::<serde::de::IgnoredAny>(&file_bytes)
-        .context(ctx!("Invalid TOML values: {}", &path.to_string_lossy()))
-        .map(|_| ());
+    reader
+        .read_to_end(&mut file_bytes)
+        .context("Failed to validate file")
+        .context("Failed to read input file")?;
 
-    println!(
-        "This file was not streamed due to TOML's limitations with streaming.\nIt was all loaded into memory as bytes then validated."
-    );
+    validate_toml_slice(&file_bytes)
+}
 
-    res
+/// Validates TOML that's already in memory (e.g. a memory-mapped file) without copying it.
+#[inline(always)]
+pub fn validate_toml_slice(bytes: &[u8]) -> CtxResult<()> {
+    toml::from_slice::<serde::de::IgnoredAny>(bytes)
+        .context("Invalid TOML values in input file")
+        .map(|_| ())
 }
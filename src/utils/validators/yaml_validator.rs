@@ -0,0 +1,34 @@
+use resext::ctx;
+use serde::{Deserialize, de::IgnoredAny};
+use std::io::{BufReader, Read};
+
+use crate::utils::{CtxResult, CtxResultErr, CtxResultExt, Log};
+
+#[inline(always)]
+pub fn validate_yaml<R: Read>(reader: R) -> CtxResult<()> {
+    let reader = BufReader::with_capacity(256 * 1024, reader);
+
+    let mut res = Ok(());
+
+    for (idx, doc) in serde_yaml::Deserializer::from_reader(reader).enumerate() {
+        let item = IgnoredAny::deserialize(doc);
+
+        let item = match item.as_ref().err().and_then(|e| e.location()) {
+            Some(loc) => item.context(ctx!(
+                "Invalid YAML values at line: {}, column: {}",
+                loc.line(),
+                loc.column()
+            )),
+            None => item.context(ctx!("Invalid YAML values in document: {}", idx + 1)),
+        };
+
+        if item.log("[WARN]").is_none() {
+            // YAML syntax errors can't be recovered from, so stop after the first one
+            res =
+                Err(CtxResultErr::new("Input file is invalid", String::from("Invalid YAML data")));
+            break;
+        }
+    }
+
+    res
+}
@@ -0,0 +1,413 @@
+use std::{borrow::Cow, fmt};
+
+use indexmap::IndexMap;
+use serde::{
+    Deserialize, Deserializer, Serialize, Serializer,
+    de::{self, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor},
+    ser::{SerializeMap, SerializeSeq},
+};
+
+/// Map of keys to values in insertion order.
+pub type Map = IndexMap<String, Value>;
+
+/// A single value in fiux's record model, every decoder produces values and every encoder
+/// consumes them, so any format can be converted into any other.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Value {
+    #[default]
+    Null,
+
+    Bool(bool),
+
+    Int(i64),
+
+    Float(f64),
+
+    /// Number that doesn't fit into an `i64` / `f64` without losing precision, kept in its textual
+    /// form (e.g. `18446744073709551615`)
+    Decimal(String),
+
+    String(String),
+
+    /// Bytes that aren't valid UTF-8 (e.g. CSV cells)
+    Bytes(Vec<u8>),
+
+    /// Date and / or time in its RFC 3339 textual form (e.g. `1979-05-27T07:32:00Z`)
+    DateTime(String),
+
+    Array(Vec<Value>),
+
+    Map(Map),
+}
+
+impl Value {
+    pub fn is_null(&self) -> bool {
+        matches!(self, Self::Null)
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) | Self::Decimal(s) | Self::DateTime(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Gets the value of a key if this is a map.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Self::Map(map) => map.get(key),
+            _ => None,
+        }
+    }
+
+    /// Name of the value's type (e.g. `int`, `map`).
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Null => "null",
+            Self::Bool(_) => "bool",
+            Self::Int(_) => "int",
+            Self::Float(_) => "float",
+            Self::Decimal(_) => "decimal",
+            Self::String(_) => "string",
+            Self::Bytes(_) => "bytes",
+            Self::DateTime(_) => "datetime",
+            Self::Array(_) => "array",
+            Self::Map(_) => "map",
+        }
+    }
+
+    /// Writes the value as a table cell, `null` is an empty cell, arrays and maps are encoded as
+    /// JSON and bytes that aren't valid UTF-8 are replaced.
+    pub fn to_cell(&self) -> Cow<'_, str> {
+        match self {
+            Self::Null => Cow::Borrowed(""),
+            Self::Bool(b) => Cow::Borrowed(if *b { "true" } else { "false" }),
+            Self::Int(i) => Cow::Owned(i.to_string()),
+            Self::Float(f) => Cow::Owned(float_to_string(*f)),
+            Self::String(s) | Self::Decimal(s) | Self::DateTime(s) => Cow::Borrowed(s),
+            Self::Bytes(b) => String::from_utf8_lossy(b),
+            Self::Array(_) | Self::Map(_) => {
+                Cow::Owned(serde_json::to_string(self).unwrap_or_default())
+            }
+        }
+    }
+
+    /// Gets the bytes of a cell without replacing invalid UTF-8, see [`Value::to_cell`].
+    pub fn to_cell_bytes(&self) -> Cow<'_, [u8]> {
+        match self {
+            Self::Bytes(b) => Cow::Borrowed(b),
+            _ => match self.to_cell() {
+                Cow::Borrowed(s) => Cow::Borrowed(s.as_bytes()),
+                Cow::Owned(s) => Cow::Owned(s.into_bytes()),
+            },
+        }
+    }
+}
+
+#[inline]
+fn float_to_string(f: f64) -> String {
+    match serde_json::Number::from_f64(f) {
+        Some(n) => n.to_string(),
+        None => f.to_string(),
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Self::Bool(b)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(i: i64) -> Self {
+        Self::Int(i)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(f: f64) -> Self {
+        Self::Float(f)
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Self::String(s)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Self::String(s.to_string())
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(arr: Vec<Value>) -> Self {
+        Self::Array(arr)
+    }
+}
+
+impl From<Map> for Value {
+    fn from(map: Map) -> Self {
+        Self::Map(map)
+    }
+}
+
+impl From<serde_json::Value> for Value {
+    fn from(json: serde_json::Value) -> Self {
+        match json {
+            serde_json::Value::Null => Self::Null,
+            serde_json::Value::Bool(b) => Self::Bool(b),
+            serde_json::Value::Number(n) => {
+                if let Some(i) = n.as_i64() {
+                    Self::Int(i)
+                } else if let Some(u) = n.as_u64() {
+                    Self::Decimal(u.to_string())
+                } else {
+                    Self::Float(n.as_f64().unwrap_or(f64::NAN))
+                }
+            }
+            serde_json::Value::String(s) => Self::String(s),
+            serde_json::Value::Array(arr) => Self::Array(arr.into_iter().map(Self::from).collect()),
+            serde_json::Value::Object(obj) => {
+                Self::Map(obj.into_iter().map(|(k, v)| (k, Self::from(v))).collect())
+            }
+        }
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Self::Null => serializer.serialize_unit(),
+            Self::Bool(b) => serializer.serialize_bool(*b),
+            Self::Int(i) => serializer.serialize_i64(*i),
+            Self::Float(f) => serializer.serialize_f64(*f),
+            Self::Decimal(d) => {
+                if let Ok(u) = d.parse::<u64>() {
+                    serializer.serialize_u64(u)
+                } else if let Ok(i) = d.parse::<i128>() {
+                    serializer.serialize_i128(i)
+                } else if let Ok(f) = d.parse::<f64>()
+                    && f.is_finite()
+                {
+                    serializer.serialize_f64(f)
+                } else {
+                    serializer.serialize_str(d)
+                }
+            }
+            Self::String(s) | Self::DateTime(s) => serializer.serialize_str(s),
+            Self::Bytes(b) => serializer.serialize_str(&String::from_utf8_lossy(b)),
+            Self::Array(arr) => {
+                let mut seq = serializer.serialize_seq(Some(arr.len()))?;
+
+                for v in arr {
+                    seq.serialize_element(v)?;
+                }
+
+                seq.end()
+            }
+            Self::Map(map) => {
+                let mut m = serializer.serialize_map(Some(map.len()))?;
+
+                for (k, v) in map {
+                    m.serialize_entry(k, v)?;
+                }
+
+                m.end()
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+/// TOML datetimes are deserialized as a single key map with this key.
+const TOML_DATETIME: &str = "$__toml_private_datetime";
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("any valid value")
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<Value, E> {
+        Ok(Value::Bool(v))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Value, E> {
+        Ok(Value::Int(v))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Value, E> {
+        Ok(i64::try_from(v).map(Value::Int).unwrap_or_else(|_| Value::Decimal(v.to_string())))
+    }
+
+    fn visit_i128<E: de::Error>(self, v: i128) -> Result<Value, E> {
+        Ok(i64::try_from(v).map(Value::Int).unwrap_or_else(|_| Value::Decimal(v.to_string())))
+    }
+
+    fn visit_u128<E: de::Error>(self, v: u128) -> Result<Value, E> {
+        Ok(i64::try_from(v).map(Value::Int).unwrap_or_else(|_| Value::Decimal(v.to_string())))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Value, E> {
+        Ok(Value::Float(v))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<Value, E> {
+        Ok(Value::String(v))
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Value, E> {
+        Ok(Value::Bytes(v.to_vec()))
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Value, E> {
+        Ok(Value::Bytes(v))
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        Value::deserialize(deserializer)
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Value, D::Error> {
+        Value::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut arr = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+
+        while let Some(v) = seq.next_element()? {
+            arr.push(v);
+        }
+
+        Ok(Value::Array(arr))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Value, A::Error> {
+        let mut map = Map::with_capacity(access.size_hint().unwrap_or(0).min(4096));
+
+        while let Some(Key(k)) = access.next_key()? {
+            let v: Value = access.next_value()?;
+
+            if k == TOML_DATETIME && map.is_empty() {
+                return Ok(match v {
+                    Value::String(s) => Value::DateTime(s),
+                    other => other,
+                });
+            }
+
+            map.insert(k, v);
+        }
+
+        Ok(Value::Map(map))
+    }
+
+    /// YAML tagged values (e.g. `!Tag value`) are kept as a single key map
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Value, A::Error> {
+        let (Key(tag), variant) = data.variant()?;
+        let value: Value = variant.newtype_variant()?;
+
+        Ok(Value::Map(Map::from_iter([(tag, value)])))
+    }
+}
+
+/// Map key that accepts non-string keys (e.g. YAML integer keys) and converts them into strings.
+struct Key(String);
+
+impl<'de> Deserialize<'de> for Key {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let key = match Value::deserialize(deserializer)? {
+            Value::String(s) => s,
+            other => other.to_cell().into_owned(),
+        };
+
+        Ok(Key(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_keeps_insertion_order() {
+        let v: Value = serde_json::from_str(r#"{"b": 1, "a": {"d": null, "c": [true]}}"#).unwrap();
+
+        assert_eq!(serde_json::to_string(&v).unwrap(), r#"{"b":1,"a":{"d":null,"c":[true]}}"#);
+    }
+
+    #[test]
+    fn test_deserialize_numbers() {
+        let v: Value = serde_json::from_str("[1, -2, 1.5, 18446744073709551615]").unwrap();
+
+        assert_eq!(
+            v,
+            Value::Array(vec![
+                Value::Int(1),
+                Value::Int(-2),
+                Value::Float(1.5),
+                Value::Decimal("18446744073709551615".to_string()),
+            ])
+        );
+
+        assert_eq!(serde_json::to_string(&v).unwrap(), "[1,-2,1.5,18446744073709551615]");
+    }
+
+    #[test]
+    fn test_deserialize_toml_datetime() {
+        let v: Value = toml::from_str("at = 1979-05-27T07:32:00Z").unwrap();
+
+        assert_eq!(v.get("at"), Some(&Value::DateTime("1979-05-27T07:32:00Z".to_string())));
+    }
+
+    #[test]
+    fn test_deserialize_yaml_non_string_keys() {
+        let v: Value = serde_yaml::from_str("1: a\ntrue: b\n").unwrap();
+
+        assert_eq!(v.get("1"), Some(&Value::from("a")));
+        assert_eq!(v.get("true"), Some(&Value::from("b")));
+    }
+
+    #[test]
+    fn test_to_cell() {
+        assert_eq!(Value::Null.to_cell(), "");
+        assert_eq!(Value::Float(2.0).to_cell(), "2.0");
+        assert_eq!(Value::Bytes(vec![b'a', 0xff]).to_cell(), "a\u{fffd}");
+        assert_eq!(Value::Bytes(vec![b'a', 0xff]).to_cell_bytes().as_ref(), [b'a', 0xff]);
+        assert_eq!(Value::Array(vec![Value::Int(1), Value::from("x")]).to_cell(), r#"[1,"x"]"#);
+    }
+}
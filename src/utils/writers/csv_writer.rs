@@ -1,18 +1,27 @@
-use crate::utils::{CtxResult, CtxResultExt, DataTypes, Log, WriterStreams, into_byte_record};
+use crate::utils::{
+    CtxResult, CtxResultErr, CtxResultExt, FlattenOpts, Log, Value, WriterStreams, flatten,
+    into_row,
+    parallel::{batches, write_ordered},
+};
 use resext::ctx;
 
-use std::io::BufWriter;
+use std::{
+    collections::{HashMap, HashSet},
+    io::{BufRead, BufReader, BufWriter, Error, ErrorKind as EK, Seek, SeekFrom, Write},
+    sync::{Arc, Mutex, PoisonError},
+};
 
 pub fn csv_writer(
-    data_stream: WriterStreams<impl Iterator<Item = CtxResult<DataTypes>>>,
-    file: std::fs::File,
+    data_stream: WriterStreams<impl Iterator<Item = CtxResult<Value>>>,
+    file: impl Write,
     delimiter: char,
+    flatten_opts: &FlattenOpts,
 ) -> CtxResult<()> {
     let buffered = BufWriter::new(file);
 
     if !delimiter.is_ascii() {
-        eprintln!("Output delimiter: {} is not valid UTF-8", delimiter);
-        std::process::exit(1);
+        Err(Error::new(EK::InvalidInput, format!("Output delimiter: {} is not ASCII", delimiter)))
+            .context("Failed to create CSV writer")?;
     }
 
     let d = delimiter as u8;
@@ -23,26 +32,327 @@ pub fn csv_writer(
             wtr.write_record(&headers).context("Failed to write headers into output file")?;
 
             for (line_no, line) in iter.enumerate() {
-                let b =
-                    into_byte_record(line).context("Failed to re-serialize object").log("[WARN]");
+                let row = into_row(line).context("Failed to re-serialize object").log("[WARN]");
 
-                let b = match b {
-                    Some(b) => b,
+                let row = match row {
+                    Some(row) => row,
                     None => continue,
                 };
 
-                wtr.write_record(&b)
+                wtr.write_record(row.iter().map(|cell| cell.to_cell_bytes()))
                     .context(ctx!("Failed to write CSV record at: {}", line_no + 1))?;
             }
+        }
 
-            wtr.flush().context("Failed to flush writer")?;
+        WriterStreams::Values { iter } => {
+            write_flattened(&mut wtr, array_rows(iter), flatten_opts)?;
         }
-        _ => {
-            eprintln!("CSV only supports table-based formats with headers");
-            eprintln!(" -> Support for non-table formats will be added soon");
-            std::process::exit(1);
+
+        WriterStreams::Ndjson { values } => {
+            write_flattened(&mut wtr, values, flatten_opts)?;
         }
     }
 
+    wtr.flush().context("Failed to flush writer")?;
+
     Ok(())
 }
+
+/// Headers discovered from flattened records in order of first appearance.
+#[derive(Clone, Default)]
+struct Headers {
+    names: Vec<String>,
+    idx: HashMap<String, usize>,
+}
+
+impl Headers {
+    #[inline]
+    fn extend(&mut self, cells: &[(String, String)]) {
+        for (k, _) in cells {
+            if !self.idx.contains_key(k) {
+                self.idx.insert(k.to_string(), self.names.len());
+                self.names.push(k.to_string());
+            }
+        }
+    }
+}
+
+fn write_flattened<W: Write>(
+    wtr: &mut csv::Writer<W>,
+    rows: impl Iterator<Item = CtxResult<Value>>,
+    opts: &FlattenOpts,
+) -> CtxResult<()> {
+    let mut rows = valid_rows(rows).map(|row| {
+        let mut cells = Vec::new();
+        flatten(&row, opts, &mut cells);
+
+        cells
+    });
+
+    let mut headers = Headers::default();
+    let mut record: Vec<String> = Vec::new();
+    let dropped: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+
+    if opts.two_pass {
+        // spill flattened records to a temporary file to get the union of all columns
+        let spill = tempfile::tempfile().context("Failed to create temporary file")?;
+        let mut spill_wtr = BufWriter::with_capacity(256 * 1024, spill);
+
+        for cells in rows {
+            headers.extend(&cells);
+
+            serde_json::to_writer(&mut spill_wtr, &cells)
+                .context("Failed to write record into temporary file")?;
+
+            writeln!(spill_wtr).context("Failed to write newline into temporary file")?;
+        }
+
+        let mut spill = spill_wtr
+            .into_inner()
+            .map_err(|e| e.into_error())
+            .context("Failed to flush temporary file")?;
+
+        spill.seek(SeekFrom::Start(0)).context("Failed to rewind temporary file")?;
+
+        if !headers.names.is_empty() {
+            wtr.write_record(&headers.names).context("Failed to write headers into output file")?;
+        }
+
+        for (line_no, line) in BufReader::with_capacity(256 * 1024, spill).lines().enumerate() {
+            let line = line.context("Failed to read record from temporary file")?;
+
+            let cells: Vec<(String, String)> =
+                serde_json::from_str(&line).context("Failed to read record from temporary file")?;
+
+            write_row(wtr, &headers, cells, &mut record, &dropped)
+                .context(ctx!("Failed to write CSV record at: {}", line_no + 1))?;
+        }
+    } else {
+        let sample: Vec<Vec<(String, String)>> =
+            rows.by_ref().take(opts.sample_size.max(1)).collect();
+
+        for cells in &sample {
+            headers.extend(cells);
+        }
+
+        if !headers.names.is_empty() {
+            wtr.write_record(&headers.names).context("Failed to write headers into output file")?;
+        }
+
+        for (line_no, cells) in sample.into_iter().chain(rows).enumerate() {
+            write_row(wtr, &headers, cells, &mut record, &dropped)
+                .context(ctx!("Failed to write CSV record at: {}", line_no + 1))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Drops invalid records, logging them.
+#[inline]
+fn valid_rows(rows: impl Iterator<Item = CtxResult<Value>>) -> impl Iterator<Item = Value> {
+    rows.enumerate().filter_map(|(idx, row)| {
+        row.context(ctx!("Failed to flatten record: {}", idx + 1)).log("[WARN]")
+    })
+}
+
+/// Top-level arrays are written as one row per element.
+#[inline]
+fn array_rows(
+    iter: impl Iterator<Item = CtxResult<Value>>,
+) -> impl Iterator<Item = CtxResult<Value>> {
+    iter.flat_map(|item| match item {
+        Ok(Value::Array(arr)) => arr.into_iter().map(Ok).collect::<Vec<_>>(),
+        other => vec![other],
+    })
+}
+
+/// Writes CSV like [`csv_writer`] on `threads` worker threads, rows are flattened and serialized
+/// in batches in parallel and written in order. Headers of flattened records are still discovered
+/// from the first `sample_size` records, `two_pass` runs on one thread.
+pub fn csv_writer_parallel(
+    data_stream: WriterStreams<impl Iterator<Item = CtxResult<Value>>>,
+    file: impl Write,
+    delimiter: char,
+    flatten_opts: &FlattenOpts,
+    threads: usize,
+) -> CtxResult<()> {
+    if flatten_opts.two_pass {
+        return csv_writer(data_stream, file, delimiter, flatten_opts);
+    }
+
+    if !delimiter.is_ascii() {
+        Err(Error::new(EK::InvalidInput, format!("Output delimiter: {} is not ASCII", delimiter)))
+            .context("Failed to create CSV writer")?;
+    }
+
+    let d = delimiter as u8;
+    let mut buffered = BufWriter::new(file);
+
+    match data_stream {
+        WriterStreams::Table { headers, iter } => {
+            let mut wtr = csv::WriterBuilder::new().delimiter(d).from_writer(&mut buffered);
+
+            wtr.write_record(&headers).context("Failed to write headers into output file")?;
+            wtr.flush().context("Failed to flush writer")?;
+            drop(wtr);
+
+            let rows = iter.enumerate().filter_map(|(line_no, line)| {
+                let row = into_row(line).context("Failed to re-serialize object").log("[WARN]")?;
+
+                Some((line_no, row))
+            });
+
+            write_ordered(&mut buffered, batches(rows), threads, move |batch| {
+                // headers are written first so rows are checked against their length like in
+                // `csv_writer`, then cut from the output
+                write_batch(d, &headers, |wtr| {
+                    for (line_no, row) in batch {
+                        wtr.write_record(row.iter().map(|cell| cell.to_cell_bytes()))
+                            .context(ctx!("Failed to write CSV record at: {}", line_no + 1))?;
+                    }
+
+                    Ok(())
+                })
+            })?;
+        }
+
+        WriterStreams::Values { iter } => {
+            write_flattened_parallel(&mut buffered, array_rows(iter), d, flatten_opts, threads)?;
+        }
+
+        WriterStreams::Ndjson { values } => {
+            write_flattened_parallel(&mut buffered, values, d, flatten_opts, threads)?;
+        }
+    }
+
+    buffered.flush().context("Failed to flush writer")
+}
+
+fn write_flattened_parallel(
+    out: &mut impl Write,
+    rows: impl Iterator<Item = CtxResult<Value>>,
+    delimiter: u8,
+    opts: &FlattenOpts,
+    threads: usize,
+) -> CtxResult<()> {
+    let mut rows = valid_rows(rows);
+
+    let sample: Vec<Vec<(String, String)>> = rows
+        .by_ref()
+        .take(opts.sample_size.max(1))
+        .map(|row| {
+            let mut cells = Vec::new();
+            flatten(&row, opts, &mut cells);
+
+            cells
+        })
+        .collect();
+
+    let mut headers = Headers::default();
+
+    for cells in &sample {
+        headers.extend(cells);
+    }
+
+    let mut wtr = csv::WriterBuilder::new().delimiter(delimiter).from_writer(&mut *out);
+
+    if !headers.names.is_empty() {
+        wtr.write_record(&headers.names).context("Failed to write headers into output file")?;
+    }
+
+    let mut record: Vec<String> = Vec::new();
+    let dropped = Arc::new(Mutex::new(HashSet::new()));
+    let sampled = sample.len();
+
+    for (line_no, cells) in sample.into_iter().enumerate() {
+        write_row(&mut wtr, &headers, cells, &mut record, &dropped)
+            .context(ctx!("Failed to write CSV record at: {}", line_no + 1))?;
+    }
+
+    wtr.flush().context("Failed to flush writer")?;
+    drop(wtr);
+
+    let rows = rows.enumerate().map(|(idx, row)| (sampled + idx, row));
+    let opts = opts.clone();
+    let names = headers.names.clone();
+
+    write_ordered(out, batches(rows), threads, move |batch| {
+        write_batch(delimiter, &names, |wtr| {
+            let mut record = Vec::new();
+
+            for (line_no, row) in batch {
+                let mut cells = Vec::new();
+                flatten(&row, &opts, &mut cells);
+
+                write_row(wtr, &headers, cells, &mut record, &dropped)
+                    .context(ctx!("Failed to write CSV record at: {}", line_no + 1))?;
+            }
+
+            Ok(())
+        })
+    })
+}
+
+/// Writes a batch of rows into a buffer with a writer that has already written `headers`, which
+/// are cut from the output, so the writer checks the length of rows like the writer of the whole
+/// file does.
+fn write_batch(
+    delimiter: u8,
+    headers: &[String],
+    write: impl FnOnce(&mut csv::Writer<Vec<u8>>) -> CtxResult<()>,
+) -> (Vec<u8>, CtxResult<()>) {
+    let mut wtr = csv::WriterBuilder::new().delimiter(delimiter).from_writer(Vec::new());
+    let mut skip = 0;
+
+    // writing into a buffer can't fail
+    if !headers.is_empty() {
+        let _ = wtr.write_record(headers);
+        let _ = wtr.flush();
+
+        skip = wtr.get_ref().len();
+    }
+
+    let res = write(&mut wtr);
+    let mut out = wtr.into_inner().unwrap_or_default();
+
+    out.drain(..skip.min(out.len()));
+
+    (out, res)
+}
+
+#[inline]
+fn write_row<W: Write>(
+    wtr: &mut csv::Writer<W>,
+    headers: &Headers,
+    cells: Vec<(String, String)>,
+    record: &mut Vec<String>,
+    dropped: &Mutex<HashSet<String>>,
+) -> CtxResult<()> {
+    record.clear();
+    record.resize(headers.names.len(), String::new());
+
+    for (k, v) in cells {
+        match headers.idx.get(&k) {
+            Some(i) => record[*i] = v,
+            None => {
+                let mut dropped = dropped.lock().unwrap_or_else(PoisonError::into_inner);
+
+                if !dropped.contains(&k) {
+                    let _ = Err::<(), _>(CtxResultErr::new(
+                        "Increase `--sample-size` or use `--two-pass` to include it",
+                        format!(
+                            "Column: {} was not found in the sampled records and was dropped",
+                            k
+                        ),
+                    ))
+                    .log("[WARN]");
+
+                    dropped.insert(k);
+                }
+            }
+        }
+    }
+
+    wtr.write_record(record.iter()).context("Failed to write record")
+}
@@ -1,4 +1,6 @@
 pub mod csv_writer;
 pub mod ndjson_writer;
+pub mod output_writer;
 pub mod toml_writer;
 pub mod write_json;
+pub mod yaml_writer;
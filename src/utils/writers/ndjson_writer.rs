@@ -1,19 +1,17 @@
 use resext::ctx;
-use std::{
-    fs::File,
-    io::{BufWriter, Write},
-};
-
-use serde_json::Value;
+use std::io::{BufWriter, Write};
 
 use crate::utils::{
-    CtxResult, CtxResultExt, DataTypes, Log, WriterStreams, escape, into_byte_record,
+    CtxResult, CtxResultExt, FlattenOpts, Log, Map, Unflattener, Value, WriterStreams, escape,
+    into_row,
+    parallel::{StreamKind, batches, write_ordered},
 };
 
 pub fn ndjson_writer(
-    data_stream: WriterStreams<impl Iterator<Item = CtxResult<DataTypes>>>,
-    file: File,
-    parse_numbers: bool,
+    data_stream: WriterStreams<impl Iterator<Item = CtxResult<Value>>>,
+    file: impl Write,
+    typed: bool,
+    flatten_opts: &FlattenOpts,
 ) -> CtxResult<()> {
     let mut wtr = BufWriter::new(file);
 
@@ -22,14 +20,10 @@ pub fn ndjson_writer(
             for (line_no, item) in iter.enumerate() {
                 let line_no = line_no + 1;
 
-                let json = serde_json::to_value(
-                    item.context("Failed to re-serialize record")
-                        .log("[WARN]")
-                        .unwrap_or_else(|| DataTypes::Json(serde_json::json!({}))),
-                )
-                .context("Failed to re-serialize record")
-                .log("[WARN]")
-                .unwrap_or_default();
+                let json = item
+                    .context("Failed to re-serialize record")
+                    .log("[WARN]")
+                    .unwrap_or_else(|| Value::Map(Map::new()));
 
                 if let Value::Array(arr) = json {
                     for (idx, obj) in arr.iter().enumerate() {
@@ -40,7 +34,7 @@ pub fn ndjson_writer(
 
                         writeln!(wtr).context("Failed to write newline")?;
                     }
-                } else if let Value::Object(_) = json {
+                } else if let Value::Map(_) = json {
                     serde_json::to_writer(&mut wtr, &json)
                         .context(ctx!("Failed to write object: {}", line_no))?;
 
@@ -49,6 +43,14 @@ pub fn ndjson_writer(
             }
         }
 
+        WriterStreams::Table { headers, iter } if flatten_opts.unflatten => {
+            let u = Unflattener::new(&headers, &flatten_opts.separator, typed);
+
+            let values = iter.map(|rec| into_row(rec).map(|r| u.unflatten(&r)));
+
+            write_lines(&mut wtr, values)?;
+        }
+
         WriterStreams::Table { headers, iter } => {
             let mut esc_buf: Vec<u8> = Vec::with_capacity(10);
 
@@ -70,21 +72,22 @@ pub fn ndjson_writer(
 
                 let mut first_value = true;
 
-                let record = into_byte_record(rec)
+                let record = into_row(rec)
                     .context("Failed to re-serialize object")
                     .log("[WARN]")
                     .unwrap_or_default();
 
-                for (h, v) in headers.iter().zip(record.iter()) {
+                for (h, cell) in headers.iter().zip(record.iter()) {
                     esc_buf.clear();
 
-                    if matches!(v, b"true" | b"false" | b"null")
-                        || (parse_numbers
-                            && v.first()
-                                .is_some_and(|b| *b == b'-' || *b == b'+' || b.is_ascii_digit())
-                            && v.last().is_some_and(|b| b.is_ascii_digit())
-                            && std::str::from_utf8(v).unwrap_or("").parse::<f64>().is_ok())
-                    {
+                    let v = cell.to_cell();
+                    let v = v.as_bytes();
+
+                    if !matches!(cell, Value::String(_) | Value::Bytes(_)) {
+                        // typed cells are written as they are
+                        serde_json::to_writer(&mut esc_buf, cell)
+                            .context(ctx!("Failed to write value in record: {}", line_no))?;
+                    } else if !typed && matches!(v, b"true" | b"false" | b"null") {
                         esc_buf.extend_from_slice(v);
                     } else {
                         esc_buf.push(b'"');
@@ -127,17 +130,74 @@ pub fn ndjson_writer(
             wtr.flush().context("Failed to flush writer")?;
         }
 
-        WriterStreams::Ndjson { values } => {
-            for (line_no, item) in values.enumerate() {
-                let json = item
-                    .context("Failed to re-serialize record")
-                    .log("[WARN]")
-                    .unwrap_or_else(|| DataTypes::Json(serde_json::json!({})));
+        WriterStreams::Ndjson { values } => write_lines(&mut wtr, values)?,
+    }
 
-                serde_json::to_writer(&mut wtr, &json)
-                    .context(ctx!("Failed to write object: {}", line_no + 1))?;
-            }
+    wtr.flush().context("Failed to flush writer")
+}
+
+/// Writes NDJSON like [`ndjson_writer`] on `threads` worker threads, records are serialized in
+/// batches in parallel and written in order.
+pub fn ndjson_writer_parallel(
+    data_stream: WriterStreams<impl Iterator<Item = CtxResult<Value>>>,
+    file: impl Write,
+    typed: bool,
+    flatten_opts: &FlattenOpts,
+    threads: usize,
+) -> CtxResult<()> {
+    let mut wtr = BufWriter::new(file);
+    let kind = StreamKind::of(&data_stream);
+
+    // invalid records are logged here to keep logs in order and written like `ndjson_writer`
+    // writes them, as empty objects
+    let records: Box<dyn Iterator<Item = Value>> = match data_stream {
+        WriterStreams::Values { iter } | WriterStreams::Ndjson { values: iter } => {
+            Box::new(iter.map(|item| {
+                item.context("Failed to re-serialize record")
+                    .log("[WARN]")
+                    .unwrap_or_else(|| Value::Map(Map::new()))
+            }))
+        }
+        WriterStreams::Table { iter, .. } => {
+            let msg = if flatten_opts.unflatten {
+                "Failed to re-serialize record"
+            } else {
+                "Failed to re-serialize object"
+            };
+
+            Box::new(iter.map(move |rec| {
+                Value::Array(into_row(rec).context(msg).log("[WARN]").unwrap_or_default())
+            }))
         }
+    };
+
+    let opts = flatten_opts.clone();
+
+    write_ordered(&mut wtr, batches(records), threads, move |batch| {
+        let mut out = Vec::new();
+        let res = ndjson_writer(kind.stream(batch), &mut out, typed, &opts);
+
+        (out, res)
+    })?;
+
+    wtr.flush().context("Failed to flush writer")
+}
+
+#[inline]
+fn write_lines(
+    wtr: &mut impl Write,
+    values: impl Iterator<Item = CtxResult<Value>>,
+) -> CtxResult<()> {
+    for (line_no, item) in values.enumerate() {
+        let json = item
+            .context("Failed to re-serialize record")
+            .log("[WARN]")
+            .unwrap_or_else(|| Value::Map(Map::new()));
+
+        serde_json::to_writer(&mut *wtr, &json)
+            .context(ctx!("Failed to write object: {}", line_no + 1))?;
+
+        writeln!(wtr).context("Failed to write newline")?;
     }
 
     Ok(())
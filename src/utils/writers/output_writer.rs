@@ -0,0 +1,67 @@
+use resext::ctx;
+use std::{
+    fs::{File, OpenOptions},
+    io::{StdoutLock, Write, stdout},
+    path::Path,
+};
+
+use crate::utils::{Compression, CtxResult, CtxResultExt, Encoder, is_std_stream};
+
+/// Opens the output for writing, `-` writes to stdout instead of a file. Paths ending in a
+/// compression extension (e.g. `.gz`, `.zst`) are compressed, see [`Output::finish`].
+#[inline]
+pub fn output_writer(path: &Path, append: bool) -> CtxResult<Output> {
+    if is_std_stream(path) {
+        return Ok(Output::Stdout(stdout().lock()));
+    }
+
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+        .context("Failed to open output file")
+        .context(ctx!("Output file: {}", path.to_string_lossy()))?;
+
+    match Compression::from_path(path) {
+        Some(compression) => Ok(Output::Compressed(compression.encoder(file)?)),
+        None => Ok(Output::File(file)),
+    }
+}
+
+/// Output opened by [`output_writer`].
+pub enum Output {
+    Stdout(StdoutLock<'static>),
+    File(File),
+    Compressed(Encoder<File>),
+}
+
+impl Output {
+    /// Flushes the output and writes the end of compressed output, compressed output that is
+    /// dropped without this is still finished but errors are only logged.
+    pub fn finish(mut self) -> CtxResult<()> {
+        match &mut self {
+            Self::Compressed(enc) => enc.finish().context("Failed to finish compressed output"),
+            output => output.flush().context("Failed to flush output"),
+        }
+    }
+
+    fn inner(&mut self) -> &mut dyn Write {
+        match self {
+            Self::Stdout(out) => out,
+            Self::File(file) => file,
+            Self::Compressed(enc) => enc,
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.inner().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner().flush()
+    }
+}
@@ -4,32 +4,31 @@ use std::io::{BufWriter, Write};
 use toml::{Value, map::Map};
 
 use crate::utils::{
-    CtxResult, CtxResultExt, DataTypes, Log, WriterStreams, escape, into_byte_record,
+    self, CtxResult, CtxResultExt, FlattenOpts, Log, Unflattener, WriterStreams, escape, into_row,
 };
 
 pub fn toml_writer(
-    data_stream: WriterStreams<impl Iterator<Item = CtxResult<DataTypes>>>,
-    file: std::fs::File,
-    parse_numbers: bool,
+    data_stream: WriterStreams<impl Iterator<Item = CtxResult<utils::Value>>>,
+    file: impl Write,
+    typed: bool,
+    flatten_opts: &FlattenOpts,
 ) -> CtxResult<()> {
     let mut wtr = BufWriter::new(file);
 
     match data_stream {
         WriterStreams::Values { iter } => {
             for item in iter {
-                let obj = Value::try_from(
-                    item.context("Failed to re-serialize object")
-                        .log("[WARN]")
-                        .unwrap_or_else(|| DataTypes::Json(serde_json::json!({}))),
-                )
-                .context("Failed to re-serialize object")
-                .log("[WARN]")
-                .unwrap_or_else(|| Value::Table(Map::new()));
-
-                if let Value::Array(_) = obj {
+                let obj = item
+                    .context("Failed to re-serialize object")
+                    .log("[WARN]")
+                    .and_then(to_toml)
+                    .unwrap_or_else(|| Value::Table(Map::new()));
+
+                if !obj.is_table() {
                     let mut map = Map::with_capacity(1);
+                    let key = if obj.is_array() { "Array" } else { "value" };
 
-                    map.insert("Array".to_string(), obj);
+                    map.insert(key.to_string(), obj);
 
                     wtr.write_all(
                         toml::to_string_pretty(&Value::Table(map))
@@ -49,6 +48,15 @@ pub fn toml_writer(
             wtr.flush().context("Failed to flush writer")?;
         }
 
+        WriterStreams::Table { headers, iter } if flatten_opts.unflatten => {
+            let u =
+                Unflattener::new(&headers, &flatten_opts.separator, typed).without_null_literal();
+
+            let values = iter.map(|rec| into_row(rec).map(|r| u.unflatten(&r)));
+
+            write_tables(&mut wtr, values, "Rows")?;
+        }
+
         WriterStreams::Table { headers, iter } => {
             let mut esc_buf: Vec<u8> = Vec::with_capacity(10);
 
@@ -86,21 +94,25 @@ pub fn toml_writer(
                     first_row = false;
                 }
 
-                let record = into_byte_record(rec)
+                let record = into_row(rec)
                     .context("Failed to re-serialize object")
                     .log("[WARN]")
                     .unwrap_or_default();
 
-                for (h, v) in headers.iter().zip(record.iter()) {
+                for (h, cell) in headers.iter().zip(record.iter()) {
                     esc_buf.clear();
 
-                    if matches!(v, b"true" | b"false")
-                        || (parse_numbers
-                            && v.first()
-                                .is_some_and(|b| *b == b'-' || *b == b'+' || b.is_ascii_digit())
-                            && v.last().is_some_and(|b| b.is_ascii_digit())
-                            && std::str::from_utf8(v).unwrap_or("").parse::<f64>().is_ok())
-                    {
+                    let v = cell.to_cell();
+                    let v = v.as_bytes();
+
+                    if !matches!(cell, utils::Value::String(_) | utils::Value::Bytes(_)) {
+                        // typed cells are written as they are, nulls are skipped
+                        let Some(val) = to_toml(cell.clone()) else {
+                            continue;
+                        };
+
+                        esc_buf.extend_from_slice(val.to_string().as_bytes());
+                    } else if !typed && matches!(v, b"true" | b"false") {
                         esc_buf.extend_from_slice(v);
                     } else {
                         esc_buf.push(b'"');
@@ -126,53 +138,77 @@ pub fn toml_writer(
             wtr.flush().context("Failed to flush writer")?;
         }
 
-        WriterStreams::Ndjson { values } => {
-            let mut first = true;
-            for (rec_no, rec) in values.enumerate() {
-                let rec_no = rec_no + 1;
-
-                let obj = Value::try_from(
-                    rec.context("Failed to re-serialize object")
-                        .log("[WARN]")
-                        .unwrap_or_else(|| DataTypes::Json(serde_json::json!({}))),
-                )
-                .context("Failed to re-serialize object")
-                .log("[WARN]")
-                .unwrap_or_else(|| Value::Table(Map::new()));
-
-                if first {
-                    wtr.write_all(b"[[Array]]\n")
-                        .context(ctx!("Failed to write array key: {}", rec_no))?;
-
-                    first = false;
-                } else {
-                    wtr.write_all(b"\n[[Array]]\n")
-                        .context(ctx!("Failed to write array key: {}", rec_no))?;
-                }
-
-                if let Value::Array(_) = obj {
-                    let mut map = Map::with_capacity(1);
-                    map.insert("Array".to_string(), obj);
+        WriterStreams::Ndjson { values } => write_tables(&mut wtr, values, "Array")?,
+    }
 
-                    wtr.write_all(
-                        toml::to_string_pretty(&Value::Table(map))
-                            .context("Failed to serialize TOML table")?
-                            .as_bytes(),
-                    )
-                    .context("Failed to write TOML table")?;
-                } else {
-                    wtr.write_all(
-                        toml::to_string_pretty(&obj)
-                            .context("Failed to serialize valid TOML table")?
-                            .as_bytes(),
-                    )
-                    .context("Failed to write TOML table")?;
-                }
+    wtr.flush().context("Failed to flush writer")
+}
 
-                wtr.flush().context("Failed to flush writer")?;
+/// Writes every record as a table in an array of tables named `key`, records are serialized
+/// separately so nested tables get the right `[key.table]` headers.
+#[inline]
+fn write_tables(
+    wtr: &mut impl Write,
+    values: impl Iterator<Item = CtxResult<utils::Value>>,
+    key: &str,
+) -> CtxResult<()> {
+    for (rec_no, rec) in values.enumerate() {
+        let rec_no = rec_no + 1;
+
+        let obj = rec
+            .context("Failed to re-serialize object")
+            .log("[WARN]")
+            .and_then(to_toml)
+            .unwrap_or_else(|| Value::Table(Map::new()));
+
+        let obj = match obj {
+            Value::Table(_) => obj,
+            _ => {
+                let mut map = Map::with_capacity(1);
+                map.insert("Array".to_string(), obj);
+
+                Value::Table(map)
             }
+        };
+
+        let mut doc = Map::with_capacity(1);
+        doc.insert(key.to_string(), Value::Array(vec![obj]));
+
+        if rec_no > 1 {
+            wtr.write_all(b"\n")
+                .context(ctx!("Failed to write newline before record: {}", rec_no))?;
         }
+
+        wtr.write_all(
+            toml::to_string_pretty(&Value::Table(doc))
+                .context(ctx!("Failed to serialize TOML table: {}", rec_no))?
+                .as_bytes(),
+        )
+        .context("Failed to write TOML table")?;
     }
 
     Ok(())
 }
+
+/// Converts a value into a TOML value, nulls are skipped since TOML doesn't have them and decimals
+/// are written as strings since TOML integers are 64-bit.
+fn to_toml(value: utils::Value) -> Option<Value> {
+    use utils::Value as V;
+
+    Some(match value {
+        V::Null => return None,
+        V::Bool(b) => Value::Boolean(b),
+        V::Int(i) => Value::Integer(i),
+        V::Float(f) => Value::Float(f),
+        V::Decimal(s) | V::String(s) => Value::String(s),
+        V::Bytes(b) => Value::String(String::from_utf8_lossy(&b).into_owned()),
+        V::DateTime(s) => match s.parse() {
+            Ok(dt) => Value::Datetime(dt),
+            Err(_) => Value::String(s),
+        },
+        V::Array(arr) => Value::Array(arr.into_iter().filter_map(to_toml).collect()),
+        V::Map(map) => {
+            Value::Table(map.into_iter().filter_map(|(k, v)| Some((k, to_toml(v)?))).collect())
+        }
+    })
+}
@@ -1,14 +1,17 @@
 use resext::ctx;
 use std::io::{BufWriter, Write};
 
-use crate::utils::{CtxResult, CtxResultExt, DataTypes, Log, WriterStreams, into_byte_record};
+use crate::utils::{
+    CtxResult, CtxResultExt, FlattenOpts, Log, Map, Unflattener, Value, WriterStreams, into_row,
+};
 
 pub fn write_json(
-    data_stream: WriterStreams<impl Iterator<Item = CtxResult<DataTypes>>>,
-    file: std::fs::File,
-    parse_numbers: bool,
+    data_stream: WriterStreams<impl Iterator<Item = CtxResult<Value>>>,
+    file: impl Write,
+    typed: bool,
+    flatten_opts: &FlattenOpts,
 ) -> CtxResult<()> {
-    let mut wtr = BufWriter::new(&file);
+    let mut wtr = BufWriter::new(file);
 
     match data_stream {
         WriterStreams::Values { iter } => {
@@ -16,7 +19,7 @@ pub fn write_json(
                 let valid_obj = obj
                     .context("Failed to re-serialize object for writing")
                     .log("[WARN]")
-                    .unwrap_or_else(|| DataTypes::Json(serde_json::json!({})));
+                    .unwrap_or_else(|| Value::Map(Map::new()));
 
                 serde_json::to_writer_pretty(&mut wtr, &valid_obj)
                     .context("Failed to write object into output JSON file")?;
@@ -25,10 +28,18 @@ pub fn write_json(
             }
         }
 
+        WriterStreams::Table { headers, iter } if flatten_opts.unflatten => {
+            let u = Unflattener::new(&headers, &flatten_opts.separator, typed);
+
+            let values = iter.map(|rec| into_row(rec).map(|r| u.unflatten(&r)));
+
+            write_array(&mut wtr, values)?;
+        }
+
         WriterStreams::Table { headers, iter } => {
             let mut esc_buf: Vec<u8> = Vec::with_capacity(10);
 
-            wtr.write_all(b"[\n").context("Failed to write opening bracket")?;
+            wtr.write_all(b"[").context("Failed to write opening bracket")?;
 
             let headers: Vec<String> = headers
                 .iter()
@@ -46,7 +57,7 @@ pub fn write_json(
             for (line_no, rec) in iter.enumerate() {
                 let line = line_no + 1;
                 if first_obj {
-                    wtr.write_all(b"  {\n").context(ctx!(
+                    wtr.write_all(b"\n  {\n").context(ctx!(
                         "Failed to write opening curly brace for record: {}",
                         line
                     ))?;
@@ -59,21 +70,22 @@ pub fn write_json(
 
                 let mut first_value = true;
 
-                let record = into_byte_record(rec)
+                let record = into_row(rec)
                     .context("Failed to re-serialize object")
                     .log("[WARN]")
                     .unwrap_or_default();
 
-                for (h, v) in headers.iter().zip(record.iter()) {
+                for (h, cell) in headers.iter().zip(record.iter()) {
                     esc_buf.clear();
 
-                    if matches!(v, b"true" | b"false" | b"null")
-                        || (parse_numbers
-                            && v.first()
-                                .is_some_and(|b| *b == b'-' || *b == b'+' || b.is_ascii_digit())
-                            && v.last().is_some_and(|b| b.is_ascii_digit())
-                            && std::str::from_utf8(v).unwrap_or("").parse::<f64>().is_ok())
-                    {
+                    let v = cell.to_cell();
+                    let v = v.as_bytes();
+
+                    if !matches!(cell, Value::String(_) | Value::Bytes(_)) {
+                        // typed cells are written as they are
+                        serde_json::to_writer(&mut esc_buf, cell)
+                            .context(ctx!("Failed to write value in record: {}", line))?;
+                    } else if !typed && matches!(v, b"true" | b"false" | b"null") {
                         esc_buf.extend_from_slice(v);
                     } else {
                         esc_buf.push(b'"');
@@ -114,39 +126,57 @@ pub fn write_json(
                     .context(ctx!("Failed to write closing curly brace for record: {}", line))?;
             }
 
-            wtr.write_all(b"\n]").context("Failed to write closing bracket")?;
+            let end: &[u8] = if first_obj { b"]\n" } else { b"\n]\n" };
+
+            wtr.write_all(end).context("Failed to write closing bracket")?;
         }
 
-        WriterStreams::Ndjson { values } => {
-            wtr.write_all(b"[\n").context("Failed to write opening bracket")?;
+        WriterStreams::Ndjson { values } => write_array(&mut wtr, values)?,
+    }
 
-            let mut first = true;
+    wtr.flush().context("Failed to flush final bytes")
+}
 
-            for (idx, obj) in values.enumerate() {
-                let idx = idx + 1;
+/// Writes records as the elements of a pretty-printed array like `serde_json::to_writer_pretty`
+/// does for a whole array, without collecting them first.
+#[inline]
+fn write_array(
+    wtr: &mut impl Write,
+    values: impl Iterator<Item = CtxResult<Value>>,
+) -> CtxResult<()> {
+    wtr.write_all(b"[").context("Failed to write opening bracket")?;
 
-                let obj = obj
-                    .context("Failed to re-serialize object")
-                    .log("[WARN]")
-                    .unwrap_or_else(|| DataTypes::Json(serde_json::json!({})));
+    let mut buf: Vec<u8> = Vec::with_capacity(256);
+    let mut first = true;
 
-                if first {
-                    serde_json::to_writer_pretty(&mut wtr, &obj)
-                        .context(ctx!("Failed to write record: {}", idx))?;
+    for (idx, obj) in values.enumerate() {
+        let idx = idx + 1;
 
-                    first = false;
-                } else {
-                    wtr.write_all(b",\n")
-                        .context(ctx!("Failed to write comma after record: {}", idx))?;
+        let obj = obj
+            .context("Failed to re-serialize object")
+            .log("[WARN]")
+            .unwrap_or_else(|| Value::Map(Map::new()));
 
-                    serde_json::to_writer_pretty(&mut wtr, &obj)
-                        .context(ctx!("Failed to write record: {}", idx))?;
-                }
+        buf.clear();
+        serde_json::to_writer_pretty(&mut buf, &obj)
+            .context(ctx!("Failed to write record: {}", idx))?;
+
+        let sep: &[u8] = if first { b"\n  " } else { b",\n  " };
+        first = false;
+
+        wtr.write_all(sep).context(ctx!("Failed to write comma after record: {}", idx))?;
+
+        // newlines in strings are escaped, so every newline starts a line of the record
+        for (line_no, line) in buf.split(|b| *b == b'\n').enumerate() {
+            if line_no > 0 {
+                wtr.write_all(b"\n  ").context(ctx!("Failed to write record: {}", idx))?;
             }
 
-            wtr.write_all(b"\n]").context("Failed to write closing bracket")?;
+            wtr.write_all(line).context(ctx!("Failed to write record: {}", idx))?;
         }
     }
 
-    wtr.flush().context("Failed to flush final bytes")
+    let end: &[u8] = if first { b"]\n" } else { b"\n]\n" };
+
+    wtr.write_all(end).context("Failed to write closing bracket")
 }
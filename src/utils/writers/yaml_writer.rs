@@ -0,0 +1,107 @@
+use resext::ctx;
+use std::io::{BufWriter, Write};
+
+use crate::utils::{
+    CtxResult, CtxResultExt, FlattenOpts, Log, Map, Unflattener, Value, WriterStreams, into_row,
+};
+
+pub fn yaml_writer(
+    data_stream: WriterStreams<impl Iterator<Item = CtxResult<Value>>>,
+    file: impl Write,
+    typed: bool,
+    flatten_opts: &FlattenOpts,
+) -> CtxResult<()> {
+    let mut wtr = BufWriter::new(file);
+
+    match data_stream {
+        WriterStreams::Values { iter } | WriterStreams::Ndjson { values: iter } => {
+            for (idx, item) in iter.enumerate() {
+                let idx = idx + 1;
+
+                let obj = item
+                    .context("Failed to re-serialize object")
+                    .log("[WARN]")
+                    .unwrap_or_else(|| Value::Map(Map::new()));
+
+                if idx > 1 {
+                    wtr.write_all(b"---\n")
+                        .context(ctx!("Failed to write document separator: {}", idx))?;
+                }
+
+                serde_yaml::to_writer(&mut wtr, &obj)
+                    .context(ctx!("Failed to write YAML document: {}", idx))?;
+            }
+        }
+
+        WriterStreams::Table { headers, iter } if flatten_opts.unflatten => {
+            let u = Unflattener::new(&headers, &flatten_opts.separator, typed);
+
+            let mut is_empty = true;
+
+            for (line_no, rec) in iter.enumerate() {
+                let line_no = line_no + 1;
+
+                let record = into_row(rec)
+                    .context("Failed to re-serialize object")
+                    .log("[WARN]")
+                    .unwrap_or_default();
+
+                serde_yaml::to_writer(&mut wtr, &[u.unflatten(&record)])
+                    .context(ctx!("Failed to write YAML record: {}", line_no))?;
+
+                is_empty = false;
+            }
+
+            if is_empty {
+                wtr.write_all(b"[]\n").context("Failed to write empty sequence")?;
+            }
+        }
+
+        WriterStreams::Table { headers, iter } => {
+            let mut is_empty = true;
+
+            for (line_no, rec) in iter.enumerate() {
+                let line_no = line_no + 1;
+
+                let record = into_row(rec)
+                    .context("Failed to re-serialize object")
+                    .log("[WARN]")
+                    .unwrap_or_default();
+
+                let mut row = Map::with_capacity(headers.len());
+
+                for (h, cell) in headers.iter().zip(record) {
+                    let v = match cell {
+                        Value::String(s) if !typed => yaml_scalar(s),
+                        other => other,
+                    };
+
+                    row.insert(h.clone(), v);
+                }
+
+                // every row is written as a one-item sequence so rows can be streamed
+                serde_yaml::to_writer(&mut wtr, &[Value::Map(row)])
+                    .context(ctx!("Failed to write YAML record: {}", line_no))?;
+
+                is_empty = false;
+            }
+
+            if is_empty {
+                wtr.write_all(b"[]\n").context("Failed to write empty sequence")?;
+            }
+        }
+    }
+
+    wtr.flush().context("Failed to flush writer")
+}
+
+/// Parses `true`, `false` and `null` in cells that weren't typed, like the JSON writers do.
+#[inline(always)]
+fn yaml_scalar(v: String) -> Value {
+    match v.as_str() {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        "null" => Value::Null,
+        _ => Value::String(v),
+    }
+}
@@ -25,6 +25,7 @@ fn test_csv_to_json_conversion() -> Result<(), Error> {
     assert!(result.contains(r#""name": "Alice""#));
     assert!(result.contains(r#""age": "30""#));
     assert!(result.contains(r#""city": "NYC""#));
+    assert!(result.ends_with("  }\n]\n"));
 
     Ok(())
 }
@@ -160,7 +161,27 @@ fn test_ndjson_to_json() -> Result<(), Error> {
 
     let result = fs::read_to_string(output.path())?;
 
-    assert!(result.contains(r#""name": "Alice""#));
+    assert_eq!(
+        result,
+        "[\n  {\n    \"name\": \"Alice\",\n    \"age\": 30\n  },\n  {\n    \"name\": \"Bob\",\n    \"age\": 25\n  }\n]\n"
+    );
+
+    // streamed JSON arrays are written like whole arrays, nested values included
+    fs::write(input.path(), r#"[{"a":1},{"a":{"b":[1,"x\ny"]}}]"#)?;
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("convert")
+        .arg(input.path())
+        .arg("--from")
+        .arg("json")
+        .arg("-o")
+        .arg(output.path())
+        .assert()
+        .success();
+
+    let value: serde_json::Value = serde_json::from_str(r#"[{"a":1},{"a":{"b":[1,"x\ny"]}}]"#)?;
+
+    assert_eq!(fs::read_to_string(output.path())?, serde_json::to_string_pretty(&value)? + "\n");
 
     Ok(())
 }
@@ -264,3 +285,1472 @@ fn test_ndjson_to_toml() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[test]
+fn test_stdin_to_stdout() -> Result<(), Error> {
+    let assert = assert_cmd::Command::new(cargo::cargo_bin!("fiux"))
+        .arg("convert")
+        .arg("-")
+        .arg("--from")
+        .arg("csv")
+        .arg("--to")
+        .arg("ndjson")
+        .arg("-o")
+        .arg("-")
+        .write_stdin("name,age\nAlice,30\nBob,25\n")
+        .assert()
+        .success();
+
+    let result = String::from_utf8_lossy(&assert.get_output().stdout).to_string();
+    assert_eq!(result.lines().count(), 2);
+    assert!(result.contains(r#""name": "Alice""#));
+
+    Ok(())
+}
+
+#[test]
+fn test_stdin_without_format_fails() -> Result<(), Error> {
+    let output = Builder::new().suffix(".json").tempfile()?;
+
+    assert_cmd::Command::new(cargo::cargo_bin!("fiux"))
+        .arg("convert")
+        .arg("-")
+        .arg("-o")
+        .arg(output.path())
+        // stdin without `--from` is detected from its content, plain text matches no format
+        .write_stdin("just some text\n")
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+#[test]
+fn test_from_flag_overrides_extension() -> Result<(), Error> {
+    let input = Builder::new().suffix(".txt").tempfile()?;
+    let output = Builder::new().suffix(".json").tempfile()?;
+
+    fs::write(input.path(), "{\"a\": 1}\n{\"a\": 2}\n")?;
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("convert")
+        .arg(input.path())
+        .arg("--from")
+        .arg("ndjson")
+        .arg("-o")
+        .arg(output.path())
+        .assert()
+        .success();
+
+    let result = fs::read_to_string(output.path())?;
+    assert!(result.contains(r#""a": 2"#));
+
+    Ok(())
+}
+
+#[test]
+fn test_csv_to_yaml() -> Result<(), Error> {
+    let input = Builder::new().suffix(".csv").tempfile()?;
+    let output = Builder::new().suffix(".yaml").tempfile()?;
+
+    fs::write(input.path(), "name,age\nAlice,30\nBob,25\n")?;
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("convert")
+        .arg(input.path())
+        .arg("-o")
+        .arg(output.path())
+        .arg("--parse-numbers")
+        .assert()
+        .success();
+
+    let result = fs::read_to_string(output.path())?;
+    assert!(result.contains("- name: Alice\n  age: 30\n"));
+
+    Ok(())
+}
+
+#[test]
+fn test_csv_literal_cells_are_written_as_literals() -> Result<(), Error> {
+    let input = Builder::new().suffix(".csv").tempfile()?;
+
+    fs::write(input.path(), "a,b,c\ntrue,false,null\n")?;
+
+    let convert = |to: &str, parse_numbers: bool| {
+        let mut cmd = Command::new(cargo::cargo_bin!("fiux"));
+        cmd.arg("convert").arg(input.path()).args(["-o", "-", "--to", to]);
+
+        if parse_numbers {
+            cmd.arg("--parse-numbers");
+        }
+
+        String::from_utf8_lossy(&cmd.assert().success().get_output().stdout).into_owned()
+    };
+
+    for parse_numbers in [false, true] {
+        assert_eq!(convert("yaml", parse_numbers), "- a: true\n  b: false\n  c: null\n");
+        assert_eq!(convert("ndjson", parse_numbers), "{\"a\": true, \"b\": false, \"c\": null}\n");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_multi_document_yaml_to_ndjson() -> Result<(), Error> {
+    let input = Builder::new().suffix(".yml").tempfile()?;
+    let output = Builder::new().suffix(".ndjson").tempfile()?;
+
+    fs::write(input.path(), "a: 1\nb: [1, 2]\n---\na: 2\n")?;
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("convert")
+        .arg(input.path())
+        .arg("-o")
+        .arg(output.path())
+        .assert()
+        .success();
+
+    let result = fs::read_to_string(output.path())?;
+    assert_eq!(result, "{\"a\":1,\"b\":[1,2]}\n{\"a\":2}\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_yaml_validation_fail() -> Result<(), Error> {
+    let input = Builder::new().suffix(".yaml").tempfile()?;
+    let log = tempfile::NamedTempFile::new()?;
+
+    fs::write(input.path(), "a: [1\nb: 2\n")?;
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("--log-file")
+        .arg(log.path())
+        .arg("validate")
+        .arg(input.path())
+        .assert()
+        .failure();
+
+    let log_content = fs::read_to_string(log.path())?;
+    assert!(log_content.contains("line: 2, column: 2"));
+
+    Ok(())
+}
+
+#[test]
+fn test_json_array_streaming_to_ndjson() -> Result<(), Error> {
+    let input = Builder::new().suffix(".json").tempfile()?;
+    let output = Builder::new().suffix(".ndjson").tempfile()?;
+
+    fs::write(input.path(), "[\n  {\"a\": 1, \"s\": \"x,]\"},\n  {\"a\": [1, 2]}\n]\n")?;
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("convert")
+        .arg(input.path())
+        .arg("-o")
+        .arg(output.path())
+        .assert()
+        .success();
+
+    let result = fs::read_to_string(output.path())?;
+    assert_eq!(result, "{\"a\":1,\"s\":\"x,]\"}\n{\"a\":[1,2]}\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_json_path_nested_array() -> Result<(), Error> {
+    let input = Builder::new().suffix(".json").tempfile()?;
+    let output = Builder::new().suffix(".ndjson").tempfile()?;
+
+    fs::write(
+        input.path(),
+        r#"{"meta": {"skip": [1, {"q": "]"}]}, "data": {"count": 2, "items": [{"id": 1}, {"id": 2}]}}"#,
+    )?;
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("convert")
+        .arg(input.path())
+        .arg("-o")
+        .arg(output.path())
+        .arg("--json-path")
+        .arg(".data.items")
+        .assert()
+        .success();
+
+    let result = fs::read_to_string(output.path())?;
+    assert_eq!(result, "{\"id\":1}\n{\"id\":2}\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_ndjson_to_csv_flattening() -> Result<(), Error> {
+    let input = Builder::new().suffix(".ndjson").tempfile()?;
+    let output = Builder::new().suffix(".csv").tempfile()?;
+
+    fs::write(
+        input.path(),
+        "{\"user\": {\"name\": \"a\", \"address\": {\"city\": \"x\"}}, \"tags\": [\"t1\", \"t2\"]}\n{\"user\": {\"name\": \"b\"}}\n",
+    )?;
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("convert")
+        .arg(input.path())
+        .arg("-o")
+        .arg(output.path())
+        .assert()
+        .success();
+
+    let result = fs::read_to_string(output.path())?;
+    assert_eq!(result, "user.name,user.address.city,tags[0],tags[1]\na,x,t1,t2\nb,,,\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_flattening_two_pass_with_json_arrays() -> Result<(), Error> {
+    let input = Builder::new().suffix(".ndjson").tempfile()?;
+    let output = Builder::new().suffix(".csv").tempfile()?;
+
+    fs::write(input.path(), "{\"a\": {\"b\": [1, 2]}}\n{\"c\": true}\n")?;
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("convert")
+        .arg(input.path())
+        .arg("-o")
+        .arg(output.path())
+        .arg("--sample-size")
+        .arg("1")
+        .arg("--two-pass")
+        .arg("--flatten-arrays")
+        .arg("json")
+        .arg("--flatten-separator")
+        .arg("_")
+        .assert()
+        .success();
+
+    let result = fs::read_to_string(output.path())?;
+    assert_eq!(result, "a_b,c\n\"[1,2]\",\n,true\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_csv_unflatten_to_ndjson() -> Result<(), Error> {
+    let input = Builder::new().suffix(".csv").tempfile()?;
+    let output = Builder::new().suffix(".ndjson").tempfile()?;
+
+    fs::write(input.path(), "id,user.name,user.tags[0],user.tags[1]\n1,a,x,y\n2,b,,\n")?;
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("convert")
+        .arg(input.path())
+        .arg("-o")
+        .arg(output.path())
+        .arg("--unflatten")
+        .arg("--parse-numbers")
+        .assert()
+        .success();
+
+    let result = fs::read_to_string(output.path())?;
+    assert_eq!(
+        result,
+        "{\"id\":1,\"user\":{\"name\":\"a\",\"tags\":[\"x\",\"y\"]}}\n{\"id\":2,\"user\":{\"name\":\"b\"}}\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_csv_unflatten_to_toml() -> Result<(), Error> {
+    let input = Builder::new().suffix(".csv").tempfile()?;
+    let output = Builder::new().suffix(".toml").tempfile()?;
+
+    fs::write(input.path(), "id,user.name\n1,a\n")?;
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("convert")
+        .arg(input.path())
+        .arg("-o")
+        .arg(output.path())
+        .arg("--unflatten")
+        .assert()
+        .success();
+
+    let result = fs::read_to_string(output.path())?;
+    assert!(result.contains("[[Rows]]\nid = \"1\"\n\n[Rows.user]\nname = \"a\"\n"));
+
+    Ok(())
+}
+
+#[test]
+fn test_csv_unflatten_keeps_literals_like_flat_rows() -> Result<(), Error> {
+    let input = Builder::new().suffix(".csv").tempfile()?;
+
+    fs::write(input.path(), "a,b,c,d\ntrue,false,null,x\n")?;
+
+    for to in ["json", "ndjson", "toml", "yaml"] {
+        let mut values = Vec::new();
+
+        for unflatten in [false, true] {
+            let output = Builder::new().suffix(&format!(".{}", to)).tempfile()?;
+
+            let mut cmd = Command::new(cargo::cargo_bin!("fiux"));
+            cmd.arg("convert").arg(input.path()).arg("-o").arg(output.path());
+
+            if unflatten {
+                cmd.arg("--unflatten");
+            }
+
+            cmd.assert().success();
+
+            // read the output back so formatting differences don't matter
+            let back = Command::new(cargo::cargo_bin!("fiux"))
+                .arg("convert")
+                .arg(output.path())
+                .args(["-o", "-", "--to", "ndjson"])
+                .assert()
+                .success();
+
+            values.push(String::from_utf8_lossy(&back.get_output().stdout).into_owned());
+        }
+
+        assert_eq!(values[0], values[1], "{}", to);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_filter_single_values() -> Result<(), Error> {
+    let shapes = [
+        ("json", "{\"a\": 1}\n{\"a\": 2}\n", "{\"a\":1}\n"),
+        ("json", "{\"a\": 2}", ""),
+        ("json", "{\"a\": 1}", "{\"a\":1}\n"),
+        ("yaml", "a: 2\n", ""),
+        ("yaml", "a: 1\n", "{\"a\":1}\n"),
+        ("toml", "a = 2\n", ""),
+        ("toml", "a = 1\n", "{\"a\":1}\n"),
+    ];
+
+    for (ext, content, expected) in shapes {
+        let input = Builder::new().suffix(&format!(".{}", ext)).tempfile()?;
+        fs::write(input.path(), content)?;
+
+        let out = Command::new(cargo::cargo_bin!("fiux"))
+            .arg("convert")
+            .arg(input.path())
+            .args(["-o", "-", "--to", "ndjson", "--filter", "a == 1"])
+            .assert()
+            .success();
+
+        assert_eq!(String::from_utf8_lossy(&out.get_output().stdout), expected, "{}", content);
+    }
+
+    let input = Builder::new().suffix(".json").tempfile()?;
+    fs::write(input.path(), "{\"a\": 1}\n{\"a\": 2}\n")?;
+
+    let out = Command::new(cargo::cargo_bin!("fiux"))
+        .arg("aggregate")
+        .arg(input.path())
+        .args(["-o", "-", "--to", "ndjson", "--agg", "count()", "--filter", "a == 1"])
+        .assert()
+        .success();
+
+    assert_eq!(String::from_utf8_lossy(&out.get_output().stdout), "{\"count\": 1}\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_library_converter() -> Result<(), fiux::CtxResultErr> {
+    use fiux::{Converter, Format};
+
+    let mut output = Vec::new();
+
+    Converter::new("{\"a\": {\"b\": 1}}\n".as_bytes(), Format::Ndjson, Format::Csv)
+        .convert(&mut output)?;
+
+    assert_eq!(output, b"a.b\n1\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_library_stages_run_in_order() -> Result<(), fiux::CtxResultErr> {
+    use fiux::{Converter, Format, Query, SortOpts, parse_sort_key};
+
+    let input = "{\"n\": 2}\n{\"n\": 1}\n{\"n\": 3}\n";
+    let sort = || SortOpts { keys: vec![parse_sort_key("n").unwrap()], memory_limit: 1 << 20 };
+    let query = || Query::parse("{m: .n}").unwrap();
+
+    let mut sorted_first = Vec::new();
+
+    Converter::new(input.as_bytes(), Format::Ndjson, Format::Ndjson)
+        .sort(sort())
+        .query(query())
+        .convert(&mut sorted_first)?;
+
+    // `n` is gone after the query, so sorting afterwards keeps the input order
+    let mut queried_first = Vec::new();
+
+    Converter::new(input.as_bytes(), Format::Ndjson, Format::Ndjson)
+        .query(query())
+        .sort(sort())
+        .convert(&mut queried_first)?;
+
+    assert_eq!(sorted_first, b"{\"m\":1}\n{\"m\":2}\n{\"m\":3}\n");
+    assert_eq!(queried_first, b"{\"m\":2}\n{\"m\":1}\n{\"m\":3}\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_library_errors_instead_of_exiting() {
+    use fiux::{Converter, Format, validate};
+
+    assert!(validate("a,b\n1,2\n3\n".as_bytes(), Format::Csv).is_err());
+
+    let res = Converter::new("a,b\n1,2\n".as_bytes(), Format::Csv, Format::Json)
+        .input_delimiter('€')
+        .convert(Vec::new());
+
+    assert!(res.is_err());
+}
+
+#[test]
+fn test_formats_subcommand() -> Result<(), Error> {
+    let out = Command::new(cargo::cargo_bin!("fiux")).arg("formats").output()?;
+
+    assert!(out.status.success());
+
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.starts_with("NAME"));
+    assert!(stdout.lines().any(|l| l.starts_with("yaml") && l.contains("yaml, yml")));
+
+    Ok(())
+}
+
+#[test]
+fn test_library_custom_format() -> Result<(), fiux::CtxResultErr> {
+    use fiux::*;
+    use std::io::{BufRead, BufReader, Read};
+    use std::sync::Arc;
+
+    /// Decodes `key=value` lines into JSON objects.
+    struct KeyValue;
+
+    impl FormatDecoder for KeyValue {
+        fn decode(
+            &self,
+            reader: Box<dyn Read>,
+            _opts: &ConvertOptions,
+        ) -> CtxResult<WriterStreams<Records>> {
+            let lines = BufReader::new(reader).lines().map(|line| {
+                let line = line.context("Failed to read line")?;
+                let (k, v) = line.split_once('=').unwrap_or((&line, ""));
+
+                Ok(Value::Map(Map::from_iter([(k.to_string(), Value::from(v))])))
+            });
+
+            Ok(WriterStreams::Ndjson { values: Box::new(lines) })
+        }
+    }
+
+    impl FileFormat for KeyValue {
+        fn name(&self) -> &str {
+            "kv"
+        }
+
+        fn extensions(&self) -> &[&str] {
+            &["kv"]
+        }
+
+        fn decoder(&self) -> Option<&dyn FormatDecoder> {
+            Some(self)
+        }
+    }
+
+    register_format(KeyValue);
+
+    let kv = find_format("kv").expect("registered format");
+    let mut output = Vec::new();
+
+    Converter::new("a=1\n".as_bytes(), Arc::clone(&kv), Format::Ndjson).convert(&mut output)?;
+
+    assert_eq!(output, b"{\"a\":\"1\"}\n");
+    assert!(validate("a=1\n".as_bytes(), kv).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_toml_to_ndjson_keeps_key_order_and_datetimes() -> Result<(), Error> {
+    let input = Builder::new().suffix(".toml").tempfile()?;
+    let output = Builder::new().suffix(".ndjson").tempfile()?;
+
+    fs::write(input.path(), "b = 1\na = 1979-05-27T07:32:00Z\n")?;
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("convert")
+        .arg(input.path())
+        .arg("-o")
+        .arg(output.path())
+        .assert()
+        .success();
+
+    let result = fs::read_to_string(output.path())?;
+    assert_eq!(result, "{\"b\":1,\"a\":\"1979-05-27T07:32:00Z\"}\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_ndjson_nulls_and_big_numbers_to_toml() -> Result<(), Error> {
+    let input = Builder::new().suffix(".ndjson").tempfile()?;
+    let output = Builder::new().suffix(".toml").tempfile()?;
+
+    fs::write(input.path(), "{\"a\": null, \"b\": 18446744073709551615}\n")?;
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("convert")
+        .arg(input.path())
+        .arg("-o")
+        .arg(output.path())
+        .assert()
+        .success();
+
+    let result = fs::read_to_string(output.path())?;
+    assert_eq!(result, "[[Array]]\nb = \"18446744073709551615\"\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_csv_type_inference() -> Result<(), Error> {
+    let input = Builder::new().suffix(".csv").tempfile()?;
+    let output = Builder::new().suffix(".ndjson").tempfile()?;
+
+    fs::write(input.path(), "zip,id,n,f,on,day\n01234,+1555,7,1.5,true,2024-01-02\n")?;
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("convert")
+        .arg(input.path())
+        .arg("-o")
+        .arg(output.path())
+        .arg("-p")
+        .assert()
+        .success();
+
+    let result = fs::read_to_string(output.path())?;
+    assert_eq!(
+        result,
+        "{\"zip\": \"01234\", \"id\": \"+1555\", \"n\": 7, \"f\": 1.5, \"on\": true, \"day\": \"2024-01-02\"}\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_csv_type_overrides_and_whole_file_inference() -> Result<(), Error> {
+    let input = Builder::new().suffix(".csv").tempfile()?;
+    let output = Builder::new().suffix(".ndjson").tempfile()?;
+
+    fs::write(input.path(), "a,b,c\n1,2,true\n2,2.5,false\n")?;
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("convert")
+        .arg(input.path())
+        .arg("-o")
+        .arg(output.path())
+        .args(["-p", "--type", "a=string", "--type", "c=string"])
+        .args(["--infer-rows", "1", "--infer-all"])
+        .assert()
+        .success();
+
+    let result = fs::read_to_string(output.path())?;
+    assert_eq!(
+        result,
+        "{\"a\": \"1\", \"b\": 2.0, \"c\": \"true\"}\n{\"a\": \"2\", \"b\": 2.5, \"c\": \"false\"}\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_select_exclude_rename_csv_columns() -> Result<(), Error> {
+    let input = Builder::new().suffix(".csv").tempfile()?;
+    let output = Builder::new().suffix(".csv").tempfile()?;
+
+    fs::write(input.path(), "id,name,email,age\n1,a,a@x,3\n")?;
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("convert")
+        .arg(input.path())
+        .arg("-o")
+        .arg(output.path())
+        .args(["--select", "name,id,age", "--exclude", "age", "--rename", "name=full_name"])
+        .assert()
+        .success();
+
+    let result = fs::read_to_string(output.path())?;
+    assert_eq!(result, "full_name,id\na,1\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_select_rename_nested_json_keys() -> Result<(), Error> {
+    let input = Builder::new().suffix(".json").tempfile()?;
+    let output = Builder::new().suffix(".ndjson").tempfile()?;
+
+    fs::write(
+        input.path(),
+        r#"[{"id": 1, "user": {"name": "a", "age": 3}, "extra": true}, {"id": 2, "user": {"name": "b"}}]"#,
+    )?;
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("convert")
+        .arg(input.path())
+        .arg("-o")
+        .arg(output.path())
+        .args(["--select", "user.name,id", "--rename", "user.name=name"])
+        .assert()
+        .success();
+
+    let result = fs::read_to_string(output.path())?;
+    assert_eq!(result, "{\"id\":1,\"name\":\"a\"}\n{\"id\":2,\"name\":\"b\"}\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_filter_ndjson_to_csv() -> Result<(), Error> {
+    let input = Builder::new().suffix(".ndjson").tempfile()?;
+    let output = Builder::new().suffix(".csv").tempfile()?;
+
+    fs::write(
+        input.path(),
+        r#"{"level": "error", "latency_ms": 620, "user": {"id": "u1"}}
+{"level": "error", "latency_ms": 12, "user": {"id": "u2"}}
+{"level": "info", "latency_ms": 900, "user": {"id": "u3"}}
+{"level": "error", "latency_ms": 501, "user": null}
+"#,
+    )?;
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("convert")
+        .arg(input.path())
+        .arg("-o")
+        .arg(output.path())
+        .args(["--filter", r#"level == "error" && latency_ms > 500 && user.id =~ "^u""#])
+        .args(["--select", "user.id,latency_ms"])
+        .assert()
+        .success();
+
+    let result = fs::read_to_string(output.path())?;
+    assert_eq!(result, "user.id,latency_ms\nu1,620\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_filter_csv_rows_and_invalid_expression() -> Result<(), Error> {
+    let input = Builder::new().suffix(".csv").tempfile()?;
+    let output = Builder::new().suffix(".csv").tempfile()?;
+
+    fs::write(input.path(), "name,age,email\na,30,\nb,9,b@x\nc,41,c@x\n")?;
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("convert")
+        .arg(input.path())
+        .arg("-o")
+        .arg(output.path())
+        .args(["--filter", "age >= 10 && email != null"])
+        .assert()
+        .success();
+
+    let result = fs::read_to_string(output.path())?;
+    assert_eq!(result, "name,age,email\nc,41,c@x\n");
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("convert")
+        .arg(input.path())
+        .arg("-o")
+        .arg(output.path())
+        .args(["--filter", "age >"])
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+#[test]
+fn test_query_toml_to_csv_stdout() -> Result<(), Error> {
+    let input = Builder::new().suffix(".toml").tempfile()?;
+
+    fs::write(
+        input.path(),
+        "[[servers]]\nhost = \"a\"\nport = 80\n\n[[servers]]\nhost = \"b\"\nport = 8080\n",
+    )?;
+
+    let output = Command::new(cargo::cargo_bin!("fiux"))
+        .arg("query")
+        .arg(input.path())
+        .arg(".servers[].host")
+        .args(["--to", "csv"])
+        .output()?;
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "value\na\nb\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_query_csv_rows_to_json() -> Result<(), Error> {
+    let input = Builder::new().suffix(".csv").tempfile()?;
+    let output = Builder::new().suffix(".json").tempfile()?;
+
+    fs::write(input.path(), "name,age,city\na,30,x\nb,5,y\nc,41,z\n")?;
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("query")
+        .arg(input.path())
+        .arg("select(.age >= 30) | {name, place: .city}")
+        .arg("-o")
+        .arg(output.path())
+        .assert()
+        .success();
+
+    let result: serde_json::Value = serde_json::from_str(&fs::read_to_string(output.path())?)?;
+
+    assert_eq!(
+        result,
+        serde_json::json!([{"name": "a", "place": "x"}, {"name": "c", "place": "z"}])
+    );
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("query")
+        .arg(input.path())
+        .arg(".[")
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+#[test]
+fn test_sort_csv_by_multiple_keys() -> Result<(), Error> {
+    let input = Builder::new().suffix(".csv").tempfile()?;
+    let output = Builder::new().suffix(".csv").tempfile()?;
+
+    fs::write(input.path(), "name,team,score\na,x,9\nb,y,10\nc,x,100\nd,y,\ne,x,10\n")?;
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("sort")
+        .arg(input.path())
+        .arg("-o")
+        .arg(output.path())
+        .args(["--key", "team", "--key", "score:desc:num"])
+        .assert()
+        .success();
+
+    let result = fs::read_to_string(output.path())?;
+    assert_eq!(result, "name,team,score\nc,x,100\ne,x,10\na,x,9\nb,y,10\nd,y,\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_sort_ndjson_spilling_to_disk() -> Result<(), Error> {
+    let input = Builder::new().suffix(".ndjson").tempfile()?;
+    let output = Builder::new().suffix(".ndjson").tempfile()?;
+
+    let lines: Vec<String> = (0..200)
+        .map(|i| format!("{{\"id\":{},\"user\":{{\"age\":{}}}}}", i, (i * 37) % 101))
+        .collect();
+
+    fs::write(input.path(), lines.join("\n"))?;
+
+    // a memory limit of 0 spills every record into its own sorted chunk
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("sort")
+        .arg(input.path())
+        .arg("-o")
+        .arg(output.path())
+        .args(["-k", "user.age:num", "-m", "0"])
+        .assert()
+        .success();
+
+    let ages: Vec<i64> = fs::read_to_string(output.path())?
+        .lines()
+        .map(|l| {
+            serde_json::from_str::<serde_json::Value>(l).unwrap()["user"]["age"].as_i64().unwrap()
+        })
+        .collect();
+
+    assert_eq!(ages.len(), 200);
+    assert!(ages.windows(2).all(|w| w[0] <= w[1]));
+
+    Ok(())
+}
+
+#[test]
+fn test_dedupe_ndjson_by_key() -> Result<(), Error> {
+    let input = Builder::new().suffix(".ndjson").tempfile()?;
+    let output = Builder::new().suffix(".ndjson").tempfile()?;
+
+    fs::write(
+        input.path(),
+        "{\"id\":1,\"v\":\"a\"}\n{\"id\":2,\"v\":\"b\"}\n{\"id\":1,\"v\":\"c\"}\n{\"id\":2,\"v\":\"b\"}\n",
+    )?;
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("dedupe")
+        .arg(input.path())
+        .arg("-o")
+        .arg(output.path())
+        .args(["--key", "id", "--keep", "last"])
+        .assert()
+        .success();
+
+    let result = fs::read_to_string(output.path())?;
+    assert_eq!(result, "{\"id\":1,\"v\":\"c\"}\n{\"id\":2,\"v\":\"b\"}\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_dedupe_csv_whole_rows_on_disk() -> Result<(), Error> {
+    let input = Builder::new().suffix(".csv").tempfile()?;
+    let output = Builder::new().suffix(".csv").tempfile()?;
+
+    fs::write(input.path(), "a,b\n1,x\n2,y\n1,x\n1,y\n2,y\n")?;
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("dedupe")
+        .arg(input.path())
+        .arg("-o")
+        .arg(output.path())
+        .arg("--on-disk")
+        .assert()
+        .success();
+
+    let result = fs::read_to_string(output.path())?;
+    assert_eq!(result, "a,b\n1,x\n2,y\n1,y\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_join_csv_with_ndjson_left() -> Result<(), Error> {
+    let left = Builder::new().suffix(".csv").tempfile()?;
+    let right = Builder::new().suffix(".ndjson").tempfile()?;
+    let output = Builder::new().suffix(".ndjson").tempfile()?;
+
+    fs::write(left.path(), "id,name\n1,Alice\n2,Bob\n3,Cara\n")?;
+    fs::write(
+        right.path(),
+        "{\"id\":1,\"team\":\"red\"}\n{\"id\":3,\"team\":\"blue\"}\n{\"id\":3,\"team\":\"green\"}\n{\"id\":4,\"team\":\"gray\"}\n",
+    )?;
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("join")
+        .arg(left.path())
+        .arg(right.path())
+        .arg("-o")
+        .arg(output.path())
+        .args(["--on", "id", "--how", "left", "-p"])
+        .assert()
+        .success();
+
+    let result = fs::read_to_string(output.path())?;
+    assert_eq!(
+        result,
+        "{\"id\":1,\"name\":\"Alice\",\"team\":\"red\"}\n{\"id\":2,\"name\":\"Bob\"}\n{\"id\":3,\"name\":\"Cara\",\"team\":\"blue\"}\n{\"id\":3,\"name\":\"Cara\",\"team\":\"green\"}\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_join_full_sort_merge_to_csv() -> Result<(), Error> {
+    let left = Builder::new().suffix(".csv").tempfile()?;
+    let right = Builder::new().suffix(".json").tempfile()?;
+    let output = Builder::new().suffix(".csv").tempfile()?;
+
+    fs::write(left.path(), "user_id,name\n3,Cara\n1,Alice\n2,Bob\n")?;
+    fs::write(
+        right.path(),
+        r#"[{"id":4,"team":"gray"},{"id":1,"team":"red"},{"id":3,"team":"blue"}]"#,
+    )?;
+
+    // a memory limit of 0 forces a sort-merge join
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("join")
+        .arg(left.path())
+        .arg(right.path())
+        .arg("-o")
+        .arg(output.path())
+        .args(["--on", "user_id=id", "--how", "full", "-m", "0"])
+        .assert()
+        .success();
+
+    let result = fs::read_to_string(output.path())?;
+    assert_eq!(result, "user_id,name,id,team\n1,Alice,1,red\n2,Bob,,\n3,Cara,3,blue\n4,,4,gray\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_aggregate_csv_by_group() -> Result<(), Error> {
+    let input = Builder::new().suffix(".csv").tempfile()?;
+    let output = Builder::new().suffix(".csv").tempfile()?;
+
+    fs::write(
+        input.path(),
+        "region,amount,latency,ts,user\neu,10,1.5,2024-01-02,a\nus,3,2,2024-01-01,b\neu,5,,2024-01-03,c\neu,1,0.5,2024-01-01,a\n",
+    )?;
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("aggregate")
+        .arg(input.path())
+        .arg("-o")
+        .arg(output.path())
+        .args(["--group-by", "region"])
+        .args(["--agg", "count(),sum(amount),avg(latency),min(ts),max(ts),distinct(user)"])
+        .assert()
+        .success();
+
+    let result = fs::read_to_string(output.path())?;
+    assert_eq!(
+        result,
+        "region,count,sum_amount,avg_latency,min_ts,max_ts,distinct_user\neu,3,16,1.0,2024-01-01,2024-01-03,2\nus,1,3,2.0,2024-01-01,2024-01-01,1\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_aggregate_ndjson_nested_keys_to_json() -> Result<(), Error> {
+    let input = Builder::new().suffix(".ndjson").tempfile()?;
+    let output = Builder::new().suffix(".ndjson").tempfile()?;
+
+    fs::write(
+        input.path(),
+        "{\"user\":{\"country\":\"de\"},\"ms\":10}\n{\"user\":{\"country\":\"fr\"},\"ms\":4}\n{\"user\":{\"country\":\"de\"},\"ms\":20}\n",
+    )?;
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("aggregate")
+        .arg(input.path())
+        .arg("-o")
+        .arg(output.path())
+        .args(["-g", "user.country", "--agg", "count()", "--agg", "max(ms)"])
+        .assert()
+        .success();
+
+    let result = fs::read_to_string(output.path())?;
+    assert_eq!(
+        result,
+        "{\"user.country\": \"de\", \"count\": 2, \"max_ms\": 20}\n{\"user.country\": \"fr\", \"count\": 1, \"max_ms\": 4}\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_stats_csv_to_csv() -> Result<(), Error> {
+    let input = Builder::new().suffix(".csv").tempfile()?;
+    let output = Builder::new().suffix(".csv").tempfile()?;
+
+    fs::write(input.path(), "id,region,score\n1,eu,2.5\n2,us,\n3,eu,4.5\n")?;
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("stats")
+        .arg(input.path())
+        .arg("-o")
+        .arg(output.path())
+        .args(["--top", "1"])
+        .assert()
+        .success();
+
+    let result = fs::read_to_string(output.path())?;
+    assert_eq!(
+        result,
+        "field,type,count,nulls,distinct,min,max,mean,stddev,min_length,max_length,top\n\
+        id,int,3,0,3,1,3,2.0,1.0,,,\"[{\"\"value\"\":1,\"\"count\"\":1}]\"\n\
+        region,string,3,0,2,eu,us,,,2,2,\"[{\"\"value\"\":\"\"eu\"\",\"\"count\"\":2}]\"\n\
+        score,float,2,1,2,2.5,4.5,3.5,1.4142135623730951,,,\"[{\"\"value\"\":2.5,\"\"count\"\":1}]\"\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_stats_ndjson_text_table_stdout() -> Result<(), Error> {
+    let input = Builder::new().suffix(".ndjson").tempfile()?;
+
+    fs::write(input.path(), "{\"user\":{\"id\":1},\"ok\":true}\n{\"user\":{\"id\":2}}\n")?;
+
+    let output =
+        Command::new(cargo::cargo_bin!("fiux")).arg("describe").arg(input.path()).output()?;
+
+    assert!(output.status.success());
+
+    let table = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = table.lines().collect();
+
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("field    type  count  nulls  distinct"));
+    assert!(lines[1].starts_with("user.id  int   2      0      2"));
+    assert!(lines[2].starts_with("ok       bool  1      1      1"));
+    assert!(lines[2].ends_with("true (1)"));
+
+    Ok(())
+}
+
+#[test]
+fn test_schema_ndjson_to_stdout() -> Result<(), Error> {
+    let input = Builder::new().suffix(".ndjson").tempfile()?;
+
+    fs::write(
+        input.path(),
+        "{\"id\":1,\"level\":\"info\",\"user\":{\"email\":\"a@x.io\"}}\n\
+        {\"id\":2,\"level\":\"info\",\"user\":{\"email\":\"b@x.io\"},\"at\":\"2024-01-31T10:00:00Z\"}\n\
+        {\"id\":3,\"level\":\"warn\",\"user\":{\"email\":\"c@x.io\"}}\n\
+        {\"id\":4,\"level\":\"info\",\"user\":{\"email\":\"d@x.io\"}}\n",
+    )?;
+
+    let output =
+        Command::new(cargo::cargo_bin!("fiux")).arg("schema").arg(input.path()).output()?;
+
+    assert!(output.status.success());
+
+    let schema: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+
+    assert_eq!(schema["$schema"], "https://json-schema.org/draft/2020-12/schema");
+    assert_eq!(schema["required"], serde_json::json!(["id", "level", "user"]));
+    assert_eq!(schema["properties"]["id"], serde_json::json!({"type": "integer"}));
+    assert_eq!(schema["properties"]["level"]["enum"], serde_json::json!(["info", "warn"]));
+    assert_eq!(schema["properties"]["user"]["properties"]["email"]["format"], "email");
+    assert_eq!(schema["properties"]["at"]["format"], "date-time");
+
+    Ok(())
+}
+
+#[test]
+fn test_validate_ndjson_with_schema() -> Result<(), Error> {
+    let input = Builder::new().suffix(".ndjson").tempfile()?;
+    let schema = Builder::new().suffix(".json").tempfile()?;
+    let log = Builder::new().suffix(".md").tempfile()?;
+
+    fs::write(
+        schema.path(),
+        r#"{"type": "object", "properties": {"id": {"type": "integer"}, "email": {"format": "email"}}, "required": ["id"]}"#,
+    )?;
+    fs::write(
+        input.path(),
+        "{\"id\":1,\"email\":\"a@x.io\"}\n{\"id\":2,\"email\":\"nope\"}\n{\"email\":\"c@x.io\"}\n",
+    )?;
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("validate")
+        .arg(input.path())
+        .arg("--schema")
+        .arg(schema.path())
+        .arg("-l")
+        .arg(log.path())
+        .assert()
+        .failure();
+
+    let log = fs::read_to_string(log.path())?;
+
+    assert!(log.contains("Line: 2, at: /email (schema: /properties/email/format)"));
+    assert!(log.contains("Line: 3, at: / (schema: /required)"));
+    assert!(!log.contains("Line: 1,"));
+
+    Ok(())
+}
+
+#[test]
+fn test_validate_csv_with_schema() -> Result<(), Error> {
+    let input = Builder::new().suffix(".csv").tempfile()?;
+    let schema = Builder::new().suffix(".yaml").tempfile()?;
+
+    fs::write(
+        schema.path(),
+        "type: object\nproperties:\n  id:\n    type: integer\n  score:\n    type: [number, \"null\"]\n",
+    )?;
+    fs::write(input.path(), "id,score\n1,2.5\n2,\n")?;
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("validate")
+        .arg(input.path())
+        .arg("--schema")
+        .arg(schema.path())
+        .assert()
+        .success();
+
+    Ok(())
+}
+
+#[test]
+fn test_validate_csv_with_its_own_schema() -> Result<(), Error> {
+    let input = Builder::new().suffix(".csv").tempfile()?;
+    let schema = Builder::new().suffix(".json").tempfile()?;
+
+    fs::write(input.path(), "g,v\na,1\na,3\nb,x\nb,\nc,2.5\n")?;
+
+    for args in [&["-p"][..], &[]] {
+        Command::new(cargo::cargo_bin!("fiux"))
+            .arg("schema")
+            .arg(input.path())
+            .args(args)
+            .arg("-o")
+            .arg(schema.path())
+            .assert()
+            .success();
+
+        for validate_args in [&["-p"][..], &[]] {
+            Command::new(cargo::cargo_bin!("fiux"))
+                .arg("validate")
+                .arg(input.path())
+                .arg("--schema")
+                .arg(schema.path())
+                .args(validate_args)
+                .assert()
+                .success();
+        }
+    }
+
+    // `v` is a string column in the schema from `schema -p`, overriding it turns `1` into a float
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("schema")
+        .arg(input.path())
+        .arg("-p")
+        .arg("-o")
+        .arg(schema.path())
+        .assert()
+        .success();
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("validate")
+        .arg(input.path())
+        .arg("--schema")
+        .arg(schema.path())
+        .arg("--type")
+        .arg("v=float")
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+#[test]
+fn test_validate_csv_structure() -> Result<(), Error> {
+    let input = Builder::new().suffix(".csv").tempfile()?;
+    let log = Builder::new().suffix(".md").tempfile()?;
+
+    fs::write(input.path(), "id,name,id\n1,a,2\n2,b\n3,\"c\"d,4\n")?;
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("validate")
+        .arg(input.path())
+        .arg("-l")
+        .arg(log.path())
+        .assert()
+        .failure();
+
+    let log = fs::read_to_string(log.path())?;
+
+    assert!(log.contains("Duplicate header -> Invalid CSV data at line: 1, column: 3"));
+    assert!(log.contains("Ragged row -> Invalid CSV data at line: 3, column: 3"));
+    assert!(log.contains("Expected 3 fields, found 2"));
+    assert!(log.contains("Unbalanced quotes -> Invalid CSV data at line: 4, column: 2"));
+
+    Ok(())
+}
+
+#[test]
+fn test_validate_csv_with_csv_schema() -> Result<(), Error> {
+    let input = Builder::new().suffix(".csv").tempfile()?;
+    let schema = Builder::new().suffix(".toml").tempfile()?;
+    let log = Builder::new().suffix(".md").tempfile()?;
+
+    fs::write(
+        schema.path(),
+        "[id]\ntype = \"int\"\nrequired = true\n\n[code]\nregex = '[A-Z]{3}'\n",
+    )?;
+    fs::write(input.path(), "id,code\n1,ABC\nx,AB\n")?;
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("validate")
+        .arg(input.path())
+        .arg("--csv-schema")
+        .arg(schema.path())
+        .arg("-l")
+        .arg(log.path())
+        .assert()
+        .failure();
+
+    let log = fs::read_to_string(log.path())?;
+
+    assert!(log.contains("Invalid type -> Invalid CSV data at line: 3, column: 1"));
+    assert!(log.contains("Regex mismatch -> Invalid CSV data at line: 3, column: 2"));
+    assert!(!log.contains("line: 2,"));
+
+    Ok(())
+}
+
+#[test]
+fn test_threads_output_matches_single_thread() -> Result<(), Error> {
+    let ndjson = Builder::new().suffix(".ndjson").tempfile()?;
+    let csv = Builder::new().suffix(".csv").tempfile()?;
+
+    // inputs over the chunk size so they're split across workers
+    let mut lines = String::new();
+    let mut rows = String::from("id,note,tag\n");
+
+    for i in 0..40_000 {
+        lines.push_str(&format!(
+            "{{\"id\": {i}, \"user\": {{\"name\": \"u{i}\", \"tags\": [\"a\", \"b,c\"]}}}}\n"
+        ));
+        rows.push_str(&format!("{i},\"line one\nline \"\"{i}\"\"\",x\n"));
+
+        if i % 9_999 == 0 {
+            lines.push_str("{broken\n\n");
+            rows.push_str("1,2\n");
+        }
+    }
+
+    fs::write(ndjson.path(), lines)?;
+    fs::write(csv.path(), rows)?;
+
+    let runs = [(ndjson.path(), ".csv", &[][..]), (csv.path(), ".ndjson", &["-p"][..])];
+
+    for (input, ext, args) in runs {
+        let mut outputs = Vec::new();
+
+        for threads in ["1", "4"] {
+            let output = Builder::new().suffix(ext).tempfile()?;
+            let log = Builder::new().suffix(".md").tempfile()?;
+
+            Command::new(cargo::cargo_bin!("fiux"))
+                .arg("convert")
+                .arg(input)
+                .arg("-o")
+                .arg(output.path())
+                .args(args)
+                .arg("--threads")
+                .arg(threads)
+                .arg("-l")
+                .arg(log.path())
+                .assert()
+                .success();
+
+            outputs.push(fs::read(output.path())?);
+        }
+
+        assert!(!outputs[0].is_empty());
+        assert_eq!(outputs[0], outputs[1]);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_mapped_input_matches_stdin() -> Result<(), Error> {
+    let ndjson = Builder::new().suffix(".ndjson").tempfile()?;
+    let csv = Builder::new().suffix(".csv").tempfile()?;
+
+    // inputs over the mmap threshold so files are mapped and stdin is read through a buffer
+    let mut lines = String::new();
+    let mut rows = String::from("id,note,tag\n");
+
+    for i in 0..120_000 {
+        lines.push_str(&format!("{{\"id\": {i}, \"user\": {{\"name\": \"u{i}\"}}}}\r\n"));
+        rows.push_str(&format!("{i},\"first line\nline \"\"{i}\"\"\",tag\n"));
+
+        if i % 29_999 == 0 {
+            lines.push_str("{broken\n\n");
+            rows.push_str("1,2\n");
+        }
+    }
+
+    fs::write(ndjson.path(), lines)?;
+    fs::write(csv.path(), rows)?;
+
+    assert!(fs::metadata(ndjson.path())?.len() >= fiux::MMAP_THRESHOLD);
+    assert!(fs::metadata(csv.path())?.len() >= fiux::MMAP_THRESHOLD);
+
+    for (input, from, to) in [(ndjson.path(), "ndjson", "csv"), (csv.path(), "csv", "json")] {
+        let mut outputs = Vec::new();
+
+        for mapped in [true, false] {
+            let log = Builder::new().suffix(".md").tempfile()?;
+            let mut cmd = assert_cmd::Command::new(cargo::cargo_bin!("fiux"));
+
+            cmd.arg("-l")
+                .arg(log.path())
+                .arg("convert")
+                .arg("--from")
+                .arg(from)
+                .arg("--to")
+                .arg(to);
+
+            if mapped {
+                cmd.arg(input);
+            } else {
+                cmd.arg("-").pipe_stdin(input)?;
+            }
+
+            let out = cmd.arg("-o").arg("-").assert().success().get_output().stdout.clone();
+            let log = fs::read_to_string(log.path())?;
+
+            let warnings: Vec<String> =
+                log.lines().filter(|l| l.contains("line: ")).map(String::from).collect();
+
+            outputs.push((out, warnings));
+        }
+
+        assert!(!outputs[0].0.is_empty());
+        assert_eq!(outputs[0], outputs[1]);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_compressed_input_and_output() -> Result<(), Error> {
+    let dir = Builder::new().tempdir()?;
+    let input = dir.path().join("events.ndjson");
+
+    fs::write(
+        &input,
+        "{\"id\": 1, \"user\": {\"name\": \"a\"}}\n{\"id\": 2, \"user\": {\"name\": \"b\"}}\n",
+    )?;
+
+    let convert = |input: &std::path::Path, output: &std::path::Path| {
+        Command::new(cargo::cargo_bin!("fiux"))
+            .arg("convert")
+            .arg(input)
+            .arg("-o")
+            .arg(output)
+            .assert()
+            .success();
+    };
+
+    let expected = dir.path().join("expected.csv");
+    convert(&input, &expected);
+    let expected = fs::read(expected)?;
+
+    for ext in ["gz", "zst", "bz2", "xz"] {
+        let compressed = dir.path().join(format!("events.ndjson.{ext}"));
+        convert(&input, &compressed);
+
+        let bytes = fs::read(&compressed)?;
+        assert_eq!(fiux::Compression::detect(&bytes), fiux::Compression::from_extension(ext));
+
+        let output = dir.path().join(format!("events.{ext}.csv"));
+        convert(&compressed, &output);
+        assert_eq!(fs::read(&output)?, expected);
+
+        Command::new(cargo::cargo_bin!("fiux")).arg("validate").arg(&compressed).assert().success();
+
+        // compressed stdin is detected from its magic bytes
+        let out = assert_cmd::Command::new(cargo::cargo_bin!("fiux"))
+            .args(["convert", "-", "--from", "ndjson", "-o", "-", "--to", "csv"])
+            .pipe_stdin(&compressed)?
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+
+        assert_eq!(out, expected);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_detect_format_from_content() -> Result<(), Error> {
+    let dir = Builder::new().tempdir()?;
+
+    let tsv = dir.path().join("data");
+    let ndjson = dir.path().join("export.txt");
+
+    fs::write(&tsv, "id\tname\n1\tAlice\n2\tBob\n")?;
+    fs::write(
+        &ndjson,
+        "\u{feff}{\"id\": \"1\", \"name\": \"Alice\"}\n{\"id\": \"2\", \"name\": \"Bob\"}\n",
+    )?;
+
+    let out = Command::new(cargo::cargo_bin!("fiux")).arg("detect").arg(&tsv).output()?;
+    let stdout = String::from_utf8_lossy(&out.stdout);
+
+    assert!(out.status.success());
+    assert!(stdout.contains("format: csv"));
+    assert!(stdout.contains("delimiter: '\\t'"));
+    assert!(stdout.contains("confidence: "));
+
+    let mut outputs = Vec::new();
+
+    for input in [&tsv, &ndjson] {
+        let out = Command::new(cargo::cargo_bin!("fiux"))
+            .arg("convert")
+            .arg(input)
+            .args(["-o", "-", "--to", "ndjson"])
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+
+        let values = String::from_utf8_lossy(&out)
+            .lines()
+            .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+            .collect::<Vec<_>>();
+
+        outputs.push(values);
+    }
+
+    assert_eq!(outputs[0], outputs[1]);
+
+    // stdin is sniffed too when `--from` isn't set
+    let out = assert_cmd::Command::new(cargo::cargo_bin!("fiux"))
+        .args(["convert", "-", "-o", "-", "--to", "ndjson"])
+        .pipe_stdin(&tsv)?
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let out = String::from_utf8_lossy(&out)
+        .lines()
+        .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+        .collect::<Vec<_>>();
+
+    assert_eq!(out, outputs[0]);
+
+    // `--from` overrides the detected format
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("validate")
+        .arg(&tsv)
+        .args(["--from", "json"])
+        .assert()
+        .failure();
+
+    fs::write(dir.path().join("notes"), "just some text\n")?;
+
+    Command::new(cargo::cargo_bin!("fiux"))
+        .arg("detect")
+        .arg(dir.path().join("notes"))
+        .assert()
+        .failure();
+
+    Ok(())
+}